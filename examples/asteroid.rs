@@ -3,8 +3,6 @@ use cgmath::{InnerSpace, Matrix4, Point3, Rad, vec3};
 use glfw::{Action, Context, Key};
 use rand::Rng;
 
-use std::ptr;
-use std::mem;
 use std::str;
 
 use game_engine::*;
@@ -96,7 +94,7 @@ fn main() {
     window.set_scroll_polling(true);
     window.set_cursor_mode(glfw::CursorMode::Disabled);
 
-    gl::load_with(|symbol| window.get_proc_address(symbol) as *const _);
+    let context = GlContext::load(&mut window);
 
     let planet_shader = unsafe {
         Shader::from_str(PLANET_VERTEX_SHADER, FRAGMENT_SHADER)
@@ -106,13 +104,17 @@ fn main() {
         Shader::from_str(ROCK_VERTEX_SHADER, FRAGMENT_SHADER)
     };
 
-    let planet = unsafe {
-        Model::load_obj("./examples/planet/planet.obj").unwrap()
+    let rock_depth_shader = unsafe {
+        Shader::from_str(ROCK_VERTEX_SHADER, DEPTH_ONLY_FRAGMENT_SHADER)
     };
 
-    let rock = unsafe {
-        Model::load_obj("./examples/rock/rock.obj").unwrap()
-    };
+    // toggle the depth prepass with P
+    let mut renderer = Renderer::new();
+    renderer.set_depth_prepass(true);
+
+    let planet = Model::load_obj(&context, "./examples/planet/planet.obj").unwrap();
+
+    let rock = Model::load_obj(&context, "./examples/rock/rock.obj").unwrap();
 
     unsafe {
         gl::Enable(gl::DEPTH_TEST);
@@ -147,43 +149,12 @@ fn main() {
         model_matrices
     };
 
-    unsafe {
-        let mut buffer = 0;
-        gl::GenBuffers(1, &mut buffer);
-        gl::BindBuffer(gl::ARRAY_BUFFER, buffer);
-        gl::BufferData(
-            gl::ARRAY_BUFFER,
-            conv!(mem::size_of::<Matrix4<f32>>() * model_matrices.len()),
-            model_matrices.as_ptr() as *const _,
-            gl::STATIC_DRAW,
-        );
-
-        for mesh in rock.meshes() {
-            let vao = mesh.vao();
-            gl::BindVertexArray(vao);
-
-            let mat4size: gl::types::GLsizei = conv!(mem::size_of::<Matrix4<f32>>());
-            let vec4size: gl::types::GLsizei = conv!(mem::size_of::<f32>() * 4);
-
-            gl::EnableVertexAttribArray(3);
-            gl::VertexAttribPointer(3, 4, gl::FLOAT, gl::FALSE, mat4size, ptr::null());
-            gl::EnableVertexAttribArray(4);
-            gl::VertexAttribPointer(4, 4, gl::FLOAT, gl::FALSE, mat4size, vec4size as *const _);
-            gl::EnableVertexAttribArray(5);
-            gl::VertexAttribPointer(5, 4, gl::FLOAT, gl::FALSE, mat4size, (2 * vec4size) as *const _);
-            gl::EnableVertexAttribArray(6);
-            gl::VertexAttribPointer(6, 4, gl::FLOAT, gl::FALSE, mat4size, (3 * vec4size) as *const _);
-
-            gl::VertexAttribDivisor(3, 1);
-            gl::VertexAttribDivisor(4, 1);
-            gl::VertexAttribDivisor(5, 1);
-            gl::VertexAttribDivisor(6, 1);
-
-            gl::BindVertexArray(0);
-        }
-
-        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+    // culls on the GPU when compute shaders are available, toggle with G
+    let mut gpu_culling = GpuCulling::new(&context, &rock).ok();
+    if let Some(culling) = gpu_culling.as_mut() {
+        culling.set_instances(&context, &model_matrices);
     }
+    let mut use_gpu_culling = gpu_culling.is_some();
 
     let mut last_time = glfw.get_time() as f32;
     let mut delta_time;
@@ -221,6 +192,12 @@ fn main() {
                 glfw::WindowEvent::Key(Key::Escape, _, Action::Press, _) => {
                     window.set_should_close(true)
                 }
+                glfw::WindowEvent::Key(Key::P, _, Action::Press, _) => {
+                    renderer.set_depth_prepass(!renderer.depth_prepass());
+                }
+                glfw::WindowEvent::Key(Key::G, _, Action::Press, _) => {
+                    use_gpu_culling = !use_gpu_culling && gpu_culling.is_some();
+                }
                 _ => {}
             }
 
@@ -236,16 +213,31 @@ fn main() {
             planet_shader.set_matrix4(c_str!("model"), &Matrix4::from_scale(4.0));
             planet_shader.set_matrix4(c_str!("projection"), &camera.projection());
             planet_shader.set_matrix4(c_str!("view"), &camera.view());
-            planet.draw(planet_shader);
+            planet.draw(&context, &planet_shader);
 
-            rock_shader.set_matrix4(c_str!("projection"), &camera.projection());
-            rock_shader.set_matrix4(c_str!("view"), &camera.view());
-            
-            for mesh in rock.meshes() {
-                mesh.draw_instanced(rock_shader, conv!(model_matrices.len()));
+            if let Some(culling) = gpu_culling.as_mut().filter(|_| use_gpu_culling) {
+                culling.cull(&context, &(camera.projection() * camera.view()));
             }
+            let culling = gpu_culling.as_ref().filter(|_| use_gpu_culling);
+
+            renderer.render(&context, |pass| {
+                let shader = match pass {
+                    PassKind::DepthOnly => &rock_depth_shader,
+                    PassKind::Shaded => &rock_shader,
+                };
+                shader.use_program();
+                shader.set_matrix4(c_str!("projection"), &camera.projection());
+                shader.set_matrix4(c_str!("view"), &camera.view());
+
+                match culling {
+                    Some(culling) => culling.draw(&context, shader, &rock),
+                    None => rock.draw_instanced(&context, shader, &model_matrices),
+                }
+            });
         }
 
+        log::info!("{}", FrameStats::end_frame());
+
         window.swap_buffers();
         glfw.poll_events();
     }
@@ -112,15 +112,13 @@ fn main() {
     window.set_scroll_polling(true);
     window.set_cursor_mode(glfw::CursorMode::Disabled);
 
-    gl::load_with(|symbol| window.get_proc_address(symbol) as *const _);
+    let context = GlContext::load(&mut window);
 
     let shader = unsafe {
         Shader::with_geometry_shader(VERTEX_SHADER, GEOMETRY_SHADER, FRAGMENT_SHADER)
     };
 
-    let model_obj = unsafe { 
-        Model::load_obj("./examples/nanosuit/nanosuit.obj").unwrap()
-    };
+    let model_obj = Model::load_obj(&context, "./examples/nanosuit/nanosuit.obj").unwrap();
 
     unsafe {
         gl::Enable(gl::DEPTH_TEST);
@@ -171,7 +169,7 @@ fn main() {
             shader.set_matrix4(c_str!("view"), &camera.view());
             shader.set_matrix4(c_str!("projection"), &camera.projection());
             shader.set_float(c_str!("time"), current_time);
-            model_obj.draw(shader);
+            model_obj.draw(&context, &shader);
             gl::DrawArrays(gl::POINTS, 0, 4);
         }
 
@@ -224,7 +224,7 @@ fn main() {
     window.set_scroll_polling(true);
     window.set_cursor_mode(glfw::CursorMode::Disabled);
 
-    gl::load_with(|symbol| window.get_proc_address(symbol) as *const _);
+    let context = GlContext::load(&mut window);
 
     let shader_program = unsafe {
         Shader::from_str(VERTEX_SHADER_SOURCE, FRAGMENT_SHADER_SOURCE)
@@ -234,9 +234,7 @@ fn main() {
         Shader::from_str(QUAD_VERTEX_SHADER, QUAD_FRAGMENT_SHADER)
     };
 
-    let model_obj = unsafe { 
-        Model::load_obj("./examples/nanosuit/nanosuit.obj").unwrap()
-    };
+    let model_obj = Model::load_obj(&context, "./examples/nanosuit/nanosuit.obj").unwrap();
 
     let quad_vao = unsafe {
         let quad_vertices: [f32; 4 * 6] = [
@@ -285,46 +283,12 @@ fn main() {
         quad_vao
     };
 
-    let (frame_buffer, tex_color_buffer) = unsafe {
-        let mut frame_buffer = 0;
-        gl::GenFramebuffers(1, &mut frame_buffer);
-        gl::BindFramebuffer(gl::FRAMEBUFFER, frame_buffer);
-
-        let mut tex_color_buffer = 0;
-        gl::GenTextures(1, &mut tex_color_buffer);
-        gl::BindTexture(gl::TEXTURE_2D, tex_color_buffer);
-        gl::TexImage2D(
-            gl::TEXTURE_2D,
-            0,
-            conv!(gl::RGB),
-            800,
-            600,
-            0,
-            gl::RGB,
-            gl::UNSIGNED_BYTE,
-            ptr::null(),
-        );
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, conv!(gl::LINEAR));
-        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, conv!(gl::LINEAR));
-        gl::BindTexture(gl::TEXTURE_2D, 0);
-
-        gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_2D, tex_color_buffer, 0);
-
-        let mut rbo = 0;
-        gl::GenRenderbuffers(1, &mut rbo);
-        gl::BindRenderbuffer(gl::RENDERBUFFER, rbo);
-        gl::RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH24_STENCIL8, 800, 600);
-        gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
-        
-        gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, gl::DEPTH_STENCIL_ATTACHMENT, gl::RENDERBUFFER, rbo);
-
-        if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
-            log::warn!("frame buffer is not complete");
-        }
-        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
-
-        (frame_buffer, tex_color_buffer)
-    };
+    let (mut window_width, mut window_height) = window.get_framebuffer_size();
+    let mut frame_buffer = Framebuffer::builder(window_width, window_height)
+        .color(AttachmentFormat::RGBA8)
+        .depth_with(AttachmentFormat::DEPTH24_STENCIL8, AttachmentStorage::Renderbuffer)
+        .resize_policy(ResizePolicy::MatchWindow(1.0))
+        .build(&context);
 
     let point_light_positions = [
         vec3(0.7, 0.2, 2.0),
@@ -358,9 +322,11 @@ fn main() {
         last_time = current_time;
 
         for (_, event) in glfw::flush_messages(&events) {
+            frame_buffer.process_event(&context, &event);
             match event {
-                glfw::WindowEvent::FramebufferSize(width, height) => unsafe {
-                    gl::Viewport(0, 0, width, height);
+                glfw::WindowEvent::FramebufferSize(width, height) => {
+                    window_width = width;
+                    window_height = height;
                 }
                 glfw::WindowEvent::Key(Key::Escape, _, Action::Press, _) => {
                     window.set_should_close(true)
@@ -424,11 +390,11 @@ fn main() {
 
         let model = Matrix4::<f32>::identity();
         let view = Matrix4::<f32>::look_at_dir(camera_pos, camera_dir, camera_up);
-        let projection = perspective(Deg(fov), 800.0 as f32 / 600.0 as f32, 0.1, 100.0);
+        let projection = perspective(Deg(fov), frame_buffer.width() as f32 / frame_buffer.height() as f32, 0.1, 100.0);
 
         unsafe {
             // first pass
-            gl::BindFramebuffer(gl::FRAMEBUFFER, frame_buffer);
+            frame_buffer.bind(&context);
 
             gl::ClearColor(0.8, 0.8, 0.8, 1.0);
             gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
@@ -487,17 +453,17 @@ fn main() {
             shader_program.set_matrix4(c_str!("view"), &view);
             shader_program.set_matrix4(c_str!("projection"), &projection);
 
-            model_obj.draw(shader_program);
+            model_obj.draw(&context, &shader_program);
 
             // second pass
-            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            Framebuffer::bind_default(&context, window_width, window_height);
             gl::ClearColor(0.0, 0.0, 0.0, 1.0);
             gl::Clear(gl::COLOR_BUFFER_BIT);
 
             screen_shader.use_program();
             gl::BindVertexArray(quad_vao);
             gl::Disable(gl::DEPTH_TEST);
-            gl::BindTexture(gl::TEXTURE_2D, tex_color_buffer);
+            gl::BindTexture(gl::TEXTURE_2D, frame_buffer.color_texture());
             gl::DrawArrays(gl::TRIANGLES, 0, 6);
         }
 
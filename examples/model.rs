@@ -167,15 +167,13 @@ fn main() {
     window.set_scroll_polling(true);
     window.set_cursor_mode(glfw::CursorMode::Disabled);
 
-    gl::load_with(|symbol| window.get_proc_address(symbol) as *const _);
+    let context = GlContext::load(&mut window);
 
     let shader_program = unsafe {
         game_engine::Shader::from_str(VERTEX_SHADER_SOURCE, FRAGMENT_SHADER_SOURCE)
     };
 
-    let model_obj = unsafe { 
-        Model::load_obj("./examples/nanosuit/nanosuit.obj").unwrap()
-    };
+    let model_obj = Model::load_obj(&context, "./examples/nanosuit/nanosuit.obj").unwrap();
 
     let point_light_positions = [
         vec3(0.7, 0.2, 2.0),
@@ -334,7 +332,7 @@ fn main() {
             shader_program.set_matrix4(c_str!("view"), &view);
             shader_program.set_matrix4(c_str!("projection"), &projection);
 
-            model_obj.draw(shader_program);
+            model_obj.draw(&context, &shader_program);
         }
 
         window.swap_buffers();
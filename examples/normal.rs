@@ -13,10 +13,6 @@ layout (location = 0) in vec3 aPos;
 layout (location = 1) in vec3 aNormal;
 layout (location = 2) in vec2 aTexCoord;
 
-out VS_OUT {
-    vec3 normal;
-} vs_out;
-
 out vec2 texCoords;
 
 uniform mat4 model;
@@ -25,8 +21,6 @@ uniform mat4 projection;
 
 void main() {
     gl_Position = projection * view * model * vec4(aPos, 1.0);
-    mat3 normalMatrix = mat3(transpose(inverse(view * model)));
-    vs_out.normal = normalize(vec3(projection * vec4(normalMatrix * aNormal, 0.0)));
     texCoords = aTexCoord;
 }
 "#;
@@ -50,43 +44,6 @@ void main()
 }
 "#;
 
-const GEOMETRY_SHADER: &str = r#"
-#version 330 core
-layout (triangles) in;
-layout (line_strip, max_vertices = 6) out;
-
-in VS_OUT {
-    vec3 normal;
-} gs_in[];
-
-void GenerateLine(int index) {
-    float magnitude = 0.4;
-
-    gl_Position = gl_in[index].gl_Position;
-    EmitVertex();
-    gl_Position = gl_in[index].gl_Position + vec4(gs_in[index].normal, 0.0) * magnitude;
-    EmitVertex();
-    
-    EndPrimitive();
-}
-
-void main() {    
-    GenerateLine(0);
-    GenerateLine(1);
-    GenerateLine(2);
-}
-"#;
-
-const NORMAL_FRAGMENT_SHADER: &str = r#"
-#version 330 core
-out vec4 FragColor;
-
-void main()
-{
-    FragColor = vec4(1.0, 1.0, 0.0, 1.0);
-}
-"#;
-
 fn main() {
     env_logger::init();
 
@@ -107,19 +64,15 @@ fn main() {
     window.set_scroll_polling(true);
     window.set_cursor_mode(glfw::CursorMode::Disabled);
 
-    gl::load_with(|symbol| window.get_proc_address(symbol) as *const _);
+    let context = GlContext::load(&mut window);
 
     let model_shader = unsafe {
         Shader::from_str(VERTEX_SHADER, FRAGMENT_SHADER)
     };
 
-    let normal_shader = unsafe {
-        Shader::with_geometry_shader(VERTEX_SHADER, GEOMETRY_SHADER, NORMAL_FRAGMENT_SHADER)
-    };
+    let normal_visualizer = NormalVisualizer::new(&context);
 
-    let model_obj = unsafe { 
-        Model::load_obj("./examples/nanosuit/nanosuit.obj").unwrap()
-    };
+    let model_obj = Model::load_obj(&context, "./examples/nanosuit/nanosuit.obj").unwrap();
 
     unsafe {
         gl::Enable(gl::DEPTH_TEST);
@@ -166,16 +119,11 @@ fn main() {
             gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
 
             model_shader.use_program();
-            model_shader.set_matrix4(c_str!("model"), &Matrix4::identity());
             model_shader.set_matrix4(c_str!("view"), &camera.view());
             model_shader.set_matrix4(c_str!("projection"), &camera.projection());
-            model_obj.draw(model_shader);
+            model_obj.draw_at(&context, &model_shader, &Matrix4::identity());
 
-            normal_shader.use_program();
-            normal_shader.set_matrix4(c_str!("model"), &Matrix4::identity());
-            normal_shader.set_matrix4(c_str!("view"), &camera.view());
-            normal_shader.set_matrix4(c_str!("projection"), &camera.projection());
-            model_obj.draw(normal_shader);
+            normal_visualizer.draw(&context, &model_obj, &Matrix4::identity(), &camera, 0.1, vec3(1.0, 1.0, 0.0));
         }
 
         window.swap_buffers();
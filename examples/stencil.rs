@@ -178,7 +178,7 @@ fn main() {
     window.set_scroll_polling(true);
     window.set_cursor_mode(glfw::CursorMode::Disabled);
 
-    gl::load_with(|symbol| window.get_proc_address(symbol) as *const _);
+    let context = GlContext::load(&mut window);
 
     let shader_program = unsafe {
         Shader::from_str(VERTEX_SHADER_SOURCE, FRAGMENT_SHADER_SOURCE)
@@ -188,9 +188,7 @@ fn main() {
         Shader::from_str(VERTEX_SHADER_SOURCE, SINGLE_COLOR_FRAGMENT_SHADER)
     };
 
-    let model_obj = unsafe { 
-        Model::load_obj("./examples/nanosuit/nanosuit.obj").unwrap()
-    };
+    let model_obj = Model::load_obj(&context, "./examples/nanosuit/nanosuit.obj").unwrap();
 
     let point_light_positions = [
         vec3(0.7, 0.2, 2.0),
@@ -353,7 +351,7 @@ fn main() {
 
             gl::StencilFunc(gl::ALWAYS, 1, 0xFF);
             gl::StencilMask(0xFF);
-            model_obj.draw(shader_program);
+            model_obj.draw(&context, &shader_program);
 
             gl::StencilFunc(gl::NOTEQUAL, 1, 0xFF);
             gl::StencilMask(0x00);
@@ -365,7 +363,7 @@ fn main() {
             border_shader.set_matrix4(c_str!("view"), &view);
             border_shader.set_matrix4(c_str!("projection"), &projection);
 
-            model_obj.draw(border_shader);
+            model_obj.draw(&context, &border_shader);
             gl::StencilMask(0xFF);
             gl::Enable(gl::DEPTH_TEST);
         }
@@ -96,12 +96,10 @@ fn main() {
         Shader::from_str(VERTEX_SHADER_SOURCE, YELLOW_FRAGMENT_SHADER)
     };
     
-    unsafe {
-        red_shader.bind_uniform_block(c_str!("Matrices"), 0);
-        green_shader.bind_uniform_block(c_str!("Matrices"), 0);
-        blue_shader.bind_uniform_block(c_str!("Matrices"), 0);
-        yellow_shader.bind_uniform_block(c_str!("Matrices"), 0);
-    }
+    red_shader.bind_uniform_block(c_str!("Matrices"), 0);
+    green_shader.bind_uniform_block(c_str!("Matrices"), 0);
+    blue_shader.bind_uniform_block(c_str!("Matrices"), 0);
+    yellow_shader.bind_uniform_block(c_str!("Matrices"), 0);
 
     let cube_vao = unsafe {
         let cube_vertices: [f32; 3 * 6 * 6] = [
@@ -0,0 +1,174 @@
+use std::error::Error;
+use std::path::Path;
+
+use cgmath::{EuclideanSpace, InnerSpace, Matrix, Matrix3, Matrix4, Point3, SquareMatrix, Transform};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::bvh::cosine_hemisphere;
+use crate::lightmap::{dilate, mesh_transforms, rasterize, SurfacePoint};
+use crate::{Bvh, GlContext, Model, Ray, Texture, TextureBuilder, TextureType};
+
+// baked ambient occlusion of one mesh over `Vertex::lightmap_coords`, 1 where nothing occludes
+#[derive(Debug, Clone, PartialEq)]
+pub struct OcclusionMap {
+    width: u32,
+    height: u32,
+    values: Vec<f32>,
+}
+
+impl OcclusionMap {
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    // rows from v = 0
+    pub fn values(&self) -> &[f32] {
+        &self.values
+    }
+
+    // as an 8 bit grayscale image
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error + 'static>> {
+        let bytes: Vec<u8> = self.values.iter().map(|v| (v.clamp(0.0, 1.0) * 255.0 + 0.5) as u8).collect();
+        image::save_buffer(path, &bytes, self.width, self.height, image::ColorType::Gray(8))?;
+        Ok(())
+    }
+
+    // a map written by `save`, ready to be added to `Mesh::textures`
+    pub fn load<P: AsRef<Path>>(context: &GlContext, path: P) -> Result<Texture, Box<dyn Error + 'static>> {
+        let id = TextureBuilder::new().wrap(gl::CLAMP_TO_EDGE).mipmaps(false).load(context, path)?;
+        Ok(Texture::adopt(id, TextureType::Occlusion))
+    }
+
+    pub fn upload(&self, context: &GlContext) -> Texture {
+        let id = TextureBuilder::new()
+            .internal_format(gl::R8)
+            .wrap(gl::CLAMP_TO_EDGE)
+            .mipmaps(false)
+            .upload_f32(context, self.width, self.height, 1, &self.values);
+        Texture::adopt(id, TextureType::Occlusion)
+    }
+}
+
+// offline ambient occlusion from ray casts against the model's own geometry,
+// for static meshes where screen space AO misses off screen occluders
+#[derive(Debug, Clone)]
+pub struct AoBaker {
+    // hemisphere rays per texel or vertex
+    pub samples: u32,
+    // occluders further away than this do not count, in world units
+    pub max_distance: f32,
+    // moves ray origins off the surface against self occlusion
+    pub bias: f32,
+    // texels grown around the charts
+    pub padding: u32,
+    pub seed: u64,
+}
+
+impl Default for AoBaker {
+    fn default() -> Self {
+        Self {
+            samples: 64,
+            max_distance: 1.0,
+            bias: 1e-3,
+            padding: 2,
+            seed: 0,
+        }
+    }
+}
+
+impl AoBaker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // one map per mesh of the model, in the order of `Model::meshes`
+    pub fn bake_textures(&self, model: &Model, model_matrix: &Matrix4<f32>, resolution: u32) -> Vec<OcclusionMap> {
+        let bvh = Bvh::from_model(model, model_matrix);
+        let transforms = mesh_transforms(model, model_matrix);
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let size = resolution.max(1);
+
+        model
+            .meshes
+            .iter()
+            .zip(transforms.iter())
+            .map(|(mesh, transform)| {
+                let surface = rasterize(mesh, transform, size, size);
+                let mut covered: Vec<bool> = surface.iter().map(Option::is_some).collect();
+                let mut values: Vec<f32> = surface
+                    .iter()
+                    .map(|point| point.map_or(1.0, |point| self.visibility(&bvh, &point, &mut rng)))
+                    .collect();
+                dilate(&mut values, &mut covered, size, size, self.padding);
+                OcclusionMap {
+                    width: size,
+                    height: size,
+                    values,
+                }
+            })
+            .collect()
+    }
+
+    // the occlusion at every vertex, per mesh, in the order of `Mesh::verticies`
+    pub fn bake_vertices(&self, model: &Model, model_matrix: &Matrix4<f32>) -> Vec<Vec<f32>> {
+        let bvh = Bvh::from_model(model, model_matrix);
+        let transforms = mesh_transforms(model, model_matrix);
+        let mut rng = StdRng::seed_from_u64(self.seed);
+
+        model
+            .meshes
+            .iter()
+            .zip(transforms.iter())
+            .map(|(mesh, transform)| {
+                let normal_matrix = Matrix3::from_cols(transform.x.truncate(), transform.y.truncate(), transform.z.truncate())
+                    .invert()
+                    .unwrap_or_else(Matrix3::identity)
+                    .transpose();
+                mesh.verticies
+                    .iter()
+                    .map(|vertex| {
+                        let point = SurfacePoint {
+                            position: transform.transform_point(Point3::from_vec(vertex.position)),
+                            normal: (normal_matrix * vertex.normal).normalize(),
+                        };
+                        self.visibility(&bvh, &point, &mut rng)
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    // multiplies the occlusion into the vertex colors and uploads them, for meshes without a lightmap unwrap
+    pub fn bake_into_vertex_colors(&self, context: &GlContext, model: &mut Model, model_matrix: &Matrix4<f32>) {
+        let occlusion = self.bake_vertices(model, model_matrix);
+        for (mesh, occlusion) in model.meshes.iter_mut().zip(occlusion) {
+            for (vertex, occlusion) in mesh.verticies.iter_mut().zip(occlusion) {
+                vertex.color.x *= occlusion;
+                vertex.color.y *= occlusion;
+                vertex.color.z *= occlusion;
+            }
+            mesh.update_vertex_buffer(context);
+        }
+    }
+
+    // the share of cosine weighted rays that escape within `max_distance`
+    fn visibility(&self, bvh: &Bvh, point: &SurfacePoint, rng: &mut StdRng) -> f32 {
+        if self.samples == 0 {
+            return 1.0;
+        }
+        let origin = point.position + point.normal * self.bias;
+        let mut open = 0;
+        for _ in 0..self.samples {
+            let direction = cosine_hemisphere(point.normal, rng.gen(), rng.gen());
+            if !bvh.occluded(&Ray::new(origin, direction), self.max_distance) {
+                open += 1;
+            }
+        }
+        open as f32 / self.samples as f32
+    }
+}
@@ -0,0 +1,226 @@
+use std::error::Error;
+use std::fmt;
+use std::time::Instant;
+
+use crate::{AttachmentFormat, Framebuffer, FrameStats, GlContext, Query, QueryKind, TestContext};
+
+// GPU timer queries in flight, enough that reading the oldest rarely waits
+const TIMERS: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BenchmarkConfig {
+    // of the offscreen target the scene renders into
+    pub width: i32,
+    pub height: i32,
+    // measured frames
+    pub frames: usize,
+    // rendered first and left out of the report, for shader compilation and driver caches
+    pub warmup_frames: usize,
+}
+
+impl Default for BenchmarkConfig {
+    fn default() -> Self {
+        Self {
+            width: 1280,
+            height: 720,
+            frames: 300,
+            warmup_frames: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FrameTiming {
+    // milliseconds spent submitting the frame
+    pub cpu_ms: f64,
+    // milliseconds the GPU spent on it, None without timer queries
+    pub gpu_ms: Option<f64>,
+    pub stats: FrameStats,
+}
+
+// the timings of the measured frames, in order
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BenchmarkReport {
+    pub config: BenchmarkConfig,
+    pub frames: Vec<FrameTiming>,
+    // wall clock milliseconds of the measured frames, waiting for the GPU included
+    pub total_ms: f64,
+}
+
+// mean, minimum, maximum and percentiles of one timing
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TimingSummary {
+    pub mean: f64,
+    pub min: f64,
+    pub max: f64,
+    pub p50: f64,
+    pub p95: f64,
+    pub p99: f64,
+}
+
+impl TimingSummary {
+    fn of(mut samples: Vec<f64>) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        let percentile = |p: f64| samples[((samples.len() - 1) as f64 * p).round() as usize];
+        Some(Self {
+            mean: samples.iter().sum::<f64>() / samples.len() as f64,
+            min: samples[0],
+            max: samples[samples.len() - 1],
+            p50: percentile(0.5),
+            p95: percentile(0.95),
+            p99: percentile(0.99),
+        })
+    }
+}
+
+impl fmt::Display for TimingSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "mean {:.3} ms, min {:.3}, p50 {:.3}, p95 {:.3}, p99 {:.3}, max {:.3}",
+            self.mean, self.min, self.p50, self.p95, self.p99, self.max,
+        )
+    }
+}
+
+impl BenchmarkReport {
+    pub fn cpu(&self) -> Option<TimingSummary> {
+        TimingSummary::of(self.frames.iter().map(|frame| frame.cpu_ms).collect())
+    }
+
+    // None unless every frame has a GPU time
+    pub fn gpu(&self) -> Option<TimingSummary> {
+        self.frames.iter().map(|frame| frame.gpu_ms).collect::<Option<Vec<_>>>().and_then(TimingSummary::of)
+    }
+
+    pub fn frames_per_second(&self) -> f64 {
+        if self.total_ms > 0.0 {
+            self.frames.len() as f64 * 1000.0 / self.total_ms
+        } else {
+            0.0
+        }
+    }
+
+    // the counters of the frames, summed
+    pub fn total_stats(&self) -> FrameStats {
+        self.frames.iter().fold(FrameStats::default(), |mut total, frame| {
+            total.draw_calls += frame.stats.draw_calls;
+            total.instances += frame.stats.instances;
+            total.triangles += frame.stats.triangles;
+            total.texture_binds += frame.stats.texture_binds;
+            total.buffer_uploads += frame.stats.buffer_uploads;
+            total.uploaded_bytes += frame.stats.uploaded_bytes;
+            total
+        })
+    }
+}
+
+impl fmt::Display for BenchmarkReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} frames at {}x{}, {:.1} fps",
+            self.frames.len(),
+            self.config.width,
+            self.config.height,
+            self.frames_per_second()
+        )?;
+        if let Some(cpu) = self.cpu() {
+            writeln!(f, "cpu: {}", cpu)?;
+        }
+        if let Some(gpu) = self.gpu() {
+            writeln!(f, "gpu: {}", gpu)?;
+        }
+        if let Some(last) = self.frames.last() {
+            write!(f, "last frame: {}", last.stats)?;
+        }
+        Ok(())
+    }
+}
+
+// renders a scene for a fixed number of frames into an offscreen target and times every frame
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Benchmark {
+    pub config: BenchmarkConfig,
+}
+
+impl Benchmark {
+    pub fn new(config: BenchmarkConfig) -> Self {
+        Self { config }
+    }
+
+    // calls `draw` with the frame number once per frame, the target bound and cleared
+    pub fn run<F: FnMut(&GlContext, &Framebuffer, usize)>(&self, context: &GlContext, mut draw: F) -> BenchmarkReport {
+        let config = self.config;
+        let target = Framebuffer::builder(config.width, config.height)
+            .color(AttachmentFormat::RGBA8)
+            .depth(AttachmentFormat::DEPTH24_STENCIL8)
+            .build(context);
+        let mut timers: Vec<Query> = (0..TIMERS).map(|_| Query::new(context, QueryKind::TimeElapsed)).collect();
+        let mut frames: Vec<FrameTiming> = Vec::with_capacity(config.frames);
+
+        let mut render = |frame: usize, timer: Option<&mut Query>| -> f64 {
+            let start = Instant::now();
+            target.bind(context);
+            unsafe {
+                gl::ClearColor(0.0, 0.0, 0.0, 1.0);
+                gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT);
+            }
+            match timer {
+                Some(timer) => timer.measure(context, || draw(context, &target, frame)),
+                None => draw(context, &target, frame),
+            }
+            start.elapsed().as_secs_f64() * 1000.0
+        };
+
+        for frame in 0..config.warmup_frames {
+            render(frame, None);
+        }
+        unsafe {
+            gl::Finish();
+        }
+        FrameStats::end_frame();
+
+        let start = Instant::now();
+        for frame in 0..config.frames {
+            let slot = frame % TIMERS;
+            // the frame that used this timer last gets its GPU time now
+            if frame >= TIMERS {
+                frames[frame - TIMERS].gpu_ms = timers[slot].wait(context).map(|ns| ns as f64 / 1e6);
+            }
+            let cpu_ms = render(config.warmup_frames + frame, Some(&mut timers[slot]));
+            frames.push(FrameTiming {
+                cpu_ms,
+                gpu_ms: None,
+                stats: FrameStats::end_frame(),
+            });
+        }
+        unsafe {
+            gl::Finish();
+        }
+        let total_ms = start.elapsed().as_secs_f64() * 1000.0;
+        for frame in config.frames.saturating_sub(TIMERS)..config.frames {
+            frames[frame].gpu_ms = timers[frame % TIMERS].wait(context).map(|ns| ns as f64 / 1e6);
+        }
+        Framebuffer::bind_default(context, config.width, config.height);
+
+        BenchmarkReport { config, frames, total_ms }
+    }
+
+    // creates a `TestContext`, builds the scene with `setup` and runs it
+    pub fn run_headless<S, Setup, Draw>(&self, setup: Setup, mut draw: Draw) -> Result<BenchmarkReport, Box<dyn Error + 'static>>
+    where
+        Setup: FnOnce(&GlContext) -> S,
+        Draw: FnMut(&mut S, &GlContext, &Framebuffer, usize),
+    {
+        let test = TestContext::with_size(conv!(self.config.width), conv!(self.config.height))?;
+        let context = test.context();
+        let mut scene = setup(context);
+        let report = self.run(context, |context, target, frame| draw(&mut scene, context, target, frame));
+        drop(scene);
+        Ok(report)
+    }
+}
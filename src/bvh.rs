@@ -0,0 +1,359 @@
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Transform, Vector3, vec3};
+
+use crate::Model;
+
+// triangles per leaf
+const LEAF_SIZE: usize = 4;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+    pub origin: Point3<f32>,
+    // need not be normalized, hit distances are in multiples of it
+    pub direction: Vector3<f32>,
+}
+
+impl Ray {
+    pub fn new(origin: Point3<f32>, direction: Vector3<f32>) -> Self {
+        Self { origin, direction }
+    }
+
+    pub fn at(&self, t: f32) -> Point3<f32> {
+        self.origin + self.direction * t
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayHit {
+    pub t: f32,
+    // index into `Bvh::triangles`
+    pub triangle: usize,
+    // barycentric weights of the second and third vertex
+    pub u: f32,
+    pub v: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Bounds {
+    min: Vector3<f32>,
+    max: Vector3<f32>,
+}
+
+impl Bounds {
+    fn empty() -> Self {
+        Self {
+            min: vec3(f32::INFINITY, f32::INFINITY, f32::INFINITY),
+            max: vec3(f32::NEG_INFINITY, f32::NEG_INFINITY, f32::NEG_INFINITY),
+        }
+    }
+
+    fn grow(&mut self, p: Point3<f32>) {
+        for i in 0..3 {
+            self.min[i] = self.min[i].min(p[i]);
+            self.max[i] = self.max[i].max(p[i]);
+        }
+    }
+
+    // slab test, the entry distance when the ray hits the box before `max_t`
+    fn hit(&self, ray: &Ray, inverse: Vector3<f32>, max_t: f32) -> Option<f32> {
+        let mut near = 0.0f32;
+        let mut far = max_t;
+        for i in 0..3 {
+            let t0 = (self.min[i] - ray.origin[i]) * inverse[i];
+            let t1 = (self.max[i] - ray.origin[i]) * inverse[i];
+            near = near.max(t0.min(t1));
+            far = far.min(t0.max(t1));
+        }
+        if near <= far {
+            Some(near)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct BvhNode {
+    bounds: Bounds,
+    // leaves: the first of `count` triangles in `order`. inner nodes: the left child, the right one follows it.
+    first: usize,
+    count: usize,
+}
+
+// a bounding volume hierarchy over triangles for ray casts on the CPU, e.g. for baking
+#[derive(Debug, Clone)]
+pub struct Bvh {
+    triangles: Vec<[Point3<f32>; 3]>,
+    nodes: Vec<BvhNode>,
+    order: Vec<usize>,
+}
+
+impl Bvh {
+    pub fn new(triangles: Vec<[Point3<f32>; 3]>) -> Self {
+        let mut bvh = Self {
+            order: (0..triangles.len()).collect(),
+            triangles,
+            nodes: vec![],
+        };
+        if !bvh.triangles.is_empty() {
+            bvh.nodes.push(BvhNode {
+                bounds: Bounds::empty(),
+                first: 0,
+                count: bvh.triangles.len(),
+            });
+            bvh.split(0);
+        }
+        bvh
+    }
+
+    // the triangles of every mesh of the model, placed by the node transforms and `model_matrix`.
+    // hidden nodes are included, the geometry is taken as static.
+    pub fn from_model(model: &Model, model_matrix: &Matrix4<f32>) -> Self {
+        let mut triangles = vec![];
+        for (index, node) in model.nodes.iter().enumerate() {
+            let transform = model_matrix * model.node_transform(index);
+            for &mesh in node.meshes.iter() {
+                let mesh = &model.meshes[mesh];
+                for face in mesh.indices.chunks_exact(3) {
+                    let corner = |i: usize| transform.transform_point(Point3::from_vec(mesh.verticies[face[i] as usize].position));
+                    triangles.push([corner(0), corner(1), corner(2)]);
+                }
+            }
+        }
+        Self::new(triangles)
+    }
+
+    pub fn triangles(&self) -> &[[Point3<f32>; 3]] {
+        &self.triangles
+    }
+
+    // not normalized, facing the side the vertices wind counter clockwise on
+    pub fn triangle_normal(&self, triangle: usize) -> Vector3<f32> {
+        let [a, b, c] = self.triangles[triangle];
+        (b - a).cross(c - a)
+    }
+
+    fn split(&mut self, index: usize) {
+        let BvhNode { first, count, .. } = self.nodes[index];
+        let mut bounds = Bounds::empty();
+        let mut centers = Bounds::empty();
+        for &triangle in self.order[first..first + count].iter() {
+            let [a, b, c] = self.triangles[triangle];
+            bounds.grow(a);
+            bounds.grow(b);
+            bounds.grow(c);
+            centers.grow(Point3::centroid(&[a, b, c]));
+        }
+        self.nodes[index].bounds = bounds;
+        if count <= LEAF_SIZE {
+            return;
+        }
+
+        // median split along the widest axis of the triangle centers
+        let extent = centers.max - centers.min;
+        let axis = if extent.x >= extent.y && extent.x >= extent.z {
+            0
+        } else if extent.y >= extent.z {
+            1
+        } else {
+            2
+        };
+        let triangles = &self.triangles;
+        let center = |triangle: usize| triangles[triangle].iter().map(|p| p[axis]).sum::<f32>();
+        self.order[first..first + count].sort_by(|&a, &b| center(a).partial_cmp(&center(b)).unwrap_or(std::cmp::Ordering::Equal));
+
+        let half = count / 2;
+        let left = self.nodes.len();
+        self.nodes.push(BvhNode {
+            bounds: Bounds::empty(),
+            first,
+            count: half,
+        });
+        self.nodes.push(BvhNode {
+            bounds: Bounds::empty(),
+            first: first + half,
+            count: count - half,
+        });
+        self.nodes[index].first = left;
+        self.nodes[index].count = 0;
+        self.split(left);
+        self.split(left + 1);
+    }
+
+    // the closest hit in (0, max_t), both sides of the triangles count
+    pub fn intersect(&self, ray: &Ray, max_t: f32) -> Option<RayHit> {
+        let mut closest: Option<RayHit> = None;
+        // the search range shrinks to each hit, so every later hit is closer
+        self.traverse(ray, max_t, &mut |hit| {
+            closest = Some(hit);
+            false
+        });
+        closest
+    }
+
+    // whether anything is hit in (0, max_t), cheaper than `intersect`
+    pub fn occluded(&self, ray: &Ray, max_t: f32) -> bool {
+        let mut occluded = false;
+        self.traverse(ray, max_t, &mut |_| {
+            occluded = true;
+            true
+        });
+        occluded
+    }
+
+    // calls `on_hit` on hits until it returns true
+    fn traverse<F: FnMut(RayHit) -> bool>(&self, ray: &Ray, max_t: f32, on_hit: &mut F) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let inverse = vec3(1.0 / ray.direction.x, 1.0 / ray.direction.y, 1.0 / ray.direction.z);
+        let mut max_t = max_t;
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let node = &self.nodes[index];
+            if node.bounds.hit(ray, inverse, max_t).is_none() {
+                continue;
+            }
+            if node.count == 0 {
+                stack.push(node.first);
+                stack.push(node.first + 1);
+                continue;
+            }
+            for &triangle in self.order[node.first..node.first + node.count].iter() {
+                if let Some(hit) = intersect_triangle(ray, &self.triangles[triangle], max_t) {
+                    let hit = RayHit { triangle, ..hit };
+                    if on_hit(hit) {
+                        return;
+                    }
+                    max_t = hit.t;
+                }
+            }
+        }
+    }
+}
+
+// Möller-Trumbore
+fn intersect_triangle(ray: &Ray, triangle: &[Point3<f32>; 3], max_t: f32) -> Option<RayHit> {
+    const EPSILON: f32 = 1e-7;
+    let [a, b, c] = *triangle;
+    let edge1 = b - a;
+    let edge2 = c - a;
+    let p = ray.direction.cross(edge2);
+    let det = edge1.dot(p);
+    if det.abs() < EPSILON {
+        return None;
+    }
+    let inverse = 1.0 / det;
+    let s = ray.origin - a;
+    let u = s.dot(p) * inverse;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = s.cross(edge1);
+    let v = ray.direction.dot(q) * inverse;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = edge2.dot(q) * inverse;
+    if t > EPSILON && t < max_t {
+        Some(RayHit { t, triangle: 0, u, v })
+    } else {
+        None
+    }
+}
+
+// a cosine weighted direction around `normal`, from two uniform numbers in [0, 1)
+pub(crate) fn cosine_hemisphere(normal: Vector3<f32>, r1: f32, r2: f32) -> Vector3<f32> {
+    let helper = if normal.x.abs() > 0.9 { vec3(0.0, 1.0, 0.0) } else { vec3(1.0, 0.0, 0.0) };
+    let tangent = normal.cross(helper).normalize();
+    let bitangent = normal.cross(tangent);
+    let phi = 2.0 * std::f32::consts::PI * r1;
+    let r = r2.sqrt();
+    tangent * (r * phi.cos()) + bitangent * (r * phi.sin()) + normal * (1.0 - r2).max(0.0).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a unit square at z = `z` facing +z, as two triangles
+    fn square(z: f32) -> Vec<[Point3<f32>; 3]> {
+        let p = |x: f32, y: f32| Point3::new(x, y, z);
+        vec![[p(0.0, 0.0), p(1.0, 0.0), p(1.0, 1.0)], [p(0.0, 0.0), p(1.0, 1.0), p(0.0, 1.0)]]
+    }
+
+    // many small triangles so the tree has more than one level
+    fn squares(count: usize) -> Bvh {
+        Bvh::new((0..count).flat_map(|i| square(i as f32)).collect())
+    }
+
+    #[test]
+    fn closest_hit() {
+        let bvh = squares(16);
+        let ray = Ray::new(Point3::new(0.25, 0.75, 20.0), vec3(0.0, 0.0, -1.0));
+        let hit = bvh.intersect(&ray, f32::INFINITY).unwrap();
+        assert!((hit.t - 5.0).abs() < 1e-5);
+        assert!((ray.at(hit.t).z - 15.0).abs() < 1e-5);
+        let [a, b, c] = bvh.triangles()[hit.triangle];
+        let point = a + (b - a) * hit.u + (c - a) * hit.v;
+        assert!((point - ray.at(hit.t)).magnitude() < 1e-4);
+        assert!(bvh.triangle_normal(hit.triangle).z > 0.0);
+    }
+
+    #[test]
+    fn max_t_and_misses() {
+        let bvh = squares(16);
+        let ray = Ray::new(Point3::new(0.5, 0.25, 20.0), vec3(0.0, 0.0, -1.0));
+        assert!(bvh.intersect(&ray, 4.0).is_none());
+        assert!(!bvh.occluded(&ray, 4.0));
+        assert!(bvh.occluded(&ray, 6.0));
+        // back faces count too
+        let below = Ray::new(Point3::new(0.5, 0.25, -1.0), vec3(0.0, 0.0, 1.0));
+        assert!((bvh.intersect(&below, f32::INFINITY).unwrap().t - 1.0).abs() < 1e-5);
+
+        let beside = Ray::new(Point3::new(2.0, 0.5, 20.0), vec3(0.0, 0.0, -1.0));
+        assert!(bvh.intersect(&beside, f32::INFINITY).is_none());
+        let away = Ray::new(Point3::new(0.5, 0.5, 20.0), vec3(0.0, 0.0, 1.0));
+        assert!(bvh.intersect(&away, f32::INFINITY).is_none());
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        let bvh = squares(32);
+        for i in 0..64 {
+            let (x, y) = ((i % 8) as f32 / 8.0 + 0.03, (i / 8) as f32 / 8.0 + 0.01);
+            let ray = Ray::new(Point3::new(x, y, 40.0), vec3(0.1, -0.05, -1.0));
+            let expected = bvh
+                .triangles()
+                .iter()
+                .enumerate()
+                .filter_map(|(triangle, &[a, b, c])| {
+                    let normal = (b - a).cross(c - a);
+                    let t = normal.dot(a - ray.origin) / normal.dot(ray.direction);
+                    let p = ray.at(t);
+                    let inside = [(a, b), (b, c), (c, a)].iter().all(|&(from, to)| (to - from).cross(p - from).dot(normal) >= 0.0);
+                    if t > 0.0 && inside { Some((t, triangle)) } else { None }
+                })
+                .fold(None, |closest: Option<(f32, usize)>, hit| match closest {
+                    Some(closest) if closest.0 <= hit.0 => Some(closest),
+                    _ => Some(hit),
+                });
+            let hit = bvh.intersect(&ray, f32::INFINITY);
+            match (expected, hit) {
+                (Some((t, _)), Some(hit)) => assert!((t - hit.t).abs() < 1e-3),
+                (None, None) => {}
+                (expected, hit) => panic!("{:?} {:?}", expected, hit),
+            }
+        }
+    }
+
+    #[test]
+    fn cosine_hemisphere_stays_above_the_surface() {
+        for &normal in &[vec3(0.0, 0.0, 1.0), vec3(1.0, 0.0, 0.0), vec3(0.0, -1.0, 0.0)] {
+            for i in 0..16 {
+                let direction = cosine_hemisphere(normal, i as f32 / 16.0, (i * 7 % 16) as f32 / 16.0);
+                assert!((direction.magnitude() - 1.0).abs() < 1e-4);
+                assert!(direction.dot(normal) >= 0.0);
+            }
+        }
+    }
+}
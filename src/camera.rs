@@ -0,0 +1,241 @@
+use std::fmt::Debug;
+
+use cgmath::{
+    Deg, EuclideanSpace, InnerSpace, Matrix4, perspective, Point3, Quaternion, Rad, Rotation, Rotation3, SquareMatrix,
+    Transform, Vector3, vec3,
+};
+use glfw::{Action, Key, Window, WindowEvent};
+
+use crate::{infinite_perspective, Frustum};
+
+// what culling, shadows and debug drawing need to know about a perspective camera
+pub trait Camera: Debug {
+    fn position(&self) -> Point3<f32>;
+    fn view(&self) -> Matrix4<f32>;
+    // vertical field of view in degrees
+    fn fov(&self) -> f32;
+    fn ratio(&self) -> f32;
+    fn near(&self) -> f32;
+    // may be infinite
+    fn far(&self) -> f32;
+
+    fn projection(&self) -> Matrix4<f32> {
+        if self.far().is_infinite() {
+            infinite_perspective(Deg(self.fov()), self.ratio(), self.near())
+        } else {
+            perspective(Deg(self.fov()), self.ratio(), self.near(), self.far())
+        }
+    }
+
+    // the six world space planes. with an infinite far plane the far plane accepts everything.
+    fn frustum(&self) -> Frustum {
+        Frustum::from_matrix(&(self.projection() * self.view()))
+    }
+
+    // world space corners of the slice between the view distances `split_near` and `split_far`,
+    // indexed by bits: 1 for right, 2 for top, 4 for the far side.
+    // e.g. the cascades of a shadow map are fitted around consecutive slices.
+    fn frustum_corners(&self, split_near: f32, split_far: f32) -> [Point3<f32>; 8] {
+        let inverse_view = self.view().invert().unwrap_or_else(Matrix4::identity);
+        let tan = (self.fov().to_radians() * 0.5).tan();
+        let mut corners = [Point3::origin(); 8];
+        for (i, corner) in corners.iter_mut().enumerate() {
+            let distance = if i & 4 == 0 { split_near } else { split_far };
+            let y = if i & 2 == 0 { -1.0 } else { 1.0 } * distance * tan;
+            let x = if i & 1 == 0 { -1.0 } else { 1.0 } * distance * tan * self.ratio();
+            // the camera looks down -z in view space
+            *corner = inverse_view.transform_point(Point3::from_vec(vec3(x, y, -distance)));
+        }
+        corners
+    }
+}
+
+// cameras that can be placed from the outside, e.g. by a `CameraPathPlayer`.
+// `orientation` rotates the view space axes (-z forward, y up) into the world.
+pub trait CameraPose {
+    fn set_pose(&mut self, position: Point3<f32>, orientation: Quaternion<f32>);
+}
+
+// a camera without a fixed up axis for flight and space games: the orientation is a quaternion,
+// so it can roll and loop over the poles without the pitch clamp of `FPSCamera`.
+#[derive(Debug, Clone)]
+pub struct FreeCamera {
+    position: Point3<f32>,
+    // rotates the view space axes (-z forward, y up) into the world
+    orientation: Quaternion<f32>,
+    fov: f32,
+    ratio: f32,
+    near: f32,
+    far: f32,
+    last_cursor: Option<(f32, f32)>,
+    pub speed: f32,
+    // radians per pixel of cursor movement
+    pub sensitivity: f32,
+    // radians per second while Q or E is held
+    pub roll_speed: f32,
+}
+
+impl FreeCamera {
+    pub fn new(position: Point3<f32>, orientation: Quaternion<f32>, fov: f32, ratio: f32) -> Self {
+        Self {
+            position,
+            orientation: orientation.normalize(),
+            fov,
+            ratio,
+            near: 0.1,
+            far: 100.0,
+            last_cursor: None,
+            speed: 5.0,
+            sensitivity: 0.001,
+            roll_speed: 1.5,
+        }
+    }
+
+    pub fn with_clip_planes(mut self, near: f32, far: f32) -> Self {
+        self.set_clip_planes(near, far);
+        self
+    }
+
+    pub fn set_clip_planes(&mut self, near: f32, far: f32) {
+        assert!(near > 0.0 && far > near, "invalid clip planes: near = {}, far = {}", near, far);
+        self.near = near;
+        self.far = far;
+    }
+
+    pub fn set_ratio(&mut self, ratio: f32) {
+        self.ratio = ratio;
+    }
+
+    pub fn set_fov(&mut self, fov: f32) {
+        self.fov = fov;
+    }
+
+    pub fn set_position(&mut self, position: Point3<f32>) {
+        self.position = position;
+    }
+
+    pub fn orientation(&self) -> Quaternion<f32> {
+        self.orientation
+    }
+
+    pub fn set_orientation(&mut self, orientation: Quaternion<f32>) {
+        self.orientation = orientation.normalize();
+    }
+
+    // points the camera at `target`, keeping `up` as close to the screen's up as possible
+    pub fn look_at(&mut self, target: Point3<f32>, up: Vector3<f32>) {
+        let direction = target - self.position;
+        if direction.magnitude2() > 0.0 {
+            // `look_at` rotates `direction` onto +z, the half turn makes it -z like the view space forward
+            let to_view = Quaternion::look_at(direction, up);
+            self.orientation = (to_view.invert() * Quaternion::from_angle_y(Deg(180.0))).normalize();
+        }
+    }
+
+    pub fn forward(&self) -> Vector3<f32> {
+        self.orientation.rotate_vector(vec3(0.0, 0.0, -1.0))
+    }
+
+    pub fn right(&self) -> Vector3<f32> {
+        self.orientation.rotate_vector(vec3(1.0, 0.0, 0.0))
+    }
+
+    pub fn up(&self) -> Vector3<f32> {
+        self.orientation.rotate_vector(vec3(0.0, 1.0, 0.0))
+    }
+
+    // rotates around the camera's own axes
+    pub fn rotate(&mut self, pitch: Rad<f32>, yaw: Rad<f32>, roll: Rad<f32>) {
+        let local = Quaternion::from_angle_y(yaw) * Quaternion::from_angle_x(pitch) * Quaternion::from_angle_z(roll);
+        // renormalize so the rounding errors don't accumulate over many frames
+        self.orientation = (self.orientation * local).normalize();
+    }
+
+    pub fn process_event(&mut self, event: &WindowEvent) {
+        match *event {
+            WindowEvent::CursorPos(x, y) => {
+                let (x, y) = (x as f32, y as f32);
+                if let Some((last_x, last_y)) = self.last_cursor {
+                    let sensitivity = self.sensitivity;
+                    self.rotate(Rad((last_y - y) * sensitivity), Rad((last_x - x) * sensitivity), Rad(0.0));
+                }
+                self.last_cursor = Some((x, y));
+            }
+            WindowEvent::Scroll(_, y) => {
+                self.fov = (self.fov - y as f32).clamp(1.0, 90.0);
+            }
+            _ => {}
+        }
+    }
+
+    // WASD moves in the view plane, space and left shift along the camera's up, Q and E roll
+    pub fn process_keys(&mut self, window: &Window, delta_time: f32) {
+        let pressed = |key| window.get_key(key) == Action::Press;
+        let step = self.speed * delta_time;
+        let mut movement = vec3(0.0, 0.0, 0.0);
+        if pressed(Key::W) {
+            movement += self.forward();
+        }
+        if pressed(Key::S) {
+            movement -= self.forward();
+        }
+        if pressed(Key::D) {
+            movement += self.right();
+        }
+        if pressed(Key::A) {
+            movement -= self.right();
+        }
+        if pressed(Key::Space) {
+            movement += self.up();
+        }
+        if pressed(Key::LeftShift) {
+            movement -= self.up();
+        }
+        self.position += movement * step;
+
+        let mut roll = 0.0;
+        if pressed(Key::Q) {
+            roll += self.roll_speed * delta_time;
+        }
+        if pressed(Key::E) {
+            roll -= self.roll_speed * delta_time;
+        }
+        if roll != 0.0 {
+            self.rotate(Rad(0.0), Rad(0.0), Rad(roll));
+        }
+    }
+}
+
+impl Camera for FreeCamera {
+    fn position(&self) -> Point3<f32> {
+        self.position
+    }
+
+    fn view(&self) -> Matrix4<f32> {
+        let rotation = Matrix4::from(self.orientation.invert());
+        rotation * Matrix4::from_translation(-self.position.to_vec())
+    }
+
+    fn fov(&self) -> f32 {
+        self.fov
+    }
+
+    fn ratio(&self) -> f32 {
+        self.ratio
+    }
+
+    fn near(&self) -> f32 {
+        self.near
+    }
+
+    fn far(&self) -> f32 {
+        self.far
+    }
+}
+
+impl CameraPose for FreeCamera {
+    fn set_pose(&mut self, position: Point3<f32>, orientation: Quaternion<f32>) {
+        self.set_position(position);
+        self.set_orientation(orientation);
+    }
+}
@@ -0,0 +1,311 @@
+use cgmath::{InnerSpace, Point3, Quaternion};
+
+use crate::{CameraPose, CubicBezier};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PathInterpolation {
+    // passes through every keyframe with tangents from the neighbours
+    CatmullRom,
+    // uses the keyframe handles, keyframes without handles behave like Catmull-Rom
+    Bezier,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Easing {
+    Linear,
+    EaseIn,
+    EaseOut,
+    EaseInOut,
+}
+
+impl Easing {
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Keyframe {
+    // seconds from the start of the path
+    pub time: f32,
+    pub position: Point3<f32>,
+    // same convention as `FreeCamera::orientation`
+    pub orientation: Quaternion<f32>,
+    // Bézier control points before and after the keyframe
+    pub handles: Option<(Point3<f32>, Point3<f32>)>,
+    // timing of the segment towards the next keyframe
+    pub easing: Easing,
+}
+
+impl Keyframe {
+    pub fn new(time: f32, position: Point3<f32>, orientation: Quaternion<f32>) -> Self {
+        Self {
+            time,
+            position,
+            orientation,
+            handles: None,
+            easing: Easing::Linear,
+        }
+    }
+
+    pub fn with_handles(mut self, incoming: Point3<f32>, outgoing: Point3<f32>) -> Self {
+        self.handles = Some((incoming, outgoing));
+        self
+    }
+
+    pub fn with_easing(mut self, easing: Easing) -> Self {
+        self.easing = easing;
+        self
+    }
+}
+
+// a fly-through as keyframes sorted by time. positions follow a spline, orientations are slerped.
+#[derive(Debug, Clone)]
+pub struct CameraPath {
+    keyframes: Vec<Keyframe>,
+    pub interpolation: PathInterpolation,
+}
+
+impl CameraPath {
+    pub fn new(interpolation: PathInterpolation) -> Self {
+        Self {
+            keyframes: vec![],
+            interpolation,
+        }
+    }
+
+    // keeps the keyframes sorted, so they can be added in any order
+    pub fn add(&mut self, keyframe: Keyframe) {
+        let index = self.keyframes.iter().position(|k| k.time > keyframe.time).unwrap_or(self.keyframes.len());
+        self.keyframes.insert(index, keyframe);
+    }
+
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keyframes.last().map_or(0.0, |k| k.time)
+    }
+
+    // the pose at `time`, clamped to the ends. None without keyframes.
+    pub fn sample(&self, time: f32) -> Option<(Point3<f32>, Quaternion<f32>)> {
+        let keys = &self.keyframes;
+        let first = keys.first()?;
+        let last = keys.last()?;
+        if keys.len() == 1 || time <= first.time {
+            return Some((first.position, first.orientation));
+        }
+        if time >= last.time {
+            return Some((last.position, last.orientation));
+        }
+
+        let i = keys.iter().rposition(|k| k.time <= time).unwrap_or(0).min(keys.len() - 2);
+        let (a, b) = (&keys[i], &keys[i + 1]);
+        let span = b.time - a.time;
+        let t = if span > 0.0 { a.easing.apply((time - a.time) / span) } else { 1.0 };
+
+        // the ends repeat the last keyframe
+        let previous = keys[i.saturating_sub(1)].position;
+        let next = keys[(i + 2).min(keys.len() - 1)].position;
+        let mut segment = CubicBezier::catmull_rom(previous, a.position, b.position, next);
+        if self.interpolation == PathInterpolation::Bezier {
+            if let Some((_, outgoing)) = a.handles {
+                segment.points[1] = outgoing;
+            }
+            if let Some((incoming, _)) = b.handles {
+                segment.points[2] = incoming;
+            }
+        }
+        let position = segment.evaluate(t);
+
+        // take the short way around
+        let target = if a.orientation.dot(b.orientation) < 0.0 { -b.orientation } else { b.orientation };
+        let orientation = a.orientation.slerp(target, t).normalize();
+        Some((position, orientation))
+    }
+}
+
+// plays a path and drives a camera with it
+#[derive(Debug, Clone)]
+pub struct CameraPathPlayer {
+    pub path: CameraPath,
+    time: f32,
+    playing: bool,
+    pub looping: bool,
+    pub speed: f32,
+}
+
+impl CameraPathPlayer {
+    pub fn new(path: CameraPath) -> Self {
+        Self {
+            path,
+            time: 0.0,
+            playing: false,
+            looping: false,
+            speed: 1.0,
+        }
+    }
+
+    pub fn play(&mut self) {
+        self.playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    // back to the start, keeps playing if it was
+    pub fn rewind(&mut self) {
+        self.time = 0.0;
+    }
+
+    pub fn seek(&mut self, time: f32) {
+        self.time = time.clamp(0.0, self.path.duration());
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    pub fn is_finished(&self) -> bool {
+        !self.looping && self.time >= self.path.duration()
+    }
+
+    // advances the time and poses `camera`. does nothing while paused, so the camera can be handed back to the player.
+    pub fn update(&mut self, delta_time: f32, camera: &mut dyn CameraPose) {
+        if !self.playing {
+            return;
+        }
+        let duration = self.path.duration();
+        self.time += delta_time * self.speed;
+        if self.time >= duration {
+            if self.looping && duration > 0.0 {
+                self.time %= duration;
+            } else {
+                self.time = duration;
+                self.playing = false;
+            }
+        }
+        if let Some((position, orientation)) = self.path.sample(self.time) {
+            camera.set_pose(position, orientation);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Deg, MetricSpace, Rotation3, Vector3};
+
+    use super::*;
+
+    #[derive(Default)]
+    struct Pose(Option<(Point3<f32>, Quaternion<f32>)>);
+
+    impl CameraPose for Pose {
+        fn set_pose(&mut self, position: Point3<f32>, orientation: Quaternion<f32>) {
+            self.0 = Some((position, orientation));
+        }
+    }
+
+    fn turn(degrees: f32) -> Quaternion<f32> {
+        Quaternion::from_axis_angle(Vector3::unit_y(), Deg(degrees))
+    }
+
+    fn path() -> CameraPath {
+        let mut path = CameraPath::new(PathInterpolation::CatmullRom);
+        // out of order on purpose
+        path.add(Keyframe::new(2.0, Point3::new(2.0, 0.0, 0.0), turn(90.0)));
+        path.add(Keyframe::new(0.0, Point3::new(0.0, 0.0, 0.0), turn(0.0)));
+        path.add(Keyframe::new(1.0, Point3::new(1.0, 1.0, 0.0), turn(45.0)));
+        path
+    }
+
+    #[test]
+    fn easing_ends() {
+        for &easing in &[Easing::Linear, Easing::EaseIn, Easing::EaseOut, Easing::EaseInOut] {
+            assert_eq!(easing.apply(0.0), 0.0);
+            assert_eq!(easing.apply(1.0), 1.0);
+            assert_eq!(easing.apply(2.0), 1.0);
+        }
+        assert!(Easing::EaseIn.apply(0.5) < 0.5);
+        assert!(Easing::EaseOut.apply(0.5) > 0.5);
+        assert_eq!(Easing::EaseInOut.apply(0.5), 0.5);
+    }
+
+    #[test]
+    fn keyframes_are_sorted_and_hit() {
+        let path = path();
+        let times: Vec<f32> = path.keyframes().iter().map(|k| k.time).collect();
+        assert_eq!(times, vec![0.0, 1.0, 2.0]);
+        assert_eq!(path.duration(), 2.0);
+        for keyframe in path.keyframes() {
+            let (position, orientation) = path.sample(keyframe.time).unwrap();
+            assert!(position.distance(keyframe.position) < 1e-4);
+            assert!(orientation.dot(keyframe.orientation).abs() > 0.9999);
+        }
+        // clamped outside
+        assert_eq!(path.sample(-1.0).unwrap().0, Point3::new(0.0, 0.0, 0.0));
+        assert_eq!(path.sample(5.0).unwrap().0, Point3::new(2.0, 0.0, 0.0));
+        assert!(CameraPath::new(PathInterpolation::Bezier).sample(0.0).is_none());
+    }
+
+    #[test]
+    fn orientations_take_the_short_way() {
+        let mut path = CameraPath::new(PathInterpolation::CatmullRom);
+        path.add(Keyframe::new(0.0, Point3::new(0.0, 0.0, 0.0), turn(170.0)));
+        // the same as -170 degrees, 20 degrees away
+        path.add(Keyframe::new(1.0, Point3::new(0.0, 0.0, 0.0), -turn(190.0)));
+        let (_, halfway) = path.sample(0.5).unwrap();
+        assert!(halfway.dot(turn(180.0)).abs() > 0.999);
+    }
+
+    #[test]
+    fn bezier_handles_bend_the_path() {
+        let mut path = CameraPath::new(PathInterpolation::Bezier);
+        path.add(Keyframe::new(0.0, Point3::new(0.0, 0.0, 0.0), turn(0.0)).with_handles(Point3::new(0.0, 0.0, 0.0), Point3::new(0.0, 3.0, 0.0)));
+        path.add(Keyframe::new(1.0, Point3::new(1.0, 0.0, 0.0), turn(0.0)).with_handles(Point3::new(1.0, 3.0, 0.0), Point3::new(1.0, 0.0, 0.0)));
+        let (middle, _) = path.sample(0.5).unwrap();
+        assert!((middle.y - 2.25).abs() < 1e-4);
+
+        path.interpolation = PathInterpolation::CatmullRom;
+        assert!(path.sample(0.5).unwrap().0.y.abs() < 1e-4);
+    }
+
+    #[test]
+    fn player_stops_or_loops_at_the_end() {
+        let mut pose = Pose::default();
+        let mut player = CameraPathPlayer::new(path());
+        player.update(0.5, &mut pose);
+        assert!(pose.0.is_none(), "paused players leave the camera alone");
+
+        player.play();
+        player.update(1.5, &mut pose);
+        assert!(pose.0.unwrap().0.distance(Point3::new(2.0, 0.0, 0.0)) > 1e-3);
+        player.update(1.0, &mut pose);
+        assert_eq!(player.time(), 2.0);
+        assert!(player.is_finished() && !player.is_playing());
+        assert!(pose.0.unwrap().0.distance(Point3::new(2.0, 0.0, 0.0)) < 1e-4);
+
+        player.looping = true;
+        player.speed = 2.0;
+        player.rewind();
+        player.play();
+        player.update(1.25, &mut pose);
+        assert!((player.time() - 0.5).abs() < 1e-5);
+        assert!(player.is_playing() && !player.is_finished());
+
+        player.seek(10.0);
+        assert_eq!(player.time(), 2.0);
+    }
+}
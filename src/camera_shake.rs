@@ -0,0 +1,99 @@
+use cgmath::{Matrix4, Rad, SquareMatrix, vec3};
+
+// smooth 1D value noise in [-1, 1], a different curve per `seed`
+fn noise(seed: u32, x: f32) -> f32 {
+    let hash = |i: i32| {
+        let mut h = (i as u32).wrapping_mul(0x27d4_eb2d) ^ seed.wrapping_mul(0x9e37_79b9);
+        h ^= h >> 15;
+        h = h.wrapping_mul(0x85eb_ca6b);
+        h ^= h >> 13;
+        (h & 0xffff) as f32 / 32767.5 - 1.0
+    };
+    let i = x.floor();
+    let t = x - i;
+    let t = t * t * (3.0 - 2.0 * t);
+    let i = i as i32;
+    hash(i) * (1.0 - t) + hash(i.wrapping_add(1)) * t
+}
+
+// trauma based shake: impacts add trauma, which decays over time, and the
+// displacement grows with its square so small hits barely register
+#[derive(Debug, Clone)]
+pub struct CameraShake {
+    trauma: f32,
+    time: f32,
+    seed: u32,
+    // noise samples per second
+    pub frequency: f32,
+    // pitch, yaw and roll at full trauma
+    pub max_rotation: Rad<f32>,
+    // view space offset at full trauma, 0 for rotation only shake
+    pub max_translation: f32,
+    // trauma lost per second
+    pub decay: f32,
+}
+
+impl Default for CameraShake {
+    fn default() -> Self {
+        Self {
+            trauma: 0.0,
+            time: 0.0,
+            seed: 0,
+            frequency: 15.0,
+            max_rotation: Rad(0.05),
+            max_translation: 0.1,
+            decay: 1.0,
+        }
+    }
+}
+
+impl CameraShake {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // separate shakes with different seeds do not move in sync
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    // trauma saturates at 1
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.trauma = (self.trauma + amount).clamp(0.0, 1.0);
+    }
+
+    pub fn trauma(&self) -> f32 {
+        self.trauma
+    }
+
+    pub fn is_shaking(&self) -> bool {
+        self.trauma > 0.0
+    }
+
+    pub fn update(&mut self, delta_time: f32) {
+        self.time += delta_time;
+        self.trauma = (self.trauma - self.decay * delta_time).max(0.0);
+    }
+
+    // the view space perturbation, identity when calm
+    pub fn offset(&self) -> Matrix4<f32> {
+        let shake = self.trauma * self.trauma;
+        if shake == 0.0 {
+            return Matrix4::identity();
+        }
+        let x = self.time * self.frequency;
+        let sample = |channel: u32| noise(self.seed.wrapping_mul(6).wrapping_add(channel), x) * shake;
+        let rotation = self.max_rotation.0;
+        let translation = self.max_translation;
+        Matrix4::from_translation(vec3(sample(0), sample(1), sample(2)) * translation)
+            * Matrix4::from_angle_z(Rad(sample(3) * rotation))
+            * Matrix4::from_angle_x(Rad(sample(4) * rotation))
+            * Matrix4::from_angle_y(Rad(sample(5) * rotation))
+    }
+
+    // the camera's view with the shake applied, the camera itself is not moved
+    pub fn apply(&self, view: &Matrix4<f32>) -> Matrix4<f32> {
+        self.offset() * view
+    }
+}
@@ -0,0 +1,393 @@
+use std::ffi::CStr;
+use std::mem;
+
+use cgmath::{Matrix4, Point3, Transform, Vector3, vec3};
+use gl::types::*;
+
+use crate::context::check_render_thread;
+use crate::{c_str, PointLight, Shader};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClusterConfig {
+    pub tiles_x: u32,
+    pub tiles_y: u32,
+    pub slices: u32,
+    // lights beyond this count in one cluster are dropped
+    pub max_lights_per_cluster: u32,
+    // where the last slice ends when the far plane is further or infinite. lights beyond it are
+    // dropped and fragments beyond it use the last slice.
+    pub max_distance: f32,
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        Self {
+            tiles_x: 16,
+            tiles_y: 9,
+            slices: 24,
+            max_lights_per_cluster: 64,
+            max_distance: 1000.0,
+        }
+    }
+}
+
+impl ClusterConfig {
+    // the slices are logarithmic, so they need a finite far end
+    fn cluster_far(&self, projection: &ClusterProjection) -> f32 {
+        projection.far.min(self.max_distance).max(projection.near * 2.0)
+    }
+
+    fn slice_depth(&self, projection: &ClusterProjection, slice: u32) -> f32 {
+        projection.near * (self.cluster_far(projection) / projection.near).powf(slice as f32 / self.slices as f32)
+    }
+
+    // the slice of a view space depth, the same one `ClusterIndex` picks for a fragment there
+    fn slice(&self, projection: &ClusterProjection, depth: f32) -> usize {
+        let scale = self.slices as f32 / (self.cluster_far(projection) / projection.near).ln();
+        let slice = ((depth.max(projection.near) / projection.near).ln() * scale).max(0.0) as usize;
+        slice.min(self.slices as usize - 1)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClusterProjection {
+    // vertical field of view in degrees
+    pub fov: f32,
+    pub ratio: f32,
+    pub near: f32,
+    // may be infinite, the clusters end at `ClusterConfig::max_distance`
+    pub far: f32,
+}
+
+// include after `#version` and call `CalcClusteredLights`. the fragment position is world space.
+pub const CLUSTERED_LIGHTING_GLSL: &str = r#"
+uniform samplerBuffer clusterLights;
+uniform usamplerBuffer clusterGrid;
+uniform usamplerBuffer clusterIndices;
+uniform uvec3 clusterDims;
+uniform vec2 clusterScreenSize;
+uniform float clusterNear;
+// of the projection, 0 for an infinite far plane
+uniform float clusterInverseFar;
+// where the last slice ends
+uniform float clusterFar;
+
+uint ClusterIndex() {
+    float ndcDepth = gl_FragCoord.z * 2.0 - 1.0;
+    float n = clusterNear * clusterInverseFar;
+    float viewDepth = 2.0 * clusterNear / max(1.0 + n - ndcDepth * (1.0 - n), 1e-6);
+    uint slice = uint(max(log(viewDepth / clusterNear) / log(clusterFar / clusterNear) * float(clusterDims.z), 0.0));
+    uvec2 tile = uvec2(gl_FragCoord.xy / clusterScreenSize * vec2(clusterDims.xy));
+    tile = min(tile, clusterDims.xy - 1u);
+    slice = min(slice, clusterDims.z - 1u);
+    return (slice * clusterDims.y + tile.y) * clusterDims.x + tile.x;
+}
+
+vec3 CalcClusteredLights(vec3 fragPos, vec3 normal, vec3 viewDir, vec3 diffuseColor, vec3 specularColor, float shininess) {
+    uvec2 cluster = texelFetch(clusterGrid, int(ClusterIndex())).xy;
+    vec3 result = vec3(0.0);
+    for (uint i = 0u; i < cluster.y; i++) {
+        int light = int(texelFetch(clusterIndices, int(cluster.x + i)).r) * 4;
+        vec4 positionRadius = texelFetch(clusterLights, light);
+        vec4 ambientConstant = texelFetch(clusterLights, light + 1);
+        vec4 diffuseLinear = texelFetch(clusterLights, light + 2);
+        vec4 specularQuadratic = texelFetch(clusterLights, light + 3);
+
+        float distance = length(positionRadius.xyz - fragPos);
+        if (distance > positionRadius.w) {
+            continue;
+        }
+        float attenuation = 1.0 / (ambientConstant.w + diffuseLinear.w * distance + specularQuadratic.w * distance * distance);
+
+        vec3 lightDir = normalize(positionRadius.xyz - fragPos);
+        float diff = max(dot(normal, lightDir), 0.0);
+        vec3 reflectDir = reflect(-lightDir, normal);
+        float spec = pow(max(dot(viewDir, reflectDir), 0.0), shininess);
+
+        result += attenuation * (ambientConstant.rgb * diffuseColor
+            + diffuseLinear.rgb * diff * diffuseColor
+            + specularQuadratic.rgb * spec * specularColor);
+    }
+    return result;
+}
+"#;
+
+#[derive(Debug)]
+struct TextureBuffer {
+    buffer: GLuint,
+    texture: GLuint,
+}
+
+impl TextureBuffer {
+    unsafe fn new(format: GLenum) -> Self {
+        let mut buffer = 0;
+        gl::GenBuffers(1, &mut buffer);
+        gl::BindBuffer(gl::TEXTURE_BUFFER, buffer);
+        gl::BufferData(gl::TEXTURE_BUFFER, 16, std::ptr::null(), gl::DYNAMIC_DRAW);
+
+        let mut texture = 0;
+        gl::GenTextures(1, &mut texture);
+        gl::BindTexture(gl::TEXTURE_BUFFER, texture);
+        gl::TexBuffer(gl::TEXTURE_BUFFER, format, buffer);
+
+        gl::BindTexture(gl::TEXTURE_BUFFER, 0);
+        gl::BindBuffer(gl::TEXTURE_BUFFER, 0);
+
+        Self { buffer, texture }
+    }
+
+    unsafe fn upload<T>(&self, data: &[T]) {
+        gl::BindBuffer(gl::TEXTURE_BUFFER, self.buffer);
+        // an empty store is not allowed, keep at least one element around
+        let size = mem::size_of_val(data).max(16);
+        gl::BufferData(gl::TEXTURE_BUFFER, conv!(size), std::ptr::null(), gl::DYNAMIC_DRAW);
+        gl::BufferSubData(gl::TEXTURE_BUFFER, 0, conv!(mem::size_of_val(data)), data.as_ptr() as *const _);
+        gl::BindBuffer(gl::TEXTURE_BUFFER, 0);
+    }
+
+    unsafe fn bind(&self, shader: &Shader, name: &CStr, unit: GLuint) {
+        gl::ActiveTexture(gl::TEXTURE0 + unit);
+        gl::BindTexture(gl::TEXTURE_BUFFER, self.texture);
+        shader.set_integer(name, conv!(unit));
+    }
+}
+
+impl Drop for TextureBuffer {
+    fn drop(&mut self) {
+        check_render_thread("TextureBuffer");
+        unsafe {
+            gl::DeleteTextures(1, &self.texture);
+            gl::DeleteBuffers(1, &self.buffer);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Aabb {
+    min: Vector3<f32>,
+    max: Vector3<f32>,
+}
+
+impl Aabb {
+    fn intersects_sphere(&self, center: Point3<f32>, radius: f32) -> bool {
+        let mut distance = 0.0;
+        for i in 0..3 {
+            let value = center[i];
+            if value < self.min[i] {
+                distance += (self.min[i] - value) * (self.min[i] - value);
+            } else if value > self.max[i] {
+                distance += (value - self.max[i]) * (value - self.max[i]);
+            }
+        }
+        distance <= radius * radius
+    }
+}
+
+#[derive(Debug)]
+pub struct ClusteredLighting {
+    config: ClusterConfig,
+    projection: Option<ClusterProjection>,
+    // view space bounds of every cluster for the current projection
+    bounds: Vec<Aabb>,
+    lights: TextureBuffer,
+    grid: TextureBuffer,
+    indices: TextureBuffer,
+}
+
+impl ClusteredLighting {
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn new(config: ClusterConfig) -> Self {
+        Self {
+            config,
+            projection: None,
+            bounds: vec![],
+            lights: TextureBuffer::new(gl::RGBA32F),
+            grid: TextureBuffer::new(gl::RG32UI),
+            indices: TextureBuffer::new(gl::R32UI),
+        }
+    }
+
+    pub fn config(&self) -> &ClusterConfig {
+        &self.config
+    }
+
+    fn cluster_count(&self) -> usize {
+        (self.config.tiles_x * self.config.tiles_y * self.config.slices) as usize
+    }
+
+    fn build_bounds(&mut self, projection: ClusterProjection) {
+        let tan_y = (projection.fov.to_radians() / 2.0).tan();
+        let tan_x = tan_y * projection.ratio;
+        let config = self.config;
+
+        self.bounds.clear();
+        for slice in 0..config.slices {
+            let near = config.slice_depth(&projection, slice);
+            let far = config.slice_depth(&projection, slice + 1);
+            for y in 0..config.tiles_y {
+                for x in 0..config.tiles_x {
+                    let ndc_x = [x as f32 / config.tiles_x as f32 * 2.0 - 1.0, (x + 1) as f32 / config.tiles_x as f32 * 2.0 - 1.0];
+                    let ndc_y = [y as f32 / config.tiles_y as f32 * 2.0 - 1.0, (y + 1) as f32 / config.tiles_y as f32 * 2.0 - 1.0];
+
+                    let mut min = vec3(f32::INFINITY, f32::INFINITY, f32::INFINITY);
+                    let mut max = -min;
+                    for &depth in &[near, far] {
+                        for &nx in &ndc_x {
+                            for &ny in &ndc_y {
+                                // view space looks down -z
+                                let corner = vec3(nx * tan_x * depth, ny * tan_y * depth, -depth);
+                                min = vec3(min.x.min(corner.x), min.y.min(corner.y), min.z.min(corner.z));
+                                max = vec3(max.x.max(corner.x), max.y.max(corner.y), max.z.max(corner.z));
+                            }
+                        }
+                    }
+                    self.bounds.push(Aabb { min, max });
+                }
+            }
+        }
+
+        self.projection = Some(projection);
+    }
+
+    /// assigns lights to clusters on the CPU and uploads the result
+    ///
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn update(&mut self, lights: &[PointLight], view: &Matrix4<f32>, projection: ClusterProjection) {
+        if self.projection != Some(projection) {
+            self.build_bounds(projection);
+        }
+
+        let mut light_data = Vec::with_capacity(lights.len() * 16);
+        for light in lights {
+            let radius = light.radius();
+            light_data.extend_from_slice(&[light.position.x, light.position.y, light.position.z, radius]);
+            light_data.extend_from_slice(&[light.ambient.r, light.ambient.g, light.ambient.b, light.constant]);
+            light_data.extend_from_slice(&[light.diffuse.r, light.diffuse.g, light.diffuse.b, light.linear]);
+            light_data.extend_from_slice(&[light.specular.r, light.specular.g, light.specular.b, light.quadratic]);
+        }
+
+        let mut per_cluster: Vec<Vec<u32>> = vec![vec![]; self.cluster_count()];
+        let cluster_far = self.config.cluster_far(&projection);
+        let cluster_size = (self.config.tiles_x * self.config.tiles_y) as usize;
+        for (i, light) in lights.iter().enumerate() {
+            let radius = light.radius();
+            let center = view.transform_point(light.position);
+            let depth = -center.z;
+            if depth + radius < projection.near || depth - radius > cluster_far {
+                continue;
+            }
+
+            // only walk the slices the sphere can overlap
+            let first = self.config.slice(&projection, depth - radius);
+            let last = self.config.slice(&projection, depth + radius);
+            let range = first * cluster_size..(last + 1) * cluster_size;
+            for (assigned, bounds) in per_cluster[range.clone()].iter_mut().zip(&self.bounds[range]) {
+                if assigned.len() < self.config.max_lights_per_cluster as usize && bounds.intersects_sphere(center, radius) {
+                    assigned.push(conv!(i));
+                }
+            }
+        }
+
+        let mut grid = Vec::with_capacity(per_cluster.len() * 2);
+        let mut indices = vec![];
+        for assigned in per_cluster.iter() {
+            grid.push(indices.len() as u32);
+            grid.push(assigned.len() as u32);
+            indices.extend_from_slice(assigned);
+        }
+
+        self.lights.upload(&light_data);
+        self.grid.upload(&grid);
+        self.indices.upload(&indices);
+    }
+
+    /// binds the buffers to `first_unit`, `first_unit + 1` and `first_unit + 2`
+    ///
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread and `shader` must be in use, the values are set with glUniform.
+    pub unsafe fn bind(&self, shader: &Shader, first_unit: GLuint, screen_width: f32, screen_height: f32) {
+        self.lights.bind(shader, c_str("clusterLights\0"), first_unit);
+        self.grid.bind(shader, c_str("clusterGrid\0"), first_unit + 1);
+        self.indices.bind(shader, c_str("clusterIndices\0"), first_unit + 2);
+        gl::ActiveTexture(gl::TEXTURE0);
+
+        shader.set_uvec3(c_str("clusterDims\0"), self.config.tiles_x, self.config.tiles_y, self.config.slices);
+        shader.set_vec2(c_str("clusterScreenSize\0"), screen_width, screen_height);
+        if let Some(projection) = self.projection {
+            shader.set_float(c_str("clusterNear\0"), projection.near);
+            shader.set_float(c_str("clusterInverseFar\0"), 1.0 / projection.far);
+            shader.set_float(c_str("clusterFar\0"), self.config.cluster_far(&projection));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{perspective, vec4, Deg};
+
+    use super::*;
+    use crate::infinite_perspective;
+
+    // `ClusterIndex` of the shader for a fragment at `depth` in front of a camera with `matrix`
+    fn fragment_slice(config: &ClusterConfig, projection: &ClusterProjection, matrix: &Matrix4<f32>, depth: f32) -> usize {
+        let clip = matrix * vec4(0.0, 0.0, -depth, 1.0);
+        let frag_depth = clip.z / clip.w * 0.5 + 0.5;
+
+        let ndc_depth = frag_depth * 2.0 - 1.0;
+        let n = projection.near / projection.far;
+        let view_depth = 2.0 * projection.near / (1.0 + n - ndc_depth * (1.0 - n)).max(1e-6);
+        let cluster_far = config.cluster_far(projection);
+        let slice = ((view_depth / projection.near).ln() / (cluster_far / projection.near).ln() * config.slices as f32).max(0.0) as usize;
+        slice.min(config.slices as usize - 1)
+    }
+
+    #[test]
+    fn fragments_land_in_the_slice_of_their_lights() {
+        let config = ClusterConfig::default();
+        // nearer than, further than and without the end of the clusters
+        for &far in &[200.0, 5000.0, f32::INFINITY] {
+            let projection = ClusterProjection {
+                fov: 60.0,
+                ratio: 1.5,
+                near: 0.1,
+                far,
+            };
+            let matrix = if far.is_infinite() {
+                infinite_perspective(Deg(60.0), 1.5, 0.1)
+            } else {
+                perspective(Deg(60.0), 1.5, 0.1, far)
+            };
+            for slice in 0..config.slices {
+                // halfway through the slice in log space, away from the rounding at its ends
+                let depth = (config.slice_depth(&projection, slice) * config.slice_depth(&projection, slice + 1)).sqrt();
+                assert_eq!(config.slice(&projection, depth), slice as usize, "far {} depth {}", far, depth);
+                assert_eq!(fragment_slice(&config, &projection, &matrix, depth), slice as usize, "far {} depth {}", far, depth);
+            }
+            // past the last slice, when the projection reaches that far
+            if far > config.max_distance {
+                let last = config.slices as usize - 1;
+                assert_eq!(config.slice(&projection, 2000.0), last);
+                assert_eq!(fragment_slice(&config, &projection, &matrix, 2000.0), last);
+            }
+        }
+    }
+
+    #[test]
+    fn spheres_against_cluster_bounds() {
+        let bounds = Aabb {
+            min: vec3(-1.0, -1.0, -4.0),
+            max: vec3(1.0, 1.0, -2.0),
+        };
+        // inside, touching a face and short of a corner
+        assert!(bounds.intersects_sphere(Point3::new(0.0, 0.0, -3.0), 0.1));
+        assert!(bounds.intersects_sphere(Point3::new(2.0, 0.0, -3.0), 1.0));
+        assert!(!bounds.intersects_sphere(Point3::new(2.0, 2.0, -1.0), 1.5));
+        assert!(bounds.intersects_sphere(Point3::new(2.0, 2.0, -1.0), 1.8));
+        assert!(!bounds.intersects_sphere(Point3::new(0.0, 0.0, 0.0), 1.9));
+    }
+}
@@ -0,0 +1,298 @@
+use std::ops::{Add, Mul};
+
+use cgmath::{Vector3, Vector4};
+
+use crate::color_temperature;
+
+// the sRGB transfer function, display value to linear light
+pub fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.040_45 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+pub fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// a color in linear RGB with straight alpha, what shaders and lights work with. colors picked by
+// eye (hex codes, color pickers, HSV) are sRGB and go through the `from_*` constructors.
+// components above one are fine for lights.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+impl Color {
+    pub const TRANSPARENT: Color = Color::new(0.0, 0.0, 0.0, 0.0);
+    pub const BLACK: Color = Color::rgb(0.0, 0.0, 0.0);
+    pub const WHITE: Color = Color::rgb(1.0, 1.0, 1.0);
+    pub const RED: Color = Color::rgb(1.0, 0.0, 0.0);
+    pub const GREEN: Color = Color::rgb(0.0, 1.0, 0.0);
+    pub const BLUE: Color = Color::rgb(0.0, 0.0, 1.0);
+    pub const YELLOW: Color = Color::rgb(1.0, 1.0, 0.0);
+    pub const CYAN: Color = Color::rgb(0.0, 1.0, 1.0);
+    pub const MAGENTA: Color = Color::rgb(1.0, 0.0, 1.0);
+
+    // linear components
+    pub const fn new(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Self { r, g, b, a }
+    }
+
+    pub const fn rgb(r: f32, g: f32, b: f32) -> Self {
+        Self::new(r, g, b, 1.0)
+    }
+
+    // sRGB components in [0, 1], alpha is never encoded
+    pub fn from_srgb(r: f32, g: f32, b: f32, a: f32) -> Self {
+        Self::new(srgb_to_linear(r), srgb_to_linear(g), srgb_to_linear(b), a)
+    }
+
+    pub fn from_srgb8(r: u8, g: u8, b: u8, a: u8) -> Self {
+        let unit = |c: u8| f32::from(c) / 255.0;
+        Self::from_srgb(unit(r), unit(g), unit(b), unit(a))
+    }
+
+    // 0xRRGGBB in sRGB, opaque
+    pub fn from_rgb_u32(rgb: u32) -> Self {
+        Self::from_srgb8((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8, 255)
+    }
+
+    // "#rgb", "#rgba", "#rrggbb" or "#rrggbbaa" in sRGB, the '#' is optional
+    pub fn from_hex(hex: &str) -> Option<Self> {
+        let hex = hex.trim().trim_start_matches('#');
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let digit = |i: usize| u8::from_str_radix(&hex[i..=i], 16).ok();
+        let byte = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        match hex.len() {
+            3 | 4 => {
+                let short = |i: usize| digit(i).map(|d| d * 17);
+                let a = if hex.len() == 4 { short(3)? } else { 255 };
+                Some(Self::from_srgb8(short(0)?, short(1)?, short(2)?, a))
+            }
+            6 | 8 => {
+                let a = if hex.len() == 8 { byte(6)? } else { 255 };
+                Some(Self::from_srgb8(byte(0)?, byte(2)?, byte(4)?, a))
+            }
+            _ => None,
+        }
+    }
+
+    // hue in degrees, saturation and value in [0, 1], of the sRGB color wheel
+    pub fn from_hsv(hue: f32, saturation: f32, value: f32) -> Self {
+        let (s, v) = (saturation.clamp(0.0, 1.0), value.clamp(0.0, 1.0));
+        let h = hue.rem_euclid(360.0) / 60.0;
+        let c = v * s;
+        let x = c * (1.0 - (h % 2.0 - 1.0).abs());
+        let (r, g, b) = match h as u32 {
+            0 => (c, x, 0.0),
+            1 => (x, c, 0.0),
+            2 => (0.0, c, x),
+            3 => (0.0, x, c),
+            4 => (x, 0.0, c),
+            _ => (c, 0.0, x),
+        };
+        let m = v - c;
+        Self::from_srgb(r + m, g + m, b + m, 1.0)
+    }
+
+    // a black body at `kelvin`, see `color_temperature`
+    pub fn from_temperature(kelvin: f32) -> Self {
+        color_temperature(kelvin).into()
+    }
+
+    // sRGB components, clamped to [0, 1]
+    pub fn to_srgb(self) -> [f32; 4] {
+        let encode = |c: f32| linear_to_srgb(c.clamp(0.0, 1.0));
+        [encode(self.r), encode(self.g), encode(self.b), self.a.clamp(0.0, 1.0)]
+    }
+
+    pub fn to_srgb8(self) -> [u8; 4] {
+        let [r, g, b, a] = self.to_srgb();
+        let byte = |c: f32| (c * 255.0).round() as u8;
+        [byte(r), byte(g), byte(b), byte(a)]
+    }
+
+    pub fn to_hex(self) -> String {
+        match self.to_srgb8() {
+            [r, g, b, 255] => format!("#{:02x}{:02x}{:02x}", r, g, b),
+            [r, g, b, a] => format!("#{:02x}{:02x}{:02x}{:02x}", r, g, b, a),
+        }
+    }
+
+    // (hue in degrees, saturation, value) of the sRGB color
+    pub fn to_hsv(self) -> (f32, f32, f32) {
+        let [r, g, b, _] = self.to_srgb();
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let delta = max - min;
+        let hue = if delta <= 0.0 {
+            0.0
+        } else if max == r {
+            60.0 * ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+        let saturation = if max > 0.0 { delta / max } else { 0.0 };
+        (hue, saturation, max)
+    }
+
+    pub fn with_alpha(self, a: f32) -> Self {
+        Self { a, ..self }
+    }
+
+    // for blending with (ONE, ONE_MINUS_SRC_ALPHA)
+    pub fn premultiplied(self) -> Self {
+        Self::new(self.r * self.a, self.g * self.a, self.b * self.a, self.a)
+    }
+
+    pub fn unpremultiplied(self) -> Self {
+        if self.a > 0.0 {
+            Self::new(self.r / self.a, self.g / self.a, self.b / self.a, self.a)
+        } else {
+            Self::TRANSPARENT
+        }
+    }
+
+    // in linear space, which is where mixing light is physically right. `Mul<f32>` leaves the
+    // alpha alone, so it is mixed on its own.
+    pub fn lerp(self, other: Color, t: f32) -> Self {
+        (self * (1.0 - t) + other * t).with_alpha(self.a + (other.a - self.a) * t)
+    }
+
+    // relative luminance of the linear color
+    pub fn luminance(self) -> f32 {
+        0.2126 * self.r + 0.7152 * self.g + 0.0722 * self.b
+    }
+}
+
+// scales the color, not the alpha, e.g. a light color by its intensity
+impl Mul<f32> for Color {
+    type Output = Color;
+
+    fn mul(self, rhs: f32) -> Color {
+        Color::new(self.r * rhs, self.g * rhs, self.b * rhs, self.a)
+    }
+}
+
+// componentwise, for tinting
+impl Mul for Color {
+    type Output = Color;
+
+    fn mul(self, rhs: Color) -> Color {
+        Color::new(self.r * rhs.r, self.g * rhs.g, self.b * rhs.b, self.a * rhs.a)
+    }
+}
+
+// componentwise including alpha, for `lerp` and accumulating light
+impl Add for Color {
+    type Output = Color;
+
+    fn add(self, rhs: Color) -> Color {
+        Color::new(self.r + rhs.r, self.g + rhs.g, self.b + rhs.b, self.a + rhs.a)
+    }
+}
+
+impl From<Vector3<f32>> for Color {
+    fn from(v: Vector3<f32>) -> Self {
+        Color::rgb(v.x, v.y, v.z)
+    }
+}
+
+impl From<Vector4<f32>> for Color {
+    fn from(v: Vector4<f32>) -> Self {
+        Color::new(v.x, v.y, v.z, v.w)
+    }
+}
+
+// linear components, the alpha is dropped
+impl From<Color> for Vector3<f32> {
+    fn from(c: Color) -> Self {
+        Vector3::new(c.r, c.g, c.b)
+    }
+}
+
+impl From<Color> for Vector4<f32> {
+    fn from(c: Color) -> Self {
+        Vector4::new(c.r, c.g, c.b, c.a)
+    }
+}
+
+impl From<Color> for [f32; 4] {
+    fn from(c: Color) -> Self {
+        [c.r, c.g, c.b, c.a]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-4
+    }
+
+    #[test]
+    fn transfer_functions_are_inverses() {
+        for i in 0..=100 {
+            let c = i as f32 / 100.0;
+            assert!(close(linear_to_srgb(srgb_to_linear(c)), c), "{}", c);
+        }
+        assert!(close(srgb_to_linear(0.5), 0.214_041));
+        assert!(close(linear_to_srgb(0.5), 0.735_357));
+    }
+
+    #[test]
+    fn hex_codes_round_trip() {
+        assert_eq!(Color::from_hex("#ff8000").unwrap().to_hex(), "#ff8000");
+        assert_eq!(Color::from_hex("12345678").unwrap().to_hex(), "#12345678");
+        assert_eq!(Color::from_hex("#f80").unwrap(), Color::from_hex("#ff8800").unwrap());
+        assert_eq!(Color::from_hex("#f808").unwrap().to_srgb8(), [255, 136, 0, 136]);
+        assert_eq!(Color::from_rgb_u32(0x336699), Color::from_hex("#336699").unwrap());
+        assert_eq!(Color::from_hex("#fff").unwrap(), Color::WHITE);
+        for bad in ["", "#ff", "#ff00f", "#gggggg", "#ff 000"].iter() {
+            assert_eq!(Color::from_hex(bad), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn hsv_is_of_the_srgb_color() {
+        assert_eq!(Color::from_hsv(0.0, 1.0, 1.0), Color::RED);
+        assert_eq!(Color::from_hsv(480.0, 1.0, 1.0), Color::GREEN);
+        assert_eq!(Color::from_hsv(-120.0, 1.0, 1.0), Color::BLUE);
+        let (hue, saturation, value) = Color::from_hsv(200.0, 0.4, 0.7).to_hsv();
+        assert!(close(hue, 200.0) && close(saturation, 0.4) && close(value, 0.7));
+        assert_eq!(Color::rgb(0.5, 0.5, 0.5).to_hsv().1, 0.0);
+    }
+
+    #[test]
+    fn alpha_and_mixing() {
+        let color = Color::new(0.8, 0.4, 0.2, 0.5);
+        assert_eq!(color.premultiplied(), Color::new(0.4, 0.2, 0.1, 0.5));
+        assert_eq!(color.premultiplied().unpremultiplied(), color);
+        assert_eq!(Color::new(1.0, 1.0, 1.0, 0.0).unpremultiplied(), Color::TRANSPARENT);
+        assert!(close(Color::WHITE.luminance(), 1.0));
+        // out of range components are clamped when encoded
+        assert_eq!(Color::new(2.0, -1.0, 0.0, 1.5).to_srgb8(), [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn lerp_mixes_the_alpha_too() {
+        assert_eq!(Color::BLACK.lerp(Color::WHITE, 0.25), Color::rgb(0.25, 0.25, 0.25));
+        let mixed = Color::TRANSPARENT.lerp(Color::RED, 0.5);
+        assert_eq!(mixed, Color::new(0.5, 0.0, 0.0, 0.5));
+    }
+}
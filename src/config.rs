@@ -0,0 +1,368 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Receiver;
+
+use glfw::{Glfw, SwapInterval, Window, WindowEvent, WindowHint, WindowMode};
+use log::LevelFilter;
+
+// what a config file or override got wrong
+#[derive(Debug)]
+pub struct ConfigError {
+    message: String,
+}
+
+impl ConfigError {
+    fn new(message: String) -> Self {
+        Self { message }
+    }
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid engine config: {}", self.message)
+    }
+}
+
+impl Error for ConfigError {}
+
+// startup settings, read from a TOML file like
+//
+//     [window]
+//     width = 1280
+//     fullscreen = false
+//
+// then overridden from the environment (`GAME_ENGINE_WINDOW_WIDTH=1920`) and the command line
+// (`--window.width=1920`, a flag alone sets a bool). only tables of plain keys, strings,
+// numbers and bools are understood, which covers what `to_toml` writes. the crate has no
+// `Engine` to hand it to, `create_window` applies the window settings and the rest is read
+// by the game, e.g. `render_size` for its targets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EngineConfig {
+    pub width: u32,
+    pub height: u32,
+    pub title: String,
+    pub fullscreen: bool,
+    pub vsync: bool,
+    // 0 disables multisampling
+    pub samples: u32,
+    // of the window size the scene is rendered at
+    pub render_scale: f32,
+    pub asset_root: PathBuf,
+    pub log_level: LevelFilter,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            width: 800,
+            height: 600,
+            title: "game-engine".into(),
+            fullscreen: false,
+            vsync: true,
+            samples: 0,
+            render_scale: 1.0,
+            asset_root: PathBuf::from("."),
+            log_level: LevelFilter::Info,
+        }
+    }
+}
+
+const ENV_PREFIX: &str = "GAME_ENGINE_";
+
+// what `set` understands
+const KEYS: [&str; 9] = [
+    "window.width",
+    "window.height",
+    "window.title",
+    "window.fullscreen",
+    "window.vsync",
+    "window.samples",
+    "render.scale",
+    "assets.root",
+    "log.level",
+];
+
+// flags that never take the next argument as their value, set them with `--key=false`
+fn is_bool(key: &str) -> bool {
+    matches!(key, "window.fullscreen" | "window.vsync")
+}
+
+// a TOML value as written in the file: quoted strings unescaped, everything else as is
+pub(crate) fn parse_value(value: &str) -> Result<String, String> {
+    let value = value.trim();
+    if let Some(rest) = value.strip_prefix('"') {
+        let mut unescaped = String::new();
+        let mut chars = rest.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '"' => {
+                    let tail = chars.as_str().trim();
+                    if !tail.is_empty() && !tail.starts_with('#') {
+                        return Err(format!("unexpected {:?} after string", tail));
+                    }
+                    return Ok(unescaped);
+                }
+                '\\' => match chars.next() {
+                    Some('n') => unescaped.push('\n'),
+                    Some('t') => unescaped.push('\t'),
+                    Some('"') => unescaped.push('"'),
+                    Some('\\') => unescaped.push('\\'),
+                    other => return Err(format!("unknown escape {:?}", other)),
+                },
+                c => unescaped.push(c),
+            }
+        }
+        return Err("unterminated string".into());
+    }
+    Ok(value.split('#').next().unwrap().trim().to_string())
+}
+
+pub(crate) fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n").replace('\t', "\\t"))
+}
+
+fn parse<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, ConfigError> {
+    value.parse().map_err(|_| ConfigError::new(format!("{} cannot be {:?}", key, value)))
+}
+
+impl EngineConfig {
+    pub fn from_toml(source: &str) -> Result<Self, ConfigError> {
+        let mut config = Self::default();
+        let mut table = String::new();
+        for (number, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| ConfigError::new(format!("line {}: {}", number + 1, message));
+            if line.starts_with('[') {
+                let end = line.find(']').ok_or_else(|| error("unterminated table header".into()))?;
+                table = line[1..end].trim().to_string();
+                continue;
+            }
+            let equals = line.find('=').ok_or_else(|| error(format!("expected key = value, found {:?}", line)))?;
+            let key = line[..equals].trim();
+            let value = parse_value(&line[equals + 1..]).map_err(error)?;
+            let key = if table.is_empty() { key.to_string() } else { format!("{}.{}", table, key) };
+            config.set(&key, &value).map_err(|e| error(e.message))?;
+        }
+        Ok(config)
+    }
+
+    // the defaults when the file does not exist
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error + 'static>> {
+        match fs::read_to_string(path) {
+            Ok(source) => Ok(Self::from_toml(&source)?),
+            Err(ref e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn to_toml(&self) -> String {
+        format!(
+            "[window]\nwidth = {}\nheight = {}\ntitle = {}\nfullscreen = {}\nvsync = {}\nsamples = {}\n\n\
+             [render]\nscale = {:?}\n\n[assets]\nroot = {}\n\n[log]\nlevel = {}\n",
+            self.width,
+            self.height,
+            quote(&self.title),
+            self.fullscreen,
+            self.vsync,
+            self.samples,
+            self.render_scale,
+            quote(&self.asset_root.to_string_lossy()),
+            quote(&self.log_level.to_string().to_lowercase()),
+        )
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        fs::write(path, self.to_toml())
+    }
+
+    // sets one setting by its dotted key, e.g. `window.width`. unknown keys are an error.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        match key {
+            "window.width" => self.width = parse(key, value)?,
+            "window.height" => self.height = parse(key, value)?,
+            "window.title" => self.title = value.to_string(),
+            "window.fullscreen" => self.fullscreen = parse(key, value)?,
+            "window.vsync" => self.vsync = parse(key, value)?,
+            "window.samples" => self.samples = parse(key, value)?,
+            "render.scale" => {
+                let scale: f32 = parse(key, value)?;
+                if scale.is_nan() || scale <= 0.0 {
+                    return Err(ConfigError::new(format!("{} must be positive, not {}", key, scale)));
+                }
+                self.render_scale = scale;
+            }
+            "assets.root" => self.asset_root = PathBuf::from(value),
+            "log.level" => self.log_level = parse(key, value)?,
+            _ => return Err(ConfigError::new(format!("unknown setting {}", key))),
+        }
+        Ok(())
+    }
+
+    // reads `GAME_ENGINE_WINDOW_WIDTH` and the like. other variables with the prefix, e.g.
+    // `GAME_ENGINE_UPDATE_GOLDEN`, are left alone.
+    pub fn apply_env(&mut self) -> Result<(), ConfigError> {
+        for (name, value) in std::env::vars() {
+            if let Some(key) = name.strip_prefix(ENV_PREFIX) {
+                // the first underscore separates the table
+                let key = key.to_lowercase().replacen('_', ".", 1);
+                if KEYS.contains(&key.as_str()) {
+                    self.set(&key, &value)?;
+                } else {
+                    log::debug!("{} is not an engine setting", name);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // reads `--window.width=1920`, `--window.width 1920` and `--window.fullscreen`. returns the
+    // other arguments in order, the game's own flags and positional arguments, for the game to
+    // parse. pass `std::env::args().skip(1)`.
+    pub fn apply_args<I: IntoIterator<Item = String>>(&mut self, args: I) -> Result<Vec<String>, ConfigError> {
+        let mut rest = vec![];
+        let mut args = args.into_iter().peekable();
+        while let Some(arg) = args.next() {
+            let setting = match arg.strip_prefix("--") {
+                Some(setting) => setting,
+                None => {
+                    rest.push(arg);
+                    continue;
+                }
+            };
+            let (key, value) = match setting.find('=') {
+                Some(equals) => (&setting[..equals], Some(&setting[equals + 1..])),
+                None => (setting, None),
+            };
+            if !KEYS.contains(&key) {
+                rest.push(arg);
+                continue;
+            }
+            match value {
+                Some(value) => self.set(key, value)?,
+                None if is_bool(key) => self.set(key, "true")?,
+                None => match args.next_if(|value| !value.starts_with("--")) {
+                    Some(value) => self.set(key, &value)?,
+                    None => return Err(ConfigError::new(format!("--{} needs a value", key))),
+                },
+            }
+        }
+        Ok(rest)
+    }
+
+    // the file, then the environment, then the command line. arguments that aren't settings
+    // are ignored, use `apply_args` to get them.
+    pub fn load_with_overrides<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error + 'static>> {
+        let mut config = Self::load(path)?;
+        config.apply_env()?;
+        config.apply_args(std::env::args().skip(1))?;
+        Ok(config)
+    }
+
+    // `path` under `asset_root`
+    pub fn asset_path<P: AsRef<Path>>(&self, path: P) -> PathBuf {
+        self.asset_root.join(path)
+    }
+
+    // the size the scene is rendered at for a window of `width` by `height`
+    pub fn render_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let scale = |size: u32| ((size as f32 * self.render_scale).round() as u32).max(1);
+        (scale(width), scale(height))
+    }
+
+    // sets the log level and creates a GL 3.3 core window with the settings, its context
+    // made current. fullscreen takes the primary monitor.
+    pub fn create_window(&self, glfw: &mut Glfw) -> Option<(Window, Receiver<(f64, WindowEvent)>)> {
+        log::set_max_level(self.log_level);
+        glfw.window_hint(WindowHint::ContextVersion(3, 3));
+        glfw.window_hint(WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
+        glfw.window_hint(WindowHint::Samples(if self.samples > 0 { Some(self.samples) } else { None }));
+
+        let (mut window, events) = if self.fullscreen {
+            glfw.with_primary_monitor(|glfw, monitor| {
+                let mode = monitor.map_or(WindowMode::Windowed, WindowMode::FullScreen);
+                glfw.create_window(self.width, self.height, &self.title, mode)
+            })?
+        } else {
+            glfw.create_window(self.width, self.height, &self.title, WindowMode::Windowed)?
+        };
+
+        glfw::Context::make_current(&mut window);
+        glfw.set_swap_interval(if self.vsync { SwapInterval::Sync(1) } else { SwapInterval::None });
+        Some((window, events))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_tables_comments_and_strings() {
+        let config = EngineConfig::from_toml(
+            "# settings\n[window]\nwidth = 1280 # pixels\ntitle = \"a \\\"game\\\" # 1\"\nfullscreen = true\n\n[render]\nscale = 0.5\n\n[log]\nlevel = \"debug\"\n",
+        )
+        .unwrap();
+        assert_eq!(config.width, 1280);
+        assert_eq!(config.height, 600);
+        assert_eq!(config.title, "a \"game\" # 1");
+        assert!(config.fullscreen);
+        assert_eq!(config.render_scale, 0.5);
+        assert_eq!(config.log_level, LevelFilter::Debug);
+    }
+
+    #[test]
+    fn to_toml_round_trips() {
+        let config = EngineConfig {
+            width: 1920,
+            title: "tab\tand \\ newline\n".into(),
+            vsync: false,
+            samples: 4,
+            render_scale: 0.75,
+            asset_root: PathBuf::from("assets/hd"),
+            log_level: LevelFilter::Warn,
+            ..EngineConfig::default()
+        };
+        assert_eq!(EngineConfig::from_toml(&config.to_toml()).unwrap(), config);
+    }
+
+    #[test]
+    fn bad_files_report_the_line() {
+        let message = |source: &str| EngineConfig::from_toml(source).unwrap_err().to_string();
+        assert_eq!(message("[window]\nwidth = wide\n"), "invalid engine config: line 2: window.width cannot be \"wide\"");
+        assert!(message("[window\n").contains("line 1: unterminated table header"));
+        assert!(message("width 5\n").contains("expected key = value"));
+        assert!(message("[window]\ntitle = \"open\n").contains("unterminated string"));
+        assert!(message("[window]\ncolor = 1\n").contains("unknown setting window.color"));
+        assert!(message("[render]\nscale = 0\n").contains("must be positive"));
+    }
+
+    #[test]
+    fn args_override_settings_and_keep_the_rest() {
+        let mut config = EngineConfig::default();
+        let args = ["--window.width=1024", "level1", "--window.fullscreen", "--window.height", "768", "--seed", "3"];
+        let rest = config.apply_args(args.iter().map(|arg| arg.to_string())).unwrap();
+        assert_eq!(rest, vec!["level1", "--seed", "3"]);
+        assert_eq!((config.width, config.height), (1024, 768));
+        assert!(config.fullscreen);
+
+        assert!(config.apply_args(vec!["--window.vsync=false".to_string()]).is_ok());
+        assert!(!config.vsync);
+        assert!(config.apply_args(vec!["--window.width".to_string(), "--window.vsync".to_string()]).is_err());
+    }
+
+    #[test]
+    fn render_size_scales_and_never_reaches_zero() {
+        let config = EngineConfig {
+            render_scale: 0.5,
+            ..EngineConfig::default()
+        };
+        assert_eq!(config.render_size(1280, 721), (640, 361));
+        assert_eq!(config.render_size(1, 1), (1, 1));
+        assert_eq!(config.asset_path("a.png"), PathBuf::from("./a.png"));
+    }
+}
@@ -0,0 +1,75 @@
+// thread affinity of the GPU handles:
+// - `GlContext`, `Shader`, `Texture`, `Mesh`, `Model` and everything holding them are neither `Send` nor `Sync`,
+//   they share their GL objects through `Rc`.
+// - the plain GLuint owners (`Framebuffer`, `ShadowMap`, `FullscreenQuad`, `ViewUniforms`, ...) are `Send`
+//   so they can be stored anywhere, but they must only be used and dropped on the render thread.
+//   debug builds check this and panic instead of corrupting another thread's context.
+// - none of them are `Sync`-safe to use concurrently, GL calls through one context are serialized anyway.
+use std::cell::Cell;
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+
+use glfw::{Context, Window};
+
+// whether any thread has made a context current through `GlContext`. before that nothing is
+// checked, so code that sets up the context by hand keeps working.
+static ANY_RENDER_THREAD: AtomicBool = AtomicBool::new(false);
+
+thread_local! {
+    // per thread, so several contexts on several threads are all fine
+    static RENDER_THREAD: Cell<bool> = const { Cell::new(false) };
+}
+
+fn set_render_thread() {
+    RENDER_THREAD.with(|current| current.set(true));
+    ANY_RENDER_THREAD.store(true, Ordering::Relaxed);
+}
+
+// panics in debug builds when `what` touches GL on a thread without a current context.
+// only logs while the thread is already panicking, `Drop` impls call this and a second panic would abort.
+#[inline]
+pub(crate) fn check_render_thread(what: &str) {
+    if cfg!(debug_assertions) && ANY_RENDER_THREAD.load(Ordering::Relaxed) && !RENDER_THREAD.with(Cell::get) {
+        let current = thread::current();
+        let message = format!(
+            "{} used on thread {:?} ({:?}), which has no current GL context",
+            what,
+            current.name().unwrap_or("unnamed"),
+            current.id(),
+        );
+        if thread::panicking() {
+            log::error!("{}", message);
+        } else {
+            panic!("{}", message);
+        }
+    }
+}
+
+// proof that a GL context is current on this thread and the function pointers are loaded.
+// not `Send`, so it cannot leave the thread the context was made current on.
+#[derive(Debug)]
+pub struct GlContext {
+    _not_send: PhantomData<*const ()>,
+}
+
+impl GlContext {
+    // makes the window's context current and loads the GL functions through it
+    pub fn load(window: &mut Window) -> Self {
+        window.make_current();
+        gl::load_with(|symbol| window.get_proc_address(symbol) as *const _);
+        set_render_thread();
+        Self { _not_send: PhantomData }
+    }
+
+    /// for contexts created elsewhere. the caller guarantees one is current and `gl` is loaded.
+    /// also records the calling thread as the render thread.
+    ///
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread and the `gl` function pointers must be loaded for it.
+    pub unsafe fn assume_current() -> Self {
+        set_render_thread();
+        Self { _not_send: PhantomData }
+    }
+}
@@ -0,0 +1,254 @@
+use std::mem;
+use std::ptr;
+
+use cgmath::{EuclideanSpace, Matrix4, Point3, Transform, Vector3, vec3};
+use gl::types::*;
+
+use crate::context::check_render_thread;
+use crate::{c_str, Camera, FrameStats, GlContext, LineRenderer, Shader, Viewport};
+
+const DEBUG_DRAW_VERTEX_SHADER: &str = r#"
+#version 330 core
+
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec3 aColor;
+
+out vec3 Color;
+
+uniform mat4 viewProjection;
+
+void main() {
+    gl_Position = viewProjection * vec4(aPos, 1.0);
+    Color = aColor;
+}
+"#;
+
+const DEBUG_DRAW_FRAGMENT_SHADER: &str = r#"
+#version 330 core
+
+in vec3 Color;
+out vec4 FragColor;
+
+void main() {
+    FragColor = vec4(Color, 1.0);
+}
+"#;
+
+const CIRCLE_SEGMENTS: usize = 32;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct LineVertex {
+    position: Point3<f32>,
+    color: Vector3<f32>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Line {
+    from: LineVertex,
+    to: LineVertex,
+    // seconds left, the line is dropped after the frame it reaches zero
+    remaining: f32,
+}
+
+// batches debug lines into one dynamic buffer, drawn after the scene with `render`
+#[derive(Debug)]
+pub struct DebugDraw {
+    shader: Shader,
+    vao: GLuint,
+    vbo: GLuint,
+    // in vertices
+    capacity: usize,
+    lines: Vec<Line>,
+    duration: f32,
+    // hide lines behind the scene geometry
+    pub depth_test: bool,
+}
+
+impl DebugDraw {
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn new() -> Self {
+        let mut vao = 0;
+        let mut vbo = 0;
+        gl::GenVertexArrays(1, &mut vao);
+        gl::GenBuffers(1, &mut vbo);
+        gl::BindVertexArray(vao);
+        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+        let stride = conv!(mem::size_of::<LineVertex>());
+        gl::EnableVertexAttribArray(0);
+        gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, stride, ptr::null());
+        gl::EnableVertexAttribArray(1);
+        gl::VertexAttribPointer(1, 3, gl::FLOAT, gl::FALSE, stride, mem::size_of::<Point3<f32>>() as *const _);
+        gl::BindVertexArray(0);
+
+        Self {
+            shader: Shader::from_str(DEBUG_DRAW_VERTEX_SHADER, DEBUG_DRAW_FRAGMENT_SHADER),
+            vao,
+            vbo,
+            capacity: 0,
+            lines: vec![],
+            duration: 0.0,
+            depth_test: true,
+        }
+    }
+
+    // how many seconds the shapes added from now on stay visible. zero limits them to the next frame.
+    pub fn set_duration(&mut self, seconds: f32) {
+        self.duration = seconds.max(0.0);
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+
+    // takes a `Color` or a linear `Vector3`
+    pub fn line<C: Into<Vector3<f32>>>(&mut self, a: Point3<f32>, b: Point3<f32>, color: C) {
+        let color = color.into();
+        self.lines.push(Line {
+            from: LineVertex { position: a, color },
+            to: LineVertex { position: b, color },
+            remaining: self.duration,
+        });
+    }
+
+    pub fn aabb<C: Into<Vector3<f32>>>(&mut self, min: Point3<f32>, max: Point3<f32>, color: C) {
+        let corner = |i: usize| {
+            Point3::new(
+                if i & 1 == 0 { min.x } else { max.x },
+                if i & 2 == 0 { min.y } else { max.y },
+                if i & 4 == 0 { min.z } else { max.z },
+            )
+        };
+        self.box_edges(&[corner(0), corner(1), corner(2), corner(3), corner(4), corner(5), corner(6), corner(7)], color.into());
+    }
+
+    // three great circles around `center`
+    pub fn sphere<C: Into<Vector3<f32>>>(&mut self, center: Point3<f32>, radius: f32, color: C) {
+        let color = color.into();
+        let point = |axis: usize, angle: f32| {
+            let (sin, cos) = angle.sin_cos();
+            let offset = match axis {
+                0 => vec3(0.0, cos, sin),
+                1 => vec3(cos, 0.0, sin),
+                _ => vec3(cos, sin, 0.0),
+            };
+            center + offset * radius
+        };
+        for axis in 0..3 {
+            for i in 0..CIRCLE_SEGMENTS {
+                let step = 2.0 * std::f32::consts::PI / CIRCLE_SEGMENTS as f32;
+                self.line(point(axis, i as f32 * step), point(axis, (i + 1) as f32 * step), color);
+            }
+        }
+    }
+
+    // unit length x (red), y (green) and z (blue) axes of `transform`
+    pub fn axes(&mut self, transform: &Matrix4<f32>) {
+        let origin = transform.transform_point(Point3::origin());
+        self.line(origin, transform.transform_point(Point3::new(1.0, 0.0, 0.0)), vec3(1.0, 0.0, 0.0));
+        self.line(origin, transform.transform_point(Point3::new(0.0, 1.0, 0.0)), vec3(0.0, 1.0, 0.0));
+        self.line(origin, transform.transform_point(Point3::new(0.0, 0.0, 1.0)), vec3(0.0, 0.0, 1.0));
+    }
+
+    // the view frustum of `camera`. an infinite far plane is drawn at 1000 units.
+    pub fn frustum<C: Camera, K: Into<Vector3<f32>>>(&mut self, camera: &C, color: K) {
+        let corners = camera.frustum_corners(camera.near(), camera.far().min(1000.0));
+        self.box_edges(&corners, color.into());
+    }
+
+    // corners indexed by bits: 1 for x, 2 for y, 4 for z
+    fn box_edges(&mut self, corners: &[Point3<f32>; 8], color: Vector3<f32>) {
+        for i in 0..8 {
+            for &bit in [1, 2, 4].iter() {
+                if i & bit == 0 {
+                    self.line(corners[i], corners[i | bit], color);
+                }
+            }
+        }
+    }
+
+    /// draws everything queued, then drops the lines whose duration ran out
+    ///
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn render(&mut self, view_projection: &Matrix4<f32>, delta_time: f32) {
+        if !self.lines.is_empty() {
+            let mut vertices = Vec::with_capacity(2 * self.lines.len());
+            for line in self.lines.iter() {
+                vertices.push(line.from);
+                vertices.push(line.to);
+            }
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            let size = mem::size_of::<LineVertex>();
+            if vertices.len() > self.capacity {
+                self.capacity = vertices.len().next_power_of_two();
+                gl::BufferData(gl::ARRAY_BUFFER, conv!(self.capacity * size), ptr::null(), gl::STREAM_DRAW);
+            }
+            gl::BufferSubData(gl::ARRAY_BUFFER, 0, conv!(vertices.len() * size), vertices.as_ptr() as *const _);
+            FrameStats::record_buffer_upload(vertices.len() * size);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+
+            let depth_test = gl::IsEnabled(gl::DEPTH_TEST) == gl::TRUE;
+            if self.depth_test {
+                gl::Enable(gl::DEPTH_TEST);
+            } else {
+                gl::Disable(gl::DEPTH_TEST);
+            }
+            self.shader.use_program();
+            self.shader.set_matrix4(c_str("viewProjection\0"), view_projection);
+            gl::BindVertexArray(self.vao);
+            gl::DrawArrays(gl::LINES, 0, conv!(vertices.len()));
+            FrameStats::record_draw(0, 1);
+            gl::BindVertexArray(0);
+            if depth_test {
+                gl::Enable(gl::DEPTH_TEST);
+            } else {
+                gl::Disable(gl::DEPTH_TEST);
+            }
+        }
+
+        self.expire(delta_time);
+    }
+
+    // like `render`, but through `renderer` so the lines are `width` pixels wide and smoothed
+    pub fn render_wide(
+        &mut self,
+        context: &GlContext,
+        renderer: &mut LineRenderer,
+        view_projection: &Matrix4<f32>,
+        viewport: &Viewport,
+        width: f32,
+        delta_time: f32,
+    ) {
+        if !self.lines.is_empty() {
+            let depth_test = renderer.depth_test;
+            renderer.depth_test = self.depth_test;
+            for line in self.lines.iter() {
+                renderer.line(line.from.position, line.to.position, line.from.color.extend(1.0), width);
+            }
+            renderer.render(context, view_projection, viewport);
+            renderer.depth_test = depth_test;
+        }
+        self.expire(delta_time);
+    }
+
+    fn expire(&mut self, delta_time: f32) {
+        self.lines.retain(|line| line.remaining > 0.0);
+        for line in self.lines.iter_mut() {
+            line.remaining -= delta_time;
+        }
+    }
+}
+
+impl Drop for DebugDraw {
+    fn drop(&mut self) {
+        check_render_thread("DebugDraw");
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+            gl::DeleteBuffers(1, &self.vbo);
+        }
+    }
+}
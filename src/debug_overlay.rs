@@ -0,0 +1,266 @@
+use std::mem;
+use std::ptr;
+
+use gl::types::*;
+
+use crate::context::check_render_thread;
+use crate::{c_str, Color, FrameStats, GlContext, Shader, Viewport};
+
+const OVERLAY_VERTEX_SHADER: &str = r#"
+#version 330 core
+
+layout (location = 0) in vec2 aPos;
+layout (location = 1) in vec4 aColor;
+
+out vec4 Color;
+
+// in pixels
+uniform vec2 viewport;
+
+void main() {
+    // pixels from the top left corner
+    gl_Position = vec4(aPos.x / viewport.x * 2.0 - 1.0, 1.0 - aPos.y / viewport.y * 2.0, 0.0, 1.0);
+    Color = aColor;
+}
+"#;
+
+const OVERLAY_FRAGMENT_SHADER: &str = r#"
+#version 330 core
+
+in vec4 Color;
+out vec4 FragColor;
+
+void main() {
+    FragColor = Color;
+}
+"#;
+
+// of the font, in font pixels
+const GLYPH_WIDTH: usize = 3;
+const GLYPH_HEIGHT: usize = 5;
+const ADVANCE: usize = GLYPH_WIDTH + 1;
+const LINE_HEIGHT: usize = GLYPH_HEIGHT + 2;
+
+// rows from the top, the highest of the 3 bits is the left column. lower case letters use the
+// upper case ones and characters without a glyph are drawn as spaces.
+fn glyph(c: char) -> [u8; GLYPH_HEIGHT] {
+    match c.to_ascii_uppercase() {
+        '0' => [0b111, 0b101, 0b101, 0b101, 0b111],
+        '1' => [0b010, 0b110, 0b010, 0b010, 0b111],
+        '2' => [0b111, 0b001, 0b111, 0b100, 0b111],
+        '3' => [0b111, 0b001, 0b111, 0b001, 0b111],
+        '4' => [0b101, 0b101, 0b111, 0b001, 0b001],
+        '5' => [0b111, 0b100, 0b111, 0b001, 0b111],
+        '6' => [0b111, 0b100, 0b111, 0b101, 0b111],
+        '7' => [0b111, 0b001, 0b001, 0b001, 0b001],
+        '8' => [0b111, 0b101, 0b111, 0b101, 0b111],
+        '9' => [0b111, 0b101, 0b111, 0b001, 0b111],
+        'A' => [0b010, 0b101, 0b111, 0b101, 0b101],
+        'B' => [0b110, 0b101, 0b110, 0b101, 0b110],
+        'C' => [0b011, 0b100, 0b100, 0b100, 0b011],
+        'D' => [0b110, 0b101, 0b101, 0b101, 0b110],
+        'E' => [0b111, 0b100, 0b110, 0b100, 0b111],
+        'F' => [0b111, 0b100, 0b110, 0b100, 0b100],
+        'G' => [0b011, 0b100, 0b101, 0b101, 0b011],
+        'H' => [0b101, 0b101, 0b111, 0b101, 0b101],
+        'I' => [0b111, 0b010, 0b010, 0b010, 0b111],
+        'J' => [0b001, 0b001, 0b001, 0b101, 0b010],
+        'K' => [0b101, 0b101, 0b110, 0b101, 0b101],
+        'L' => [0b100, 0b100, 0b100, 0b100, 0b111],
+        'M' => [0b101, 0b111, 0b111, 0b101, 0b101],
+        'N' => [0b110, 0b101, 0b101, 0b101, 0b101],
+        'O' => [0b010, 0b101, 0b101, 0b101, 0b010],
+        'P' => [0b110, 0b101, 0b110, 0b100, 0b100],
+        'Q' => [0b010, 0b101, 0b101, 0b110, 0b011],
+        'R' => [0b110, 0b101, 0b110, 0b101, 0b101],
+        'S' => [0b011, 0b100, 0b010, 0b001, 0b110],
+        'T' => [0b111, 0b010, 0b010, 0b010, 0b010],
+        'U' => [0b101, 0b101, 0b101, 0b101, 0b111],
+        'V' => [0b101, 0b101, 0b101, 0b101, 0b010],
+        'W' => [0b101, 0b101, 0b111, 0b111, 0b101],
+        'X' => [0b101, 0b101, 0b010, 0b101, 0b101],
+        'Y' => [0b101, 0b101, 0b010, 0b010, 0b010],
+        'Z' => [0b111, 0b001, 0b010, 0b100, 0b111],
+        ':' => [0b000, 0b010, 0b000, 0b010, 0b000],
+        '(' => [0b001, 0b010, 0b010, 0b010, 0b001],
+        ')' => [0b100, 0b010, 0b010, 0b010, 0b100],
+        ',' => [0b000, 0b000, 0b000, 0b010, 0b100],
+        '.' => [0b000, 0b000, 0b000, 0b000, 0b010],
+        '-' => [0b000, 0b000, 0b111, 0b000, 0b000],
+        '/' => [0b001, 0b001, 0b010, 0b100, 0b100],
+        '%' => [0b101, 0b001, 0b010, 0b100, 0b101],
+        _ => [0; GLYPH_HEIGHT],
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct OverlayVertex {
+    position: [f32; 2],
+    color: [f32; 4],
+}
+
+// lines of text in the top left corner of the viewport, drawn with a built-in pixel font over a
+// translucent box. queue them with `text` or `frame_stats`, `render` draws and clears.
+#[derive(Debug)]
+pub struct DebugOverlay {
+    shader: Shader,
+    vao: GLuint,
+    vbo: GLuint,
+    // of the buffer, in verticies
+    capacity: usize,
+    lines: Vec<String>,
+    verticies: Vec<OverlayVertex>,
+    // screen pixels per font pixel
+    pub scale: f32,
+    pub color: Color,
+    pub background: Color,
+}
+
+impl DebugOverlay {
+    pub fn new(context: &GlContext) -> Self {
+        let (mut vao, mut vbo) = (0, 0);
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+            gl::GenBuffers(1, &mut vbo);
+            gl::BindVertexArray(vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+            let stride = conv!(mem::size_of::<OverlayVertex>());
+            gl::EnableVertexAttribArray(0);
+            gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, stride, ptr::null());
+            gl::EnableVertexAttribArray(1);
+            gl::VertexAttribPointer(1, 4, gl::FLOAT, gl::FALSE, stride, mem::size_of::<[f32; 2]>() as *const _);
+            gl::BindVertexArray(0);
+        }
+
+        Self {
+            shader: Shader::new(context, OVERLAY_VERTEX_SHADER, OVERLAY_FRAGMENT_SHADER),
+            vao,
+            vbo,
+            capacity: 0,
+            lines: vec![],
+            verticies: vec![],
+            scale: 2.0,
+            color: Color::WHITE,
+            background: Color::new(0.0, 0.0, 0.0, 0.6),
+        }
+    }
+
+    pub fn text(&mut self, line: &str) {
+        self.lines.extend(line.lines().map(str::to_owned));
+    }
+
+    // one line per counter, usually of `FrameStats::end_frame`
+    pub fn frame_stats(&mut self, stats: &FrameStats) {
+        self.text(&format!("draw calls: {}", stats.draw_calls));
+        self.text(&format!("instances: {}", stats.instances));
+        self.text(&format!("triangles: {}", stats.triangles));
+        self.text(&format!("texture binds: {}", stats.texture_binds));
+        self.text(&format!("buffer uploads: {} ({} kb)", stats.buffer_uploads, stats.uploaded_bytes / 1024));
+        let culling = &stats.culling;
+        if culling.tested > 0 {
+            self.text(&format!(
+                "culling: {} tested, {} frustum, {} occlusion, {} visible",
+                culling.tested, culling.frustum_culled, culling.occlusion_culled, culling.visible,
+            ));
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.lines.clear();
+    }
+
+    // draws the queued lines into `viewport` with alpha blending, then clears the queue
+    pub fn render(&mut self, _context: &GlContext, viewport: &Viewport) {
+        check_render_thread("DebugOverlay");
+        if self.is_empty() {
+            return;
+        }
+        self.build_verticies();
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            let bytes = mem::size_of_val(&self.verticies[..]);
+            if self.verticies.len() > self.capacity {
+                self.capacity = self.verticies.len().next_power_of_two();
+                gl::BufferData(gl::ARRAY_BUFFER, conv!(self.capacity * mem::size_of::<OverlayVertex>()), ptr::null(), gl::STREAM_DRAW);
+            }
+            gl::BufferSubData(gl::ARRAY_BUFFER, 0, conv!(bytes), self.verticies.as_ptr() as *const _);
+            FrameStats::record_buffer_upload(bytes);
+
+            let blend = gl::IsEnabled(gl::BLEND) == gl::TRUE;
+            let depth_test = gl::IsEnabled(gl::DEPTH_TEST) == gl::TRUE;
+            let cull_face = gl::IsEnabled(gl::CULL_FACE) == gl::TRUE;
+            let (mut src, mut dst) = (0, 0);
+            gl::GetIntegerv(gl::BLEND_SRC_RGB, &mut src);
+            gl::GetIntegerv(gl::BLEND_DST_RGB, &mut dst);
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            gl::Disable(gl::DEPTH_TEST);
+            gl::Disable(gl::CULL_FACE);
+            viewport.apply();
+            self.shader.use_program();
+            self.shader.set_vec2(c_str("viewport\0"), viewport.width as f32, viewport.height as f32);
+            gl::DrawArrays(gl::TRIANGLES, 0, conv!(self.verticies.len()));
+            FrameStats::record_draw(self.verticies.len() / 3, 1);
+            gl::BindVertexArray(0);
+
+            gl::BlendFunc(conv!(src), conv!(dst));
+            if !blend {
+                gl::Disable(gl::BLEND);
+            }
+            if depth_test {
+                gl::Enable(gl::DEPTH_TEST);
+            }
+            if cull_face {
+                gl::Enable(gl::CULL_FACE);
+            }
+        }
+        self.clear();
+    }
+
+    // two triangles per lit font pixel after one box behind all lines
+    fn build_verticies(&mut self) {
+        let scale = self.scale;
+        let columns = self.lines.iter().map(|line| line.chars().count()).max().unwrap_or(0);
+        let background: [f32; 4] = self.background.into();
+        let color: [f32; 4] = self.color.into();
+        let verticies = &mut self.verticies;
+        verticies.clear();
+        let mut quad = |x: f32, y: f32, width: f32, height: f32, color: [f32; 4]| {
+            let corners = [(x, y), (x + width, y), (x, y + height), (x + width, y), (x + width, y + height), (x, y + height)];
+            verticies.extend(corners.iter().map(|&(x, y)| OverlayVertex { position: [x, y], color }));
+        };
+
+        // a font pixel of padding around the text
+        let width = (columns * ADVANCE + 1) as f32 * scale;
+        let height = (self.lines.len() * LINE_HEIGHT) as f32 * scale;
+        quad(0.0, 0.0, width, height, background);
+        for (row, line) in self.lines.iter().enumerate() {
+            for (column, c) in line.chars().enumerate() {
+                let (left, top) = ((column * ADVANCE + 1) as f32 * scale, (row * LINE_HEIGHT + 1) as f32 * scale);
+                for (y, bits) in glyph(c).iter().enumerate() {
+                    for x in 0..GLYPH_WIDTH {
+                        if bits & (1 << (GLYPH_WIDTH - 1 - x)) != 0 {
+                            quad(left + x as f32 * scale, top + y as f32 * scale, scale, scale, color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Drop for DebugOverlay {
+    fn drop(&mut self) {
+        check_render_thread("DebugOverlay");
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+            gl::DeleteBuffers(1, &self.vbo);
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use glfw::{Action, Key, WindowEvent};
+
+use crate::post::bind_texture;
+use crate::{c_str, Framebuffer, FullscreenQuad, Shader, Viewport, FULLSCREEN_VERTEX_SHADER, STANDARD_VERTEX_SHADER};
+
+const ALBEDO_FRAGMENT_SHADER: &str = r#"
+#version 330 core
+
+struct Material {
+    sampler2D texture_diffuse1;
+};
+
+in vec2 TexCoords;
+out vec4 FragColor;
+
+uniform Material material;
+
+void main() {
+    FragColor = vec4(texture(material.texture_diffuse1, TexCoords).rgb, 1.0);
+}
+"#;
+
+const NORMALS_FRAGMENT_SHADER: &str = r#"
+#version 330 core
+
+in vec3 Normal;
+out vec4 FragColor;
+
+void main() {
+    // world space normals mapped from [-1, 1] to [0, 1]
+    FragColor = vec4(normalize(Normal) * 0.5 + 0.5, 1.0);
+}
+"#;
+
+const DEPTH_FRAGMENT_SHADER: &str = r#"
+#version 330 core
+
+in vec3 FragPos;
+out vec4 FragColor;
+
+uniform mat4 view;
+uniform float depthRange;
+
+void main() {
+    float depth = -(view * vec4(FragPos, 1.0)).z / depthRange;
+    FragColor = vec4(vec3(1.0 - clamp(depth, 0.0, 1.0)), 1.0);
+}
+"#;
+
+const OVERDRAW_FRAGMENT_SHADER: &str = r#"
+#version 330 core
+
+out vec4 FragColor;
+
+void main() {
+    // accumulated with additive blending, one unit per fragment
+    FragColor = vec4(1.0, 0.0, 0.0, 1.0);
+}
+"#;
+
+const HEATMAP_FRAGMENT_SHADER: &str = r#"
+#version 330 core
+
+in vec2 TexCoords;
+out vec4 FragColor;
+
+uniform sampler2D overdraw;
+uniform float maxOverdraw;
+
+void main() {
+    float t = clamp(texture(overdraw, TexCoords).r / maxOverdraw, 0.0, 1.0);
+    // black, blue, green, yellow, red
+    vec3 color = t < 0.25 ? mix(vec3(0.0), vec3(0.0, 0.0, 1.0), t * 4.0)
+        : t < 0.5 ? mix(vec3(0.0, 0.0, 1.0), vec3(0.0, 1.0, 0.0), t * 4.0 - 1.0)
+        : t < 0.75 ? mix(vec3(0.0, 1.0, 0.0), vec3(1.0, 1.0, 0.0), t * 4.0 - 2.0)
+        : mix(vec3(1.0, 1.0, 0.0), vec3(1.0, 0.0, 0.0), t * 4.0 - 3.0);
+    FragColor = vec4(color, 1.0);
+}
+"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DebugView {
+    Normal,
+    Wireframe,
+    // diffuse texture without lighting
+    Albedo,
+    Normals,
+    Depth,
+    // how many fragments were shaded per pixel
+    Overdraw,
+}
+
+impl DebugView {
+    pub const ALL: [DebugView; 6] = [
+        DebugView::Normal,
+        DebugView::Wireframe,
+        DebugView::Albedo,
+        DebugView::Normals,
+        DebugView::Depth,
+        DebugView::Overdraw,
+    ];
+
+    pub fn next(self) -> Self {
+        let index = Self::ALL.iter().position(|&view| view == self).unwrap();
+        Self::ALL[(index + 1) % Self::ALL.len()]
+    }
+}
+
+// switches how the scene is drawn at runtime. the override shaders take the same
+// `model`, `view` and `projection` uniforms and vertex layout as the standard shader.
+#[derive(Debug)]
+pub struct DebugViews {
+    view: DebugView,
+    albedo_shader: Shader,
+    normals_shader: Shader,
+    depth_shader: Shader,
+    overdraw_shader: Shader,
+    heatmap_shader: Shader,
+    quad: FullscreenQuad,
+    overdraw: Option<Framebuffer>,
+    // view space distance shown as black in the depth view
+    pub depth_range: f32,
+    // overdraw count shown as red in the heatmap
+    pub max_overdraw: f32,
+}
+
+impl DebugViews {
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn new() -> Self {
+        Self {
+            view: DebugView::Normal,
+            albedo_shader: Shader::from_str(STANDARD_VERTEX_SHADER, ALBEDO_FRAGMENT_SHADER),
+            normals_shader: Shader::from_str(STANDARD_VERTEX_SHADER, NORMALS_FRAGMENT_SHADER),
+            depth_shader: Shader::from_str(STANDARD_VERTEX_SHADER, DEPTH_FRAGMENT_SHADER),
+            overdraw_shader: Shader::from_str(STANDARD_VERTEX_SHADER, OVERDRAW_FRAGMENT_SHADER),
+            heatmap_shader: Shader::from_str(FULLSCREEN_VERTEX_SHADER, HEATMAP_FRAGMENT_SHADER),
+            quad: FullscreenQuad::new(),
+            overdraw: None,
+            depth_range: 100.0,
+            max_overdraw: 8.0,
+        }
+    }
+
+    pub fn view(&self) -> DebugView {
+        self.view
+    }
+
+    pub fn set_view(&mut self, view: DebugView) {
+        self.view = view;
+    }
+
+    // F1 to F6 select a view, F7 cycles through them
+    pub fn process_event(&mut self, event: &WindowEvent) {
+        if let WindowEvent::Key(key, _, Action::Press, _) = event {
+            self.view = match key {
+                Key::F1 => DebugView::Normal,
+                Key::F2 => DebugView::Wireframe,
+                Key::F3 => DebugView::Albedo,
+                Key::F4 => DebugView::Normals,
+                Key::F5 => DebugView::Depth,
+                Key::F6 => DebugView::Overdraw,
+                Key::F7 => self.view.next(),
+                _ => return,
+            };
+        }
+    }
+
+    /// calls `draw` with the shader to use: `None` means the scene's own shaders.
+    /// an override shader is already in use; set its matrices and draw the meshes with it.
+    ///
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn render<F: FnMut(Option<&Shader>)>(&mut self, mut draw: F) {
+        match self.view {
+            DebugView::Normal => draw(None),
+            DebugView::Wireframe => {
+                gl::PolygonMode(gl::FRONT_AND_BACK, gl::LINE);
+                draw(None);
+                gl::PolygonMode(gl::FRONT_AND_BACK, gl::FILL);
+            }
+            DebugView::Albedo => {
+                self.albedo_shader.use_program();
+                draw(Some(&self.albedo_shader));
+            }
+            DebugView::Normals => {
+                self.normals_shader.use_program();
+                draw(Some(&self.normals_shader));
+            }
+            DebugView::Depth => {
+                self.depth_shader.use_program();
+                self.depth_shader.set_float(c_str("depthRange\0"), self.depth_range);
+                draw(Some(&self.depth_shader));
+            }
+            DebugView::Overdraw => self.render_overdraw(draw),
+        }
+    }
+
+    unsafe fn render_overdraw<F: FnMut(Option<&Shader>)>(&mut self, mut draw: F) {
+        let mut output = 0;
+        gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut output);
+        let viewport = Viewport::current();
+        if self.overdraw.as_ref().map(|o| (o.width(), o.height())) != Some((viewport.width, viewport.height)) {
+            self.overdraw = Some(Framebuffer::allocate(viewport.width, viewport.height));
+        }
+        let overdraw = self.overdraw.as_ref().unwrap();
+
+        overdraw.bind_raw();
+        let mut clear_color = [0.0; 4];
+        gl::GetFloatv(gl::COLOR_CLEAR_VALUE, clear_color.as_mut_ptr());
+        gl::ClearColor(0.0, 0.0, 0.0, 0.0);
+        gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        let [r, g, b, a] = clear_color;
+        gl::ClearColor(r, g, b, a);
+        let depth_test = gl::IsEnabled(gl::DEPTH_TEST) == gl::TRUE;
+        let blend = gl::IsEnabled(gl::BLEND) == gl::TRUE;
+        let (mut src, mut dst) = (0, 0);
+        gl::GetIntegerv(gl::BLEND_SRC_RGB, &mut src);
+        gl::GetIntegerv(gl::BLEND_DST_RGB, &mut dst);
+        gl::Disable(gl::DEPTH_TEST);
+        gl::Enable(gl::BLEND);
+        gl::BlendFunc(gl::ONE, gl::ONE);
+        self.overdraw_shader.use_program();
+        draw(Some(&self.overdraw_shader));
+        gl::BlendFunc(conv!(src), conv!(dst));
+        if !blend {
+            gl::Disable(gl::BLEND);
+        }
+        if depth_test {
+            gl::Enable(gl::DEPTH_TEST);
+        }
+
+        gl::BindFramebuffer(gl::FRAMEBUFFER, conv!(output));
+        gl::Viewport(viewport.x, viewport.y, viewport.width, viewport.height);
+        self.heatmap_shader.use_program();
+        bind_texture(&self.heatmap_shader, c_str("overdraw\0"), 0, overdraw.color_texture());
+        self.heatmap_shader.set_float(c_str("maxOverdraw\0"), self.max_overdraw);
+        self.quad.draw();
+    }
+}
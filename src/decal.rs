@@ -0,0 +1,237 @@
+use std::mem;
+use std::ptr;
+
+use cgmath::{Matrix4, MetricSpace, Point3, SquareMatrix, Transform, Vector4, vec4};
+use gl::types::*;
+
+use crate::context::check_render_thread;
+use crate::post::bind_texture;
+use crate::{c_str, Framebuffer, FrameStats, Shader};
+
+const DECAL_VERTEX_SHADER: &str = r#"
+#version 330 core
+
+layout (location = 0) in vec3 aPos;
+
+uniform mat4 model;
+uniform mat4 viewProjection;
+
+void main() {
+    gl_Position = viewProjection * model * vec4(aPos, 1.0);
+}
+"#;
+
+const DECAL_FRAGMENT_SHADER: &str = r#"
+#version 330 core
+
+out vec4 FragColor;
+
+uniform sampler2D screenDepth;
+uniform sampler2D decal;
+uniform vec2 screenSize;
+uniform mat4 inverseViewProjection;
+uniform mat4 model;
+uniform mat4 inverseModel;
+uniform vec4 tint;
+uniform float normalFade;
+
+void main() {
+    vec2 uv = gl_FragCoord.xy / screenSize;
+    float depth = texture(screenDepth, uv).r;
+    vec4 world = inverseViewProjection * vec4(uv * 2.0 - 1.0, depth * 2.0 - 1.0, 1.0);
+    world /= world.w;
+
+    // the decal box spans [-0.5, 0.5] and projects along its local y axis
+    vec3 local = (inverseModel * world).xyz;
+    if (any(greaterThan(abs(local), vec3(0.5)))) {
+        discard;
+    }
+
+    // reconstruct the surface normal from the depth buffer
+    vec3 normal = normalize(cross(dFdx(world.xyz), dFdy(world.xyz)));
+    vec3 axis = normalize(mat3(model)[1]);
+    float facing = abs(dot(normal, axis));
+    float fade = smoothstep(normalFade, 1.0, facing) * (1.0 - smoothstep(0.4, 0.5, abs(local.y)));
+
+    vec4 color = texture(decal, local.xz + 0.5) * tint;
+    FragColor = vec4(color.rgb, color.a * fade);
+}
+"#;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Decal {
+    // maps the unit box [-0.5, 0.5]^3 into the world; the texture is projected along the local y axis
+    pub transform: Matrix4<f32>,
+    pub texture: GLuint,
+    pub tint: Vector4<f32>,
+    // cosine below which surfaces facing away from the projection axis fade out
+    pub normal_fade: f32,
+    // higher priorities are drawn on top
+    pub priority: i32,
+}
+
+impl Decal {
+    pub fn new(transform: Matrix4<f32>, texture: GLuint) -> Self {
+        Self {
+            transform,
+            texture,
+            tint: vec4(1.0, 1.0, 1.0, 1.0),
+            normal_fade: 0.3,
+            priority: 0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct DecalRenderer {
+    shader: Shader,
+    vao: GLuint,
+    vbo: GLuint,
+    decals: Vec<Decal>,
+}
+
+#[rustfmt::skip]
+const CUBE: [f32; 108] = [
+    -0.5, -0.5, -0.5,  0.5,  0.5, -0.5,  0.5, -0.5, -0.5,
+     0.5,  0.5, -0.5, -0.5, -0.5, -0.5, -0.5,  0.5, -0.5,
+    -0.5, -0.5,  0.5,  0.5, -0.5,  0.5,  0.5,  0.5,  0.5,
+     0.5,  0.5,  0.5, -0.5,  0.5,  0.5, -0.5, -0.5,  0.5,
+    -0.5,  0.5,  0.5, -0.5,  0.5, -0.5, -0.5, -0.5, -0.5,
+    -0.5, -0.5, -0.5, -0.5, -0.5,  0.5, -0.5,  0.5,  0.5,
+     0.5,  0.5,  0.5,  0.5, -0.5, -0.5,  0.5,  0.5, -0.5,
+     0.5, -0.5, -0.5,  0.5,  0.5,  0.5,  0.5, -0.5,  0.5,
+    -0.5, -0.5, -0.5,  0.5, -0.5, -0.5,  0.5, -0.5,  0.5,
+     0.5, -0.5,  0.5, -0.5, -0.5,  0.5, -0.5, -0.5, -0.5,
+    -0.5,  0.5, -0.5,  0.5,  0.5,  0.5,  0.5,  0.5, -0.5,
+     0.5,  0.5,  0.5, -0.5,  0.5, -0.5, -0.5,  0.5,  0.5,
+];
+
+impl DecalRenderer {
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn new() -> Self {
+        let mut vao = 0;
+        let mut vbo = 0;
+        gl::GenVertexArrays(1, &mut vao);
+        gl::GenBuffers(1, &mut vbo);
+        gl::BindVertexArray(vao);
+        gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+        gl::BufferData(gl::ARRAY_BUFFER, conv!(mem::size_of_val(&CUBE)), CUBE.as_ptr() as *const _, gl::STATIC_DRAW);
+        gl::EnableVertexAttribArray(0);
+        gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, conv!(3 * mem::size_of::<f32>()), ptr::null());
+        gl::BindVertexArray(0);
+
+        Self {
+            shader: Shader::from_str(DECAL_VERTEX_SHADER, DECAL_FRAGMENT_SHADER),
+            vao,
+            vbo,
+            decals: vec![],
+        }
+    }
+
+    pub fn add(&mut self, decal: Decal) {
+        self.decals.push(decal);
+    }
+
+    pub fn clear(&mut self) {
+        self.decals.clear();
+    }
+
+    pub fn decals(&self) -> &[Decal] {
+        &self.decals
+    }
+
+    pub fn decals_mut(&mut self) -> &mut Vec<Decal> {
+        &mut self.decals
+    }
+
+    /// blends the decals into the color of `scene` after the opaque geometry. depth is only read.
+    ///
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn render(&mut self, scene: &Framebuffer, view: &Matrix4<f32>, projection: &Matrix4<f32>, camera: Point3<f32>) {
+        let view_projection = projection * view;
+        let inverse_view_projection = view_projection.invert().unwrap_or_else(Matrix4::identity);
+
+        // by priority, then back to front so overlapping decals blend correctly
+        let distance = |decal: &Decal| decal.transform.transform_point(Point3::new(0.0, 0.0, 0.0)).distance2(camera);
+        self.decals.sort_by(|a, b| {
+            a.priority
+                .cmp(&b.priority)
+                .then(distance(b).partial_cmp(&distance(a)).unwrap_or(std::cmp::Ordering::Equal))
+        });
+
+        scene.bind_raw();
+        // restored afterwards, the decals go between passes of the caller
+        let (depth_test, cull_face, blend) = (
+            gl::IsEnabled(gl::DEPTH_TEST) == gl::TRUE,
+            gl::IsEnabled(gl::CULL_FACE) == gl::TRUE,
+            gl::IsEnabled(gl::BLEND) == gl::TRUE,
+        );
+        let mut depth_mask = gl::TRUE;
+        gl::GetBooleanv(gl::DEPTH_WRITEMASK, &mut depth_mask);
+        let mut cull_mode = 0;
+        gl::GetIntegerv(gl::CULL_FACE_MODE, &mut cull_mode);
+        let mut blend_func = [0; 4];
+        for (value, &name) in blend_func.iter_mut().zip(&[gl::BLEND_SRC_RGB, gl::BLEND_DST_RGB, gl::BLEND_SRC_ALPHA, gl::BLEND_DST_ALPHA]) {
+            gl::GetIntegerv(name, value);
+        }
+
+        gl::DepthMask(gl::FALSE);
+        gl::Disable(gl::DEPTH_TEST);
+        // back faces stay visible while the camera is inside a box
+        gl::Enable(gl::CULL_FACE);
+        gl::CullFace(gl::FRONT);
+        gl::Enable(gl::BLEND);
+        gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+
+        self.shader.use_program();
+        bind_texture(&self.shader, c_str("screenDepth\0"), 0, scene.depth_texture());
+        self.shader.set_vec2(c_str("screenSize\0"), scene.width() as f32, scene.height() as f32);
+        self.shader.set_matrix4(c_str("viewProjection\0"), &view_projection);
+        self.shader.set_matrix4(c_str("inverseViewProjection\0"), &inverse_view_projection);
+
+        gl::BindVertexArray(self.vao);
+        for decal in self.decals.iter() {
+            let inverse_model = match decal.transform.invert() {
+                Some(inverse) => inverse,
+                None => continue,
+            };
+            bind_texture(&self.shader, c_str("decal\0"), 1, decal.texture);
+            self.shader.set_matrix4(c_str("model\0"), &decal.transform);
+            self.shader.set_matrix4(c_str("inverseModel\0"), &inverse_model);
+            self.shader.set_vec4(c_str("tint\0"), decal.tint.x, decal.tint.y, decal.tint.z, decal.tint.w);
+            self.shader.set_float(c_str("normalFade\0"), decal.normal_fade);
+            gl::DrawArrays(gl::TRIANGLES, 0, 36);
+            FrameStats::record_draw(12, 1);
+        }
+        gl::BindVertexArray(0);
+        gl::ActiveTexture(gl::TEXTURE0);
+
+        let [src_rgb, dst_rgb, src_alpha, dst_alpha] = blend_func;
+        gl::BlendFuncSeparate(conv!(src_rgb), conv!(dst_rgb), conv!(src_alpha), conv!(dst_alpha));
+        if !blend {
+            gl::Disable(gl::BLEND);
+        }
+        gl::CullFace(conv!(cull_mode));
+        if !cull_face {
+            gl::Disable(gl::CULL_FACE);
+        }
+        if depth_test {
+            gl::Enable(gl::DEPTH_TEST);
+        }
+        gl::DepthMask(depth_mask);
+    }
+}
+
+impl Drop for DecalRenderer {
+    fn drop(&mut self) {
+        check_render_thread("DecalRenderer");
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+            gl::DeleteBuffers(1, &self.vbo);
+        }
+    }
+}
@@ -0,0 +1,193 @@
+use rand::{Error, RngCore, SeedableRng};
+
+use crate::{Schedule, Time};
+
+const MULTIPLIER: u64 = 6_364_136_223_846_793_005;
+
+// the random numbers of the simulation, PCG32 so the sequence of a seed is the same on every
+// platform and version of rand. use it through `rand::Rng`, and `fork` a generator per system
+// so the order systems draw in does not matter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimRng {
+    state: u64,
+    // odd, selects one of 2^63 sequences
+    increment: u64,
+}
+
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        Self::with_stream(seed, 0)
+    }
+
+    // generators of the same seed but different streams do not overlap
+    pub fn with_stream(seed: u64, stream: u64) -> Self {
+        let mut rng = Self {
+            state: 0,
+            increment: (stream << 1) | 1,
+        };
+        rng.step();
+        rng.state = rng.state.wrapping_add(seed);
+        rng.step();
+        rng
+    }
+
+    // a generator for `stream` seeded from this one
+    pub fn fork(&mut self, stream: u64) -> Self {
+        Self::with_stream(self.next_u64(), stream)
+    }
+
+    // the state and stream, for saving and restoring with `from_state`
+    pub fn state(&self) -> (u64, u64) {
+        (self.state, self.increment)
+    }
+
+    pub fn from_state(state: u64, increment: u64) -> Self {
+        Self {
+            state,
+            increment: increment | 1,
+        }
+    }
+
+    fn step(&mut self) {
+        self.state = self.state.wrapping_mul(MULTIPLIER).wrapping_add(self.increment);
+    }
+}
+
+impl RngCore for SimRng {
+    fn next_u32(&mut self) -> u32 {
+        let old = self.state;
+        self.step();
+        let shifted = (((old >> 18) ^ old) >> 27) as u32;
+        shifted.rotate_right((old >> 59) as u32)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        let low = self.next_u32() as u64;
+        (self.next_u32() as u64) << 32 | low
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        for chunk in dest.chunks_mut(4) {
+            let bytes = self.next_u32().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+impl SeedableRng for SimRng {
+    type Seed = [u8; 8];
+
+    fn from_seed(seed: Self::Seed) -> Self {
+        Self::new(u64::from_le_bytes(seed))
+    }
+
+    fn seed_from_u64(seed: u64) -> Self {
+        Self::new(seed)
+    }
+}
+
+// what makes runs repeat exactly: a seed and a fixed step. the time it creates advances by the
+// frame index instead of the clock, the schedule runs one fixed step per frame in the order the
+// systems were added, and the rng draws the same numbers for the seed. keep wall clock reads
+// (`real_delta`, `Instant`) and unordered collections such as `HashMap` out of the systems.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Determinism {
+    pub seed: u64,
+    // seconds a frame advances the simulation by
+    pub step: f32,
+}
+
+impl Default for Determinism {
+    fn default() -> Self {
+        Self { seed: 0, step: 1.0 / 60.0 }
+    }
+}
+
+impl Determinism {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, ..Self::default() }
+    }
+
+    pub fn with_step(self, step: f32) -> Self {
+        Self { step, ..self }
+    }
+
+    pub fn time(&self) -> Time {
+        Time::deterministic(self.step)
+    }
+
+    pub fn rng(&self) -> SimRng {
+        SimRng::new(self.seed)
+    }
+
+    pub fn schedule<S>(&self) -> Schedule<S> {
+        let mut schedule = Schedule::new();
+        schedule.fixed_step = self.step;
+        schedule
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::Rng;
+
+    use super::*;
+
+    #[test]
+    fn matches_the_pcg32_reference_sequence() {
+        // the first outputs of the reference implementation's demo for seed 42, stream 54
+        let mut rng = SimRng::with_stream(42, 54);
+        let expected = [0xa15c02b7, 0x7b47f409, 0xba1d3330, 0x83d2f293, 0xbfa4784b, 0xcbed606e];
+        for &value in expected.iter() {
+            assert_eq!(rng.next_u32(), value);
+        }
+    }
+
+    #[test]
+    fn seeds_and_streams_give_different_sequences() {
+        let draw = |mut rng: SimRng| (0..8).map(|_| rng.next_u32()).collect::<Vec<_>>();
+        assert_eq!(draw(SimRng::new(7)), draw(SimRng::new(7)));
+        assert_eq!(draw(SimRng::new(7)), draw(SimRng::seed_from_u64(7)));
+        assert_eq!(draw(SimRng::new(7)), draw(SimRng::from_seed(7u64.to_le_bytes())));
+        assert_ne!(draw(SimRng::new(7)), draw(SimRng::new(8)));
+        assert_ne!(draw(SimRng::with_stream(7, 0)), draw(SimRng::with_stream(7, 1)));
+    }
+
+    #[test]
+    fn state_round_trips() {
+        let mut rng = SimRng::new(3);
+        rng.gen::<f32>();
+        let (state, increment) = rng.state();
+        let mut restored = SimRng::from_state(state, increment);
+        for _ in 0..8 {
+            assert_eq!(rng.next_u64(), restored.next_u64());
+        }
+    }
+
+    #[test]
+    fn forks_depend_only_on_the_parent_and_stream() {
+        let mut first = SimRng::new(1);
+        let mut second = SimRng::new(1);
+        assert_eq!(first.fork(2), second.fork(2));
+        assert_ne!(first.fork(2), first.fork(2));
+
+        let mut bytes = [0; 7];
+        SimRng::new(5).fill_bytes(&mut bytes);
+        let mut rng = SimRng::new(5);
+        let (low, high) = (rng.next_u32().to_le_bytes(), rng.next_u32().to_le_bytes());
+        assert_eq!(bytes[..4], low);
+        assert_eq!(bytes[4..], high[..3]);
+    }
+
+    #[test]
+    fn determinism_sets_up_the_step() {
+        let determinism = Determinism::new(9).with_step(0.01);
+        assert_eq!(determinism.rng(), SimRng::new(9));
+        assert_eq!(determinism.schedule::<()>().fixed_step, 0.01);
+    }
+}
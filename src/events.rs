@@ -0,0 +1,185 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::mpsc::Receiver;
+
+use cgmath::{Point3, Vector3};
+use glfw::WindowEvent;
+
+// published when a file an asset was loaded from changed and the asset was loaded again
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AssetReloaded {
+    pub path: PathBuf,
+}
+
+// two bodies touched. the ids are whatever the game identifies its bodies with.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Collision {
+    pub first: usize,
+    pub second: usize,
+    pub point: Point3<f32>,
+    // pointing from `first` to `second`
+    pub normal: Vector3<f32>,
+}
+
+struct Queue<T> {
+    // published since the last flush
+    pending: Vec<T>,
+    // readable until the next flush
+    current: Vec<T>,
+}
+
+trait AnyQueue {
+    fn flush(&mut self);
+    fn clear(&mut self);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: 'static> AnyQueue for Queue<T> {
+    fn flush(&mut self) {
+        self.current.clear();
+        std::mem::swap(&mut self.current, &mut self.pending);
+    }
+
+    fn clear(&mut self) {
+        self.pending.clear();
+        self.current.clear();
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// queues of events by type, so systems talk without knowing each other. events published
+// now are read after the next `flush`, which the frame loop calls at fixed points, e.g.
+// before each `Stage`; all readers in between see the same events, whatever their order.
+#[derive(Default)]
+pub struct EventBus {
+    queues: HashMap<TypeId, Box<dyn AnyQueue>>,
+}
+
+impl std::fmt::Debug for EventBus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EventBus").field("types", &self.queues.len()).finish()
+    }
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn queue<T: 'static>(&mut self) -> &mut Queue<T> {
+        self.queues
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| {
+                Box::new(Queue::<T> {
+                    pending: vec![],
+                    current: vec![],
+                })
+            })
+            .as_any_mut()
+            .downcast_mut()
+            .unwrap()
+    }
+
+    pub fn publish<T: 'static>(&mut self, event: T) {
+        self.queue().pending.push(event);
+    }
+
+    pub fn publish_all<T: 'static, I: IntoIterator<Item = T>>(&mut self, events: I) {
+        self.queue().pending.extend(events);
+    }
+
+    // the events of type `T` published before the last flush
+    pub fn read<T: 'static>(&self) -> &[T] {
+        match self.queues.get(&TypeId::of::<T>()) {
+            Some(queue) => &queue.as_any().downcast_ref::<Queue<T>>().unwrap().current,
+            None => &[],
+        }
+    }
+
+    // takes the readable events of type `T`, so no later reader sees them
+    pub fn take<T: 'static>(&mut self) -> Vec<T> {
+        std::mem::take(&mut self.queue().current)
+    }
+
+    // makes the events published since the last flush readable, dropping the ones read so far
+    pub fn flush(&mut self) {
+        for queue in self.queues.values_mut() {
+            queue.flush();
+        }
+    }
+
+    // drops every event, e.g. when a scene is unloaded
+    pub fn clear(&mut self) {
+        for queue in self.queues.values_mut() {
+            queue.clear();
+        }
+    }
+
+    // publishes everything glfw received as `WindowEvent`s, in place of `glfw::flush_messages`
+    pub fn publish_window_events(&mut self, events: &Receiver<(f64, WindowEvent)>) {
+        self.publish_all(glfw::flush_messages(events).map(|(_, event)| event));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_readable_after_a_flush() {
+        let mut bus = EventBus::new();
+        bus.publish(1u32);
+        bus.publish_all(vec![2u32, 3]);
+        assert!(bus.read::<u32>().is_empty());
+
+        bus.flush();
+        assert_eq!(bus.read::<u32>(), &[1, 2, 3]);
+        // every reader sees them until the next flush
+        assert_eq!(bus.read::<u32>(), &[1, 2, 3]);
+        bus.publish(4u32);
+        assert_eq!(bus.read::<u32>(), &[1, 2, 3]);
+
+        bus.flush();
+        assert_eq!(bus.read::<u32>(), &[4]);
+        bus.flush();
+        assert!(bus.read::<u32>().is_empty());
+    }
+
+    #[test]
+    fn types_have_their_own_queues() {
+        let mut bus = EventBus::new();
+        assert!(bus.read::<AssetReloaded>().is_empty());
+        bus.publish(AssetReloaded { path: "a.png".into() });
+        bus.publish(7i64);
+        bus.flush();
+        assert_eq!(bus.read::<AssetReloaded>(), &[AssetReloaded { path: "a.png".into() }]);
+        assert_eq!(bus.read::<i64>(), &[7]);
+        assert!(bus.read::<i32>().is_empty());
+    }
+
+    #[test]
+    fn take_and_clear() {
+        let mut bus = EventBus::new();
+        bus.publish_all(vec!['a', 'b']);
+        bus.flush();
+        assert_eq!(bus.take::<char>(), vec!['a', 'b']);
+        assert!(bus.read::<char>().is_empty());
+
+        bus.publish('c');
+        bus.flush();
+        bus.publish('d');
+        bus.clear();
+        assert!(bus.read::<char>().is_empty());
+        bus.flush();
+        assert!(bus.read::<char>().is_empty());
+    }
+}
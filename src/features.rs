@@ -0,0 +1,368 @@
+use std::ffi::CStr;
+use std::os::raw::c_void;
+use std::ptr;
+
+use gl::types::*;
+
+use crate::{FrameStats, GpuInfo};
+
+// not part of the 4.5 core bindings
+const COMPRESSED_RGB_S3TC_DXT1: GLenum = 0x83F0;
+const COMPRESSED_RGBA_S3TC_DXT1: GLenum = 0x83F1;
+const COMPRESSED_RGBA_S3TC_DXT3: GLenum = 0x83F2;
+const COMPRESSED_RGBA_S3TC_DXT5: GLenum = 0x83F3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CompressedFormat {
+    S3tc,
+    Rgtc,
+    Bptc,
+    Astc,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Features {
+    pub direct_state_access: bool,
+    pub buffer_storage: bool,
+    pub debug_output: bool,
+    pub transform_feedback_objects: bool,
+    // compute shaders together with the shader storage buffers they write to
+    pub compute_shader: bool,
+    // several indirect draws in one call, otherwise they are issued one by one
+    pub multi_draw_indirect: bool,
+    pub s3tc: bool,
+    pub rgtc: bool,
+    pub bptc: bool,
+    pub astc: bool,
+}
+
+impl Features {
+    pub fn detect(info: &GpuInfo) -> Self {
+        Self {
+            direct_state_access: (info.has_version(4, 5) || info.has_extension("GL_ARB_direct_state_access"))
+                && gl::CreateBuffers::is_loaded()
+                && gl::NamedBufferData::is_loaded(),
+            buffer_storage: (info.has_version(4, 4) || info.has_extension("GL_ARB_buffer_storage"))
+                && gl::BufferStorage::is_loaded(),
+            debug_output: (info.has_version(4, 3) || info.has_extension("GL_KHR_debug") || info.has_extension("GL_ARB_debug_output"))
+                && gl::DebugMessageCallback::is_loaded(),
+            transform_feedback_objects: (info.has_version(4, 0) || info.has_extension("GL_ARB_transform_feedback2"))
+                && gl::GenTransformFeedbacks::is_loaded(),
+            compute_shader: (info.has_version(4, 3)
+                || (info.has_extension("GL_ARB_compute_shader") && info.has_extension("GL_ARB_shader_storage_buffer_object")))
+                && gl::DispatchCompute::is_loaded(),
+            multi_draw_indirect: (info.has_version(4, 3) || info.has_extension("GL_ARB_multi_draw_indirect"))
+                && gl::MultiDrawElementsIndirect::is_loaded(),
+            s3tc: info.has_extension("GL_EXT_texture_compression_s3tc"),
+            // core since 3.0
+            rgtc: true,
+            bptc: info.has_version(4, 2) || info.has_extension("GL_ARB_texture_compression_bptc"),
+            astc: info.has_extension("GL_KHR_texture_compression_astc_ldr"),
+        }
+    }
+
+    pub fn supports_compressed_format(&self, format: CompressedFormat) -> bool {
+        match format {
+            CompressedFormat::S3tc => self.s3tc,
+            CompressedFormat::Rgtc => self.rgtc,
+            CompressedFormat::Bptc => self.bptc,
+            CompressedFormat::Astc => self.astc,
+        }
+    }
+
+    // maps a compressed internal format to its family, None for uncompressed ones
+    pub fn compressed_format_of(internal_format: GLenum) -> Option<CompressedFormat> {
+        match internal_format {
+            COMPRESSED_RGB_S3TC_DXT1
+            | COMPRESSED_RGBA_S3TC_DXT1
+            | COMPRESSED_RGBA_S3TC_DXT3
+            | COMPRESSED_RGBA_S3TC_DXT5 => Some(CompressedFormat::S3tc),
+            gl::COMPRESSED_RED_RGTC1
+            | gl::COMPRESSED_SIGNED_RED_RGTC1
+            | gl::COMPRESSED_RG_RGTC2
+            | gl::COMPRESSED_SIGNED_RG_RGTC2 => Some(CompressedFormat::Rgtc),
+            gl::COMPRESSED_RGBA_BPTC_UNORM
+            | gl::COMPRESSED_SRGB_ALPHA_BPTC_UNORM
+            | gl::COMPRESSED_RGB_BPTC_SIGNED_FLOAT
+            | gl::COMPRESSED_RGB_BPTC_UNSIGNED_FLOAT => Some(CompressedFormat::Bptc),
+            0x93B0..=0x93BD | 0x93D0..=0x93DD => Some(CompressedFormat::Astc),
+            _ => None,
+        }
+    }
+}
+
+// decodes S3TC data to tightly packed RGBA8 rows for drivers without the extension. the other
+// families have no CPU decoder: RGTC is core anyway and BPTC and ASTC need an uncompressed copy.
+pub(crate) fn decompress_s3tc(internal_format: GLenum, width: u32, height: u32, data: &[u8]) -> Option<Vec<u8>> {
+    let block_size = match internal_format {
+        COMPRESSED_RGB_S3TC_DXT1 | COMPRESSED_RGBA_S3TC_DXT1 => 8,
+        COMPRESSED_RGBA_S3TC_DXT3 | COMPRESSED_RGBA_S3TC_DXT5 => 16,
+        _ => return None,
+    };
+    let (width, height) = (width as usize, height as usize);
+    let blocks_x = width.div_ceil(4);
+    let blocks_y = height.div_ceil(4);
+    if data.len() < blocks_x * blocks_y * block_size {
+        return None;
+    }
+
+    let mut pixels = vec![0; width * height * 4];
+    for (i, block) in data.chunks_exact(block_size).take(blocks_x * blocks_y).enumerate() {
+        let (colors, alpha) = match internal_format {
+            COMPRESSED_RGB_S3TC_DXT1 => (decode_color_block(block, true), [255; 16]),
+            COMPRESSED_RGBA_S3TC_DXT1 => {
+                let colors = decode_color_block(block, true);
+                let mut alpha = [0; 16];
+                for (a, color) in alpha.iter_mut().zip(colors.iter()) {
+                    *a = color[3];
+                }
+                (colors, alpha)
+            }
+            COMPRESSED_RGBA_S3TC_DXT3 => {
+                let mut alpha = [0; 16];
+                for (j, a) in alpha.iter_mut().enumerate() {
+                    let nibble = (block[j / 2] >> (4 * (j % 2))) & 0xf;
+                    *a = nibble * 17;
+                }
+                (decode_color_block(&block[8..], false), alpha)
+            }
+            _ => (decode_color_block(&block[8..], false), decode_alpha_block(block)),
+        };
+
+        let (bx, by) = (i % blocks_x * 4, i / blocks_x * 4);
+        for j in 0..16 {
+            let (x, y) = (bx + j % 4, by + j / 4);
+            // blocks on the right and bottom edges hang over small textures
+            if x < width && y < height {
+                let offset = (y * width + x) * 4;
+                pixels[offset..offset + 3].copy_from_slice(&colors[j][..3]);
+                pixels[offset + 3] = alpha[j];
+            }
+        }
+    }
+    Some(pixels)
+}
+
+// two RGB565 endpoints and 2 bit indices. only DXT1 stores the three color mode with punch-through alpha.
+fn decode_color_block(block: &[u8], dxt1: bool) -> [[u8; 4]; 16] {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let expand = |c: u16| {
+        let (r, g, b) = (u32::from(c >> 11), u32::from((c >> 5) & 0x3f), u32::from(c & 0x1f));
+        [(r * 255 + 15) / 31, (g * 255 + 31) / 63, (b * 255 + 15) / 31]
+    };
+    let (e0, e1) = (expand(c0), expand(c1));
+    // weighted average of the endpoints
+    let mix = |w0: u32, w1: u32| -> [u8; 4] {
+        let channel = |k: usize| ((e0[k] * w0 + e1[k] * w1) / (w0 + w1)) as u8;
+        [channel(0), channel(1), channel(2), 255]
+    };
+    let palette = if c0 > c1 || !dxt1 {
+        [mix(1, 0), mix(0, 1), mix(2, 1), mix(1, 2)]
+    } else {
+        [mix(1, 0), mix(0, 1), mix(1, 1), [0, 0, 0, 0]]
+    };
+
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    let mut colors = [[0; 4]; 16];
+    for (j, color) in colors.iter_mut().enumerate() {
+        *color = palette[(indices >> (2 * j)) as usize & 3];
+    }
+    colors
+}
+
+// two 8 bit endpoints and 3 bit indices, the alpha half of a DXT5 block
+fn decode_alpha_block(block: &[u8]) -> [u8; 16] {
+    let (a0, a1) = (u32::from(block[0]), u32::from(block[1]));
+    let mut palette = [a0, a1, 0, 0, 0, 0, 0, 255];
+    if a0 > a1 {
+        for (k, a) in palette.iter_mut().enumerate().skip(2) {
+            let k = k as u32;
+            *a = ((8 - k) * a0 + (k - 1) * a1) / 7;
+        }
+    } else {
+        for (k, a) in palette.iter_mut().enumerate().take(6).skip(2) {
+            let k = k as u32;
+            *a = ((6 - k) * a0 + (k - 1) * a1) / 5;
+        }
+    }
+
+    let mut bits = [0; 8];
+    bits[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(bits);
+    let mut alpha = [0; 16];
+    for (j, a) in alpha.iter_mut().enumerate() {
+        *a = palette[(indices >> (3 * j)) as usize & 7] as u8;
+    }
+    alpha
+}
+
+/// creates a buffer filled with `data`. the buffer is left bound to `target`.
+///
+/// # Safety
+///
+/// a GL context must be current on the calling thread. `data` must be null or point to at least `size` readable bytes.
+pub unsafe fn create_buffer(target: GLenum, size: usize, data: *const c_void, usage: GLenum) -> GLuint {
+    let mut buffer = 0;
+    if !data.is_null() {
+        FrameStats::record_buffer_upload(size);
+    }
+    if GpuInfo::current().features.direct_state_access {
+        gl::CreateBuffers(1, &mut buffer);
+        gl::NamedBufferData(buffer, conv!(size), data, usage);
+        gl::BindBuffer(target, buffer);
+    } else {
+        gl::GenBuffers(1, &mut buffer);
+        gl::BindBuffer(target, buffer);
+        gl::BufferData(target, conv!(size), data, usage);
+    }
+    buffer
+}
+
+/// allocates the bound buffer's store, immutably when the driver allows it.
+/// `usage` is only consulted on the BufferData fallback.
+///
+/// # Safety
+///
+/// a GL context must be current on the calling thread with a buffer bound to `target`. `data` must be null or point to at least `size` readable bytes.
+pub unsafe fn buffer_storage(target: GLenum, size: usize, data: *const c_void, flags: GLbitfield, usage: GLenum) {
+    if !data.is_null() {
+        FrameStats::record_buffer_upload(size);
+    }
+    if GpuInfo::current().features.buffer_storage {
+        gl::BufferStorage(target, conv!(size), data, flags);
+    } else {
+        gl::BufferData(target, conv!(size), data, usage);
+    }
+}
+
+extern "system" fn debug_callback(
+    _source: GLenum,
+    type_: GLenum,
+    _id: GLuint,
+    severity: GLenum,
+    _length: GLsizei,
+    message: *const GLchar,
+    _user_param: *mut c_void,
+) {
+    let message = unsafe { CStr::from_ptr(message) }.to_string_lossy();
+    match (type_, severity) {
+        (gl::DEBUG_TYPE_ERROR, _) | (_, gl::DEBUG_SEVERITY_HIGH) => log::error!("GL: {}", message),
+        (_, gl::DEBUG_SEVERITY_MEDIUM) | (_, gl::DEBUG_SEVERITY_LOW) => log::warn!("GL: {}", message),
+        _ => log::debug!("GL: {}", message),
+    }
+}
+
+/// routes driver messages to the `log` crate. returns false when debug output is unavailable.
+///
+/// # Safety
+///
+/// a GL context must be current on the calling thread.
+pub unsafe fn enable_debug_output() -> bool {
+    if !GpuInfo::current().features.debug_output {
+        log::info!("debug output is not supported by this context");
+        return false;
+    }
+
+    gl::Enable(gl::DEBUG_OUTPUT);
+    gl::Enable(gl::DEBUG_OUTPUT_SYNCHRONOUS);
+    gl::DebugMessageCallback(debug_callback, ptr::null());
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // pure red and pure blue endpoints in RGB565
+    const RED: [u8; 2] = [0x00, 0xf8];
+    const BLUE: [u8; 2] = [0x1f, 0x00];
+    // indices 0, 1, 2, 3 from left to right on every row
+    const RAMP: [u8; 4] = [0xe4; 4];
+
+    fn color_block(c0: [u8; 2], c1: [u8; 2], indices: [u8; 4]) -> Vec<u8> {
+        [&c0[..], &c1[..], &indices[..]].concat()
+    }
+
+    fn pixel(pixels: &[u8], width: usize, x: usize, y: usize) -> [u8; 4] {
+        let offset = (y * width + x) * 4;
+        [pixels[offset], pixels[offset + 1], pixels[offset + 2], pixels[offset + 3]]
+    }
+
+    #[test]
+    fn dxt1_interpolates_four_colors() {
+        let pixels = decompress_s3tc(COMPRESSED_RGB_S3TC_DXT1, 4, 4, &color_block(RED, BLUE, RAMP)).unwrap();
+        assert_eq!(pixels.len(), 4 * 4 * 4);
+        for y in 0..4 {
+            assert_eq!(pixel(&pixels, 4, 0, y), [255, 0, 0, 255]);
+            assert_eq!(pixel(&pixels, 4, 1, y), [0, 0, 255, 255]);
+            assert_eq!(pixel(&pixels, 4, 2, y), [170, 0, 85, 255]);
+            assert_eq!(pixel(&pixels, 4, 3, y), [85, 0, 170, 255]);
+        }
+    }
+
+    #[test]
+    fn dxt1_three_color_mode_has_punch_through_alpha() {
+        // c0 <= c1 selects the three color mode, index 3 is transparent black
+        let block = color_block(BLUE, RED, RAMP);
+        let rgba = decompress_s3tc(COMPRESSED_RGBA_S3TC_DXT1, 4, 4, &block).unwrap();
+        assert_eq!(pixel(&rgba, 4, 2, 0), [127, 0, 127, 255]);
+        assert_eq!(pixel(&rgba, 4, 3, 0), [0, 0, 0, 0]);
+        // without alpha the same block is opaque
+        let rgb = decompress_s3tc(COMPRESSED_RGB_S3TC_DXT1, 4, 4, &block).unwrap();
+        assert_eq!(pixel(&rgb, 4, 3, 0), [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn dxt3_stores_explicit_alpha() {
+        // 4 bits per pixel, the low nibble first
+        let mut block = vec![0x21, 0xf0, 0, 0, 0, 0, 0, 0];
+        block.extend(color_block(BLUE, RED, [0; 4]));
+        let pixels = decompress_s3tc(COMPRESSED_RGBA_S3TC_DXT3, 4, 4, &block).unwrap();
+        assert_eq!(pixel(&pixels, 4, 0, 0), [0, 0, 255, 17]);
+        assert_eq!(pixel(&pixels, 4, 1, 0), [0, 0, 255, 34]);
+        assert_eq!(pixel(&pixels, 4, 2, 0), [0, 0, 255, 0]);
+        assert_eq!(pixel(&pixels, 4, 3, 0), [0, 0, 255, 255]);
+        // DXT3 and DXT5 colors always use four colors, even with c0 <= c1
+        block[8..].copy_from_slice(&color_block(BLUE, RED, RAMP));
+        let pixels = decompress_s3tc(COMPRESSED_RGBA_S3TC_DXT3, 4, 4, &block).unwrap();
+        assert_eq!(pixel(&pixels, 4, 3, 1)[..3], [170, 0, 85]);
+    }
+
+    #[test]
+    fn dxt5_interpolates_alpha() {
+        // 3 bit indices 2, 1, 0, 7 for the first pixels
+        let indices: u64 = 2 | 1 << 3 | 7 << 9;
+        let eight = |a0: u8, a1: u8| {
+            let mut block = vec![a0, a1];
+            block.extend_from_slice(&indices.to_le_bytes()[..6]);
+            block.extend(color_block(RED, BLUE, [0; 4]));
+            decompress_s3tc(COMPRESSED_RGBA_S3TC_DXT5, 4, 4, &block).unwrap()
+        };
+        let alpha = |pixels: &[u8]| [pixels[3], pixels[7], pixels[11], pixels[15]];
+
+        // a0 > a1 interpolates six values between them
+        assert_eq!(alpha(&eight(255, 0)), [218, 0, 255, 36]);
+        // otherwise four, with 0 and 255 at the end
+        assert_eq!(alpha(&eight(0, 255)), [51, 255, 0, 255]);
+        assert_eq!(pixel(&eight(255, 0), 4, 0, 0)[..3], [255, 0, 0]);
+    }
+
+    #[test]
+    fn small_and_partial_textures() {
+        // a 2x2 mip takes the top left of a whole block
+        let pixels = decompress_s3tc(COMPRESSED_RGB_S3TC_DXT1, 2, 2, &color_block(RED, BLUE, RAMP)).unwrap();
+        assert_eq!(pixels, [[255, 0, 0, 255], [0, 0, 255, 255], [255, 0, 0, 255], [0, 0, 255, 255]].concat());
+
+        // 5x5 needs four blocks, the second starts at x = 4
+        let mut data = color_block(RED, BLUE, [0; 4]);
+        data.extend(color_block(BLUE, RED, [0; 4]).repeat(3));
+        let pixels = decompress_s3tc(COMPRESSED_RGBA_S3TC_DXT1, 5, 5, &data).unwrap();
+        assert_eq!(pixel(&pixels, 5, 3, 3), [255, 0, 0, 255]);
+        assert_eq!(pixel(&pixels, 5, 4, 0), [0, 0, 255, 255]);
+        assert_eq!(pixel(&pixels, 5, 4, 4), [0, 0, 255, 255]);
+
+        assert!(decompress_s3tc(COMPRESSED_RGB_S3TC_DXT1, 5, 5, &data[..24]).is_none());
+        assert!(decompress_s3tc(gl::COMPRESSED_RED_RGTC1, 4, 4, &[0; 8]).is_none());
+    }
+}
@@ -0,0 +1,86 @@
+use crate::{c_str, Color, Shader};
+
+// GLSL for `uniform Fog fog` and `ApplyFog`, shared by the standard shaders and usable in custom ones
+macro_rules! fog_glsl {
+    () => {
+        r#"
+struct Fog {
+    // 0: off, 1: linear, 2: exp, 3: exp2
+    int mode;
+    vec3 color;
+    float density;
+    float start;
+    float end;
+};
+
+uniform Fog fog;
+
+vec3 ApplyFog(vec3 color, float distance) {
+    float factor = 1.0;
+    if (fog.mode == 1) {
+        factor = (fog.end - distance) / (fog.end - fog.start);
+    } else if (fog.mode == 2) {
+        factor = exp(-fog.density * distance);
+    } else if (fog.mode == 3) {
+        factor = exp(-pow(fog.density * distance, 2.0));
+    }
+    return mix(fog.color, color, clamp(factor, 0.0, 1.0));
+}
+"#
+    };
+}
+
+pub(crate) use fog_glsl;
+
+pub const FOG_GLSL: &str = fog_glsl!();
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FogMode {
+    Off,
+    // fades between `start` and `end`
+    Linear,
+    Exponential,
+    ExponentialSquared,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FogSettings {
+    pub mode: FogMode,
+    pub color: Color,
+    // used by the exponential modes
+    pub density: f32,
+    // used by the linear mode
+    pub start: f32,
+    pub end: f32,
+}
+
+impl Default for FogSettings {
+    fn default() -> Self {
+        Self {
+            mode: FogMode::Off,
+            color: Color::rgb(0.5, 0.6, 0.7),
+            density: 0.05,
+            start: 10.0,
+            end: 100.0,
+        }
+    }
+}
+
+impl FogSettings {
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread and `shader` must be in use, the values are set with glUniform.
+    pub unsafe fn set_uniforms(&self, shader: &Shader) {
+        let mode = match self.mode {
+            FogMode::Off => 0,
+            FogMode::Linear => 1,
+            FogMode::Exponential => 2,
+            FogMode::ExponentialSquared => 3,
+        };
+        shader.set_integer(c_str("fog.mode\0"), mode);
+        shader.set_vec3(c_str("fog.color\0"), self.color.r, self.color.g, self.color.b);
+        shader.set_float(c_str("fog.density\0"), self.density);
+        shader.set_float(c_str("fog.start\0"), self.start);
+        shader.set_float(c_str("fog.end\0"), self.end);
+    }
+}
@@ -0,0 +1,563 @@
+use std::ptr;
+
+use gl::types::*;
+use glfw::WindowEvent;
+
+use crate::context::{check_render_thread, GlContext};
+use crate::Readback;
+
+// storage of one attachment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct AttachmentFormat {
+    pub internal_format: GLenum,
+    pub format: GLenum,
+    pub type_: GLenum,
+}
+
+impl AttachmentFormat {
+    pub const RGBA8: Self = Self::new(gl::RGBA8, gl::RGBA, gl::UNSIGNED_BYTE);
+    pub const RGBA16F: Self = Self::new(gl::RGBA16F, gl::RGBA, gl::FLOAT);
+    pub const RGB16F: Self = Self::new(gl::RGB16F, gl::RGB, gl::FLOAT);
+    pub const RG16F: Self = Self::new(gl::RG16F, gl::RG, gl::FLOAT);
+    pub const R32F: Self = Self::new(gl::R32F, gl::RED, gl::FLOAT);
+    pub const R11F_G11F_B10F: Self = Self::new(gl::R11F_G11F_B10F, gl::RGB, gl::FLOAT);
+    pub const DEPTH24_STENCIL8: Self = Self::new(gl::DEPTH24_STENCIL8, gl::DEPTH_STENCIL, gl::UNSIGNED_INT_24_8);
+    pub const DEPTH32F: Self = Self::new(gl::DEPTH_COMPONENT32F, gl::DEPTH_COMPONENT, gl::FLOAT);
+
+    pub const fn new(internal_format: GLenum, format: GLenum, type_: GLenum) -> Self {
+        Self {
+            internal_format,
+            format,
+            type_,
+        }
+    }
+
+    pub fn has_stencil(&self) -> bool {
+        self.format == gl::DEPTH_STENCIL
+    }
+}
+
+// how the depth attachment is sampled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DepthSampler {
+    // a GL comparison function like gl::LEQUAL. reads through `sampler2DShadow` then return
+    // the fraction of texels passing `reference <op> depth` instead of the depth itself.
+    pub compare: Option<GLenum>,
+    // with comparison, linear filtering gives hardware 2x2 PCF
+    pub linear: bool,
+    // depth returned outside [0, 1], None clamps to the edge
+    pub border: Option<f32>,
+}
+
+impl DepthSampler {
+    // everything outside the light frustum is lit
+    pub const SHADOW: Self = Self {
+        compare: Some(gl::LEQUAL),
+        linear: true,
+        border: Some(1.0),
+    };
+
+    // also used for the layers of depth texture arrays
+    pub(crate) unsafe fn apply(&self, target: GLenum, texture: GLuint) {
+        gl::BindTexture(target, texture);
+        match self.compare {
+            Some(func) => {
+                gl::TexParameteri(target, gl::TEXTURE_COMPARE_MODE, conv!(gl::COMPARE_REF_TO_TEXTURE));
+                gl::TexParameteri(target, gl::TEXTURE_COMPARE_FUNC, conv!(func));
+            }
+            None => gl::TexParameteri(target, gl::TEXTURE_COMPARE_MODE, conv!(gl::NONE)),
+        }
+        let filter = if self.linear { gl::LINEAR } else { gl::NEAREST };
+        gl::TexParameteri(target, gl::TEXTURE_MIN_FILTER, conv!(filter));
+        gl::TexParameteri(target, gl::TEXTURE_MAG_FILTER, conv!(filter));
+        let wrap = match self.border {
+            Some(depth) => {
+                let border = [depth; 4];
+                gl::TexParameterfv(target, gl::TEXTURE_BORDER_COLOR, border.as_ptr());
+                gl::CLAMP_TO_BORDER
+            }
+            None => gl::CLAMP_TO_EDGE,
+        };
+        gl::TexParameteri(target, gl::TEXTURE_WRAP_S, conv!(wrap));
+        gl::TexParameteri(target, gl::TEXTURE_WRAP_T, conv!(wrap));
+        gl::BindTexture(target, 0);
+    }
+}
+
+impl Default for DepthSampler {
+    // plain depth reads, for SSAO, soft particles and depth reconstruction
+    fn default() -> Self {
+        Self {
+            compare: None,
+            linear: true,
+            border: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResizePolicy {
+    // keeps the size it was built with
+    Fixed,
+    // follows the window framebuffer scaled by the factor, e.g. 0.5 for half resolution effects.
+    // the builder size is the window size then.
+    MatchWindow(f32),
+}
+
+impl ResizePolicy {
+    fn apply(self, width: i32, height: i32) -> (i32, i32) {
+        match self {
+            ResizePolicy::Fixed => (width, height),
+            // a minimized window reports 0x0
+            ResizePolicy::MatchWindow(scale) => (
+                ((width as f32 * scale).round() as i32).max(1),
+                ((height as f32 * scale).round() as i32).max(1),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AttachmentStorage {
+    // can be sampled after rendering
+    Texture,
+    // cheaper for targets that are only rendered to, resolved or blitted
+    Renderbuffer,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct AttachmentDesc {
+    format: AttachmentFormat,
+    storage: AttachmentStorage,
+}
+
+// describes the attachments, color attachment i is written by fragment output i
+#[derive(Debug, Clone)]
+pub struct FramebufferBuilder {
+    width: i32,
+    height: i32,
+    colors: Vec<AttachmentDesc>,
+    depth: Option<AttachmentDesc>,
+    depth_sampler: DepthSampler,
+    samples: i32,
+    resize_policy: ResizePolicy,
+}
+
+impl FramebufferBuilder {
+    pub fn color(self, format: AttachmentFormat) -> Self {
+        self.color_with(format, AttachmentStorage::Texture)
+    }
+
+    pub fn color_with(mut self, format: AttachmentFormat, storage: AttachmentStorage) -> Self {
+        self.colors.push(AttachmentDesc { format, storage });
+        self
+    }
+
+    pub fn depth(self, format: AttachmentFormat) -> Self {
+        self.depth_with(format, AttachmentStorage::Texture)
+    }
+
+    pub fn depth_with(mut self, format: AttachmentFormat, storage: AttachmentStorage) -> Self {
+        self.depth = Some(AttachmentDesc { format, storage });
+        self
+    }
+
+    // only applies to a depth texture without multisampling
+    pub fn depth_sampler(mut self, sampler: DepthSampler) -> Self {
+        self.depth_sampler = sampler;
+        self
+    }
+
+    // multisampled textures are sampled with sampler2DMS, or `resolve` into a single sampled target first.
+    // 0 and 1 both mean no multisampling.
+    pub fn samples(mut self, samples: u32) -> Self {
+        self.samples = conv!(samples.max(1));
+        self
+    }
+
+    pub fn resize_policy(mut self, policy: ResizePolicy) -> Self {
+        self.resize_policy = policy;
+        self
+    }
+
+    pub fn build(self, _context: &GlContext) -> Framebuffer {
+        check_render_thread("Framebuffer");
+        unsafe { self.build_raw() }
+    }
+
+    unsafe fn build_raw(self) -> Framebuffer {
+        let (width, height) = self.resize_policy.apply(self.width, self.height);
+        let colors: Vec<GLuint> = self.colors.iter().map(|desc| self.allocate(desc, width, height)).collect();
+        let depth = match &self.depth {
+            Some(desc) => self.allocate(desc, width, height),
+            None => 0,
+        };
+        if let Some(desc) = &self.depth {
+            if desc.storage == AttachmentStorage::Texture && self.samples == 1 {
+                self.depth_sampler.apply(gl::TEXTURE_2D, depth);
+            }
+        }
+        gl::BindTexture(gl::TEXTURE_2D, 0);
+        gl::BindRenderbuffer(gl::RENDERBUFFER, 0);
+
+        let mut fbo = 0;
+        gl::GenFramebuffers(1, &mut fbo);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+        let mut draw_buffers = vec![];
+        for (i, (desc, &color)) in self.colors.iter().zip(colors.iter()).enumerate() {
+            let attachment = gl::COLOR_ATTACHMENT0 + i as GLenum;
+            self.attach(attachment, desc, color);
+            draw_buffers.push(attachment);
+        }
+        if let Some(desc) = &self.depth {
+            let attachment = if desc.format.has_stencil() { gl::DEPTH_STENCIL_ATTACHMENT } else { gl::DEPTH_ATTACHMENT };
+            self.attach(attachment, desc, depth);
+        }
+        if draw_buffers.is_empty() {
+            // depth only
+            gl::DrawBuffer(gl::NONE);
+            gl::ReadBuffer(gl::NONE);
+        } else {
+            gl::DrawBuffers(conv!(draw_buffers.len()), draw_buffers.as_ptr());
+        }
+        if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+            log::error!("framebuffer is not complete");
+        }
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+
+        Framebuffer {
+            fbo,
+            colors,
+            depth,
+            width,
+            height,
+            desc: self,
+            generation: 0,
+        }
+    }
+
+    fn texture_target(&self) -> GLenum {
+        if self.samples > 1 {
+            gl::TEXTURE_2D_MULTISAMPLE
+        } else {
+            gl::TEXTURE_2D
+        }
+    }
+
+    unsafe fn allocate(&self, desc: &AttachmentDesc, width: i32, height: i32) -> GLuint {
+        let format = desc.format;
+        match (desc.storage, self.samples > 1) {
+            (AttachmentStorage::Texture, false) => {
+                texture_2d(format.internal_format, width, height, format.format, format.type_)
+            }
+            (AttachmentStorage::Texture, true) => {
+                let mut texture = 0;
+                gl::GenTextures(1, &mut texture);
+                gl::BindTexture(gl::TEXTURE_2D_MULTISAMPLE, texture);
+                gl::TexImage2DMultisample(
+                    gl::TEXTURE_2D_MULTISAMPLE,
+                    self.samples,
+                    format.internal_format,
+                    width,
+                    height,
+                    gl::TRUE,
+                );
+                gl::BindTexture(gl::TEXTURE_2D_MULTISAMPLE, 0);
+                texture
+            }
+            (AttachmentStorage::Renderbuffer, _) => {
+                let mut renderbuffer = 0;
+                gl::GenRenderbuffers(1, &mut renderbuffer);
+                gl::BindRenderbuffer(gl::RENDERBUFFER, renderbuffer);
+                if self.samples > 1 {
+                    gl::RenderbufferStorageMultisample(
+                        gl::RENDERBUFFER,
+                        self.samples,
+                        format.internal_format,
+                        width,
+                        height,
+                    );
+                } else {
+                    gl::RenderbufferStorage(gl::RENDERBUFFER, format.internal_format, width, height);
+                }
+                renderbuffer
+            }
+        }
+    }
+
+    unsafe fn attach(&self, attachment: GLenum, desc: &AttachmentDesc, id: GLuint) {
+        match desc.storage {
+            AttachmentStorage::Texture => {
+                gl::FramebufferTexture2D(gl::FRAMEBUFFER, attachment, self.texture_target(), id, 0)
+            }
+            AttachmentStorage::Renderbuffer => {
+                gl::FramebufferRenderbuffer(gl::FRAMEBUFFER, attachment, gl::RENDERBUFFER, id)
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Framebuffer {
+    fbo: GLuint,
+    // textures or renderbuffers, see `desc`
+    colors: Vec<GLuint>,
+    // 0 without a depth attachment
+    depth: GLuint,
+    width: i32,
+    height: i32,
+    desc: FramebufferBuilder,
+    // bumped on every reallocation
+    generation: u64,
+}
+
+unsafe fn texture_2d(internal_format: GLenum, width: i32, height: i32, format: GLenum, type_: GLenum) -> GLuint {
+    let mut texture = 0;
+    gl::GenTextures(1, &mut texture);
+    gl::BindTexture(gl::TEXTURE_2D, texture);
+    gl::TexImage2D(gl::TEXTURE_2D, 0, conv!(internal_format), width, height, 0, format, type_, ptr::null());
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, conv!(gl::LINEAR));
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, conv!(gl::LINEAR));
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, conv!(gl::CLAMP_TO_EDGE));
+    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, conv!(gl::CLAMP_TO_EDGE));
+    texture
+}
+
+unsafe fn delete_attachment(desc: &AttachmentDesc, id: GLuint) {
+    match desc.storage {
+        AttachmentStorage::Texture => gl::DeleteTextures(1, &id),
+        AttachmentStorage::Renderbuffer => gl::DeleteRenderbuffers(1, &id),
+    }
+}
+
+impl Framebuffer {
+    // a color texture plus a sampleable depth/stencil texture
+    pub fn new(_context: &GlContext, width: i32, height: i32) -> Self {
+        check_render_thread("Framebuffer");
+        unsafe { Self::allocate(width, height) }
+    }
+
+    pub(crate) unsafe fn allocate(width: i32, height: i32) -> Self {
+        Self::builder(width, height)
+            .color(AttachmentFormat::RGBA16F)
+            .depth(AttachmentFormat::DEPTH24_STENCIL8)
+            .build_raw()
+    }
+
+    // no attachments yet
+    pub fn builder(width: i32, height: i32) -> FramebufferBuilder {
+        FramebufferBuilder {
+            width,
+            height,
+            colors: vec![],
+            depth: None,
+            depth_sampler: DepthSampler::default(),
+            samples: 1,
+            resize_policy: ResizePolicy::Fixed,
+        }
+    }
+
+    // binds for drawing and sets the viewport to cover the whole target
+    pub fn bind(&self, _context: &GlContext) {
+        check_render_thread("Framebuffer");
+        unsafe { self.bind_raw() }
+    }
+
+    pub(crate) unsafe fn bind_raw(&self) {
+        gl::BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+        gl::Viewport(0, 0, self.width, self.height);
+    }
+
+    pub fn bind_default(_context: &GlContext, width: i32, height: i32) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl::Viewport(0, 0, width, height);
+        }
+    }
+
+    // reallocates every attachment, the contents are lost. with `MatchWindow` the size is the window size.
+    pub fn resize(&mut self, context: &GlContext, width: i32, height: i32) {
+        let mut desc = self.desc.clone();
+        desc.width = width;
+        desc.height = height;
+        if desc.resize_policy.apply(width, height) == (self.width, self.height) {
+            self.desc = desc;
+            return;
+        }
+        let generation = self.generation + 1;
+        *self = desc.build(context);
+        self.generation = generation;
+    }
+
+    // rebuilds the attachments with another sample count, returns whether they were reallocated
+    pub fn set_samples(&mut self, context: &GlContext, samples: u32) -> bool {
+        let mut desc = self.desc.clone();
+        desc.samples = conv!(samples.max(1));
+        self.rebuild(context, desc)
+    }
+
+    // e.g. another `MatchWindow` scale for a render scale setting, returns whether the attachments were reallocated
+    pub fn set_resize_policy(&mut self, context: &GlContext, policy: ResizePolicy) -> bool {
+        let mut desc = self.desc.clone();
+        desc.resize_policy = policy;
+        self.rebuild(context, desc)
+    }
+
+    fn rebuild(&mut self, context: &GlContext, desc: FramebufferBuilder) -> bool {
+        if desc.samples == self.desc.samples && desc.resize_policy == self.desc.resize_policy {
+            return false;
+        }
+        let generation = self.generation + 1;
+        *self = desc.build(context);
+        self.generation = generation;
+        true
+    }
+
+    // follows the window for `MatchWindow`, returns whether the attachments were reallocated
+    pub fn process_event(&mut self, context: &GlContext, event: &WindowEvent) -> bool {
+        match (event, self.desc.resize_policy) {
+            (&WindowEvent::FramebufferSize(width, height), ResizePolicy::MatchWindow(_)) => {
+                let generation = self.generation;
+                self.resize(context, width, height);
+                generation != self.generation
+            }
+            _ => false,
+        }
+    }
+
+    // passes caching attachment names or sizes compare this to notice reallocations
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    pub fn resize_policy(&self) -> ResizePolicy {
+        self.desc.resize_policy
+    }
+
+    // clears one color attachment, the framebuffer must be bound
+    pub fn clear_color<C: Into<[f32; 4]>>(&self, _context: &GlContext, index: usize, color: C) {
+        let color = color.into();
+        assert!(index < self.colors.len(), "no color attachment {}", index);
+        unsafe {
+            gl::ClearBufferfv(gl::COLOR, conv!(index), color.as_ptr());
+        }
+    }
+
+    // clears depth and stencil, the framebuffer must be bound
+    pub fn clear_depth(&self, _context: &GlContext, depth: f32, stencil: i32) {
+        unsafe {
+            match self.depth_format() {
+                Some(format) if format.has_stencil() => gl::ClearBufferfi(gl::DEPTH_STENCIL, 0, depth, stencil),
+                Some(_) => gl::ClearBufferfv(gl::DEPTH, 0, &depth),
+                None => {}
+            }
+        }
+    }
+
+    // copies the color texture without waiting for the GPU, see `Readback::poll`.
+    // values are clamped to [0, 1] and stored as RGBA8.
+    pub fn read_async(&self, context: &GlContext) -> Readback {
+        Readback::start(context, self.fbo, 0, 0, self.width, self.height)
+    }
+
+    // a single pixel is enough for picking
+    pub fn read_region_async(&self, context: &GlContext, x: i32, y: i32, width: i32, height: i32) -> Readback {
+        Readback::start(context, self.fbo, x, y, width, height)
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.fbo
+    }
+
+    // the first color attachment, 0 for depth only targets.
+    // like `depth_texture` this is a renderbuffer name for `AttachmentStorage::Renderbuffer`.
+    pub fn color_texture(&self) -> GLuint {
+        self.colors.first().copied().unwrap_or(0)
+    }
+
+    // a texture or a renderbuffer name depending on the storage
+    pub fn color_attachment(&self, index: usize) -> GLuint {
+        self.colors[index]
+    }
+
+    pub fn color_count(&self) -> usize {
+        self.colors.len()
+    }
+
+    pub fn color_format(&self, index: usize) -> AttachmentFormat {
+        self.desc.colors[index].format
+    }
+
+    pub fn color_storage(&self, index: usize) -> AttachmentStorage {
+        self.desc.colors[index].storage
+    }
+
+    pub fn depth_texture(&self) -> GLuint {
+        self.depth
+    }
+
+    pub fn depth_format(&self) -> Option<AttachmentFormat> {
+        self.desc.depth.map(|desc| desc.format)
+    }
+
+    pub fn depth_storage(&self) -> Option<AttachmentStorage> {
+        self.desc.depth.map(|desc| desc.storage)
+    }
+
+    pub fn depth_sampler(&self) -> DepthSampler {
+        self.desc.depth_sampler
+    }
+
+    // e.g. switch between comparison reads for shadows and raw depth for debugging
+    pub fn set_depth_sampler(&mut self, _context: &GlContext, sampler: DepthSampler) {
+        if self.depth_storage() == Some(AttachmentStorage::Texture) && self.desc.samples == 1 {
+            unsafe { sampler.apply(gl::TEXTURE_2D, self.depth) };
+        }
+        self.desc.depth_sampler = sampler;
+    }
+
+    pub fn samples(&self) -> u32 {
+        conv!(self.desc.samples)
+    }
+
+    // resolves multisampled color attachment 0 and depth into `target`, which must have the same size
+    pub fn resolve(&self, _context: &GlContext, target: &Framebuffer) {
+        check_render_thread("Framebuffer");
+        let mut mask = 0;
+        if !self.colors.is_empty() && !target.colors.is_empty() {
+            mask |= gl::COLOR_BUFFER_BIT;
+        }
+        if self.depth != 0 && target.depth != 0 {
+            mask |= gl::DEPTH_BUFFER_BIT;
+        }
+        unsafe {
+            gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.fbo);
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, target.fbo);
+            gl::BlitFramebuffer(0, 0, self.width, self.height, 0, 0, target.width, target.height, mask, gl::NEAREST);
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        check_render_thread("Framebuffer");
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.fbo);
+            for (desc, &color) in self.desc.colors.iter().zip(self.colors.iter()) {
+                delete_attachment(desc, color);
+            }
+            if let Some(desc) = &self.desc.depth {
+                delete_attachment(desc, self.depth);
+            }
+        }
+    }
+}
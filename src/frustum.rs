@@ -0,0 +1,128 @@
+use cgmath::{InnerSpace, Matrix4, Point3, Vector3, Vector4, vec3};
+
+// points with `normal . p + distance >= 0` are on the inner side
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Plane {
+    pub normal: Vector3<f32>,
+    pub distance: f32,
+}
+
+impl Plane {
+    // from `ax + by + cz + d`, normalized. a degenerate plane (an infinite far plane) accepts everything.
+    fn from_coefficients(v: Vector4<f32>) -> Self {
+        let normal = vec3(v.x, v.y, v.z);
+        let length = normal.magnitude();
+        if length < 1e-6 {
+            return Self {
+                normal: vec3(0.0, 0.0, 0.0),
+                distance: f32::INFINITY,
+            };
+        }
+        Self {
+            normal: normal / length,
+            distance: v.w / length,
+        }
+    }
+
+    pub fn signed_distance(&self, point: Point3<f32>) -> f32 {
+        self.normal.dot(vec3(point.x, point.y, point.z)) + self.distance
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frustum {
+    // left, right, bottom, top, near, far
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    // the planes of a view projection matrix in world space (Gribb and Hartmann)
+    pub fn from_matrix(view_projection: &Matrix4<f32>) -> Self {
+        let m = view_projection;
+        let row = |i: usize| Vector4::new(m.x[i], m.y[i], m.z[i], m.w[i]);
+        let (r0, r1, r2, r3) = (row(0), row(1), row(2), row(3));
+        Self {
+            planes: [
+                Plane::from_coefficients(r3 + r0),
+                Plane::from_coefficients(r3 - r0),
+                Plane::from_coefficients(r3 + r1),
+                Plane::from_coefficients(r3 - r1),
+                Plane::from_coefficients(r3 + r2),
+                Plane::from_coefficients(r3 - r2),
+            ],
+        }
+    }
+
+    pub fn contains_point(&self, point: Point3<f32>) -> bool {
+        self.planes.iter().all(|plane| plane.signed_distance(point) >= 0.0)
+    }
+
+    pub fn intersects_sphere(&self, center: Point3<f32>, radius: f32) -> bool {
+        self.planes.iter().all(|plane| plane.signed_distance(center) >= -radius)
+    }
+
+    // conservative: boxes near the frustum corners may pass although they are outside
+    pub fn intersects_aabb(&self, min: Point3<f32>, max: Point3<f32>) -> bool {
+        self.planes.iter().all(|plane| {
+            // the corner farthest along the normal
+            let positive = Point3::new(
+                if plane.normal.x >= 0.0 { max.x } else { min.x },
+                if plane.normal.y >= 0.0 { max.y } else { min.y },
+                if plane.normal.z >= 0.0 { max.z } else { min.z },
+            );
+            plane.signed_distance(positive) >= 0.0
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{perspective, Deg, Point3};
+
+    use super::*;
+    use crate::infinite_perspective;
+
+    // looking down -z from the origin, 90 degrees each way so the sides are at 45 degrees
+    fn frustum(far: f32) -> Frustum {
+        let projection = if far.is_infinite() { infinite_perspective(Deg(90.0), 1.0, 0.1) } else { perspective(Deg(90.0), 1.0, 0.1, far) };
+        Frustum::from_matrix(&projection)
+    }
+
+    #[test]
+    fn points_inside_and_outside() {
+        let frustum = frustum(100.0);
+        assert!(frustum.contains_point(Point3::new(0.0, 0.0, -1.0)));
+        assert!(frustum.contains_point(Point3::new(0.9, -0.9, -1.0)));
+        assert!(!frustum.contains_point(Point3::new(1.1, 0.0, -1.0)));
+        assert!(!frustum.contains_point(Point3::new(0.0, 0.0, 1.0)));
+        assert!(!frustum.contains_point(Point3::new(0.0, 0.0, -0.05)));
+        assert!(!frustum.contains_point(Point3::new(0.0, 0.0, -101.0)));
+    }
+
+    #[test]
+    fn planes_are_normalized() {
+        for plane in frustum(100.0).planes.iter() {
+            assert!((plane.normal.magnitude() - 1.0).abs() < 1e-4);
+        }
+        let near = frustum(100.0).planes[4];
+        assert!((near.signed_distance(Point3::new(0.0, 0.0, -1.1)) - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn spheres_and_boxes_touching_the_sides_pass() {
+        let frustum = frustum(100.0);
+        assert!(frustum.intersects_sphere(Point3::new(1.5, 0.0, -1.0), 0.5));
+        assert!(!frustum.intersects_sphere(Point3::new(2.0, 0.0, -1.0), 0.5));
+        assert!(frustum.intersects_aabb(Point3::new(0.5, -0.1, -1.1), Point3::new(2.0, 0.1, -0.9)));
+        assert!(!frustum.intersects_aabb(Point3::new(2.0, -0.1, -1.1), Point3::new(3.0, 0.1, -0.9)));
+        assert!(!frustum.intersects_aabb(Point3::new(-1.0, -1.0, 1.0), Point3::new(1.0, 1.0, 2.0)));
+    }
+
+    #[test]
+    fn an_infinite_far_plane_accepts_everything_behind_it() {
+        let frustum = frustum(f32::INFINITY);
+        assert!(frustum.contains_point(Point3::new(0.0, 0.0, -1.0e6)));
+        assert!(!frustum.contains_point(Point3::new(0.0, 0.0, 1.0)));
+        assert!(frustum.intersects_sphere(Point3::new(0.0, 0.0, -1.0e6), 1.0));
+    }
+}
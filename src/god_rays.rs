@@ -0,0 +1,155 @@
+use cgmath::{InnerSpace, Vector3, vec3, vec4};
+use gl::types::*;
+
+use crate::post::{bind_texture, COPY_FRAGMENT_SHADER};
+use crate::{c_str, Framebuffer, FullscreenQuad, PostContext, PostEffect, Shader, VolumetricFog, FULLSCREEN_VERTEX_SHADER};
+
+const OCCLUSION_FRAGMENT_SHADER: &str = r#"
+#version 330 core
+
+in vec2 TexCoords;
+out vec4 FragColor;
+
+uniform sampler2D screenDepth;
+uniform vec2 lightPosition;
+uniform vec3 lightColor;
+uniform float sunSize;
+uniform float aspect;
+
+void main() {
+    // only the sky (nothing drawn, depth at the far plane) emits rays
+    float sky = texture(screenDepth, TexCoords).r >= 0.99999 ? 1.0 : 0.0;
+    vec2 offset = (TexCoords - lightPosition) * vec2(aspect, 1.0);
+    float disc = 1.0 - smoothstep(0.0, sunSize, length(offset));
+    FragColor = vec4(lightColor * sky * disc, 1.0);
+}
+"#;
+
+const RADIAL_BLUR_FRAGMENT_SHADER: &str = r#"
+#version 330 core
+
+in vec2 TexCoords;
+out vec4 FragColor;
+
+uniform sampler2D screenColor;
+uniform sampler2D occlusion;
+uniform vec2 lightPosition;
+uniform float density;
+uniform float weight;
+uniform float decay;
+uniform float exposure;
+uniform int samples;
+
+void main() {
+    vec2 delta = (TexCoords - lightPosition) * density / float(samples);
+    vec2 uv = TexCoords;
+    float illumination = 1.0;
+    vec3 rays = vec3(0.0);
+    for (int i = 0; i < samples; i++) {
+        uv -= delta;
+        rays += texture(occlusion, uv).rgb * illumination * weight;
+        illumination *= decay;
+    }
+    FragColor = vec4(texture(screenColor, TexCoords).rgb + rays * exposure, 1.0);
+}
+"#;
+
+// screen space light shafts from a directional light shining through the sky
+#[derive(Debug)]
+pub struct GodRays {
+    occlusion_shader: Shader,
+    blur_shader: Shader,
+    copy_shader: Shader,
+    quad: FullscreenQuad,
+    // the occlusion mask is rendered at half resolution
+    occlusion: Option<Framebuffer>,
+    // direction the light travels in
+    pub light_direction: Vector3<f32>,
+    pub light_color: Vector3<f32>,
+    // radius of the emitting disc in screen heights
+    pub sun_size: f32,
+    pub density: f32,
+    pub weight: f32,
+    pub decay: f32,
+    pub exposure: f32,
+    pub samples: u32,
+}
+
+impl GodRays {
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn new() -> Self {
+        Self {
+            occlusion_shader: Shader::from_str(FULLSCREEN_VERTEX_SHADER, OCCLUSION_FRAGMENT_SHADER),
+            blur_shader: Shader::from_str(FULLSCREEN_VERTEX_SHADER, RADIAL_BLUR_FRAGMENT_SHADER),
+            copy_shader: Shader::from_str(FULLSCREEN_VERTEX_SHADER, COPY_FRAGMENT_SHADER),
+            quad: FullscreenQuad::new(),
+            occlusion: None,
+            light_direction: vec3(-0.2, -1.0, -0.3).normalize(),
+            light_color: vec3(1.0, 0.9, 0.7),
+            sun_size: 0.15,
+            density: 0.9,
+            weight: 0.05,
+            decay: 0.96,
+            exposure: 0.6,
+            samples: 64,
+        }
+    }
+
+    // follow the light of the volumetric fog so both effects agree
+    pub fn match_fog(&mut self, fog: &VolumetricFog) {
+        self.light_direction = fog.light_direction;
+        self.light_color = fog.light_color;
+    }
+}
+
+impl PostEffect for GodRays {
+    unsafe fn render(&mut self, color: GLuint, depth: GLuint, context: &PostContext) {
+        // project the direction towards the light as a point at infinity
+        let towards = -self.light_direction.normalize();
+        let clip = context.view_projection * vec4(towards.x, towards.y, towards.z, 0.0);
+        if clip.w <= 0.0 {
+            // the light is behind the camera
+            self.copy_shader.use_program();
+            bind_texture(&self.copy_shader, c_str("screenColor\0"), 0, color);
+            self.quad.draw();
+            return;
+        }
+        let light_x = clip.x / clip.w * 0.5 + 0.5;
+        let light_y = clip.y / clip.w * 0.5 + 0.5;
+
+        let mut output = 0;
+        gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut output);
+        let mut viewport = [0; 4];
+        gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+        let (width, height) = ((viewport[2] / 2).max(1), (viewport[3] / 2).max(1));
+        if self.occlusion.as_ref().map(|o| (o.width(), o.height())) != Some((width, height)) {
+            self.occlusion = Some(Framebuffer::allocate(width, height));
+        }
+        let occlusion = self.occlusion.as_ref().unwrap();
+
+        occlusion.bind_raw();
+        self.occlusion_shader.use_program();
+        bind_texture(&self.occlusion_shader, c_str("screenDepth\0"), 0, depth);
+        self.occlusion_shader.set_vec2(c_str("lightPosition\0"), light_x, light_y);
+        self.occlusion_shader.set_vec3(c_str("lightColor\0"), self.light_color.x, self.light_color.y, self.light_color.z);
+        self.occlusion_shader.set_float(c_str("sunSize\0"), self.sun_size);
+        self.occlusion_shader.set_float(c_str("aspect\0"), viewport[2] as f32 / viewport[3].max(1) as f32);
+        self.quad.draw();
+
+        gl::BindFramebuffer(gl::FRAMEBUFFER, conv!(output));
+        gl::Viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+        self.blur_shader.use_program();
+        bind_texture(&self.blur_shader, c_str("screenColor\0"), 0, color);
+        bind_texture(&self.blur_shader, c_str("occlusion\0"), 1, occlusion.color_texture());
+        gl::ActiveTexture(gl::TEXTURE0);
+        self.blur_shader.set_vec2(c_str("lightPosition\0"), light_x, light_y);
+        self.blur_shader.set_float(c_str("density\0"), self.density);
+        self.blur_shader.set_float(c_str("weight\0"), self.weight);
+        self.blur_shader.set_float(c_str("decay\0"), self.decay);
+        self.blur_shader.set_float(c_str("exposure\0"), self.exposure);
+        self.blur_shader.set_integer(c_str("samples\0"), conv!(self.samples.max(1)));
+        self.quad.draw();
+    }
+}
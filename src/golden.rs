@@ -0,0 +1,195 @@
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use image::{imageops, ImageBuffer, Rgba, RgbaImage};
+
+use crate::{AttachmentFormat, Framebuffer, GlContext};
+
+// set to rewrite the reference images with what renders now, after checking the change is wanted
+pub const UPDATE_GOLDEN_ENV: &str = "GAME_ENGINE_UPDATE_GOLDEN";
+
+// width and height of references that do not exist yet
+const NEW_REFERENCE_SIZE: u32 = 256;
+
+// how far a rendering is from its reference
+#[derive(Debug, Clone)]
+pub struct ImageDiff {
+    // pixels with a channel further off than the tolerance
+    pub differing_pixels: usize,
+    pub total_pixels: usize,
+    // the largest channel difference anywhere
+    pub max_difference: u8,
+    // red where the pixels differ, the reference dimmed elsewhere
+    pub diff: RgbaImage,
+}
+
+impl ImageDiff {
+    pub fn matches(&self) -> bool {
+        self.differing_pixels == 0
+    }
+}
+
+#[derive(Debug)]
+pub enum GoldenError {
+    // the reference could not be read
+    Image(image::ImageError),
+    // the output could not be written
+    Io(std::io::Error),
+    // there is no reference yet and `UPDATE_GOLDEN_ENV` is not set to create it
+    MissingReference(PathBuf),
+    SizeMismatch { reference: (u32, u32), actual: (u32, u32) },
+    Mismatch {
+        differing_pixels: usize,
+        total_pixels: usize,
+        max_difference: u8,
+        actual_path: PathBuf,
+        diff_path: PathBuf,
+    },
+}
+
+impl fmt::Display for GoldenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GoldenError::Image(e) => write!(f, "golden image: {}", e),
+            GoldenError::Io(e) => write!(f, "golden image: {}", e),
+            GoldenError::MissingReference(path) => {
+                write!(f, "no golden image at {}, set {} to create it", path.display(), UPDATE_GOLDEN_ENV)
+            }
+            GoldenError::SizeMismatch { reference, actual } => {
+                write!(f, "rendered {}x{}, but the reference is {}x{}", actual.0, actual.1, reference.0, reference.1)
+            }
+            GoldenError::Mismatch {
+                differing_pixels,
+                total_pixels,
+                max_difference,
+                actual_path,
+                diff_path,
+            } => write!(
+                f,
+                "{} of {} pixels differ by up to {}, see {} and {}",
+                differing_pixels,
+                total_pixels,
+                max_difference,
+                actual_path.display(),
+                diff_path.display(),
+            ),
+        }
+    }
+}
+
+impl Error for GoldenError {}
+
+impl From<std::io::Error> for GoldenError {
+    fn from(e: std::io::Error) -> Self {
+        GoldenError::Io(e)
+    }
+}
+
+impl From<image::ImageError> for GoldenError {
+    fn from(e: image::ImageError) -> Self {
+        GoldenError::Image(e)
+    }
+}
+
+// compares two images of the same size channel by channel, `tolerance` is the largest difference still equal
+pub fn compare_images(actual: &RgbaImage, reference: &RgbaImage, tolerance: u8) -> Result<ImageDiff, GoldenError> {
+    if actual.dimensions() != reference.dimensions() {
+        return Err(GoldenError::SizeMismatch {
+            reference: reference.dimensions(),
+            actual: actual.dimensions(),
+        });
+    }
+    let mut differing_pixels = 0;
+    let mut max_difference = 0;
+    let diff = ImageBuffer::from_fn(actual.width(), actual.height(), |x, y| {
+        let (a, r) = (actual.get_pixel(x, y), reference.get_pixel(x, y));
+        let difference = a.0.iter().zip(r.0.iter()).map(|(&a, &r)| (a as i16 - r as i16).unsigned_abs() as u8).max().unwrap();
+        max_difference = max_difference.max(difference);
+        if difference > tolerance {
+            differing_pixels += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            Rgba([r.0[0] / 4, r.0[1] / 4, r.0[2] / 4, 255])
+        }
+    });
+    Ok(ImageDiff {
+        differing_pixels,
+        total_pixels: (actual.width() * actual.height()) as usize,
+        max_difference,
+        diff,
+    })
+}
+
+// color attachment 0 of an RGBA8 target, top row first like image files. stalls until it is rendered.
+pub fn read_image(context: &GlContext, target: &Framebuffer) -> RgbaImage {
+    let (width, height) = (target.width(), target.height());
+    let pixels = target.read_async(context).wait(context).to_vec();
+    let image = RgbaImage::from_raw(conv!(width), conv!(height), pixels).expect("readback of unexpected size");
+    imageops::flip_vertical(&image)
+}
+
+fn sibling(reference: &Path, suffix: &str) -> PathBuf {
+    let stem = reference.file_stem().map_or_else(|| "golden".into(), |stem| stem.to_string_lossy().into_owned());
+    reference.with_file_name(format!("{}.{}.png", stem, suffix))
+}
+
+// renders `scene` into an offscreen RGBA8 target the size of the reference image and compares
+// them. on a mismatch the rendering and a diff image are written next to the reference as
+// `<name>.actual.png` and `<name>.diff.png`. a missing reference is an error, so a typo in the
+// path can't pass silently. while `GAME_ENGINE_UPDATE_GOLDEN` is set references are written
+// from the rendering instead, at 256x256 if they don't exist yet.
+pub fn render_and_compare<F, P>(context: &GlContext, scene: F, reference: P, tolerance: u8) -> Result<ImageDiff, GoldenError>
+where
+    F: FnOnce(&GlContext, &Framebuffer),
+    P: AsRef<Path>,
+{
+    let reference = reference.as_ref();
+    let existing = if reference.exists() { Some(image::open(reference)?.to_rgba()) } else { None };
+    // updating keeps the size of the old reference
+    let (width, height) = existing.as_ref().map_or((NEW_REFERENCE_SIZE, NEW_REFERENCE_SIZE), |existing| existing.dimensions());
+    let update = std::env::var_os(UPDATE_GOLDEN_ENV).is_some();
+    if existing.is_none() && !update {
+        return Err(GoldenError::MissingReference(reference.to_owned()));
+    }
+    let expected = existing.filter(|_| !update);
+
+    let target = Framebuffer::builder(conv!(width), conv!(height))
+        .color(AttachmentFormat::RGBA8)
+        .depth(AttachmentFormat::DEPTH24_STENCIL8)
+        .build(context);
+    target.bind(context);
+    unsafe {
+        gl::ClearColor(0.0, 0.0, 0.0, 1.0);
+        gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT | gl::STENCIL_BUFFER_BIT);
+    }
+    scene(context, &target);
+    let actual = read_image(context, &target);
+    unsafe {
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+    }
+
+    let expected = match expected {
+        Some(expected) => expected,
+        None => {
+            log::info!("writing golden image {}", reference.display());
+            actual.save(reference)?;
+            return compare_images(&actual, &actual, tolerance);
+        }
+    };
+
+    let diff = compare_images(&actual, &expected, tolerance)?;
+    if diff.matches() {
+        return Ok(diff);
+    }
+    let (actual_path, diff_path) = (sibling(reference, "actual"), sibling(reference, "diff"));
+    actual.save(&actual_path)?;
+    diff.diff.save(&diff_path)?;
+    Err(GoldenError::Mismatch {
+        differing_pixels: diff.differing_pixels,
+        total_pixels: diff.total_pixels,
+        max_difference: diff.max_difference,
+        actual_path,
+        diff_path,
+    })
+}
@@ -0,0 +1,233 @@
+use std::ffi::CString;
+use std::mem;
+use std::ptr;
+
+use cgmath::{EuclideanSpace, InnerSpace, Matrix4, Point3, Vector3, vec3};
+use gl::types::*;
+
+use crate::{c_str, check_render_thread, CreateShaderError, DrawElementsIndirectCommand, FrameStats, Frustum, GlContext, IndirectBuffer, Model, Shader};
+
+const LOCAL_SIZE: u32 = 64;
+
+const CULLING_COMPUTE_SHADER: &str = r#"
+#version 430 core
+
+layout (local_size_x = 64) in;
+
+struct DrawCommand {
+    uint count;
+    uint instanceCount;
+    uint firstIndex;
+    uint baseVertex;
+    uint baseInstance;
+};
+
+layout (std430, binding = 0) readonly buffer Instances {
+    mat4 instances[];
+};
+layout (std430, binding = 1) writeonly buffer Visible {
+    mat4 visible[];
+};
+layout (std430, binding = 2) buffer Commands {
+    DrawCommand commands[];
+};
+
+uniform int instanceCount;
+uniform int commandCount;
+// inner side where dot(xyz, p) + w >= 0
+uniform vec4 planes[6];
+// center and radius in model space
+uniform vec4 boundingSphere;
+
+void main() {
+    uint index = gl_GlobalInvocationID.x;
+    if (index >= uint(instanceCount)) {
+        return;
+    }
+
+    mat4 model = instances[index];
+    vec3 center = (model * vec4(boundingSphere.xyz, 1.0)).xyz;
+    float scale = max(length(model[0].xyz), max(length(model[1].xyz), length(model[2].xyz)));
+    float radius = boundingSphere.w * scale;
+    for (int i = 0; i < 6; i++) {
+        if (dot(planes[i].xyz, center) + planes[i].w < -radius) {
+            return;
+        }
+    }
+
+    // every mesh of the model draws the same instances
+    uint slot = atomicAdd(commands[0].instanceCount, 1u);
+    for (int i = 1; i < commandCount; i++) {
+        atomicAdd(commands[i].instanceCount, 1u);
+    }
+    visible[slot] = model;
+}
+"#;
+
+// frustum culls the instances of a model in a compute shader and draws the survivors
+// indirectly, so neither the test nor the visible count ever goes through the CPU.
+// needs `Features::compute_shader`, GL 4.3.
+#[derive(Debug)]
+pub struct GpuCulling {
+    program: Shader,
+    // the bounds of all meshes, ignoring the node transforms like `Model::draw_instanced`
+    center: Point3<f32>,
+    radius: f32,
+    // one per mesh of the model, with no instances
+    commands: Vec<DrawElementsIndirectCommand>,
+    command_buffer: IndirectBuffer<DrawElementsIndirectCommand>,
+    instances: GLuint,
+    visible: GLuint,
+    // in matrices
+    capacity: usize,
+    instance_count: usize,
+}
+
+impl GpuCulling {
+    pub fn new(context: &GlContext, model: &Model) -> Result<Self, CreateShaderError> {
+        let program = Shader::builder().compute(CULLING_COMPUTE_SHADER).build(context)?;
+
+        // straight from the vertices, without copying the meshes into `MeshData`
+        let positions = || model.meshes.iter().flat_map(|mesh| mesh.verticies.iter()).map(|vertex| vertex.position);
+        let bounds = positions().fold(None, |bounds: Option<(Vector3<f32>, Vector3<f32>)>, p| match bounds {
+            Some((min, max)) => Some((
+                vec3(p.x.min(min.x), p.y.min(min.y), p.z.min(min.z)),
+                vec3(p.x.max(max.x), p.y.max(max.y), p.z.max(max.z)),
+            )),
+            None => Some((p, p)),
+        });
+        let center = bounds.map_or_else(Point3::origin, |(min, max)| Point3::from_vec((min + max) * 0.5));
+        let radius = positions().map(|p| (Point3::from_vec(p) - center).magnitude()).fold(0.0, f32::max);
+
+        let commands: Vec<_> = model.meshes.iter().map(|mesh| DrawElementsIndirectCommand::for_mesh(mesh, 0)).collect();
+        let command_buffer = IndirectBuffer::new(context, &commands);
+
+        let mut culling = Self {
+            program,
+            center,
+            radius,
+            commands,
+            command_buffer,
+            instances: 0,
+            visible: 0,
+            capacity: 0,
+            instance_count: 0,
+        };
+        unsafe {
+            gl::GenBuffers(1, &mut culling.instances);
+            gl::GenBuffers(1, &mut culling.visible);
+        }
+        Ok(culling)
+    }
+
+    // the model matrices to cull from, kept on the GPU until they are set again
+    pub fn set_instances(&mut self, _context: &GlContext, models: &[Matrix4<f32>]) {
+        check_render_thread("GpuCulling");
+        let matrix_size = mem::size_of::<Matrix4<f32>>();
+        unsafe {
+            if models.len() > self.capacity {
+                self.capacity = models.len().next_power_of_two();
+                gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, self.visible);
+                gl::BufferData(gl::SHADER_STORAGE_BUFFER, conv!(self.capacity * matrix_size), ptr::null(), gl::DYNAMIC_COPY);
+                gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, self.instances);
+                gl::BufferData(gl::SHADER_STORAGE_BUFFER, conv!(self.capacity * matrix_size), ptr::null(), gl::STATIC_DRAW);
+            }
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, self.instances);
+            gl::BufferSubData(gl::SHADER_STORAGE_BUFFER, 0, conv!(mem::size_of_val(models)), models.as_ptr() as *const _);
+            gl::BindBuffer(gl::SHADER_STORAGE_BUFFER, 0);
+        }
+        FrameStats::record_buffer_upload(mem::size_of_val(models));
+        self.instance_count = models.len();
+    }
+
+    pub fn instance_count(&self) -> usize {
+        self.instance_count
+    }
+
+    // tests every instance against the frustum of `view_projection`, for the next `draw`
+    pub fn cull(&mut self, context: &GlContext, view_projection: &Matrix4<f32>) {
+        check_render_thread("GpuCulling");
+        let frustum = Frustum::from_matrix(view_projection);
+        // the counts start from zero again
+        self.command_buffer.update(context, 0, &self.commands);
+        unsafe {
+            if self.instance_count == 0 {
+                return;
+            }
+
+            self.program.use_program();
+            self.program.set_integer(c_str("instanceCount\0"), conv!(self.instance_count));
+            self.program.set_integer(c_str("commandCount\0"), conv!(self.commands.len()));
+            for (i, plane) in frustum.planes.iter().enumerate() {
+                let name = CString::new(format!("planes[{}]", i)).unwrap();
+                // an infinite far plane accepts everything
+                let distance = if plane.distance.is_finite() { plane.distance } else { f32::MAX };
+                self.program.set_vec4(&name, plane.normal.x, plane.normal.y, plane.normal.z, distance);
+            }
+            self.program.set_vec4(c_str("boundingSphere\0"), self.center.x, self.center.y, self.center.z, self.radius);
+
+            gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, 0, self.instances);
+            gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, 1, self.visible);
+            gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, 2, self.command_buffer.id());
+            let instance_count: GLuint = conv!(self.instance_count);
+            gl::DispatchCompute(instance_count.div_ceil(LOCAL_SIZE), 1, 1);
+            gl::MemoryBarrier(gl::COMMAND_BARRIER_BIT | gl::VERTEX_ATTRIB_ARRAY_BARRIER_BIT);
+            for binding in 0..3 {
+                gl::BindBufferBase(gl::SHADER_STORAGE_BUFFER, binding, 0);
+            }
+        }
+    }
+
+    // draws the instances that passed the last `cull` with the same model, at attributes 3 to 6
+    // as for `Model::draw_instanced`
+    pub fn draw(&self, context: &GlContext, shader: &Shader, model: &Model) {
+        check_render_thread("GpuCulling");
+        for (index, node) in model.nodes.iter().enumerate() {
+            if !model.is_visible(index) {
+                continue;
+            }
+            for &mesh_index in node.meshes.iter() {
+                let mesh = match model.meshes.get(mesh_index) {
+                    Some(mesh) if mesh_index < self.commands.len() => mesh,
+                    _ => continue,
+                };
+                unsafe {
+                    mesh.point_instances(self.visible, 0, mem::size_of::<Matrix4<f32>>());
+                }
+                mesh.draw_indirect(context, shader, &self.command_buffer, mesh_index, 1);
+            }
+        }
+    }
+
+    // the commands the last `cull` wrote, one per mesh
+    pub fn commands(&self) -> &IndirectBuffer<DrawElementsIndirectCommand> {
+        &self.command_buffer
+    }
+
+    // reads the number of visible instances back, stalling until the last `cull` finished
+    pub fn visible_count(&self, context: &GlContext) -> u32 {
+        unsafe {
+            // the counts were written by the compute shader, not through the buffer API
+            gl::MemoryBarrier(gl::BUFFER_UPDATE_BARRIER_BIT);
+        }
+        self.command_buffer.read(context).first().map_or(0, |command| command.instance_count)
+    }
+
+    // adds the last `cull` to `FrameStats`. stalls like `visible_count`, so it is left to the
+    // caller, e.g. while debugging.
+    pub fn record_stats(&self, context: &GlContext) {
+        let tested: u32 = conv!(self.instance_count);
+        let visible = self.visible_count(context).min(tested);
+        FrameStats::record_culling(tested, tested - visible, 0);
+    }
+}
+
+impl Drop for GpuCulling {
+    fn drop(&mut self) {
+        check_render_thread("GpuCulling");
+        unsafe {
+            gl::DeleteBuffers(1, &self.instances);
+            gl::DeleteBuffers(1, &self.visible);
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::ffi::CStr;
+use std::os::raw::c_char;
+use std::rc::Rc;
+
+use gl::types::*;
+
+use crate::features::Features;
+
+// not part of the 4.5 core bindings
+const MAX_TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FF;
+
+#[derive(Debug, Clone)]
+pub struct GpuInfo {
+    pub vendor: String,
+    pub renderer: String,
+    pub version: String,
+    pub shading_language_version: String,
+    pub major_version: GLint,
+    pub minor_version: GLint,
+    pub max_texture_size: GLint,
+    pub max_cube_map_texture_size: GLint,
+    pub max_texture_image_units: GLint,
+    pub max_samples: GLint,
+    pub max_uniform_block_size: GLint,
+    pub max_uniform_buffer_bindings: GLint,
+    pub max_vertex_uniform_blocks: GLint,
+    pub max_fragment_uniform_blocks: GLint,
+    pub max_anisotropy: Option<f32>,
+    pub extensions: HashSet<String>,
+    pub features: Features,
+}
+
+thread_local! {
+    // a GL context is current on at most one thread, so the cache is per thread
+    static CURRENT: RefCell<Option<Rc<GpuInfo>>> = const { RefCell::new(None) };
+}
+
+unsafe fn get_string(name: GLenum) -> String {
+    let ptr = gl::GetString(name);
+    if ptr.is_null() {
+        return String::new();
+    }
+    CStr::from_ptr(ptr as *const c_char).to_string_lossy().into_owned()
+}
+
+unsafe fn get_integer(name: GLenum) -> GLint {
+    let mut value = 0;
+    gl::GetIntegerv(name, &mut value);
+    value
+}
+
+impl GpuInfo {
+    /// query the context that is current on this thread
+    ///
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn query() -> Self {
+        let mut extensions = HashSet::new();
+        for i in 0..get_integer(gl::NUM_EXTENSIONS) {
+            let ptr = gl::GetStringi(gl::EXTENSIONS, conv!(i));
+            if !ptr.is_null() {
+                extensions.insert(CStr::from_ptr(ptr as *const c_char).to_string_lossy().into_owned());
+            }
+        }
+
+        let mut info = Self {
+            vendor: get_string(gl::VENDOR),
+            renderer: get_string(gl::RENDERER),
+            version: get_string(gl::VERSION),
+            shading_language_version: get_string(gl::SHADING_LANGUAGE_VERSION),
+            major_version: get_integer(gl::MAJOR_VERSION),
+            minor_version: get_integer(gl::MINOR_VERSION),
+            max_texture_size: get_integer(gl::MAX_TEXTURE_SIZE),
+            max_cube_map_texture_size: get_integer(gl::MAX_CUBE_MAP_TEXTURE_SIZE),
+            max_texture_image_units: get_integer(gl::MAX_TEXTURE_IMAGE_UNITS),
+            max_samples: get_integer(gl::MAX_SAMPLES),
+            max_uniform_block_size: get_integer(gl::MAX_UNIFORM_BLOCK_SIZE),
+            max_uniform_buffer_bindings: get_integer(gl::MAX_UNIFORM_BUFFER_BINDINGS),
+            max_vertex_uniform_blocks: get_integer(gl::MAX_VERTEX_UNIFORM_BLOCKS),
+            max_fragment_uniform_blocks: get_integer(gl::MAX_FRAGMENT_UNIFORM_BLOCKS),
+            max_anisotropy: None,
+            extensions,
+            features: Features::default(),
+        };
+
+        if info.has_version(4, 6)
+            || info.has_extension("GL_ARB_texture_filter_anisotropic")
+            || info.has_extension("GL_EXT_texture_filter_anisotropic")
+        {
+            let mut max = 0.0;
+            gl::GetFloatv(MAX_TEXTURE_MAX_ANISOTROPY, &mut max);
+            if max > 1.0 {
+                info.max_anisotropy = Some(max);
+            }
+        }
+
+        info.features = Features::detect(&info);
+
+        info
+    }
+
+    /// cached result of `query` for the context current on this thread
+    ///
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread. the cache is per thread, so recreating the context needs a `refresh`.
+    pub unsafe fn current() -> Rc<Self> {
+        CURRENT.with(|current| {
+            current
+                .borrow_mut()
+                .get_or_insert_with(|| Rc::new(Self::query()))
+                .clone()
+        })
+    }
+
+    /// re-query after the context has been recreated
+    ///
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn refresh() -> Rc<Self> {
+        let info = Rc::new(Self::query());
+        CURRENT.with(|current| *current.borrow_mut() = Some(info.clone()));
+        info
+    }
+
+    pub fn has_version(&self, major: GLint, minor: GLint) -> bool {
+        (self.major_version, self.minor_version) >= (major, minor)
+    }
+
+    pub fn has_extension(&self, name: &str) -> bool {
+        self.extensions.contains(name)
+    }
+
+    pub fn supports_anisotropy(&self) -> bool {
+        self.max_anisotropy.is_some()
+    }
+
+    pub fn supports_bindless_textures(&self) -> bool {
+        self.has_extension("GL_ARB_bindless_texture")
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::HashMap;
+
+use crate::context::check_render_thread;
+use crate::texture_builder::{apply_anisotropy, set_default_anisotropy};
+use crate::{EngineConfig, Framebuffer, GlContext, PostEffectId, PostStack, Renderer, ResizePolicy, ShadowMap, ShadowSettings, Texture};
+
+// the quality options of an in-game menu, applied with `Renderer::apply_settings`
+#[derive(Debug, Clone, PartialEq)]
+pub struct GraphicsSettings {
+    // 1 turns multisampling off
+    pub msaa_samples: u32,
+    pub shadow_resolution: u32,
+    // of the window size the scene targets are rendered at
+    pub render_scale: f32,
+    // 1 turns anisotropic filtering off, higher levels are clamped to what the GPU supports
+    pub anisotropy: f32,
+    // effects left out keep their state
+    pub post_effects: HashMap<PostEffectId, bool>,
+}
+
+impl Default for GraphicsSettings {
+    fn default() -> Self {
+        Self {
+            msaa_samples: 1,
+            shadow_resolution: ShadowSettings::default().resolution,
+            render_scale: 1.0,
+            anisotropy: 16.0,
+            post_effects: HashMap::new(),
+        }
+    }
+}
+
+impl GraphicsSettings {
+    // the startup values of the settings the config file has
+    pub fn from_config(config: &EngineConfig) -> Self {
+        Self {
+            msaa_samples: config.samples.max(1),
+            render_scale: config.render_scale,
+            ..Self::default()
+        }
+    }
+
+    pub fn with_post_effect(mut self, id: PostEffectId, enabled: bool) -> Self {
+        self.post_effects.insert(id, enabled);
+        self
+    }
+}
+
+// what the settings apply to, the game keeps ownership
+#[derive(Debug, Default)]
+pub struct GraphicsTargets<'a> {
+    // rendered into with multisampling. resolve them into single sampled targets for post processing.
+    pub multisampled: Vec<&'a mut Framebuffer>,
+    // scaled by the render scale, the ones following the window only
+    pub scaled: Vec<&'a mut Framebuffer>,
+    pub shadow_maps: Vec<&'a mut ShadowMap>,
+    // get the anisotropy. textures built later get it as well.
+    pub textures: Vec<&'a Texture>,
+    pub post: Option<&'a mut PostStack>,
+}
+
+// what applying the settings changed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AppliedSettings {
+    // reallocated framebuffers, whose attachment names passes have to fetch again
+    pub framebuffers: usize,
+    pub shadow_maps: usize,
+    pub textures: usize,
+    pub post_effects: usize,
+}
+
+impl Renderer {
+    // the settings last applied
+    pub fn settings(&self) -> &GraphicsSettings {
+        &self.settings
+    }
+
+    // brings the targets in line with `settings`, reallocating only what differs. apply again
+    // with the same settings after creating new targets.
+    pub fn apply_settings(&mut self, context: &GlContext, settings: &GraphicsSettings, targets: GraphicsTargets) -> AppliedSettings {
+        check_render_thread("Renderer");
+        let mut applied = AppliedSettings::default();
+
+        for framebuffer in targets.multisampled {
+            if framebuffer.set_samples(context, settings.msaa_samples) {
+                applied.framebuffers += 1;
+            }
+        }
+        for framebuffer in targets.scaled {
+            if let ResizePolicy::MatchWindow(_) = framebuffer.resize_policy() {
+                if framebuffer.set_resize_policy(context, ResizePolicy::MatchWindow(settings.render_scale)) {
+                    applied.framebuffers += 1;
+                }
+            }
+        }
+
+        for shadow_map in targets.shadow_maps {
+            if shadow_map.settings().resolution != settings.shadow_resolution {
+                let shadow_settings = ShadowSettings {
+                    resolution: settings.shadow_resolution,
+                    ..*shadow_map.settings()
+                };
+                unsafe {
+                    shadow_map.set_settings(shadow_settings);
+                }
+                applied.shadow_maps += 1;
+            }
+        }
+
+        set_default_anisotropy(settings.anisotropy);
+        unsafe {
+            for texture in targets.textures {
+                gl::BindTexture(gl::TEXTURE_2D, texture.id());
+                if apply_anisotropy(settings.anisotropy).is_some() {
+                    applied.textures += 1;
+                }
+            }
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+        }
+
+        if let Some(post) = targets.post {
+            for (&id, &enabled) in settings.post_effects.iter() {
+                if post.is_enabled(id) != enabled {
+                    post.set_enabled(id, enabled);
+                    applied.post_effects += 1;
+                }
+            }
+        }
+
+        self.settings = settings.clone();
+        applied
+    }
+}
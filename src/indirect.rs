@@ -0,0 +1,195 @@
+use std::marker::PhantomData;
+use std::mem;
+use std::ptr;
+
+use gl::types::*;
+
+use crate::{check_render_thread, FrameStats, GlContext, GpuInfo, Mesh, Shader};
+
+// the record glDrawArraysIndirect reads
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct DrawArraysIndirectCommand {
+    pub count: GLuint,
+    pub instance_count: GLuint,
+    pub first: GLuint,
+    pub base_instance: GLuint,
+}
+
+// the record glDrawElementsIndirect reads
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct DrawElementsIndirectCommand {
+    pub count: GLuint,
+    pub instance_count: GLuint,
+    pub first_index: GLuint,
+    pub base_vertex: GLint,
+    pub base_instance: GLuint,
+}
+
+impl DrawElementsIndirectCommand {
+    // every index of `mesh`, `instance_count` times
+    pub fn for_mesh(mesh: &Mesh, instance_count: GLuint) -> Self {
+        Self {
+            count: conv!(mesh.indices.len()),
+            instance_count,
+            ..Self::default()
+        }
+    }
+}
+
+mod private {
+    pub trait Sealed {}
+    impl Sealed for super::DrawArraysIndirectCommand {}
+    impl Sealed for super::DrawElementsIndirectCommand {}
+}
+
+// the records an `IndirectBuffer` may hold
+pub trait IndirectCommand: private::Sealed + Copy {}
+impl IndirectCommand for DrawArraysIndirectCommand {}
+impl IndirectCommand for DrawElementsIndirectCommand {}
+
+// draw commands in a GL buffer, written from the CPU or by shaders (bound as a shader
+// storage buffer, see `id`) and read by the indirect draw calls
+#[derive(Debug)]
+pub struct IndirectBuffer<T: IndirectCommand> {
+    buffer: GLuint,
+    len: usize,
+    _command: PhantomData<T>,
+}
+
+impl<T: IndirectCommand> IndirectBuffer<T> {
+    pub fn new(_context: &GlContext, commands: &[T]) -> Self {
+        let mut buffer = 0;
+        unsafe {
+            gl::GenBuffers(1, &mut buffer);
+            gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, buffer);
+            gl::BufferData(gl::DRAW_INDIRECT_BUFFER, conv!(mem::size_of_val(commands)), commands.as_ptr() as *const _, gl::DYNAMIC_DRAW);
+            gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, 0);
+        }
+        FrameStats::record_buffer_upload(mem::size_of_val(commands));
+        Self {
+            buffer,
+            len: commands.len(),
+            _command: PhantomData,
+        }
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.buffer
+    }
+
+    // in commands
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // overwrites the commands from `offset` on, which must fit
+    pub fn update(&mut self, _context: &GlContext, offset: usize, commands: &[T]) {
+        check_render_thread("IndirectBuffer");
+        assert!(offset + commands.len() <= self.len, "commands {}..{} out of {}", offset, offset + commands.len(), self.len);
+        unsafe {
+            gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, self.buffer);
+            gl::BufferSubData(
+                gl::DRAW_INDIRECT_BUFFER,
+                conv!(offset * mem::size_of::<T>()),
+                conv!(mem::size_of_val(commands)),
+                commands.as_ptr() as *const _,
+            );
+            gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, 0);
+        }
+        FrameStats::record_buffer_upload(mem::size_of_val(commands));
+    }
+
+    // replaces all commands, reallocating when the count changes
+    pub fn set(&mut self, context: &GlContext, commands: &[T]) {
+        if commands.len() != self.len {
+            check_render_thread("IndirectBuffer");
+            unsafe {
+                gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, self.buffer);
+                gl::BufferData(gl::DRAW_INDIRECT_BUFFER, conv!(mem::size_of_val(commands)), ptr::null(), gl::DYNAMIC_DRAW);
+                gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, 0);
+            }
+            self.len = commands.len();
+        }
+        self.update(context, 0, commands);
+    }
+
+    // reads the commands back, e.g. the counts written by a culling shader. stalls until they are done.
+    pub fn read(&self, _context: &GlContext) -> Vec<T> {
+        check_render_thread("IndirectBuffer");
+        let mut commands = Vec::with_capacity(self.len);
+        unsafe {
+            gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, self.buffer);
+            gl::GetBufferSubData(gl::DRAW_INDIRECT_BUFFER, 0, conv!(self.len * mem::size_of::<T>()), commands.as_mut_ptr() as *mut _);
+            gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, 0);
+            commands.set_len(self.len);
+        }
+        commands
+    }
+
+    fn check_range(&self, offset: usize, count: usize) {
+        assert!(offset + count <= self.len, "commands {}..{} out of {}", offset, offset + count, self.len);
+    }
+}
+
+impl IndirectBuffer<DrawArraysIndirectCommand> {
+    // draws `count` commands from `offset` with the vertex array bound by the caller
+    pub fn draw_arrays(&self, _context: &GlContext, mode: GLenum, offset: usize, count: usize) {
+        check_render_thread("IndirectBuffer");
+        self.check_range(offset, count);
+        let stride = mem::size_of::<DrawArraysIndirectCommand>();
+        unsafe {
+            gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, self.buffer);
+            if GpuInfo::current().features.multi_draw_indirect {
+                gl::MultiDrawArraysIndirect(mode, (offset * stride) as *const _, conv!(count), 0);
+                FrameStats::record_draw(0, 0);
+            } else {
+                for command in offset..offset + count {
+                    gl::DrawArraysIndirect(mode, (command * stride) as *const _);
+                    FrameStats::record_draw(0, 0);
+                }
+            }
+            gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, 0);
+        }
+    }
+}
+
+impl<T: IndirectCommand> Drop for IndirectBuffer<T> {
+    fn drop(&mut self) {
+        check_render_thread("IndirectBuffer");
+        unsafe {
+            gl::DeleteBuffers(1, &self.buffer);
+        }
+    }
+}
+
+impl Mesh {
+    // draws `count` commands from `offset` (in commands) with the mesh's textures. the
+    // counts are only known to the GPU, so `FrameStats` sees the draw calls alone.
+    pub fn draw_indirect(&self, _context: &GlContext, shader: &Shader, buffer: &IndirectBuffer<DrawElementsIndirectCommand>, offset: usize, count: usize) {
+        check_render_thread("Mesh");
+        buffer.check_range(offset, count);
+        let stride = mem::size_of::<DrawElementsIndirectCommand>();
+        unsafe {
+            self.set_texture(shader);
+            gl::BindVertexArray(self.vao);
+            gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, buffer.id());
+            if GpuInfo::current().features.multi_draw_indirect {
+                gl::MultiDrawElementsIndirect(gl::TRIANGLES, gl::UNSIGNED_INT, (offset * stride) as *const _, conv!(count), 0);
+                FrameStats::record_draw(0, 0);
+            } else {
+                for command in offset..offset + count {
+                    gl::DrawElementsIndirect(gl::TRIANGLES, gl::UNSIGNED_INT, (command * stride) as *const _);
+                    FrameStats::record_draw(0, 0);
+                }
+            }
+            gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, 0);
+            gl::BindVertexArray(0);
+        }
+    }
+}
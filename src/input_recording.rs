@@ -0,0 +1,336 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::sync::mpsc::Receiver;
+
+use glfw::{Action, Key, Modifiers, MouseButton, WindowEvent};
+
+use crate::EventBus;
+
+const HEADER: &str = "# game-engine input recording 1";
+
+#[derive(Debug)]
+pub struct InputRecordingError {
+    message: String,
+}
+
+impl fmt::Display for InputRecordingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid input recording: {}", self.message)
+    }
+}
+
+impl Error for InputRecordingError {}
+
+// an event and when it arrived: the frame it was handled in, for replaying in lockstep with
+// a fixed time step, and the glfw time in seconds since the recording started
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedEvent {
+    pub frame: u64,
+    pub time: f64,
+    pub event: WindowEvent,
+}
+
+fn key_from_i32(code: i32) -> Option<Key> {
+    let valid = matches!(
+        code,
+        -1 | 32 | 39 | 44..=57 | 59 | 61 | 65..=93 | 96 | 161 | 162 | 256..=269 | 280..=284 | 290..=314 | 320..=336 | 340..=348
+    );
+    // `Key` is `repr(i32)` with exactly these GLFW key codes
+    if valid {
+        Some(unsafe { std::mem::transmute::<i32, Key>(code) })
+    } else {
+        None
+    }
+}
+
+fn action_from_i32(code: i32) -> Option<Action> {
+    match code {
+        0 => Some(Action::Release),
+        1 => Some(Action::Press),
+        2 => Some(Action::Repeat),
+        _ => None,
+    }
+}
+
+// the words of a line after the frame and time. file drops are not recorded.
+fn encode(event: &WindowEvent) -> Option<String> {
+    Some(match *event {
+        WindowEvent::Pos(x, y) => format!("pos {} {}", x, y),
+        WindowEvent::Size(width, height) => format!("size {} {}", width, height),
+        WindowEvent::Close => "close".into(),
+        WindowEvent::Refresh => "refresh".into(),
+        WindowEvent::Focus(focused) => format!("focus {}", focused),
+        WindowEvent::Iconify(iconified) => format!("iconify {}", iconified),
+        WindowEvent::FramebufferSize(width, height) => format!("framebuffer_size {} {}", width, height),
+        WindowEvent::MouseButton(button, action, modifiers) => {
+            format!("mouse_button {} {} {}", button as i32, action as i32, modifiers.bits())
+        }
+        WindowEvent::CursorPos(x, y) => format!("cursor_pos {:?} {:?}", x, y),
+        WindowEvent::CursorEnter(entered) => format!("cursor_enter {}", entered),
+        WindowEvent::Scroll(x, y) => format!("scroll {:?} {:?}", x, y),
+        WindowEvent::Key(key, scancode, action, modifiers) => {
+            format!("key {} {} {} {}", key as i32, scancode, action as i32, modifiers.bits())
+        }
+        WindowEvent::Char(c) => format!("char {}", c as u32),
+        WindowEvent::CharModifiers(c, modifiers) => format!("char_modifiers {} {}", c as u32, modifiers.bits()),
+        WindowEvent::FileDrop(_) => return None,
+        WindowEvent::Maximize(maximized) => format!("maximize {}", maximized),
+        WindowEvent::ContentScale(x, y) => format!("content_scale {:?} {:?}", x, y),
+    })
+}
+
+fn decode(kind: &str, args: &[&str]) -> Result<WindowEvent, String> {
+    fn arg<T: std::str::FromStr>(args: &[&str], index: usize) -> Result<T, String> {
+        let word = args.get(index).ok_or_else(|| format!("missing argument {}", index + 1))?;
+        word.parse().map_err(|_| format!("bad argument {:?}", word))
+    }
+    let modifiers = |index| arg(args, index).map(Modifiers::from_bits_truncate);
+    let character = |code: u32| std::char::from_u32(code).ok_or_else(|| format!("bad character {}", code));
+    let action = |index| arg(args, index).and_then(|code| action_from_i32(code).ok_or_else(|| format!("bad action {}", code)));
+
+    Ok(match kind {
+        "pos" => WindowEvent::Pos(arg(args, 0)?, arg(args, 1)?),
+        "size" => WindowEvent::Size(arg(args, 0)?, arg(args, 1)?),
+        "close" => WindowEvent::Close,
+        "refresh" => WindowEvent::Refresh,
+        "focus" => WindowEvent::Focus(arg(args, 0)?),
+        "iconify" => WindowEvent::Iconify(arg(args, 0)?),
+        "framebuffer_size" => WindowEvent::FramebufferSize(arg(args, 0)?, arg(args, 1)?),
+        "mouse_button" => {
+            let code = arg(args, 0)?;
+            let button = MouseButton::from_i32(code).ok_or_else(|| format!("bad mouse button {}", code))?;
+            WindowEvent::MouseButton(button, action(1)?, modifiers(2)?)
+        }
+        "cursor_pos" => WindowEvent::CursorPos(arg(args, 0)?, arg(args, 1)?),
+        "cursor_enter" => WindowEvent::CursorEnter(arg(args, 0)?),
+        "scroll" => WindowEvent::Scroll(arg(args, 0)?, arg(args, 1)?),
+        "key" => {
+            let code = arg(args, 0)?;
+            let key = key_from_i32(code).ok_or_else(|| format!("bad key {}", code))?;
+            WindowEvent::Key(key, arg(args, 1)?, action(2)?, modifiers(3)?)
+        }
+        "char" => WindowEvent::Char(character(arg(args, 0)?)?),
+        "char_modifiers" => WindowEvent::CharModifiers(character(arg(args, 0)?)?, modifiers(1)?),
+        "maximize" => WindowEvent::Maximize(arg(args, 0)?),
+        "content_scale" => WindowEvent::ContentScale(arg(args, 0)?, arg(args, 1)?),
+        _ => return Err(format!("unknown event {:?}", kind)),
+    })
+}
+
+// recorded events in order, saved as one line per event
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InputRecording {
+    pub events: Vec<RecordedEvent>,
+}
+
+impl InputRecording {
+    pub fn parse(source: &str) -> Result<Self, InputRecordingError> {
+        let mut events = vec![];
+        for (number, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| InputRecordingError {
+                message: format!("line {}: {}", number + 1, message),
+            };
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.len() < 3 {
+                return Err(error("expected frame, time and event".into()));
+            }
+            let frame = words[0].parse().map_err(|_| error(format!("bad frame {:?}", words[0])))?;
+            let time = words[1].parse().map_err(|_| error(format!("bad time {:?}", words[1])))?;
+            let event = decode(words[2], &words[3..]).map_err(error)?;
+            events.push(RecordedEvent { frame, time, event });
+        }
+        Ok(Self { events })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error + 'static>> {
+        Ok(Self::parse(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{}\n", HEADER);
+        for recorded in self.events.iter() {
+            if let Some(event) = encode(&recorded.event) {
+                text.push_str(&format!("{} {:?} {}\n", recorded.frame, recorded.time, event));
+            }
+        }
+        text
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        fs::write(path, self.to_text())
+    }
+
+    // the last frame with an event
+    pub fn last_frame(&self) -> Option<u64> {
+        self.events.last().map(|recorded| recorded.frame)
+    }
+}
+
+// records the events the game handles. frames count from the first `record`, e.g. pass
+// `Time::frame_count` or a counter of the game's own.
+#[derive(Debug, Clone, Default)]
+pub struct InputRecorder {
+    recording: InputRecording,
+    // the first frame and time, everything is stored relative to them
+    start: Option<(u64, f64)>,
+}
+
+impl InputRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&mut self, frame: u64, time: f64, event: &WindowEvent) {
+        if let WindowEvent::FileDrop(_) = event {
+            return;
+        }
+        let (start_frame, start_time) = *self.start.get_or_insert((frame, time));
+        self.recording.events.push(RecordedEvent {
+            frame: frame.saturating_sub(start_frame),
+            time: (time - start_time).max(0.0),
+            event: event.clone(),
+        });
+    }
+
+    // drains the window's events, recording them, and returns them for the game to handle
+    // as if it had called `glfw::flush_messages`
+    pub fn flush(&mut self, frame: u64, events: &Receiver<(f64, WindowEvent)>) -> Vec<WindowEvent> {
+        glfw::flush_messages(events)
+            .map(|(time, event)| {
+                self.record(frame, time, &event);
+                event
+            })
+            .collect()
+    }
+
+    pub fn recording(&self) -> &InputRecording {
+        &self.recording
+    }
+
+    pub fn finish(self) -> InputRecording {
+        self.recording
+    }
+}
+
+// hands out recorded events again. step through frames with the same fixed time step as when
+// recording to get the same simulation, or by time to replay at the recorded pace.
+#[derive(Debug, Clone)]
+pub struct InputPlayback {
+    recording: InputRecording,
+    next: usize,
+}
+
+impl InputPlayback {
+    pub fn new(recording: InputRecording) -> Self {
+        Self { recording, next: 0 }
+    }
+
+    // the events of the `frame`th frame since the playback started. frames must not go back.
+    pub fn frame(&mut self, frame: u64) -> Vec<WindowEvent> {
+        self.take_while(|recorded| recorded.frame <= frame)
+    }
+
+    // the events up to `elapsed` seconds since the playback started
+    pub fn until(&mut self, elapsed: f64) -> Vec<WindowEvent> {
+        self.take_while(|recorded| recorded.time <= elapsed)
+    }
+
+    fn take_while<F: Fn(&RecordedEvent) -> bool>(&mut self, due: F) -> Vec<WindowEvent> {
+        let start = self.next;
+        while self.next < self.recording.events.len() && due(&self.recording.events[self.next]) {
+            self.next += 1;
+        }
+        self.recording.events[start..self.next].iter().map(|recorded| recorded.event.clone()).collect()
+    }
+
+    // publishes the events of `frame` on the bus in place of the window's
+    pub fn publish_frame(&mut self, frame: u64, bus: &mut EventBus) {
+        bus.publish_all(self.frame(frame));
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.recording.events.len()
+    }
+
+    pub fn rewind(&mut self) {
+        self.next = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events() -> Vec<WindowEvent> {
+        vec![
+            WindowEvent::Key(Key::W, 17, Action::Press, Modifiers::Shift | Modifiers::Control),
+            WindowEvent::CursorPos(0.1, 1.0 / 3.0),
+            WindowEvent::MouseButton(MouseButton::Button2, Action::Release, Modifiers::empty()),
+            WindowEvent::Scroll(0.0, -1.5),
+            WindowEvent::Char('é'),
+            WindowEvent::CharModifiers('a', Modifiers::Alt),
+            WindowEvent::FramebufferSize(1920, 1080),
+            WindowEvent::Focus(false),
+            WindowEvent::ContentScale(1.25, 1.25),
+            WindowEvent::Close,
+        ]
+    }
+
+    #[test]
+    fn recordings_round_trip() {
+        let mut recorder = InputRecorder::new();
+        for (index, event) in events().iter().enumerate() {
+            recorder.record(100 + index as u64 / 3, 5.0 + index as f64 * 0.1, event);
+        }
+        recorder.record(110, 7.0, &WindowEvent::FileDrop(vec!["a".into()]));
+        let recording = recorder.finish();
+        assert_eq!(recording.events.len(), 10);
+        assert_eq!((recording.events[0].frame, recording.events[0].time), (0, 0.0));
+        assert_eq!(recording.last_frame(), Some(3));
+
+        let text = recording.to_text();
+        assert!(text.starts_with(HEADER));
+        assert_eq!(InputRecording::parse(&text).unwrap(), recording);
+    }
+
+    #[test]
+    fn bad_lines_are_errors() {
+        let message = |source: &str| InputRecording::parse(source).unwrap_err().to_string();
+        assert!(message("0 0.0\n").contains("line 1: expected frame, time and event"));
+        assert!(message("x 0.0 close\n").contains("bad frame"));
+        assert!(message("0 0.0 key 1000 0 1 0\n").contains("bad key 1000"));
+        assert!(message("0 0.0 key 87 0 5 0\n").contains("bad action 5"));
+        assert!(message("# header\n0 0.0 jump\n").contains("line 2: unknown event \"jump\""));
+        assert!(message("0 0.0 size 5\n").contains("missing argument 2"));
+    }
+
+    #[test]
+    fn playback_hands_out_events_by_frame_and_time() {
+        let mut recorder = InputRecorder::new();
+        let events = events();
+        for (index, event) in events.iter().enumerate() {
+            recorder.record(index as u64 / 2, index as f64, event);
+        }
+        let mut playback = InputPlayback::new(recorder.finish());
+        assert_eq!(playback.frame(0), events[..2].to_vec());
+        assert!(playback.frame(0).is_empty());
+        assert_eq!(playback.frame(2), events[2..6].to_vec());
+        assert_eq!(playback.until(7.5), events[6..8].to_vec());
+
+        let mut bus = EventBus::new();
+        playback.publish_frame(10, &mut bus);
+        bus.flush();
+        assert_eq!(bus.read::<WindowEvent>(), &events[8..]);
+        assert!(playback.is_finished());
+
+        playback.rewind();
+        assert!(!playback.is_finished());
+        assert_eq!(playback.until(0.0), events[..1].to_vec());
+    }
+}
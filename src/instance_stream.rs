@@ -0,0 +1,124 @@
+use std::mem;
+use std::ptr;
+use std::slice;
+
+use gl::types::*;
+
+use crate::context::check_render_thread;
+use crate::{FrameStats, GlContext, GpuInfo, Mesh};
+
+// persistently mapped buffers are split in this many regions, written in turn so the CPU
+// never overwrites instances the GPU may still be reading
+const REGIONS: usize = 3;
+
+// instance data rewritten every frame. with `Features::buffer_storage` the buffer stays
+// mapped and the regions are fenced, otherwise its storage is orphaned on every update.
+#[derive(Debug)]
+pub(crate) struct InstanceStream {
+    pub(crate) buffer: GLuint,
+    // in bytes, of each region when mapped
+    capacity: usize,
+    mapped: *mut u8,
+    fences: [GLsync; REGIONS],
+    region: usize,
+}
+
+impl Default for InstanceStream {
+    fn default() -> Self {
+        Self {
+            buffer: 0,
+            capacity: 0,
+            mapped: ptr::null_mut(),
+            fences: [ptr::null(); REGIONS],
+            region: 0,
+        }
+    }
+}
+
+impl InstanceStream {
+    // writes `data` and returns the offset it starts at in `buffer`
+    pub(crate) unsafe fn update(&mut self, data: &[u8]) -> usize {
+        check_render_thread("InstanceStream");
+        if self.buffer == 0 || data.len() > self.capacity {
+            self.allocate(data.len().next_power_of_two());
+        }
+        FrameStats::record_buffer_upload(data.len());
+
+        if self.mapped.is_null() {
+            // a fresh store, the driver keeps the old one alive for draws still reading it
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.buffer);
+            gl::BufferData(gl::ARRAY_BUFFER, conv!(self.capacity), ptr::null(), gl::STREAM_DRAW);
+            gl::BufferSubData(gl::ARRAY_BUFFER, 0, conv!(data.len()), data.as_ptr() as *const _);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            return 0;
+        }
+
+        // everything drawn from the current region has been issued by now
+        self.fences[self.region] = gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0);
+        self.region = (self.region + 1) % REGIONS;
+        let fence = mem::replace(&mut self.fences[self.region], ptr::null());
+        if !fence.is_null() {
+            while gl::ClientWaitSync(fence, gl::SYNC_FLUSH_COMMANDS_BIT, 1_000_000_000) == gl::TIMEOUT_EXPIRED {}
+            gl::DeleteSync(fence);
+        }
+        let offset = self.region * self.capacity;
+        slice::from_raw_parts_mut(self.mapped.add(offset), data.len()).copy_from_slice(data);
+        offset
+    }
+
+    unsafe fn allocate(&mut self, capacity: usize) {
+        self.destroy();
+        self.capacity = capacity.max(mem::size_of::<cgmath::Matrix4<f32>>());
+        gl::GenBuffers(1, &mut self.buffer);
+        gl::BindBuffer(gl::ARRAY_BUFFER, self.buffer);
+        if GpuInfo::current().features.buffer_storage {
+            let flags = gl::MAP_WRITE_BIT | gl::MAP_PERSISTENT_BIT | gl::MAP_COHERENT_BIT;
+            let size = self.capacity * REGIONS;
+            gl::BufferStorage(gl::ARRAY_BUFFER, conv!(size), ptr::null(), flags);
+            self.mapped = gl::MapBufferRange(gl::ARRAY_BUFFER, 0, conv!(size), flags) as *mut u8;
+        } else {
+            gl::BufferData(gl::ARRAY_BUFFER, conv!(self.capacity), ptr::null(), gl::STREAM_DRAW);
+        }
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+    }
+
+    pub(crate) fn destroy(&mut self) {
+        unsafe {
+            for fence in self.fences.iter_mut() {
+                if !fence.is_null() {
+                    gl::DeleteSync(*fence);
+                    *fence = ptr::null();
+                }
+            }
+            if !self.mapped.is_null() {
+                gl::BindBuffer(gl::ARRAY_BUFFER, self.buffer);
+                gl::UnmapBuffer(gl::ARRAY_BUFFER);
+                gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+                self.mapped = ptr::null_mut();
+            }
+            // deleting 0 is ignored
+            gl::DeleteBuffers(1, &self.buffer);
+        }
+        self.buffer = 0;
+        self.capacity = 0;
+        self.region = 0;
+    }
+}
+
+impl Mesh {
+    // per-instance data for the following `draw_instanced` calls, rewritten every frame for
+    // moving crowds or particles. each `T` starts with its model matrix, read at attributes
+    // 3 to 6, and the stride is the size of `T`.
+    pub fn update_instance_data<T: Copy>(&self, _context: &GlContext, instances: &[T]) {
+        assert!(mem::size_of::<T>() >= mem::size_of::<cgmath::Matrix4<f32>>(), "instances must start with a model matrix");
+        if instances.is_empty() {
+            return;
+        }
+        unsafe {
+            let bytes = slice::from_raw_parts(instances.as_ptr() as *const u8, mem::size_of_val(instances));
+            let mut stream = self.instance_stream.borrow_mut();
+            let offset = stream.update(bytes);
+            self.point_instances(stream.buffer, offset, mem::size_of::<T>());
+        }
+    }
+}
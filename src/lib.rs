@@ -1,3 +1,6 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::rc::Rc;
 use std::ffi::{CStr, CString};
 use std::ptr;
 use std::path::Path;
@@ -5,11 +8,14 @@ use std::str;
 use std::error::Error;
 use std::mem;
 
-use cgmath::{Deg, InnerSpace, Matrix, Matrix4, perspective, Point3, Vector2, Vector3, vec2, vec3};
+use cgmath::{InnerSpace, Matrix, Matrix3, Matrix4, Point3, Quaternion, Rotation, Rad, SquareMatrix, Vector2, Vector3, Vector4, vec3, vec4};
 use gl::types::*;
 use glfw::{Action, Key, Window, WindowEvent};
 use image::{open, DynamicImage::*, GenericImageView};
 
+use context::check_render_thread;
+use instance_stream::InstanceStream;
+
 #[macro_export]
 macro_rules! conv {
     ($e:expr) => {
@@ -17,9 +23,174 @@ macro_rules! conv {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+// for uniform names inside the crate; `name` must end with a nul byte
+pub(crate) fn c_str(name: &str) -> &CStr {
+    CStr::from_bytes_with_nul(name.as_bytes()).unwrap()
+}
+
+mod ao_bake;
+mod benchmark;
+mod bvh;
+mod camera;
+mod camera_path;
+mod camera_shake;
+mod clustered;
+mod color;
+mod config;
+mod context;
+mod debug_draw;
+mod debug_overlay;
+mod debug_view;
+mod decal;
+mod determinism;
+mod events;
+mod features;
+mod fog;
+mod framebuffer;
+mod frustum;
+mod god_rays;
+mod golden;
+mod gpu_culling;
+mod gpu_info;
+mod graphics_settings;
+mod indirect;
+mod input_recording;
+mod instance_stream;
+mod light;
+mod lightmap;
+mod line_renderer;
+mod mesh_data;
+mod model_data;
+mod net;
+mod noise;
+mod normal_visualizer;
+mod pbr;
+mod pixel_upload;
+mod point_cloud;
+mod post;
+mod query;
+mod readback;
+mod renderer;
+mod scene;
+mod schedule;
+mod shader_builder;
+mod shadow;
+mod simplify;
+mod sky;
+mod snapshot;
+mod spline;
+mod srgb;
+mod standard;
+mod state;
+mod static_batch;
+mod stats;
+mod stereo;
+mod sun_cycle;
+mod tangents;
+mod test_context;
+mod texture_3d;
+mod texture_array;
+mod texture_builder;
+mod texture_streaming;
+mod time;
+mod transform_feedback;
+mod vertex_cache;
+mod viewport;
+mod volumetric_fog;
+mod voxel;
+mod world_streaming;
+
+pub use ao_bake::{AoBaker, OcclusionMap};
+pub use benchmark::{Benchmark, BenchmarkConfig, BenchmarkReport, FrameTiming, TimingSummary};
+pub use bvh::{Bvh, Ray, RayHit};
+pub use camera::{Camera, CameraPose, FreeCamera};
+pub use camera_path::{CameraPath, CameraPathPlayer, Easing, Keyframe, PathInterpolation};
+pub use camera_shake::CameraShake;
+pub use clustered::{ClusterConfig, ClusterProjection, ClusteredLighting, CLUSTERED_LIGHTING_GLSL};
+pub use color::{linear_to_srgb, srgb_to_linear, Color};
+pub use config::{ConfigError, EngineConfig};
+pub use context::GlContext;
+pub use debug_draw::DebugDraw;
+pub use debug_overlay::DebugOverlay;
+pub use debug_view::{DebugView, DebugViews};
+pub use decal::{Decal, DecalRenderer};
+pub use determinism::{Determinism, SimRng};
+pub use events::{AssetReloaded, Collision, EventBus};
+pub use features::{buffer_storage, create_buffer, enable_debug_output, CompressedFormat, Features};
+pub use fog::{FogMode, FogSettings, FOG_GLSL};
+pub use framebuffer::{AttachmentFormat, AttachmentStorage, DepthSampler, Framebuffer, FramebufferBuilder, ResizePolicy};
+pub use frustum::{Frustum, Plane};
+pub use god_rays::GodRays;
+pub use golden::{compare_images, read_image, render_and_compare, GoldenError, ImageDiff, UPDATE_GOLDEN_ENV};
+pub use gpu_culling::GpuCulling;
+pub use gpu_info::GpuInfo;
+pub use graphics_settings::{AppliedSettings, GraphicsSettings, GraphicsTargets};
+pub use indirect::{DrawArraysIndirectCommand, DrawElementsIndirectCommand, IndirectBuffer, IndirectCommand};
+pub use input_recording::{InputPlayback, InputRecorder, InputRecording, InputRecordingError, RecordedEvent};
+pub use light::{DirectionalLight, PointLight, SpotLight, COOKIE_GLSL};
+pub use lightmap::{Lightmap, LightmapBaker};
+pub use line_renderer::{LinePoint, LineRenderer};
+pub use mesh_data::MeshData;
+pub use model_data::ModelData;
+pub use net::{Client, PlayerPose, Server, SnapshotDelta};
+pub use noise::{Noise, NoiseKind};
+pub use normal_visualizer::NormalVisualizer;
+pub use pbr::PbrMaterial;
+pub use pixel_upload::{PixelUploader, StagingBuffer};
+pub use point_cloud::{CloudPoint, PointCloud, PointCloudError, PointCloudRenderer, PointReader};
+pub use post::{AsAny, ColorGrading, DepthOfField, Fade, FilmGrain, FullscreenQuad, MotionBlur, PostContext, PostEffect, PostEffectId, PostStack, Vignette, FULLSCREEN_VERTEX_SHADER};
+pub use query::{Query, QueryKind};
+pub use readback::Readback;
+pub use renderer::{PassContext, PassDesc, PassKind, Renderer, View, DEPTH_ONLY_FRAGMENT_SHADER};
+pub use scene::{LoadProgress, Scene, SceneDesc, SceneEvent, SceneManager};
+pub use schedule::{Schedule, ScheduleError, Stage, StageContext, SystemConfig};
+pub use shader_builder::{FeedbackBufferMode, ShaderBuilder};
+pub use shadow::{directional_light_space, ShadowMap, ShadowSettings, SHADOW_GLSL};
+pub use sky::ProceduralSky;
+pub use snapshot::{SaveState, Snapshot, SnapshotError};
+pub use spline::{rotation_minimizing_frames, ArcLength, BSpline, BezierEasing, CatmullRom, CubicBezier, Curve, CurveFrame};
+pub use srgb::{default_framebuffer_is_srgb, request_srgb_framebuffer, with_srgb_writes, OutputEncoding};
+pub use standard::{set_standard_uniforms, standard_shader, StandardLights, MAX_POINT_LIGHTS, STANDARD_FRAGMENT_SHADER, STANDARD_VERTEX_SHADER};
+pub use state::{GameState, StateStack, Transition};
+pub use static_batch::StaticBatch;
+pub use stats::{CullResult, CullingStats, FrameStats};
+pub use stereo::{Eye, StereoRenderer, StereoSettings};
+pub use sun_cycle::{color_temperature, SunCycle};
+pub use tangents::compute_tangents;
+pub use test_context::{TestContext, TestContextError};
+pub use texture_3d::Texture3D;
+pub use texture_array::TextureArray;
+pub use texture_builder::{default_anisotropy, set_default_anisotropy, PixelFormat, TextureBuilder};
+pub use texture_streaming::{Residency, StreamedTextureId, StreamingConfig, TextureStreamer};
+pub use time::Time;
+pub use transform_feedback::{FeedbackPrimitive, TransformFeedback};
+pub use viewport::{Rect, Viewport, ViewUniforms, VIEW_UNIFORMS_GLSL};
+pub use volumetric_fog::VolumetricFog;
+pub use voxel::{block_textures, greedy_mesh, BlockId, BlockMaterial, BlockRegistry, VoxelChunk, VoxelRenderer, VoxelWorld, AIR, VOXEL_CHUNK_SIZE};
+pub use world_streaming::{
+    Chunk, ChunkCoord, ChunkData, ChunkSource, HeightfieldSource, StreamingWorld, WorldStreamingConfig, WorldStreamingStats,
+};
+
+#[derive(Debug)]
+struct ProgramHandle {
+    // 0 once invalidated
+    id: Cell<GLuint>,
+}
+
+impl Drop for ProgramHandle {
+    fn drop(&mut self) {
+        check_render_thread("ProgramHandle");
+        unsafe {
+            gl::DeleteProgram(self.id.get());
+        }
+    }
+}
+
+// shared handle to a linked program, the last clone deletes it.
+// a shader can only be created with a current context and is not `Send`, so its methods are safe.
+#[derive(Debug, Clone)]
 pub struct Shader {
-    id: GLuint,
+    program: Rc<ProgramHandle>,
 }
 
 #[derive(Debug)]
@@ -33,6 +204,8 @@ impl std::fmt::Display for CreateShaderError {
     }
 }
 
+impl Error for CreateShaderError {}
+
 struct DeleteShaderOnDrop(GLuint);
 
 impl Drop for DeleteShaderOnDrop {
@@ -43,40 +216,42 @@ impl Drop for DeleteShaderOnDrop {
     }
 }
 
-unsafe fn compile_shader(ty: GLuint, src: &str) -> DeleteShaderOnDrop {
-    let shader = gl::CreateShader(ty);
+unsafe fn compile_shader(ty: GLuint, src: &str) -> Result<DeleteShaderOnDrop, CreateShaderError> {
+    let shader = DeleteShaderOnDrop(gl::CreateShader(ty));
     let src = CString::new(src.as_bytes()).unwrap();
-    gl::ShaderSource(shader, 1, &src.as_ptr(), ptr::null());
-    gl::CompileShader(shader);
+    gl::ShaderSource(shader.0, 1, &src.as_ptr(), ptr::null());
+    gl::CompileShader(shader.0);
 
     let mut success = conv!(gl::FALSE);
-    gl::GetShaderiv(shader, gl::COMPILE_STATUS, &mut success);
+    gl::GetShaderiv(shader.0, gl::COMPILE_STATUS, &mut success);
     if success != conv!(gl::TRUE) {
         let mut info_log = vec![0; 512];
         gl::GetShaderInfoLog(
-            shader,
+            shader.0,
             512,
             ptr::null_mut(),
             info_log.as_mut_ptr() as *mut GLchar,
         );
         let pos = info_log.iter().position(|&x| x == 0).unwrap();
-        panic!(
-            "failed to compile {} shader: {}",
-            match ty {
-                gl::VERTEX_SHADER => "vertex",
-                gl::GEOMETRY_SHADER => "geometry",
-                gl::FRAGMENT_SHADER => "fragment",
-                _ => "unknown"
-            },
-            CStr::from_bytes_with_nul(&info_log[0..(pos + 1)])
-                .unwrap()
-                .to_string_lossy(),
-        );
+        return Err(CreateShaderError {
+            message: format!(
+                "failed to compile {} shader: {}",
+                match ty {
+                    gl::VERTEX_SHADER => "vertex",
+                    gl::GEOMETRY_SHADER => "geometry",
+                    gl::FRAGMENT_SHADER => "fragment",
+                    _ => "unknown"
+                },
+                CStr::from_bytes_with_nul(&info_log[0..(pos + 1)])
+                    .unwrap()
+                    .to_string_lossy(),
+            ),
+        });
     }
-    DeleteShaderOnDrop(shader)
+    Ok(shader)
 }
 
-unsafe fn link_program(shader_program: GLuint) {
+unsafe fn link_program(shader_program: GLuint) -> Result<(), CreateShaderError> {
     gl::LinkProgram(shader_program);
 
     let mut success = conv!(gl::FALSE);
@@ -90,80 +265,184 @@ unsafe fn link_program(shader_program: GLuint) {
             info_log.as_mut_ptr() as *mut GLchar,
         );
         let pos = info_log.iter().position(|&x| x == 0).unwrap();
-        panic!(
-            "failed to link program: {}",
-            CStr::from_bytes_with_nul(&info_log[0..(pos + 1)])
-                .unwrap()
-                .to_string_lossy()
-        );
+        return Err(CreateShaderError {
+            message: format!(
+                "failed to link program: {}",
+                CStr::from_bytes_with_nul(&info_log[0..(pos + 1)])
+                    .unwrap()
+                    .to_string_lossy()
+            ),
+        });
     }
+    Ok(())
 }
 
 impl Shader {
-    pub unsafe fn from_str(vertex: &str, fragment: &str) -> Self {
-        let vertex_shader = compile_shader(gl::VERTEX_SHADER, vertex);
-        let fragment_shader = compile_shader(gl::FRAGMENT_SHADER, fragment);
+    fn from_program(id: GLuint) -> Self {
+        Self {
+            program: Rc::new(ProgramHandle { id: Cell::new(id) }),
+        }
+    }
 
-        let shader_program = gl::CreateProgram();
-        gl::AttachShader(shader_program, vertex_shader.0);
-        gl::AttachShader(shader_program, fragment_shader.0);
-        link_program(shader_program);
+    pub fn id(&self) -> GLuint {
+        self.program.id.get()
+    }
 
-        Self { id: shader_program }
+    pub fn is_valid(&self) -> bool {
+        self.id() != 0
+    }
+
+    // deletes the program now for every clone of this handle, e.g. when hot reload replaces it.
+    // using an invalidated shader binds no program.
+    pub fn invalidate(&self) {
+        unsafe {
+            gl::DeleteProgram(self.id());
+            self.program.id.set(0);
+        }
+    }
+
+    // moves the program of `new` into this handle and all its clones, deleting the old one
+    pub fn replace(&self, new: Shader) {
+        self.invalidate();
+        self.program.id.set(new.program.id.replace(0));
+    }
+
+    pub fn new(_context: &GlContext, vertex: &str, fragment: &str) -> Self {
+        unsafe { Self::from_str(vertex, fragment) }
+    }
+
+    pub fn with_geometry(_context: &GlContext, vertex: &str, geometry: &str, fragment: &str) -> Self {
+        unsafe { Self::with_geometry_shader(vertex, geometry, fragment) }
+    }
+
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread, `Shader::new` checks that with a `GlContext`.
+    pub unsafe fn from_str(vertex: &str, fragment: &str) -> Self {
+        Self::builder()
+            .vertex(vertex)
+            .fragment(fragment)
+            .link()
+            .unwrap_or_else(|e| panic!("{}", e))
     }
 
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread, `Shader::new` checks that with a `GlContext`.
     pub unsafe fn with_geometry_shader(vertex: &str, geometry: &str, fragment: &str) -> Self {
-        let vertex_shader = compile_shader(gl::VERTEX_SHADER, vertex);
-        let geometry_shader = compile_shader(gl::GEOMETRY_SHADER, geometry);
-        let fragment_shader = compile_shader(gl::FRAGMENT_SHADER, fragment);
-
-        let shader_program = gl::CreateProgram();
-        gl::AttachShader(shader_program, vertex_shader.0);
-        gl::AttachShader(shader_program, geometry_shader.0);
-        gl::AttachShader(shader_program, fragment_shader.0);
-        link_program(shader_program);
-        
-        Self { id: shader_program }
+        Self::builder()
+            .vertex(vertex)
+            .geometry(geometry)
+            .fragment(fragment)
+            .link()
+            .unwrap_or_else(|e| panic!("{}", e))
     }
 
-    pub unsafe fn use_program(&self) {
-        gl::UseProgram(self.id);
+    pub fn builder<'a>() -> ShaderBuilder<'a> {
+        ShaderBuilder::default()
     }
 
-    unsafe fn get_uniform_location(&self, name: &CStr) -> GLint {
-        let result = gl::GetUniformLocation(self.id, name.as_ptr());
-        if result == -1 {
-            log::warn!("failed to retrieve uniform location: {}", name.to_string_lossy());
+    pub fn use_program(&self) {
+        check_render_thread("Shader");
+        unsafe {
+            gl::UseProgram(self.id());
         }
-        result
     }
 
-    pub unsafe fn set_float(&self, name: &CStr, value: f32) {
-        gl::Uniform1f(self.get_uniform_location(name), value);
+    fn get_uniform_location(&self, name: &CStr) -> GLint {
+        unsafe {
+            let result = gl::GetUniformLocation(self.id(), name.as_ptr());
+            if result == -1 {
+                log::warn!("failed to retrieve uniform location: {}", name.to_string_lossy());
+            }
+            result
+        }
+    }
+
+    pub fn set_float(&self, name: &CStr, value: f32) {
+        unsafe {
+            gl::Uniform1f(self.get_uniform_location(name), value);
+        }
     }
 
-    pub unsafe fn set_integer(&self, name: &CStr, value: i32) {
-        gl::Uniform1i(self.get_uniform_location(name), value);
+    pub fn set_integer(&self, name: &CStr, value: i32) {
+        unsafe {
+            gl::Uniform1i(self.get_uniform_location(name), value);
+        }
     }
 
-    pub unsafe fn set_matrix4(&self, name: &CStr, mat: &Matrix4<f32>) {
-        gl::UniformMatrix4fv(self.get_uniform_location(name), 1, gl::FALSE, mat.as_ptr());
+    pub fn set_matrix4(&self, name: &CStr, mat: &Matrix4<f32>) {
+        unsafe {
+            gl::UniformMatrix4fv(self.get_uniform_location(name), 1, gl::FALSE, mat.as_ptr());
+        }
     }
 
-    pub unsafe fn set_vec2(&self, name: &CStr, x: f32, y: f32) {
-        gl::Uniform2f(self.get_uniform_location(name), x, y);
+    pub fn set_matrix3(&self, name: &CStr, mat: &Matrix3<f32>) {
+        unsafe {
+            gl::UniformMatrix3fv(self.get_uniform_location(name), 1, gl::FALSE, mat.as_ptr());
+        }
     }
 
-    pub unsafe fn set_vec3(&self, name: &CStr, x: f32, y: f32, z: f32) {
-        gl::Uniform3f(self.get_uniform_location(name), x, y, z);
+    // whether the program has an active uniform called `name`, without warning if not
+    pub fn has_uniform(&self, name: &CStr) -> bool {
+        unsafe {
+            gl::GetUniformLocation(self.id(), name.as_ptr()) != -1
+        }
+    }
+
+    // sets `model`, plus `normalMatrix` if the shader declares it
+    pub fn set_model_matrix(&self, model: &Matrix4<f32>) {
+        self.set_matrix4(c_str("model\0"), model);
+        if self.has_uniform(c_str("normalMatrix\0")) {
+            let upper = Matrix3::from_cols(model.x.truncate(), model.y.truncate(), model.z.truncate());
+            let normal = upper.invert().unwrap_or_else(Matrix3::identity).transpose();
+            self.set_matrix3(c_str("normalMatrix\0"), &normal);
+        }
+    }
+
+    pub fn set_vec2(&self, name: &CStr, x: f32, y: f32) {
+        unsafe {
+            gl::Uniform2f(self.get_uniform_location(name), x, y);
+        }
+    }
+
+    pub fn set_vec3(&self, name: &CStr, x: f32, y: f32, z: f32) {
+        unsafe {
+            gl::Uniform3f(self.get_uniform_location(name), x, y, z);
+        }
+    }
+
+    pub fn set_vec4(&self, name: &CStr, x: f32, y: f32, z: f32, w: f32) {
+        unsafe {
+            gl::Uniform4f(self.get_uniform_location(name), x, y, z, w);
+        }
+    }
+
+    pub fn set_uvec3(&self, name: &CStr, x: u32, y: u32, z: u32) {
+        unsafe {
+            gl::Uniform3ui(self.get_uniform_location(name), x, y, z);
+        }
     }
 
-    pub unsafe fn bind_uniform_block(&self, name: &CStr, binding_point: GLuint) {
-        let index = gl::GetUniformBlockIndex(self.id, name.as_ptr());
-        gl::UniformBlockBinding(self.id, index, binding_point);
+    pub fn bind_uniform_block(&self, name: &CStr, binding_point: GLuint) {
+        unsafe {
+            let index = gl::GetUniformBlockIndex(self.id(), name.as_ptr());
+            gl::UniformBlockBinding(self.id(), index, binding_point);
+        }
     }
 }
 
+// perspective projection whose far plane is at infinity
+pub fn infinite_perspective<A: Into<Rad<f32>>>(fovy: A, aspect: f32, near: f32) -> Matrix4<f32> {
+    let f = 1.0 / (fovy.into().0 / 2.0).tan();
+    Matrix4::new(
+        f / aspect, 0.0, 0.0, 0.0,
+        0.0, f, 0.0, 0.0,
+        0.0, 0.0, -1.0, -1.0,
+        0.0, 0.0, -2.0 * near, 0.0,
+    )
+}
+
 #[derive(Debug)]
 pub struct FPSCamera {
     position: Point3<f32>,
@@ -174,6 +453,8 @@ pub struct FPSCamera {
     last_x: f32,
     last_y: f32,
     ratio: f32,
+    near: f32,
+    far: f32,
     first_mouse: bool,
 }
 
@@ -195,17 +476,60 @@ impl FPSCamera {
             last_x: 0.0,
             last_y: 0.0,
             ratio,
+            near: 0.1,
+            far: 100.0,
             first_mouse: true,
         }
     }
 
+    // pass f32::INFINITY as `far` for an infinite far plane
+    pub fn with_clip_planes(mut self, near: f32, far: f32) -> Self {
+        self.set_clip_planes(near, far);
+        self
+    }
+
+    pub fn set_clip_planes(&mut self, near: f32, far: f32) {
+        assert!(near > 0.0 && far > near, "invalid clip planes: near = {}, far = {}", near, far);
+        self.near = near;
+        self.far = far;
+    }
+
+    pub fn near(&self) -> f32 {
+        self.near
+    }
+
+    pub fn far(&self) -> f32 {
+        self.far
+    }
+
+    // vertical field of view in degrees
+    pub fn fov(&self) -> f32 {
+        self.fov
+    }
+
+    pub fn ratio(&self) -> f32 {
+        self.ratio
+    }
+
+    pub fn set_ratio(&mut self, ratio: f32) {
+        self.ratio = ratio;
+    }
+
+    pub fn position(&self) -> Point3<f32> {
+        self.position
+    }
+
+    pub fn direction(&self) -> Vector3<f32> {
+        self.direction
+    }
+
     pub fn view(&self) -> Matrix4<f32> {
         let up = vec3(0.0, 1.0, 0.0);
         Matrix4::look_at_dir(self.position, self.direction, up)
     }
 
     pub fn projection(&self) -> Matrix4<f32> {
-        perspective(Deg(self.fov), self.ratio, 0.1, 100.0)
+        Camera::projection(self)
     }
 
     pub fn process_event(&mut self, event: &WindowEvent) {
@@ -230,12 +554,7 @@ impl FPSCamera {
                 self.yaw += xoffset * SENSITIVITY;
                 self.pitch += yoffset * SENSITIVITY;
 
-                if self.pitch > 89.0 {
-                    self.pitch = 89.0;
-                }
-                if self.pitch < -89.0 {
-                    self.pitch = -89.0;
-                }
+                self.pitch = self.pitch.clamp(-89.0, 89.0);
 
                 self.direction.x = self.pitch.to_radians().cos() * self.yaw.to_radians().cos();
                 self.direction.y = self.pitch.to_radians().sin();
@@ -243,15 +562,10 @@ impl FPSCamera {
                 self.direction = self.direction.normalize();
             }
             WindowEvent::Scroll(_xoffset, yoffset) => {
-                if self.fov >= 1.0 && self.fov <= 45.0 {
+                if (1.0..=45.0).contains(&self.fov) {
                     self.fov -= *yoffset as f32;
                 }
-                if self.fov <= 1.0 {
-                    self.fov = 1.0;
-                }
-                if self.fov >= 45.0 {
-                    self.fov = 45.0;
-                }
+                self.fov = self.fov.clamp(1.0, 45.0);
             }
             _ => {}
         }
@@ -275,44 +589,55 @@ impl FPSCamera {
     }
 }
 
+// the roll of `orientation` is lost, the FPS camera always keeps its up axis
+impl CameraPose for FPSCamera {
+    fn set_pose(&mut self, position: Point3<f32>, orientation: Quaternion<f32>) {
+        let direction = orientation.rotate_vector(vec3(0.0, 0.0, -1.0)).normalize();
+        self.position = position;
+        self.direction = direction;
+        self.pitch = direction.y.clamp(-1.0, 1.0).asin().to_degrees().clamp(-89.0, 89.0);
+        self.yaw = direction.z.atan2(direction.x).to_degrees();
+    }
+}
 
-pub unsafe fn load_texture<P: AsRef<Path>>(path: P) -> GLuint {
-    let img = open(path).expect("failed to open image file");
+impl Camera for FPSCamera {
+    fn position(&self) -> Point3<f32> {
+        FPSCamera::position(self)
+    }
 
-    let mut texture = 0;
-    gl::GenTextures(1, &mut texture);
+    fn view(&self) -> Matrix4<f32> {
+        FPSCamera::view(self)
+    }
+
+    fn fov(&self) -> f32 {
+        self.fov
+    }
 
-    let pixels = img.raw_pixels();
-
-    let format = match img {
-        ImageRgb8(_) => gl::RGB,
-        ImageRgba8(_) => gl::RGBA,
-        img => unimplemented!("image type not supported: {:?}", img.color()),
-    };
-
-    gl::BindTexture(gl::TEXTURE_2D, texture);
-    gl::TexImage2D(
-        gl::TEXTURE_2D,
-        0,
-        format as i32,
-        img.width() as i32,
-        img.height() as i32,
-        0,
-        format,
-        gl::UNSIGNED_BYTE,
-        pixels.as_ptr() as *const _,
-    );
-
-    gl::GenerateMipmap(gl::TEXTURE_2D);
-
-    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, if format == gl::RGBA { gl::CLAMP_TO_EDGE } else { gl::REPEAT } as i32);
-    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, if format == gl::RGBA { gl::CLAMP_TO_EDGE } else { gl::REPEAT } as i32);
-    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR_MIPMAP_LINEAR as i32);
-    gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::LINEAR as i32);
+    fn ratio(&self) -> f32 {
+        self.ratio
+    }
 
-    texture
+    fn near(&self) -> f32 {
+        self.near
+    }
+
+    fn far(&self) -> f32 {
+        self.far
+    }
+}
+
+/// panics on files that fail to load, use `TextureBuilder` to handle the error or pick the format
+///
+/// # Safety
+///
+/// a GL context must be current on the calling thread.
+pub unsafe fn load_texture<P: AsRef<Path>>(path: P) -> GLuint {
+    TextureBuilder::new().load_raw(path).expect("failed to open image file")
 }
 
+/// # Safety
+///
+/// a GL context must be current on the calling thread.
 pub unsafe fn load_cubemap<P: AsRef<Path>>(paths: &[P]) -> GLuint {
     let mut texture = 0;
     gl::GenTextures(1, &mut texture);
@@ -353,34 +678,181 @@ pub unsafe fn load_cubemap<P: AsRef<Path>>(paths: &[P]) -> GLuint {
 
 // not sure about alignment
 #[repr(C)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Vertex {
     pub position: Vector3<f32>,
     pub normal: Vector3<f32>,
     pub tex_coords: Vector2<f32>,
+    // multiplied into the albedo, white for meshes without vertex colors
+    pub color: Vector4<f32>,
+    // a second, non overlapping unwrap for lightmaps. OBJ has none, so it starts as `tex_coords`.
+    pub lightmap_coords: Vector2<f32>,
+    // xyz along +u, w the sign of the bitangent. see `compute_tangents`, zero falls back to screen space derivatives.
+    pub tangent: Vector4<f32>,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum TextureType {
     Diffuse,
     Specular,
+    // tangent space normals, uploaded as linear data
+    Normal,
+    // added on top of the lit color and written to the emissive output
+    Emissive,
+    // replaces the diffuse color in the ambient term
+    Ambient,
+    // single channel pbr maps from map_Pr, map_Pm and map_Ps, read from the red channel
+    Roughness,
+    Metallic,
+    Sheen,
+    // glTF packing: roughness in green, metallic in blue
+    MetallicRoughness,
+    // baked light over `Vertex::lightmap_coords`, multiplied with the diffuse color
+    Lightmap,
+    // ambient occlusion over `Vertex::lightmap_coords`, read from the red channel
+    Occlusion,
 }
 
-#[derive(Debug, Clone, Copy)]
-pub struct Texture {
+impl TextureType {
+    pub const ALL: [TextureType; 11] = [
+        TextureType::Diffuse,
+        TextureType::Specular,
+        TextureType::Normal,
+        TextureType::Emissive,
+        TextureType::Ambient,
+        TextureType::Roughness,
+        TextureType::Metallic,
+        TextureType::Sheen,
+        TextureType::MetallicRoughness,
+        TextureType::Lightmap,
+        TextureType::Occlusion,
+    ];
+
+    // the `material.has*Map` uniforms telling shaders whether a map is bound
+    const OPTIONAL_FLAGS: [(TextureType, &'static str); 5] = [
+        (TextureType::Normal, "material.hasNormalMap\0"),
+        (TextureType::Emissive, "material.hasEmissiveMap\0"),
+        (TextureType::Ambient, "material.hasAmbientMap\0"),
+        (TextureType::Lightmap, "material.hasLightmap\0"),
+        (TextureType::Occlusion, "material.hasOcclusionMap\0"),
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+
+    // maps painted as colors, stored in sRGB. the others hold linear data like normals or roughness.
+    pub fn is_color(self) -> bool {
+        matches!(self, TextureType::Diffuse | TextureType::Emissive | TextureType::Ambient)
+    }
+
+    // textures are bound as `material.texture_<name><n>`
+    pub fn uniform_name(self) -> &'static str {
+        match self {
+            TextureType::Diffuse => "diffuse",
+            TextureType::Specular => "specular",
+            TextureType::Normal => "normal",
+            TextureType::Emissive => "emissive",
+            TextureType::Ambient => "ambient",
+            TextureType::Roughness => "roughness",
+            TextureType::Metallic => "metallic",
+            TextureType::Sheen => "sheen",
+            TextureType::MetallicRoughness => "metallicRoughness",
+            TextureType::Lightmap => "lightmap",
+            TextureType::Occlusion => "occlusion",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TextureHandle {
     id: GLuint,
+    // false for textures wrapped with `Texture::from_id`
+    owned: bool,
+}
+
+impl Drop for TextureHandle {
+    fn drop(&mut self) {
+        check_render_thread("TextureHandle");
+        if self.owned {
+            unsafe {
+                gl::DeleteTextures(1, &self.id);
+            }
+        }
+    }
+}
+
+// shared handle to a GL texture, the last clone deletes it
+#[derive(Debug, Clone)]
+pub struct Texture {
+    handle: Rc<TextureHandle>,
     type_: TextureType,
 }
 
 impl Texture {
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
     pub unsafe fn new<P: AsRef<Path>>(path: P, type_: TextureType) -> Self {
+        let id = TextureBuilder::new().srgb(type_.is_color()).load_raw(path).expect("failed to open image file");
+        Self::adopt(id, type_)
+    }
+
+    pub fn load<P: AsRef<Path>>(_context: &GlContext, path: P, type_: TextureType) -> Self {
+        unsafe { Self::new(path, type_) }
+    }
+
+    // takes ownership of a texture created elsewhere
+    pub fn adopt(id: GLuint, type_: TextureType) -> Self {
         Self {
-            id: load_texture(path),
+            handle: Rc::new(TextureHandle { id, owned: true }),
+            type_,
+        }
+    }
+
+    // wraps a texture created elsewhere, it is not deleted
+    pub fn from_id(id: GLuint, type_: TextureType) -> Self {
+        Self {
+            handle: Rc::new(TextureHandle { id, owned: false }),
+            type_,
+        }
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.handle.id
+    }
+
+    pub fn type_(&self) -> TextureType {
+        self.type_
+    }
+
+    // the same texture used in another role, sharing the GL object
+    pub fn with_type(&self, type_: TextureType) -> Self {
+        Self {
+            handle: self.handle.clone(),
             type_,
         }
     }
 }
 
+// textures bound in place of a mesh's own ones. an empty material binds nothing,
+// which suits passes whose shader samples no textures (depth only, outlines).
+#[derive(Debug, Clone, Default)]
+pub struct Material {
+    pub textures: Vec<Texture>,
+}
+
+impl Material {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_texture(mut self, texture: Texture) -> Self {
+        self.textures.push(texture);
+        self
+    }
+}
+
 #[derive(Debug)]
 pub struct Mesh {
     pub verticies: Vec<Vertex>,
@@ -389,213 +861,508 @@ pub struct Mesh {
     vao: GLuint,
     vbo: GLuint,
     ebo: GLuint,
+    // the buffer, offset and stride attributes 3 to 6 take the instance matrices from
+    instance_source: Cell<(GLuint, usize, usize)>,
+    instance_stream: RefCell<InstanceStream>,
 }
 
 impl Mesh {
-    pub unsafe fn new(verticies: Vec<Vertex>, indices: Vec<GLuint>, textures: Vec<Texture>) -> Self {
-        // require a vertex is tightly packed
-        let vertex_size = mem::size_of::<Vertex>();
-        assert!(vertex_size == mem::size_of::<f32>() * 8, "size of vertex is: {}", vertex_size);
-
-        let mut mesh = Mesh {
-            verticies,
-            indices,
-            textures,
-            vao: 0,
-            vbo: 0,
-            ebo: 0,
-        };
-
-        gl::GenVertexArrays(1, &mut mesh.vao);
-        gl::GenBuffers(1, &mut mesh.vbo);
-        gl::GenBuffers(1, &mut mesh.ebo);
-
-        gl::BindVertexArray(mesh.vao);
-        gl::BindBuffer(gl::ARRAY_BUFFER, mesh.vbo);
-
-        gl::BufferData(
-            gl::ARRAY_BUFFER,
-            conv!(mesh.verticies.len() * vertex_size),
-            mesh.verticies.as_ptr() as *const _,
-            gl::STATIC_DRAW,
-        );
-
-        gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, mesh.ebo);
-        gl::BufferData(
-            gl::ELEMENT_ARRAY_BUFFER,
-            conv!(mesh.indices.len() * mem::size_of::<GLuint>()),
-            mesh.indices.as_ptr() as *const _,
-            gl::STATIC_DRAW,
-        );
-
-        // position
-        gl::EnableVertexAttribArray(0);
-        gl::VertexAttribPointer(
-            0, 
-            3, 
-            gl::FLOAT, 
-            gl::FALSE, 
-            conv!(vertex_size),
-            ptr::null(),
-        );
-
-        // normal
-        gl::EnableVertexAttribArray(1);
-        gl::VertexAttribPointer(
-            1,
-            3,
-            gl::FLOAT,
-            gl::FALSE,
-            conv!(vertex_size),
-            (3 * mem::size_of::<f32>()) as *const _,
-        );
-
-        // texture coordinate
-        gl::EnableVertexAttribArray(2);
-        gl::VertexAttribPointer(
-            2,
-            2,
-            gl::FLOAT,
-            gl::FALSE,
-            conv!(vertex_size),
-            (6 * mem::size_of::<f32>()) as *const _,
-        );
-
-        // reset global vao
-        gl::BindVertexArray(0);
+    pub fn new(_context: &GlContext, verticies: Vec<Vertex>, indices: Vec<GLuint>, textures: Vec<Texture>) -> Self {
+        unsafe {
+            // require a vertex is tightly packed
+            let vertex_size = mem::size_of::<Vertex>();
+            assert!(vertex_size == mem::size_of::<f32>() * 18, "size of vertex is: {}", vertex_size);
+
+            let mut mesh = Mesh {
+                verticies,
+                indices,
+                textures,
+                vao: 0,
+                vbo: 0,
+                ebo: 0,
+                instance_source: Cell::new((0, 0, 0)),
+                instance_stream: RefCell::default(),
+            };
+
+            gl::GenVertexArrays(1, &mut mesh.vao);
+            gl::BindVertexArray(mesh.vao);
+
+            mesh.vbo = features::create_buffer(
+                gl::ARRAY_BUFFER,
+                mesh.verticies.len() * vertex_size,
+                mesh.verticies.as_ptr() as *const _,
+                gl::STATIC_DRAW,
+            );
+
+            mesh.ebo = features::create_buffer(
+                gl::ELEMENT_ARRAY_BUFFER,
+                mesh.indices.len() * mem::size_of::<GLuint>(),
+                mesh.indices.as_ptr() as *const _,
+                gl::STATIC_DRAW,
+            );
+
+            // position
+            gl::EnableVertexAttribArray(0);
+            gl::VertexAttribPointer(
+                0, 
+                3, 
+                gl::FLOAT, 
+                gl::FALSE, 
+                conv!(vertex_size),
+                ptr::null(),
+            );
+
+            // normal
+            gl::EnableVertexAttribArray(1);
+            gl::VertexAttribPointer(
+                1,
+                3,
+                gl::FLOAT,
+                gl::FALSE,
+                conv!(vertex_size),
+                (3 * mem::size_of::<f32>()) as *const _,
+            );
+
+            // texture coordinate
+            gl::EnableVertexAttribArray(2);
+            gl::VertexAttribPointer(
+                2,
+                2,
+                gl::FLOAT,
+                gl::FALSE,
+                conv!(vertex_size),
+                (6 * mem::size_of::<f32>()) as *const _,
+            );
+
+            // color, after the instance matrix in 3 to 6
+            gl::EnableVertexAttribArray(7);
+            gl::VertexAttribPointer(
+                7,
+                4,
+                gl::FLOAT,
+                gl::FALSE,
+                conv!(vertex_size),
+                (8 * mem::size_of::<f32>()) as *const _,
+            );
+
+            // lightmap coordinate
+            gl::EnableVertexAttribArray(8);
+            gl::VertexAttribPointer(
+                8,
+                2,
+                gl::FLOAT,
+                gl::FALSE,
+                conv!(vertex_size),
+                (12 * mem::size_of::<f32>()) as *const _,
+            );
+
+            // tangent
+            gl::EnableVertexAttribArray(9);
+            gl::VertexAttribPointer(
+                9,
+                4,
+                gl::FLOAT,
+                gl::FALSE,
+                conv!(vertex_size),
+                (14 * mem::size_of::<f32>()) as *const _,
+            );
+
+            // reset global vao
+            gl::BindVertexArray(0);
+
+            mesh
+        }
+    }
 
-        mesh
+    unsafe fn set_texture(&self, shader: &Shader) {
+        Self::bind_textures(shader, &self.textures);
     }
 
-    unsafe fn set_texture(&self, shader: Shader) {
-        let mut diffuse_num = 0;
-        let mut specular_num = 0;
+    unsafe fn bind_textures(shader: &Shader, textures: &[Texture]) {
+        // textures of each type are numbered from 1 in the order they appear
+        let mut counts = [0; TextureType::ALL.len()];
 
-        for (i, texture) in self.textures.iter().enumerate() {
+        for (i, texture) in textures.iter().enumerate() {
             let i: GLuint = conv!(i);
             gl::ActiveTexture(gl::TEXTURE0 + i);
 
-            match texture.type_ {
-                TextureType::Diffuse => {
-                    diffuse_num += 1;
-                    let name = CString::new(format!("material.texture_diffuse{}", diffuse_num)).unwrap();
-                    shader.set_integer(name.as_ref(), conv!(i));
-                }
-                TextureType::Specular => {
-                    specular_num += 1;
-                    let name = CString::new(format!("material.texture_specular{}", specular_num)).unwrap();
-                    shader.set_integer(name.as_ref(), conv!(i));
-                }
-            }
+            let count = &mut counts[texture.type_.index()];
+            *count += 1;
+            let name = CString::new(format!("material.texture_{}{}", texture.type_.uniform_name(), count)).unwrap();
+            shader.set_integer(name.as_ref(), conv!(i));
 
-            gl::BindTexture(gl::TEXTURE_2D, texture.id);
+            gl::BindTexture(gl::TEXTURE_2D, texture.id());
+            FrameStats::record_texture_bind();
         }
 
         // reset active texture: needed?
         gl::ActiveTexture(gl::TEXTURE0);
+
+        // optional maps are switched off for meshes without them, e.g. keeping the interpolated normal
+        for &(type_, flag) in TextureType::OPTIONAL_FLAGS.iter() {
+            let flag = c_str(flag);
+            if shader.has_uniform(flag) {
+                shader.set_integer(flag, (counts[type_.index()] > 0) as i32);
+            }
+        }
     }
 
-    pub unsafe fn draw(&self, shader: Shader) {
-        self.set_texture(shader);
+    pub fn draw(&self, _context: &GlContext, shader: &Shader) {
+        unsafe {
+            self.set_texture(shader);
 
-        // draw mesh
+            // draw mesh
+            self.draw_elements();
+        }
+    }
+
+    // sets the `model` uniform (see `Shader::set_model_matrix`) before drawing
+    pub fn draw_at(&self, context: &GlContext, shader: &Shader, model: &Matrix4<f32>) {
+        shader.set_model_matrix(model);
+        self.draw(context, shader);
+    }
+
+    // draws with `material` instead of the mesh's textures
+    pub fn draw_with_material(&self, _context: &GlContext, shader: &Shader, material: &Material) {
+        unsafe {
+            Self::bind_textures(shader, &material.textures);
+            self.draw_elements();
+        }
+    }
+
+    unsafe fn draw_elements(&self) {
+        check_render_thread("Mesh");
         gl::BindVertexArray(self.vao);
         gl::DrawElements(gl::TRIANGLES, conv!(self.indices.len()), gl::UNSIGNED_INT, ptr::null());
         gl::BindVertexArray(0);
+        FrameStats::record_draw(self.triangle_count(), 1);
     }
 
-    pub unsafe fn draw_instanced(&self, shader: Shader, amount: GLsizei) {
-        self.set_texture(shader);
+    // points the instance matrix attributes at `buffer`, a matrix every `stride` bytes from `offset`
+    pub(crate) unsafe fn point_instances(&self, buffer: GLuint, offset: usize, stride: usize) {
+        if self.instance_source.get() == (buffer, offset, stride) {
+            return;
+        }
+        self.instance_source.set((buffer, offset, stride));
 
-        // draw mesh
+        let vec4_size = 4 * mem::size_of::<f32>();
         gl::BindVertexArray(self.vao);
-        gl::DrawElementsInstanced(gl::TRIANGLES, conv!(self.indices.len()), gl::UNSIGNED_INT, ptr::null(), amount);
+        gl::BindBuffer(gl::ARRAY_BUFFER, buffer);
+        for column in 0..4 {
+            let location = 3 + column;
+            gl::EnableVertexAttribArray(location);
+            gl::VertexAttribPointer(location, 4, gl::FLOAT, gl::FALSE, conv!(stride), (offset + column as usize * vec4_size) as *const _);
+            gl::VertexAttribDivisor(location, 1);
+        }
         gl::BindVertexArray(0);
+        gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+    }
+
+    pub fn draw_instanced(&self, _context: &GlContext, shader: &Shader, amount: GLsizei) {
+        unsafe {
+            check_render_thread("Mesh");
+            self.set_texture(shader);
+
+            // draw mesh
+            gl::BindVertexArray(self.vao);
+            gl::DrawElementsInstanced(gl::TRIANGLES, conv!(self.indices.len()), gl::UNSIGNED_INT, ptr::null(), amount);
+            gl::BindVertexArray(0);
+            FrameStats::record_draw(self.triangle_count(), conv!(amount));
+        }
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
     }
 
-    pub unsafe fn vao(&self) -> GLuint {
+    // uploads `verticies` again after editing them in place, the count must not change
+    pub fn update_vertex_buffer(&self, _context: &GlContext) {
+        check_render_thread("Mesh");
+        unsafe {
+            let size = self.verticies.len() * mem::size_of::<Vertex>();
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            gl::BufferSubData(gl::ARRAY_BUFFER, 0, conv!(size), self.verticies.as_ptr() as *const _);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+            FrameStats::record_buffer_upload(size);
+        }
+    }
+
+    // bytes of the vertex and index buffers on the GPU
+    pub fn buffer_size(&self) -> usize {
+        self.verticies.len() * mem::size_of::<Vertex>() + self.indices.len() * mem::size_of::<GLuint>()
+    }
+
+    pub fn vao(&self) -> GLuint {
         self.vao
     }
+
+    // deletes the GPU buffers now instead of on drop, e.g. before the context goes away.
+    // the textures are shared and go away with their last handle.
+    pub fn destroy(&mut self) {
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+            gl::DeleteBuffers(1, &self.vbo);
+            gl::DeleteBuffers(1, &self.ebo);
+            self.vao = 0;
+            self.vbo = 0;
+            self.ebo = 0;
+        }
+        self.instance_stream.get_mut().destroy();
+        self.instance_source.set((0, 0, 0));
+    }
+}
+
+impl Drop for Mesh {
+    fn drop(&mut self) {
+        check_render_thread("Mesh");
+        // deleting 0 is ignored, so destroyed meshes are fine
+        self.destroy();
+    }
+}
+
+// a named part of a model, an OBJ object or group
+#[derive(Debug, Clone, PartialEq)]
+pub struct Node {
+    pub name: String,
+    // relative to the parent. OBJ has no transforms so they start as the identity.
+    pub transform: Matrix4<f32>,
+    pub visible: bool,
+    // indices into `Model::meshes`
+    pub meshes: Vec<usize>,
+    // index into `Model::nodes`, always before this node
+    pub parent: Option<usize>,
+}
+
+// a named attachment point on a node, e.g. where a weapon is held.
+// skeletal joints can carry sockets as well once models are skinned.
+#[derive(Debug, Clone)]
+pub struct Socket {
+    pub name: String,
+    // index into `Model::nodes`
+    pub node: usize,
+    // relative to the node
+    pub offset: Matrix4<f32>,
+}
+
+// tobj drops the `v x y z r g b [a]` extension, so the colors are picked out of the same
+// source text by the index of their `v` statement. empty when no vertex has a color.
+fn obj_vertex_colors(source: &str) -> Vec<Option<Vector4<f32>>> {
+    let mut colors = vec![];
+    for line in source.lines() {
+        let mut words = line.split_whitespace();
+        if words.next() != Some("v") {
+            continue;
+        }
+        let values: Vec<f32> = words.filter_map(|word| word.parse().ok()).collect();
+        colors.push(if values.len() >= 6 {
+            let alpha = values.get(6).cloned().unwrap_or(1.0);
+            Some(vec4(values[3], values[4], values[5], alpha))
+        } else {
+            None
+        });
+    }
+    if colors.iter().all(Option::is_none) {
+        colors.clear();
+    }
+    colors
+}
+
+// `source` with the position of every `v` statement replaced by its index, split in two to stay
+// exact as f32. tobj groups and reorders the vertices of this the same way as of `source`, so the
+// positions it returns tell which `v` statement each vertex came from.
+fn obj_vertex_indices(source: &str) -> String {
+    let mut indexed = String::with_capacity(source.len());
+    let mut index = 0u32;
+    for line in source.lines() {
+        if line.split_whitespace().next() == Some("v") {
+            indexed.push_str(&format!("v {} {} 0", index >> 16, index & 0xffff));
+            index += 1;
+        } else {
+            indexed.push_str(line);
+        }
+        indexed.push('\n');
+    }
+    indexed
+}
+
+fn obj_vertex_index(position: &[f32]) -> usize {
+    ((position[0] as usize) << 16) | position[1] as usize
 }
 
 #[derive(Debug)]
 pub struct Model {
     pub meshes: Vec<Mesh>,
+    pub nodes: Vec<Node>,
+    pub sockets: Vec<Socket>,
     //pub textures: Vec<Texture>,
+    // per-instance model matrices shared by all meshes, at attributes 3 to 6
+    instances: RefCell<InstanceStream>,
 }
 
 impl Model {
-    pub unsafe fn load_obj<P: AsRef<Path>>(name: P) -> Result<Self, Box<dyn Error + 'static>> {
-        use std::collections::HashMap;
-        use std::collections::hash_map::Entry::*;
-
-        let name = name.as_ref();
-        let mut meshes = vec![];
+    pub fn load_obj<P: AsRef<Path>>(context: &GlContext, name: P) -> Result<Self, Box<dyn Error + 'static>> {
+        Ok(ModelData::load_obj(name)?.upload(context))
+    }
 
-        let (models, materials) = tobj::load_obj(name)?;
-        
-        for model in models.into_iter() {
-            let mesh = model.mesh;
-
-            let len = mesh.positions.len() / 3;
-            let mut verticies = Vec::with_capacity(len);
-
-            for i in 0..len {
-                verticies.push(Vertex {
-                    position: vec3(mesh.positions[3 * i], mesh.positions[3 * i + 1], mesh.positions[3 * i + 2]),
-                    normal: vec3(mesh.normals[3 * i], mesh.normals[3 * i + 1], mesh.normals[3 * i + 2]),
-                    tex_coords: vec2(mesh.texcoords[2 * i], mesh.texcoords[2 * i + 1]),
-                });
+    // draws the meshes of the visible nodes, a hidden node hides its children too.
+    // the `model` uniform is left to the caller, use `draw_at` to apply the node transforms.
+    pub fn draw(&self, context: &GlContext, shader: &Shader) {
+        for (index, node) in self.nodes.iter().enumerate() {
+            if self.is_visible(index) {
+                for &mesh in node.meshes.iter() {
+                    self.meshes[mesh].draw(context, shader);
+                }
             }
+        }
+    }
 
-            let mut loaded_textures = HashMap::new();
+    // draws the visible nodes placed by `model` and their node transforms
+    pub fn draw_at(&self, context: &GlContext, shader: &Shader, model: &Matrix4<f32>) {
+        for (index, node) in self.nodes.iter().enumerate() {
+            if self.is_visible(index) {
+                shader.set_model_matrix(&(model * self.node_transform(index)));
+                for &mesh in node.meshes.iter() {
+                    self.meshes[mesh].draw(context, shader);
+                }
+            }
+        }
+    }
 
-            let mut textures = vec![];
-            if let Some(material_id) = mesh.material_id {
-                let material = &materials[material_id];
+    // draws the visible nodes once per matrix, which the vertex shader reads as a mat4 at location 3.
+    // node transforms are not applied.
+    pub fn draw_instanced(&self, context: &GlContext, shader: &Shader, models: &[Matrix4<f32>]) {
+        unsafe {
+            if models.is_empty() {
+                return;
+            }
 
-                if !material.diffuse_texture.is_empty() {
-                    let tex_name = name.with_file_name(&material.diffuse_texture);
+            let mut instances = self.instances.borrow_mut();
+            let bytes = std::slice::from_raw_parts(models.as_ptr() as *const u8, mem::size_of_val(models));
+            let offset = instances.update(bytes);
+            for mesh in self.meshes.iter() {
+                mesh.point_instances(instances.buffer, offset, mem::size_of::<Matrix4<f32>>());
+            }
 
-                    match loaded_textures.entry(tex_name) {
-                        Occupied(o) => textures.push(*o.get()),
-                        Vacant(v) => {
-                            let texture = Texture::new(v.key(), TextureType::Diffuse);
-                            v.insert(texture);
-                            textures.push(texture);
-                        }
+            for (index, node) in self.nodes.iter().enumerate() {
+                if self.is_visible(index) {
+                    for &mesh in node.meshes.iter() {
+                        self.meshes[mesh].draw_instanced(context, shader, conv!(models.len()));
                     }
                 }
+            }
+        }
+    }
 
-                if !material.specular_texture.is_empty() {
-                    let tex_name = name.with_file_name(&material.specular_texture);
+    // draws the whole model with `material`, leaving the stored textures alone
+    pub fn draw_with_material(&self, context: &GlContext, shader: &Shader, material: &Material) {
+        self.draw_with_overrides(context, shader, |_| Some(material));
+    }
 
-                    match loaded_textures.entry(tex_name) {
-                        Occupied(o) => textures.push(*o.get()),
-                        Vacant(v) => {
-                            let texture = Texture::new(v.key(), TextureType::Specular);
-                            v.insert(texture);
-                            textures.push(texture);
-                        }
+    // `material(index)` may replace the textures of the mesh at `index`, `None` keeps the mesh's own
+    pub fn draw_with_overrides<'a, F: Fn(usize) -> Option<&'a Material>>(&self, context: &GlContext, shader: &Shader, material: F) {
+        for (index, node) in self.nodes.iter().enumerate() {
+            if self.is_visible(index) {
+                for &mesh in node.meshes.iter() {
+                    match material(mesh) {
+                        Some(material) => self.meshes[mesh].draw_with_material(context, shader, material),
+                        None => self.meshes[mesh].draw(context, shader),
                     }
                 }
             }
-
-            meshes.push(Mesh::new(verticies, mesh.indices, textures));
         }
+    }
+
+    pub fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    pub fn node_index(&self, name: &str) -> Option<usize> {
+        self.nodes.iter().position(|node| node.name == name)
+    }
 
-        Ok(Self {
-            meshes,
-        })
+    pub fn node(&self, name: &str) -> Option<&Node> {
+        self.nodes.iter().find(|node| node.name == name)
     }
 
-    pub unsafe fn draw(&self, shader: Shader) {
-        for mesh in self.meshes.iter() {
-            mesh.draw(shader);
+    pub fn node_mut(&mut self, name: &str) -> Option<&mut Node> {
+        self.nodes.iter_mut().find(|node| node.name == name)
+    }
+
+    pub fn is_visible(&self, index: usize) -> bool {
+        let node = &self.nodes[index];
+        match node.parent {
+            Some(parent) => node.visible && self.is_visible(parent),
+            None => node.visible,
+        }
+    }
+
+    // the node's transform relative to the model, including its ancestors
+    pub fn node_transform(&self, index: usize) -> Matrix4<f32> {
+        let node = &self.nodes[index];
+        match node.parent {
+            Some(parent) => self.node_transform(parent) * node.transform,
+            None => node.transform,
         }
     }
 
     pub fn meshes(&self) -> &[Mesh] {
         &self.meshes
     }
+
+    // replaces a socket with the same name
+    pub fn add_socket(&mut self, name: &str, node: usize, offset: Matrix4<f32>) {
+        assert!(node < self.nodes.len(), "node {} does not exist", node);
+        let socket = Socket {
+            name: name.to_string(),
+            node,
+            offset,
+        };
+        match self.sockets.iter_mut().find(|socket| socket.name == name) {
+            Some(existing) => *existing = socket,
+            None => self.sockets.push(socket),
+        }
+    }
+
+    // the world transform of a socket, or of a node with that name, for a model placed by `model`.
+    // evaluated on every call so it follows the nodes as they move.
+    pub fn socket(&self, name: &str, model: &Matrix4<f32>) -> Option<Matrix4<f32>> {
+        match self.sockets.iter().find(|socket| socket.name == name) {
+            Some(socket) => Some(model * self.node_transform(socket.node) * socket.offset),
+            None => self.node_index(name).map(|index| model * self.node_transform(index)),
+        }
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.meshes.iter().map(Mesh::triangle_count).sum()
+    }
+
+    // approximate GPU memory of the buffers and the (shared) textures including their mipmaps
+    pub fn memory_estimate(&self, _context: &GlContext) -> usize {
+        unsafe {
+            let mut textures = HashSet::new();
+            let mut total = 0;
+            for mesh in self.meshes.iter() {
+                total += mesh.buffer_size();
+                for texture in mesh.textures.iter() {
+                    if textures.insert(texture.id()) {
+                        let (mut width, mut height) = (0, 0);
+                        gl::BindTexture(gl::TEXTURE_2D, texture.id());
+                        gl::GetTexLevelParameteriv(gl::TEXTURE_2D, 0, gl::TEXTURE_WIDTH, &mut width);
+                        gl::GetTexLevelParameteriv(gl::TEXTURE_2D, 0, gl::TEXTURE_HEIGHT, &mut height);
+                        // RGBA8, the mip chain adds a third
+                        total += width as usize * height as usize * 4 * 4 / 3;
+                    }
+                }
+            }
+            gl::BindTexture(gl::TEXTURE_2D, 0);
+            total
+        }
+    }
+}
+
+impl Drop for Model {
+    fn drop(&mut self) {
+        check_render_thread("Model");
+        self.instances.get_mut().destroy();
+    }
 }
@@ -0,0 +1,200 @@
+use std::ffi::{CStr, CString};
+
+use cgmath::{InnerSpace, Matrix4, perspective, Point3, Rad, Vector3, vec3};
+use gl::types::*;
+
+use crate::post::bind_texture;
+use crate::{Color, Shader};
+
+// GLSL for `SampleCookie`, which projects a spotlight cookie onto a world position
+macro_rules! cookie_glsl {
+    () => {
+        r#"
+vec3 SampleCookie(sampler2D cookie, mat4 projector, vec3 fragPos) {
+    vec4 projected = projector * vec4(fragPos, 1.0);
+    // behind the light
+    if (projected.w <= 0.0) {
+        return vec3(0.0);
+    }
+    vec2 coords = projected.xy / projected.w * 0.5 + 0.5;
+    if (any(lessThan(coords, vec2(0.0))) || any(greaterThan(coords, vec2(1.0)))) {
+        return vec3(0.0);
+    }
+    return texture(cookie, coords).rgb;
+}
+"#
+    };
+}
+
+pub(crate) use cookie_glsl;
+
+pub const COOKIE_GLSL: &str = cookie_glsl!();
+
+fn uniform(name: &str, field: &str) -> CString {
+    CString::new(format!("{}.{}", name, field)).unwrap()
+}
+
+// matches the `PointLight` struct used by the example shaders
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PointLight {
+    pub position: Point3<f32>,
+
+    pub ambient: Color,
+    pub diffuse: Color,
+    pub specular: Color,
+
+    pub constant: f32,
+    pub linear: f32,
+    pub quadratic: f32,
+}
+
+impl PointLight {
+    // distance at which the attenuation drops below 1/256 of the brightest channel
+    pub fn radius(&self) -> f32 {
+        let brightest = self.diffuse.r.max(self.diffuse.g).max(self.diffuse.b).max(self.specular.r.max(self.specular.g).max(self.specular.b));
+        let c = self.constant - 256.0 * brightest;
+        // black or dim enough lights never reach the threshold
+        if c >= 0.0 {
+            0.0
+        } else if self.quadratic > 0.0 {
+            (-self.linear + (self.linear * self.linear - 4.0 * self.quadratic * c).sqrt()) / (2.0 * self.quadratic)
+        } else if self.linear > 0.0 {
+            -c / self.linear
+        } else {
+            f32::INFINITY
+        }
+    }
+
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread and `shader` must be in use, the values are set with glUniform.
+    pub unsafe fn set_uniforms(&self, shader: &Shader, name: &str) {
+        shader.set_vec3(&uniform(name, "position"), self.position.x, self.position.y, self.position.z);
+        shader.set_vec3(&uniform(name, "ambient"), self.ambient.r, self.ambient.g, self.ambient.b);
+        shader.set_vec3(&uniform(name, "diffuse"), self.diffuse.r, self.diffuse.g, self.diffuse.b);
+        shader.set_vec3(&uniform(name, "specular"), self.specular.r, self.specular.g, self.specular.b);
+        shader.set_float(&uniform(name, "constant"), self.constant);
+        shader.set_float(&uniform(name, "linear"), self.linear);
+        shader.set_float(&uniform(name, "quadratic"), self.quadratic);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DirectionalLight {
+    // direction the light travels in
+    pub direction: Vector3<f32>,
+
+    pub ambient: Color,
+    pub diffuse: Color,
+    pub specular: Color,
+}
+
+impl DirectionalLight {
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread and `shader` must be in use, the values are set with glUniform.
+    pub unsafe fn set_uniforms(&self, shader: &Shader, name: &str) {
+        shader.set_vec3(&uniform(name, "direction"), self.direction.x, self.direction.y, self.direction.z);
+        shader.set_vec3(&uniform(name, "ambient"), self.ambient.r, self.ambient.g, self.ambient.b);
+        shader.set_vec3(&uniform(name, "diffuse"), self.diffuse.r, self.diffuse.g, self.diffuse.b);
+        shader.set_vec3(&uniform(name, "specular"), self.specular.r, self.specular.g, self.specular.b);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpotLight {
+    pub position: Point3<f32>,
+    pub direction: Vector3<f32>,
+
+    // in cosine
+    pub cut_off: f32,
+    pub outer_cut_off: f32,
+
+    pub ambient: Color,
+    pub diffuse: Color,
+    pub specular: Color,
+
+    // texture projected through the light's frustum, tinting the diffuse and specular terms
+    pub cookie: Option<GLuint>,
+}
+
+impl SpotLight {
+    // view projection of the light whose frustum encloses the outer cone.
+    // depth is not used for the cookie, so the clip planes are arbitrary.
+    pub fn projector(&self) -> Matrix4<f32> {
+        let direction = self.direction.normalize();
+        let up = if direction.y.abs() > 0.99 { vec3(0.0, 0.0, 1.0) } else { vec3(0.0, 1.0, 0.0) };
+        let view = Matrix4::look_at_dir(self.position, direction, up);
+        // perspective needs 0 < fovy < 180°, a wider cone gets the widest frustum around its middle
+        let fovy = Rad((2.0 * self.outer_cut_off.clamp(-1.0, 1.0).acos()).clamp(1f32.to_radians(), 179f32.to_radians()));
+        perspective(fovy, 1.0, 0.1, 100.0) * view
+    }
+
+    /// sets `hasCookie` and `projector` alongside the light parameters. bind the texture with `bind_cookie`.
+    ///
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread and `shader` must be in use, the values are set with glUniform.
+    pub unsafe fn set_uniforms(&self, shader: &Shader, name: &str) {
+        shader.set_vec3(&uniform(name, "position"), self.position.x, self.position.y, self.position.z);
+        shader.set_vec3(&uniform(name, "direction"), self.direction.x, self.direction.y, self.direction.z);
+        shader.set_float(&uniform(name, "cutOff"), self.cut_off);
+        shader.set_float(&uniform(name, "outerCutOff"), self.outer_cut_off);
+        shader.set_vec3(&uniform(name, "ambient"), self.ambient.r, self.ambient.g, self.ambient.b);
+        shader.set_vec3(&uniform(name, "diffuse"), self.diffuse.r, self.diffuse.g, self.diffuse.b);
+        shader.set_vec3(&uniform(name, "specular"), self.specular.r, self.specular.g, self.specular.b);
+        shader.set_integer(&uniform(name, "hasCookie"), self.cookie.is_some() as i32);
+        if self.cookie.is_some() {
+            shader.set_matrix4(&uniform(name, "projector"), &self.projector());
+        }
+    }
+
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread and `shader` must be in use, the values are set with glUniform.
+    pub unsafe fn bind_cookie(&self, shader: &Shader, sampler: &CStr, unit: GLuint) {
+        if let Some(cookie) = self.cookie {
+            bind_texture(shader, sampler, unit, cookie);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{vec4, Vector4};
+
+    use super::*;
+
+    fn spot_light(outer_cut_off: f32) -> SpotLight {
+        SpotLight {
+            position: Point3::new(0.0, 0.0, 0.0),
+            direction: vec3(0.0, 0.0, -1.0),
+            cut_off: outer_cut_off,
+            outer_cut_off,
+            ambient: Color::BLACK,
+            diffuse: Color::WHITE,
+            specular: Color::WHITE,
+            cookie: None,
+        }
+    }
+
+    fn ndc(clip: Vector4<f32>) -> (f32, f32) {
+        (clip.x / clip.w, clip.y / clip.w)
+    }
+
+    #[test]
+    fn projectors_enclose_the_outer_cone() {
+        let projector = spot_light(30f32.to_radians().cos()).projector();
+        let (_, y) = ndc(projector * vec4(0.0, 30f32.to_radians().tan(), -1.0, 1.0));
+        assert!((y - 1.0).abs() < 1e-4, "{}", y);
+    }
+
+    #[test]
+    fn cones_of_a_half_space_or_wider_still_project() {
+        for &outer_cut_off in [0.0, -0.5, -1.0, 1.0].iter() {
+            let projector = spot_light(outer_cut_off).projector();
+            let (x, y) = ndc(projector * vec4(0.0, 0.0, -1.0, 1.0));
+            assert_eq!((x, y), (0.0, 0.0), "{}", outer_cut_off);
+        }
+    }
+}
@@ -0,0 +1,292 @@
+use std::error::Error;
+use std::path::Path;
+
+use cgmath::{ElementWise, EuclideanSpace, InnerSpace, Matrix, Matrix3, Matrix4, MetricSpace, Point3, SquareMatrix, Transform, Vector2, Vector3, Zero, vec3};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::bvh::cosine_hemisphere;
+use crate::{linear_to_srgb, Bvh, DirectionalLight, GlContext, Mesh, Model, PointLight, Ray, Texture, TextureBuilder, TextureType};
+
+// a world space point covered by a lightmap texel
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SurfacePoint {
+    pub(crate) position: Point3<f32>,
+    pub(crate) normal: Vector3<f32>,
+}
+
+// the world transform meshes are baked with: that of the first node drawing them
+pub(crate) fn mesh_transforms(model: &Model, model_matrix: &Matrix4<f32>) -> Vec<Matrix4<f32>> {
+    let mut transforms = vec![*model_matrix; model.meshes.len()];
+    let mut seen = vec![false; model.meshes.len()];
+    for (index, node) in model.nodes.iter().enumerate() {
+        for &mesh in node.meshes.iter() {
+            if !seen[mesh] {
+                seen[mesh] = true;
+                transforms[mesh] = model_matrix * model.node_transform(index);
+            }
+        }
+    }
+    transforms
+}
+
+// the surface under each texel center in `Vertex::lightmap_coords`, row 0 at v = 0
+pub(crate) fn rasterize(mesh: &Mesh, transform: &Matrix4<f32>, width: u32, height: u32) -> Vec<Option<SurfacePoint>> {
+    let normal_matrix = Matrix3::from_cols(transform.x.truncate(), transform.y.truncate(), transform.z.truncate())
+        .invert()
+        .unwrap_or_else(Matrix3::identity)
+        .transpose();
+    let mut texels = vec![None; width as usize * height as usize];
+    let size = Vector2::new(width as f32, height as f32);
+
+    for face in mesh.indices.chunks_exact(3) {
+        let v = [&mesh.verticies[face[0] as usize], &mesh.verticies[face[1] as usize], &mesh.verticies[face[2] as usize]];
+        let uv = [
+            v[0].lightmap_coords.mul_element_wise(size),
+            v[1].lightmap_coords.mul_element_wise(size),
+            v[2].lightmap_coords.mul_element_wise(size),
+        ];
+        let area = (uv[1] - uv[0]).perp_dot(uv[2] - uv[0]);
+        if area.abs() < 1e-12 {
+            continue;
+        }
+
+        let min_x = uv.iter().map(|p| p.x).fold(f32::INFINITY, f32::min).floor().max(0.0) as u32;
+        let min_y = uv.iter().map(|p| p.y).fold(f32::INFINITY, f32::min).floor().max(0.0) as u32;
+        let max_x = (uv.iter().map(|p| p.x).fold(f32::NEG_INFINITY, f32::max).ceil() as u32).min(width);
+        let max_y = (uv.iter().map(|p| p.y).fold(f32::NEG_INFINITY, f32::max).ceil() as u32).min(height);
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                let p = Vector2::new(x as f32 + 0.5, y as f32 + 0.5);
+                let w1 = (p - uv[0]).perp_dot(uv[2] - uv[0]) / area;
+                let w2 = (uv[1] - uv[0]).perp_dot(p - uv[0]) / area;
+                let w0 = 1.0 - w1 - w2;
+                // a little slack so texels on shared edges are not lost
+                if w0 < -1e-3 || w1 < -1e-3 || w2 < -1e-3 {
+                    continue;
+                }
+                let position = v[0].position * w0 + v[1].position * w1 + v[2].position * w2;
+                let normal = v[0].normal * w0 + v[1].normal * w1 + v[2].normal * w2;
+                texels[(y * width + x) as usize] = Some(SurfacePoint {
+                    position: transform.transform_point(Point3::from_vec(position)),
+                    normal: (normal_matrix * normal).normalize(),
+                });
+            }
+        }
+    }
+    texels
+}
+
+// grows the covered texels into their empty neighbours, against dark seams when filtering
+pub(crate) fn dilate<T: Copy + Zero + std::ops::Mul<f32, Output = T>>(
+    values: &mut [T],
+    covered: &mut [bool],
+    width: u32,
+    height: u32,
+    passes: u32,
+) {
+    let (w, h) = (width as i32, height as i32);
+    for _ in 0..passes {
+        let previous = covered.to_vec();
+        for y in 0..h {
+            for x in 0..w {
+                let index = (y * w + x) as usize;
+                if previous[index] {
+                    continue;
+                }
+                let mut sum = T::zero();
+                let mut count = 0;
+                for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)].iter() {
+                    let (nx, ny) = (x + dx, y + dy);
+                    if nx >= 0 && ny >= 0 && nx < w && ny < h && previous[(ny * w + nx) as usize] {
+                        sum = sum + values[(ny * w + nx) as usize];
+                        count += 1;
+                    }
+                }
+                if count > 0 {
+                    values[index] = sum * (1.0 / count as f32);
+                    covered[index] = true;
+                }
+            }
+        }
+    }
+}
+
+// baked light of one mesh, to be multiplied with the diffuse color
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lightmap {
+    width: u32,
+    height: u32,
+    texels: Vec<Vector3<f32>>,
+}
+
+impl Lightmap {
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    // rows from v = 0
+    pub fn texels(&self) -> &[Vector3<f32>] {
+        &self.texels
+    }
+
+    // as an 8 bit sRGB image, light above 1 is clipped
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error + 'static>> {
+        let encode = |c: f32| (linear_to_srgb(c.clamp(0.0, 1.0)) * 255.0 + 0.5) as u8;
+        let bytes: Vec<u8> = self.texels.iter().flat_map(|t| vec![encode(t.x), encode(t.y), encode(t.z)]).collect();
+        image::save_buffer(path, &bytes, self.width, self.height, image::ColorType::RGB(8))?;
+        Ok(())
+    }
+
+    // a lightmap written by `save`, ready to be added to `Mesh::textures`
+    pub fn load<P: AsRef<Path>>(context: &GlContext, path: P) -> Result<Texture, Box<dyn Error + 'static>> {
+        let id = TextureBuilder::new()
+            .internal_format(gl::SRGB8)
+            .wrap(gl::CLAMP_TO_EDGE)
+            .mipmaps(false)
+            .load(context, path)?;
+        Ok(Texture::adopt(id, TextureType::Lightmap))
+    }
+
+    // uploads at full precision, skipping the round trip through a file
+    pub fn upload(&self, context: &GlContext) -> Texture {
+        let data: Vec<f32> = self.texels.iter().flat_map(|t| vec![t.x, t.y, t.z]).collect();
+        let id = TextureBuilder::new()
+            .internal_format(gl::RGB16F)
+            .wrap(gl::CLAMP_TO_EDGE)
+            .mipmaps(false)
+            .upload_f32(context, self.width, self.height, 3, &data);
+        Texture::adopt(id, TextureType::Lightmap)
+    }
+}
+
+// offline baking of static lighting into lightmaps on the CPU. direct light is traced with
+// shadow rays, bounced light by sampling the hemisphere above each texel.
+#[derive(Debug, Clone)]
+pub struct LightmapBaker {
+    // texels along each side of a lightmap
+    pub resolution: u32,
+    pub directional: Option<DirectionalLight>,
+    pub points: Vec<PointLight>,
+    // hemisphere rays per texel for bounced light, 0 bakes direct light only
+    pub samples: u32,
+    pub bounces: u32,
+    // reflectance assumed for the bounces, textures are not sampled
+    pub albedo: f32,
+    // light of rays leaving the scene
+    pub sky: Vector3<f32>,
+    // moves ray origins off the surface against self shadowing, in world units
+    pub bias: f32,
+    // texels grown around the charts
+    pub padding: u32,
+    pub seed: u64,
+}
+
+impl LightmapBaker {
+    pub fn new(resolution: u32) -> Self {
+        Self {
+            resolution,
+            directional: None,
+            points: vec![],
+            samples: 64,
+            bounces: 1,
+            albedo: 0.5,
+            sky: vec3(0.0, 0.0, 0.0),
+            bias: 1e-3,
+            padding: 2,
+            seed: 0,
+        }
+    }
+
+    // one lightmap per mesh of the model, in the order of `Model::meshes`. meshes are placed by
+    // the first node drawing them and all of them cast shadows.
+    pub fn bake(&self, model: &Model, model_matrix: &Matrix4<f32>) -> Vec<Lightmap> {
+        let bvh = Bvh::from_model(model, model_matrix);
+        let transforms = mesh_transforms(model, model_matrix);
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let size = self.resolution.max(1);
+
+        model
+            .meshes
+            .iter()
+            .zip(transforms.iter())
+            .map(|(mesh, transform)| {
+                let surface = rasterize(mesh, transform, size, size);
+                let mut covered: Vec<bool> = surface.iter().map(Option::is_some).collect();
+                let mut texels: Vec<Vector3<f32>> = surface
+                    .iter()
+                    .map(|point| match point {
+                        Some(point) => self.irradiance(&bvh, point, self.bounces, self.samples, &mut rng),
+                        None => vec3(0.0, 0.0, 0.0),
+                    })
+                    .collect();
+                dilate(&mut texels, &mut covered, size, size, self.padding);
+                Lightmap {
+                    width: size,
+                    height: size,
+                    texels,
+                }
+            })
+            .collect()
+    }
+
+    fn direct(&self, bvh: &Bvh, point: &SurfacePoint) -> Vector3<f32> {
+        let origin = point.position + point.normal * self.bias;
+        let mut light = vec3(0.0, 0.0, 0.0);
+        if let Some(directional) = &self.directional {
+            let towards = -directional.direction.normalize();
+            let cosine = point.normal.dot(towards);
+            if cosine > 0.0 && !bvh.occluded(&Ray::new(origin, towards), f32::INFINITY) {
+                light += Vector3::from(directional.diffuse) * cosine;
+            }
+        }
+        for point_light in self.points.iter() {
+            let offset = point_light.position - origin;
+            let distance = offset.magnitude();
+            let towards = offset / distance;
+            let cosine = point.normal.dot(towards);
+            if cosine > 0.0 && !bvh.occluded(&Ray::new(origin, towards), distance) {
+                let distance = point_light.position.distance(point.position);
+                let attenuation = 1.0 / (point_light.constant + point_light.linear * distance + point_light.quadratic * distance * distance);
+                light += Vector3::from(point_light.diffuse) * (cosine * attenuation);
+            }
+        }
+        light
+    }
+
+    // later bounces follow a single ray so the cost grows linearly with them
+    fn irradiance(&self, bvh: &Bvh, point: &SurfacePoint, bounces: u32, samples: u32, rng: &mut StdRng) -> Vector3<f32> {
+        let mut light = self.direct(bvh, point);
+        if bounces == 0 || samples == 0 {
+            return light;
+        }
+
+        // with cosine weighted directions the average incoming light is all that is needed
+        let origin = point.position + point.normal * self.bias;
+        let mut indirect = vec3(0.0, 0.0, 0.0);
+        for _ in 0..samples {
+            let direction = cosine_hemisphere(point.normal, rng.gen(), rng.gen());
+            let ray = Ray::new(origin, direction);
+            indirect += match bvh.intersect(&ray, f32::INFINITY) {
+                Some(hit) => {
+                    let mut normal = bvh.triangle_normal(hit.triangle).normalize();
+                    if normal.dot(direction) > 0.0 {
+                        normal = -normal;
+                    }
+                    let hit_point = SurfacePoint {
+                        position: ray.at(hit.t),
+                        normal,
+                    };
+                    self.irradiance(bvh, &hit_point, bounces - 1, 1, rng) * self.albedo
+                }
+                None => self.sky,
+            };
+        }
+        light += indirect / samples as f32;
+        light
+    }
+}
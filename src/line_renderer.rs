@@ -0,0 +1,336 @@
+use std::mem;
+use std::ptr;
+
+use cgmath::{Matrix4, Point3, Vector4};
+use gl::types::*;
+
+use crate::context::check_render_thread;
+use crate::{c_str, FrameStats, GlContext, Shader, Viewport};
+
+const LINE_VERTEX_SHADER: &str = r#"
+#version 330 core
+
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec4 aColor;
+layout (location = 2) in float aWidth;
+
+out VS_OUT {
+    vec4 color;
+    float width;
+} vs_out;
+
+uniform mat4 viewProjection;
+
+void main() {
+    gl_Position = viewProjection * vec4(aPos, 1.0);
+    vs_out.color = aColor;
+    vs_out.width = aWidth;
+}
+"#;
+
+// each segment comes with its neighbors, so both ends can be mitered against them
+const LINE_GEOMETRY_SHADER: &str = r#"
+#version 330 core
+layout (lines_adjacency) in;
+layout (triangle_strip, max_vertices = 4) out;
+
+in VS_OUT {
+    vec4 color;
+    float width;
+} gs_in[];
+
+out vec4 color;
+// signed pixels from the center of the line
+out float distance;
+out float halfWidth;
+
+uniform vec2 viewport;
+// miters longer than this many half widths are cut, e.g. at sharp turns
+uniform float miterLimit;
+
+vec2 toScreen(vec4 clip) {
+    return (clip.xy / clip.w * 0.5 + 0.5) * viewport;
+}
+
+// the offset of the edge at `p` per pixel of half width
+vec2 miter(vec2 previous, vec2 p, vec2 next, vec2 normal) {
+    vec2 a = p - previous;
+    vec2 b = next - p;
+    if (dot(a, a) < 1e-6 || dot(b, b) < 1e-6) {
+        return normal;
+    }
+    vec2 sum = normalize(a) + normalize(b);
+    if (dot(sum, sum) < 1e-6) {
+        return normal;
+    }
+    vec2 tangent = normalize(sum);
+    vec2 m = vec2(-tangent.y, tangent.x);
+    float scale = 1.0 / max(dot(m, normal), 1e-4);
+    return scale > miterLimit ? normal : m * scale;
+}
+
+void emit(vec4 clip, vec2 screen, vec4 c, float half, float side) {
+    vec2 ndc = screen / viewport * 2.0 - 1.0;
+    gl_Position = vec4(ndc * clip.w, clip.z, clip.w);
+    color = c;
+    // one pixel wider for the smoothed edge
+    distance = side * (half + 1.0);
+    halfWidth = half;
+    EmitVertex();
+}
+
+void main() {
+    vec4 c1 = gl_in[1].gl_Position;
+    vec4 c2 = gl_in[2].gl_Position;
+    // segments reaching behind the camera are left out
+    if (c1.w <= 0.0 || c2.w <= 0.0) {
+        return;
+    }
+    vec2 p0 = gl_in[0].gl_Position.w > 0.0 ? toScreen(gl_in[0].gl_Position) : toScreen(c1);
+    vec2 p1 = toScreen(c1);
+    vec2 p2 = toScreen(c2);
+    vec2 p3 = gl_in[3].gl_Position.w > 0.0 ? toScreen(gl_in[3].gl_Position) : toScreen(c2);
+    vec2 direction = p2 - p1;
+    if (dot(direction, direction) < 1e-8) {
+        return;
+    }
+    direction = normalize(direction);
+    vec2 normal = vec2(-direction.y, direction.x);
+    vec2 m1 = miter(p0, p1, p2, normal);
+    vec2 m2 = miter(p1, p2, p3, normal);
+    float h1 = gs_in[1].width * 0.5;
+    float h2 = gs_in[2].width * 0.5;
+
+    emit(c1, p1 + m1 * (h1 + 1.0), gs_in[1].color, h1, 1.0);
+    emit(c1, p1 - m1 * (h1 + 1.0), gs_in[1].color, h1, -1.0);
+    emit(c2, p2 + m2 * (h2 + 1.0), gs_in[2].color, h2, 1.0);
+    emit(c2, p2 - m2 * (h2 + 1.0), gs_in[2].color, h2, -1.0);
+    EndPrimitive();
+}
+"#;
+
+const LINE_FRAGMENT_SHADER: &str = r#"
+#version 330 core
+out vec4 FragColor;
+
+in vec4 color;
+in float distance;
+in float halfWidth;
+
+void main() {
+    // coverage of the pixel, fading out over the last one
+    float coverage = clamp(halfWidth + 0.5 - abs(distance), 0.0, 1.0);
+    FragColor = vec4(color.rgb, color.a * coverage);
+}
+"#;
+
+// a vertex of a polyline
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinePoint {
+    pub position: Point3<f32>,
+    // straight alpha
+    pub color: Vector4<f32>,
+    // in pixels
+    pub width: f32,
+}
+
+impl LinePoint {
+    pub fn new(position: Point3<f32>, color: Vector4<f32>, width: f32) -> Self {
+        Self { position, color, width }
+    }
+}
+
+// the `lines_adjacency` indices of the `n` points from `base` on
+fn adjacency_indices(base: GLuint, n: usize, closed: bool, indices: &mut Vec<GLuint>) {
+    let index = |i: usize| -> GLuint {
+        let offset: GLuint = conv!(i);
+        base + offset
+    };
+    let segments = if closed { n } else { n - 1 };
+    for i in 0..segments {
+        let (previous, next) = if closed {
+            ((i + n - 1) % n, (i + 2) % n)
+        } else {
+            // the ends repeat themselves, which the shader takes as no join
+            (i.saturating_sub(1), (i + 2).min(n - 1))
+        };
+        indices.extend_from_slice(&[index(previous), index(i), index((i + 1) % n), index(next)]);
+    }
+}
+
+// polylines of any width with mitered joins and smoothed edges, expanded into screen space
+// quads by a geometry shader. queue them with `polyline` and `line`, `render` draws and clears.
+#[derive(Debug)]
+pub struct LineRenderer {
+    shader: Shader,
+    vao: GLuint,
+    vbo: GLuint,
+    ebo: GLuint,
+    // of the buffers, in points and indices
+    capacity: (usize, usize),
+    points: Vec<LinePoint>,
+    // four per segment: the point before, the segment, the point after
+    indices: Vec<GLuint>,
+    // see `miterLimit` in the geometry shader
+    pub miter_limit: f32,
+    pub depth_test: bool,
+}
+
+impl LineRenderer {
+    pub fn new(context: &GlContext) -> Self {
+        let (mut vao, mut vbo, mut ebo) = (0, 0, 0);
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+            gl::GenBuffers(1, &mut vbo);
+            gl::GenBuffers(1, &mut ebo);
+            gl::BindVertexArray(vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+            gl::BindBuffer(gl::ELEMENT_ARRAY_BUFFER, ebo);
+            let stride = conv!(mem::size_of::<LinePoint>());
+            gl::EnableVertexAttribArray(0);
+            gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, stride, ptr::null());
+            gl::EnableVertexAttribArray(1);
+            gl::VertexAttribPointer(1, 4, gl::FLOAT, gl::FALSE, stride, mem::size_of::<Point3<f32>>() as *const _);
+            gl::EnableVertexAttribArray(2);
+            let width_offset = mem::size_of::<Point3<f32>>() + mem::size_of::<Vector4<f32>>();
+            gl::VertexAttribPointer(2, 1, gl::FLOAT, gl::FALSE, stride, width_offset as *const _);
+            gl::BindVertexArray(0);
+        }
+
+        Self {
+            shader: Shader::with_geometry(context, LINE_VERTEX_SHADER, LINE_GEOMETRY_SHADER, LINE_FRAGMENT_SHADER),
+            vao,
+            vbo,
+            ebo,
+            capacity: (0, 0),
+            points: vec![],
+            indices: vec![],
+            miter_limit: 4.0,
+            depth_test: true,
+        }
+    }
+
+    // connects the points in order, and the last back to the first when `closed`
+    pub fn polyline(&mut self, points: &[LinePoint], closed: bool) {
+        if points.len() < 2 {
+            return;
+        }
+        let base: GLuint = conv!(self.points.len());
+        self.points.extend_from_slice(points);
+        adjacency_indices(base, points.len(), closed, &mut self.indices);
+    }
+
+    // takes a `Color` or a linear `Vector4` with straight alpha
+    pub fn line<C: Into<Vector4<f32>>>(&mut self, from: Point3<f32>, to: Point3<f32>, color: C, width: f32) {
+        let color = color.into();
+        self.polyline(&[LinePoint::new(from, color, width), LinePoint::new(to, color, width)], false);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.points.clear();
+        self.indices.clear();
+    }
+
+    // draws everything queued into `viewport` with alpha blending, then clears the queue
+    pub fn render(&mut self, _context: &GlContext, view_projection: &Matrix4<f32>, viewport: &Viewport) {
+        check_render_thread("LineRenderer");
+        if self.is_empty() {
+            return;
+        }
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            let (point_bytes, index_bytes) = (mem::size_of_val(&self.points[..]), mem::size_of_val(&self.indices[..]));
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+            if self.points.len() > self.capacity.0 {
+                self.capacity.0 = self.points.len().next_power_of_two();
+                gl::BufferData(gl::ARRAY_BUFFER, conv!(self.capacity.0 * mem::size_of::<LinePoint>()), ptr::null(), gl::STREAM_DRAW);
+            }
+            gl::BufferSubData(gl::ARRAY_BUFFER, 0, conv!(point_bytes), self.points.as_ptr() as *const _);
+            if self.indices.len() > self.capacity.1 {
+                self.capacity.1 = self.indices.len().next_power_of_two();
+                gl::BufferData(gl::ELEMENT_ARRAY_BUFFER, conv!(self.capacity.1 * mem::size_of::<GLuint>()), ptr::null(), gl::STREAM_DRAW);
+            }
+            gl::BufferSubData(gl::ELEMENT_ARRAY_BUFFER, 0, conv!(index_bytes), self.indices.as_ptr() as *const _);
+            FrameStats::record_buffer_upload(point_bytes + index_bytes);
+
+            let blend = gl::IsEnabled(gl::BLEND) == gl::TRUE;
+            let depth_test = gl::IsEnabled(gl::DEPTH_TEST) == gl::TRUE;
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            if self.depth_test {
+                gl::Enable(gl::DEPTH_TEST);
+            } else {
+                gl::Disable(gl::DEPTH_TEST);
+            }
+            viewport.apply();
+            self.shader.use_program();
+            self.shader.set_matrix4(c_str("viewProjection\0"), view_projection);
+            self.shader.set_vec2(c_str("viewport\0"), viewport.width as f32, viewport.height as f32);
+            self.shader.set_float(c_str("miterLimit\0"), self.miter_limit);
+            gl::DrawElements(gl::LINES_ADJACENCY, conv!(self.indices.len()), gl::UNSIGNED_INT, ptr::null());
+            FrameStats::record_draw(self.indices.len() / 2, 1);
+            gl::BindVertexArray(0);
+
+            if !blend {
+                gl::Disable(gl::BLEND);
+            }
+            if depth_test {
+                gl::Enable(gl::DEPTH_TEST);
+            } else {
+                gl::Disable(gl::DEPTH_TEST);
+            }
+        }
+        self.clear();
+    }
+}
+
+impl Drop for LineRenderer {
+    fn drop(&mut self) {
+        check_render_thread("LineRenderer");
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+            gl::DeleteBuffers(1, &self.vbo);
+            gl::DeleteBuffers(1, &self.ebo);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn indices(base: GLuint, n: usize, closed: bool) -> Vec<GLuint> {
+        let mut indices = vec![];
+        adjacency_indices(base, n, closed, &mut indices);
+        indices
+    }
+
+    #[test]
+    fn open_polylines_repeat_their_ends() {
+        assert_eq!(indices(0, 2, false), vec![0, 0, 1, 1]);
+        assert_eq!(indices(0, 4, false), vec![0, 0, 1, 2, 0, 1, 2, 3, 1, 2, 3, 3]);
+    }
+
+    #[test]
+    fn closed_polylines_wrap_around() {
+        assert_eq!(indices(0, 3, true), vec![2, 0, 1, 2, 0, 1, 2, 0, 1, 2, 0, 1]);
+        // every point starts one segment and is joined on both sides
+        let square = indices(0, 4, true);
+        assert_eq!(square.len(), 16);
+        for (i, segment) in square.chunks(4).enumerate() {
+            let i = i as GLuint;
+            assert_eq!(segment, &[(i + 3) % 4, i, (i + 1) % 4, (i + 2) % 4]);
+        }
+    }
+
+    #[test]
+    fn polylines_follow_the_points_queued_before() {
+        assert_eq!(indices(5, 3, false), vec![5, 5, 6, 7, 5, 6, 7, 7]);
+        assert_eq!(indices(5, 2, true), vec![6, 5, 6, 5, 5, 6, 5, 6]);
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+
+use cgmath::{EuclideanSpace, InnerSpace, Matrix, Matrix3, Matrix4, Point3, Rad, SquareMatrix, Transform, Vector3, vec3};
+use gl::types::*;
+
+use crate::{GlContext, Mesh, Texture, Vertex};
+
+// triangle geometry on the CPU, for editing before it is uploaded as a `Mesh`.
+// the operations return new data and leave `self` as it is.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MeshData {
+    pub verticies: Vec<Vertex>,
+    pub indices: Vec<GLuint>,
+}
+
+impl MeshData {
+    pub fn new(verticies: Vec<Vertex>, indices: Vec<GLuint>) -> Self {
+        Self { verticies, indices }
+    }
+
+    // a copy of the geometry a mesh was made from
+    pub fn from_mesh(mesh: &Mesh) -> Self {
+        Self::new(mesh.verticies.clone(), mesh.indices.clone())
+    }
+
+    pub fn upload(&self, context: &GlContext, textures: Vec<Texture>) -> Mesh {
+        Mesh::new(context, self.verticies.clone(), self.indices.clone(), textures)
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.indices.len() / 3
+    }
+
+    // all meshes in one, the indices renumbered after the verticies that come before
+    pub fn merge(meshes: &[MeshData]) -> Self {
+        let mut merged = Self::new(
+            Vec::with_capacity(meshes.iter().map(|mesh| mesh.verticies.len()).sum()),
+            Vec::with_capacity(meshes.iter().map(|mesh| mesh.indices.len()).sum()),
+        );
+        for mesh in meshes.iter() {
+            let base: GLuint = conv!(merged.verticies.len());
+            merged.verticies.extend_from_slice(&mesh.verticies);
+            merged.indices.extend(mesh.indices.iter().map(|index| base + index));
+        }
+        merged
+    }
+
+    // normals go through the inverse transpose. mirroring matrices also reverse the
+    // winding so the faces keep pointing outwards, and the bitangent signs.
+    pub fn transform(&self, matrix: &Matrix4<f32>) -> Self {
+        let linear = Matrix3::from_cols(matrix.x.truncate(), matrix.y.truncate(), matrix.z.truncate());
+        let normal_matrix = linear.invert().unwrap_or_else(Matrix3::identity).transpose();
+        let mirrored = linear.determinant() < 0.0;
+        let verticies = self
+            .verticies
+            .iter()
+            .map(|vertex| {
+                let tangent = linear * vertex.tangent.truncate();
+                let tangent = if tangent.magnitude2() > 0.0 { tangent.normalize() } else { tangent };
+                Vertex {
+                    position: matrix.transform_point(Point3::from_vec(vertex.position)).to_vec(),
+                    normal: (normal_matrix * vertex.normal).normalize(),
+                    tangent: tangent.extend(if mirrored { -vertex.tangent.w } else { vertex.tangent.w }),
+                    ..*vertex
+                }
+            })
+            .collect();
+        let mut transformed = Self::new(verticies, self.indices.clone());
+        if mirrored {
+            transformed.reverse_triangles();
+        }
+        transformed
+    }
+
+    // turns the faces around: reversed winding and negated normals
+    pub fn flip_winding(&self) -> Self {
+        let mut flipped = self.clone();
+        flipped.reverse_triangles();
+        for vertex in flipped.verticies.iter_mut() {
+            vertex.normal = -vertex.normal;
+            vertex.tangent.w = -vertex.tangent.w;
+        }
+        flipped
+    }
+
+    fn reverse_triangles(&mut self) {
+        for face in self.indices.chunks_exact_mut(3) {
+            face.swap(1, 2);
+        }
+    }
+
+    // the axis aligned bounds of the verticies, None when there are none
+    pub fn bounds(&self) -> Option<(Vector3<f32>, Vector3<f32>)> {
+        let first = self.verticies.first()?.position;
+        Some(self.verticies.iter().fold((first, first), |(min, max), vertex| {
+            let p = vertex.position;
+            (vec3(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)), vec3(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)))
+        }))
+    }
+
+    // moved so the center of the bounds is at the origin
+    pub fn recenter(&self) -> Self {
+        let center = match self.bounds() {
+            Some((min, max)) => (min + max) * 0.5,
+            None => return self.clone(),
+        };
+        let mut centered = self.clone();
+        for vertex in centered.verticies.iter_mut() {
+            vertex.position -= center;
+        }
+        centered
+    }
+
+    // area weighted normals from the faces around each position. faces meeting at more than
+    // `smooth_angle` keep a hard edge, splitting the verticies there. tangents go stale,
+    // see `compute_tangents`.
+    pub fn recompute_normals(&self, smooth_angle: Rad<f32>) -> Self {
+        let position = |index: GLuint| self.verticies[index as usize].position;
+        // not normalized, their length is twice the area
+        let faces: Vec<Vector3<f32>> = self
+            .indices
+            .chunks_exact(3)
+            .map(|face| (position(face[1]) - position(face[0])).cross(position(face[2]) - position(face[0])))
+            .collect();
+
+        let mut position_faces: HashMap<[u32; 3], Vec<usize>> = HashMap::new();
+        for (face, indices) in self.indices.chunks_exact(3).enumerate() {
+            for &index in indices.iter() {
+                let p = position(index);
+                position_faces.entry([p.x.to_bits(), p.y.to_bits(), p.z.to_bits()]).or_default().push(face);
+            }
+        }
+
+        let threshold = smooth_angle.0.cos();
+        let mut result = self.clone();
+        // the normal each vertex got first, corners wanting another one get a copy
+        let mut assigned: Vec<Option<Vector3<f32>>> = vec![None; self.verticies.len()];
+        let mut copies: HashMap<(GLuint, [u32; 3]), GLuint> = HashMap::new();
+        for (corner, index) in result.indices.iter_mut().enumerate() {
+            let face = corner / 3;
+            let p = position(*index);
+            let own = faces[face];
+            let mut normal = vec3(0.0, 0.0, 0.0);
+            for &other in position_faces[&[p.x.to_bits(), p.y.to_bits(), p.z.to_bits()]].iter() {
+                let candidate = faces[other];
+                let lengths = own.magnitude() * candidate.magnitude();
+                if other == face || (lengths > 0.0 && own.dot(candidate) >= threshold * lengths) {
+                    normal += candidate;
+                }
+            }
+            let normal = if normal.magnitude2() > 0.0 { normal.normalize() } else { self.verticies[*index as usize].normal };
+
+            match assigned[*index as usize] {
+                None => {
+                    assigned[*index as usize] = Some(normal);
+                    result.verticies[*index as usize].normal = normal;
+                }
+                Some(first) if (first - normal).magnitude2() < 1e-10 => {}
+                Some(_) => {
+                    let key = (*index, [normal.x.to_bits(), normal.y.to_bits(), normal.z.to_bits()]);
+                    let verticies = &mut result.verticies;
+                    *index = *copies.entry(key).or_insert_with(|| {
+                        verticies.push(Vertex { normal, ..verticies[*index as usize] });
+                        conv!(verticies.len() - 1)
+                    });
+                }
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    use cgmath::{vec2, vec4, Deg};
+
+    pub(crate) fn vertex(position: Vector3<f32>, normal: Vector3<f32>, tex_coords: cgmath::Vector2<f32>) -> Vertex {
+        Vertex {
+            position,
+            normal,
+            tex_coords,
+            color: vec4(1.0, 1.0, 1.0, 1.0),
+            lightmap_coords: tex_coords,
+            tangent: vec4(0.0, 0.0, 0.0, 1.0),
+        }
+    }
+
+    // `n` by `n` quads over [0, 1] in the xy plane facing +z, uv following xy
+    pub(crate) fn grid(n: u32) -> MeshData {
+        let mut verticies = vec![];
+        for y in 0..=n {
+            for x in 0..=n {
+                let (u, v) = (x as f32 / n as f32, y as f32 / n as f32);
+                verticies.push(vertex(vec3(u, v, 0.0), vec3(0.0, 0.0, 1.0), vec2(u, v)));
+            }
+        }
+        let mut indices = vec![];
+        for y in 0..n {
+            for x in 0..n {
+                let i = y * (n + 1) + x;
+                indices.extend_from_slice(&[i, i + 1, i + n + 2, i, i + n + 2, i + n + 1]);
+            }
+        }
+        MeshData::new(verticies, indices)
+    }
+
+    fn face_normal(data: &MeshData, face: usize) -> Vector3<f32> {
+        let p = |corner: usize| data.verticies[data.indices[face * 3 + corner] as usize].position;
+        (p(1) - p(0)).cross(p(2) - p(0)).normalize()
+    }
+
+    #[test]
+    fn grid_faces_point_along_the_normals() {
+        let grid = grid(2);
+        assert_eq!(grid.triangle_count(), 8);
+        for face in 0..grid.triangle_count() {
+            assert!((face_normal(&grid, face) - vec3(0.0, 0.0, 1.0)).magnitude() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn merge_renumbers_indices() {
+        let (a, b) = (grid(1), grid(2));
+        let merged = MeshData::merge(&[a.clone(), b.clone()]);
+        assert_eq!(merged.verticies.len(), a.verticies.len() + b.verticies.len());
+        assert_eq!(merged.triangle_count(), a.triangle_count() + b.triangle_count());
+        assert_eq!(&merged.indices[..a.indices.len()], &a.indices[..]);
+        let base = a.verticies.len() as GLuint;
+        assert!(merged.indices[a.indices.len()..].iter().zip(b.indices.iter()).all(|(&m, &i)| m == i + base));
+    }
+
+    #[test]
+    fn mirroring_keeps_faces_outwards() {
+        let mirrored = grid(1).transform(&Matrix4::from_nonuniform_scale(1.0, 1.0, -1.0));
+        for (face, vertex) in (0..mirrored.triangle_count()).zip(mirrored.verticies.iter()) {
+            assert!((face_normal(&mirrored, face) - vertex.normal).magnitude() < 1e-5);
+        }
+        assert_eq!(mirrored.verticies[0].normal, vec3(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn mirroring_flips_the_bitangent_sign() {
+        let mirrored = grid(1).transform(&Matrix4::from_nonuniform_scale(1.0, 1.0, -1.0));
+        assert_eq!(mirrored.verticies[0].tangent.w, -1.0);
+    }
+
+    #[test]
+    fn flip_winding_turns_faces_around() {
+        let flipped = grid(1).flip_winding();
+        assert!((face_normal(&flipped, 0) - vec3(0.0, 0.0, -1.0)).magnitude() < 1e-5);
+        assert_eq!(flipped.verticies[0].normal, vec3(0.0, 0.0, -1.0));
+    }
+
+    #[test]
+    fn bounds_and_recenter() {
+        assert_eq!(MeshData::default().bounds(), None);
+        let moved = grid(2).transform(&Matrix4::from_translation(vec3(2.0, 3.0, 4.0)));
+        assert_eq!(moved.bounds(), Some((vec3(2.0, 3.0, 4.0), vec3(3.0, 4.0, 4.0))));
+        let (min, max) = moved.recenter().bounds().unwrap();
+        assert!((min - vec3(-0.5, -0.5, 0.0)).magnitude() < 1e-5);
+        assert!((max - vec3(0.5, 0.5, 0.0)).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn recompute_normals_keeps_hard_edges() {
+        // a wall standing on the far edge of the floor, at x = 1 facing +x. `0.0 - x` keeps the
+        // shared edge at +0.0 so its positions match the floor's.
+        let mut wall = grid(1);
+        for vertex in wall.verticies.iter_mut() {
+            let p = vertex.position;
+            vertex.position = vec3(1.0, p.y, 0.0 - p.x);
+        }
+        let mut folded = MeshData::merge(&[grid(1), wall]);
+        for vertex in folded.verticies.iter_mut() {
+            vertex.normal = vec3(0.0, 1.0, 0.0);
+        }
+
+        let hard = folded.recompute_normals(Rad::from(Deg(30.0)));
+        for face in 0..hard.triangle_count() {
+            for corner in 0..3 {
+                let normal = hard.verticies[hard.indices[face * 3 + corner] as usize].normal;
+                assert!((normal - face_normal(&hard, face)).magnitude() < 1e-4);
+            }
+        }
+
+        let smooth = folded.recompute_normals(Rad::from(Deg(100.0)));
+        for vertex in smooth.verticies.iter().filter(|v| v.position.x == 1.0 && v.position.z == 0.0) {
+            assert!(vertex.normal.x > 0.1 && vertex.normal.z > 0.1);
+        }
+    }
+}
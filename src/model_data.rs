@@ -0,0 +1,207 @@
+use std::cell::RefCell;
+use std::collections::hash_map::Entry::*;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use cgmath::{vec2, vec3, vec4, Matrix4, SquareMatrix};
+use image::DynamicImage;
+
+use crate::{compute_tangents, obj_vertex_colors, obj_vertex_index, obj_vertex_indices, GlContext, MeshData, Model, Node, Texture, TextureBuilder, TextureType, Vertex};
+
+// a model read and decoded into memory without touching GL, so it can be loaded on another
+// thread and uploaded with `upload` on the render thread
+#[derive(Clone)]
+pub struct ModelData {
+    pub meshes: Vec<MeshData>,
+    // the image files of each mesh by role
+    pub textures: Vec<Vec<(PathBuf, TextureType)>>,
+    pub nodes: Vec<Node>,
+    // decoded once per file
+    pub images: HashMap<PathBuf, DynamicImage>,
+}
+
+impl std::fmt::Debug for ModelData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ModelData")
+            .field("meshes", &self.meshes.len())
+            .field("textures", &self.textures)
+            .field("nodes", &self.nodes)
+            .field("images", &self.images.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl ModelData {
+    pub fn load_obj<P: AsRef<Path>>(name: P) -> Result<Self, Box<dyn Error + 'static>> {
+        let name = name.as_ref();
+        let mut data = Self {
+            meshes: vec![],
+            textures: vec![],
+            nodes: vec![],
+            images: HashMap::new(),
+        };
+
+        // read once for tobj and the vertex colors it doesn't keep
+        let source = std::fs::read_to_string(name)?;
+        let load_mtl = |material: &Path| tobj::load_mtl(&name.parent().map_or_else(|| material.to_owned(), |parent| parent.join(material)));
+        let (models, materials) = tobj::load_obj_buf(&mut source.as_bytes(), load_mtl)?;
+        let colors = obj_vertex_colors(&source);
+        // the same materials split the models the same way
+        let indices = if colors.is_empty() {
+            vec![]
+        } else {
+            tobj::load_obj_buf(&mut obj_vertex_indices(&source).as_bytes(), load_mtl)?.0
+        };
+
+        for (m, model) in models.into_iter().enumerate() {
+            data.nodes.push(Node {
+                name: model.name,
+                transform: Matrix4::identity(),
+                visible: true,
+                meshes: vec![data.meshes.len()],
+                parent: None,
+            });
+            let mesh = model.mesh;
+
+            let len = mesh.positions.len() / 3;
+            let mut verticies = Vec::with_capacity(len);
+
+            for i in 0..len {
+                let color = indices
+                    .get(m)
+                    .and_then(|indexed| colors.get(obj_vertex_index(&indexed.mesh.positions[3 * i..3 * i + 3])))
+                    .and_then(|color| *color);
+                let tex_coords = vec2(mesh.texcoords[2 * i], mesh.texcoords[2 * i + 1]);
+                verticies.push(Vertex {
+                    position: vec3(mesh.positions[3 * i], mesh.positions[3 * i + 1], mesh.positions[3 * i + 2]),
+                    normal: vec3(mesh.normals[3 * i], mesh.normals[3 * i + 1], mesh.normals[3 * i + 2]),
+                    tex_coords,
+                    color: color.unwrap_or_else(|| vec4(1.0, 1.0, 1.0, 1.0)),
+                    lightmap_coords: tex_coords,
+                    tangent: vec4(0.0, 0.0, 0.0, 1.0),
+                });
+            }
+
+            let mut textures = vec![];
+            if let Some(material_id) = mesh.material_id {
+                let material = &materials[material_id];
+
+                // statements tobj does not know end up as unknown parameters
+                let unknown = |keys: &[&str]| {
+                    keys.iter()
+                        .filter_map(|&key| material.unknown_param.get(key))
+                        // options like `-bm 1.0` come before the file name
+                        .filter_map(|value| value.split_whitespace().last())
+                        .next()
+                        .unwrap_or("")
+                };
+                // tobj stores map_Ns in `normal_texture`
+                let normal_texture = unknown(&["norm", "map_bump", "map_Bump", "bump"]);
+                let emissive_texture = unknown(&["map_Ke"]);
+                let mut roughness_texture = unknown(&["map_Pr"]);
+                let mut metallic_texture = unknown(&["map_Pm"]);
+                let sheen_texture = unknown(&["map_Ps"]);
+                // glTF converters point both statements at the packed texture
+                let mut metallic_roughness_texture = "";
+                if !roughness_texture.is_empty() && roughness_texture == metallic_texture {
+                    metallic_roughness_texture = roughness_texture;
+                    roughness_texture = "";
+                    metallic_texture = "";
+                }
+
+                let maps = [
+                    (material.diffuse_texture.as_str(), TextureType::Diffuse),
+                    (material.specular_texture.as_str(), TextureType::Specular),
+                    (normal_texture, TextureType::Normal),
+                    (emissive_texture, TextureType::Emissive),
+                    (material.ambient_texture.as_str(), TextureType::Ambient),
+                    (roughness_texture, TextureType::Roughness),
+                    (metallic_texture, TextureType::Metallic),
+                    (sheen_texture, TextureType::Sheen),
+                    (metallic_roughness_texture, TextureType::MetallicRoughness),
+                ];
+                for &(file, type_) in maps.iter() {
+                    if file.is_empty() {
+                        continue;
+                    }
+                    let tex_name = name.with_file_name(file);
+                    if let Vacant(v) = data.images.entry(tex_name.clone()) {
+                        v.insert(image::open(&tex_name)?);
+                    }
+                    textures.push((tex_name, type_));
+                }
+            }
+
+            // verticies OBJ files list for each face corner are mostly repeats
+            let mut mesh_data = MeshData::new(verticies, mesh.indices).deduplicate();
+            compute_tangents(&mut mesh_data);
+            data.meshes.push(mesh_data.optimize_vertex_cache());
+            data.textures.push(textures);
+        }
+
+        Ok(data)
+    }
+
+    // bytes of vertex, index and pixel data, roughly what the upload costs
+    pub fn size_bytes(&self) -> usize {
+        let meshes: usize = self
+            .meshes
+            .iter()
+            .map(|mesh| mesh.verticies.len() * std::mem::size_of::<Vertex>() + mesh.indices.len() * 4)
+            .sum();
+        let images: usize = self.images.values().map(|img| img.raw_pixels().len()).sum();
+        meshes + images
+    }
+
+    pub fn upload(&self, context: &GlContext) -> Model {
+        // shared by all meshes, a texture used in several roles is uploaded once per encoding
+        let mut uploaded: HashMap<(&Path, bool), Texture> = HashMap::new();
+        let meshes = self
+            .meshes
+            .iter()
+            .zip(self.textures.iter())
+            .map(|(mesh, files)| {
+                let textures = files
+                    .iter()
+                    .map(|(path, type_)| match uploaded.entry((path.as_path(), type_.is_color())) {
+                        Occupied(o) => o.get().with_type(*type_),
+                        Vacant(v) => {
+                            let id = TextureBuilder::new().srgb(type_.is_color()).upload_image(context, &self.images[path]);
+                            v.insert(Texture::adopt(id, *type_)).clone()
+                        }
+                    })
+                    .collect();
+                mesh.upload(context, textures)
+            })
+            .collect();
+        Model {
+            meshes,
+            nodes: self.nodes.clone(),
+            sockets: vec![],
+            instances: RefCell::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vertex_colors_follow_their_v_statement() {
+        // the two triangles share a corner position with different colors
+        let source = "v 0 0 0 1 0 0\nv 1 0 0 1 0 0\nv 0 1 0 1 0 0\nv 0 0 0 0 0 1 0.5\nv 1 1 0 0 0 1 0.5\nv 0 1 0\n\
+                      vt 0 0\nvn 0 0 1\no red\nf 1/1/1 2/1/1 3/1/1\no blue\nf 4/1/1 5/1/1 -1/1/1\n";
+        let path = std::env::temp_dir().join(format!("game-engine-{}-colors.obj", std::process::id()));
+        std::fs::write(&path, source).unwrap();
+        let data = ModelData::load_obj(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let data = data.unwrap();
+        let colors = |mesh: &MeshData| mesh.verticies.iter().map(|vertex| vertex.color).collect::<Vec<_>>();
+        assert_eq!(colors(&data.meshes[0]), vec![vec4(1.0, 0.0, 0.0, 1.0); 3]);
+        let blue = vec4(0.0, 0.0, 1.0, 0.5);
+        assert_eq!(colors(&data.meshes[1]), vec![blue, blue, vec4(1.0, 1.0, 1.0, 1.0)]);
+    }
+}
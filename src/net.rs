@@ -0,0 +1,720 @@
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Quaternion};
+
+use crate::{Camera, CameraPose, FreeCamera, SaveState, Snapshot, SnapshotError};
+
+// world snapshots the server keeps as baselines for deltas
+const HISTORY: usize = 64;
+const MAX_DATAGRAM: usize = 65507;
+
+// the position and orientation of a player's camera, sent by its client and replicated to the others
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PlayerPose {
+    pub position: Point3<f32>,
+    pub orientation: Quaternion<f32>,
+}
+
+impl PlayerPose {
+    pub fn of(camera: &FreeCamera) -> Self {
+        Self {
+            position: camera.position(),
+            orientation: camera.orientation(),
+        }
+    }
+
+    pub fn apply<C: CameraPose>(&self, camera: &mut C) {
+        camera.set_pose(self.position, self.orientation);
+    }
+
+    // `t` from 0 at `self` to 1 at `other`
+    pub fn interpolate(&self, other: &Self, t: f32) -> Self {
+        Self {
+            position: Point3::from_vec(self.position.to_vec() + (other.position - self.position) * t),
+            orientation: self.orientation.slerp(other.orientation, t).normalize(),
+        }
+    }
+
+    fn to_words(self) -> String {
+        let (p, q) = (self.position, self.orientation);
+        format!("{:?} {:?} {:?} {:?} {:?} {:?} {:?}", p.x, p.y, p.z, q.s, q.v.x, q.v.y, q.v.z)
+    }
+
+    fn from_words(words: &[&str]) -> Option<Self> {
+        let values: Vec<f32> = words.iter().map(|word| word.parse().ok()).collect::<Option<_>>()?;
+        if values.len() != 7 {
+            return None;
+        }
+        Some(Self {
+            position: Point3::new(values[0], values[1], values[2]),
+            orientation: Quaternion::new(values[3], values[4], values[5], values[6]),
+        })
+    }
+}
+
+impl SaveState for PlayerPose {
+    fn save_state(&self, snapshot: &mut Snapshot, key: &str) {
+        let (p, q) = (self.position, self.orientation);
+        snapshot.set_floats(&format!("{}.position", key), &[p.x, p.y, p.z]);
+        snapshot.set_floats(&format!("{}.orientation", key), &[q.s, q.v.x, q.v.y, q.v.z]);
+    }
+
+    fn load_state(&mut self, snapshot: &Snapshot, key: &str) -> Result<(), SnapshotError> {
+        let (p, q) = (snapshot.floats(&format!("{}.position", key))?, snapshot.floats(&format!("{}.orientation", key))?);
+        if p.len() != 3 || q.len() != 4 {
+            return Err(SnapshotError::new(format!("{} is not a pose", key)));
+        }
+        self.position = Point3::new(p[0], p[1], p[2]);
+        self.orientation = Quaternion::new(q[0], q[1], q[2], q[3]);
+        Ok(())
+    }
+}
+
+fn player_key(id: u32) -> String {
+    format!("player.{}", id)
+}
+
+// the ids of the players in a world snapshot
+fn player_ids(world: &Snapshot) -> Vec<u32> {
+    let mut ids: Vec<u32> = world
+        .keys()
+        .filter_map(|key| key.strip_prefix("player.")?.strip_suffix(".position")?.parse().ok())
+        .collect();
+    ids.sort_unstable();
+    ids
+}
+
+// what changed in a world since a baseline both sides have: changed values and removed keys
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SnapshotDelta {
+    // the tick of the world this leads to
+    pub tick: u64,
+    // the tick of the baseline, 0 for the empty world
+    pub baseline: u64,
+    // seconds on the server clock
+    pub server_time: f64,
+    pub changed: Snapshot,
+    pub removed: Vec<String>,
+}
+
+impl SnapshotDelta {
+    pub fn between(baseline: &Snapshot, world: &Snapshot) -> (Snapshot, Vec<String>) {
+        let mut changed = Snapshot::new();
+        for (key, value) in world.iter() {
+            if baseline.raw(key).ok() != Some(value) {
+                changed.set_raw(key, value);
+            }
+        }
+        let removed = baseline.keys().filter(|key| !world.contains(key)).map(String::from).collect();
+        (changed, removed)
+    }
+
+    pub fn apply(&self, baseline: &Snapshot) -> Snapshot {
+        let mut world = baseline.clone();
+        for key in self.removed.iter() {
+            world.remove(key);
+        }
+        for (key, value) in self.changed.iter() {
+            world.set_raw(key, value);
+        }
+        world
+    }
+
+    // split between lines into as many datagrams as it takes, e.g. the whole world for a new client.
+    // all parts have to fit into the client's socket buffer at once, a few hundred KB by default.
+    fn to_datagrams(&self) -> Vec<String> {
+        let lines = self
+            .removed
+            .iter()
+            .map(|key| format!("- {}\n", key))
+            .chain(self.changed.iter().map(|(key, value)| format!("{} = {}\n", key, value)));
+        // leaves room for the header
+        let limit = MAX_DATAGRAM - 128;
+        let mut bodies = vec![String::new()];
+        for line in lines {
+            let body = bodies.last_mut().unwrap();
+            if !body.is_empty() && body.len() + line.len() > limit {
+                bodies.push(line);
+            } else {
+                body.push_str(&line);
+            }
+        }
+        let parts = bodies.len();
+        bodies
+            .into_iter()
+            .enumerate()
+            .map(|(part, body)| format!("state {} {} {:?} {} {}\n{}", self.tick, self.baseline, self.server_time, part, parts, body))
+            .collect()
+    }
+
+    // one part of a delta with its index and the number of parts
+    fn parse(header: &[&str], body: &str) -> Option<(Self, usize, usize)> {
+        if header.len() != 5 {
+            return None;
+        }
+        let (mut changes, mut removed) = (String::new(), vec![]);
+        for line in body.lines() {
+            match line.strip_prefix("- ") {
+                Some(key) => removed.push(key.to_string()),
+                None => {
+                    changes.push_str(line);
+                    changes.push('\n');
+                }
+            }
+        }
+        let delta = Self {
+            tick: header[0].parse().ok()?,
+            baseline: header[1].parse().ok()?,
+            server_time: header[2].parse().ok()?,
+            changed: Snapshot::parse(&changes).ok()?,
+            removed,
+        };
+        let (part, parts) = (header[3].parse().ok()?, header[4].parse().ok()?);
+        if part >= parts {
+            return None;
+        }
+        Some((delta, part, parts))
+    }
+
+    // joins the parts of one delta
+    fn merge(parts: Vec<SnapshotDelta>) -> Self {
+        let mut parts = parts.into_iter();
+        let mut delta = parts.next().unwrap_or_default();
+        for part in parts {
+            delta.removed.extend(part.removed);
+            for (key, value) in part.changed.iter() {
+                delta.changed.set_raw(key, value);
+            }
+        }
+        delta
+    }
+}
+
+// the first line of a datagram and the rest
+fn split_message(datagram: &[u8]) -> Option<(Vec<&str>, &str)> {
+    let text = std::str::from_utf8(datagram).ok()?;
+    let (header, body) = match text.find('\n') {
+        Some(index) => (&text[..index], &text[index + 1..]),
+        None => (text, ""),
+    };
+    Some((header.split_whitespace().collect(), body))
+}
+
+// the messages waiting on a non-blocking socket
+fn receive_all(socket: &UdpSocket, buffer: &mut [u8]) -> Vec<(SocketAddr, Vec<u8>)> {
+    let mut datagrams = vec![];
+    loop {
+        match socket.recv_from(buffer) {
+            Ok((len, from)) => datagrams.push((from, buffer[..len].to_vec())),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return datagrams,
+            // e.g. a client that went away without saying so, on Windows
+            Err(ref e) if e.kind() == io::ErrorKind::ConnectionReset => continue,
+            Err(e) => {
+                log::warn!("receiving failed: {}", e);
+                return datagrams;
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Peer {
+    id: u32,
+    // the last world tick the client has, the baseline of its next delta
+    acked: u64,
+    // seconds since the client was last heard from
+    silent: f32,
+}
+
+// keeps the authoritative world and sends every client what changed since the last state it
+// acknowledged, so lost datagrams need no resending. clients own their player's pose, everything
+// else in `world` is the game's to fill with `SaveState`s.
+#[derive(Debug)]
+pub struct Server {
+    socket: UdpSocket,
+    peers: HashMap<SocketAddr, Peer>,
+    next_id: u32,
+    world: Snapshot,
+    history: VecDeque<(u64, Snapshot)>,
+    tick: u64,
+    time: f64,
+    since_send: f32,
+    // seconds between state updates
+    pub send_interval: f32,
+    // clients silent for longer are dropped
+    pub timeout: f32,
+    buffer: Vec<u8>,
+}
+
+impl Server {
+    pub fn bind<A: ToSocketAddrs>(address: A) -> io::Result<Self> {
+        let socket = UdpSocket::bind(address)?;
+        socket.set_nonblocking(true)?;
+        Ok(Self {
+            socket,
+            peers: HashMap::new(),
+            next_id: 1,
+            world: Snapshot::new(),
+            history: VecDeque::new(),
+            tick: 0,
+            time: 0.0,
+            since_send: 0.0,
+            send_interval: 1.0 / 20.0,
+            timeout: 5.0,
+            buffer: vec![0; MAX_DATAGRAM],
+        })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub fn world(&self) -> &Snapshot {
+        &self.world
+    }
+
+    // replicated state besides the players, sent with the next update
+    pub fn world_mut(&mut self) -> &mut Snapshot {
+        &mut self.world
+    }
+
+    pub fn players(&self) -> Vec<(u32, PlayerPose)> {
+        poses(&self.world)
+    }
+
+    // receives, drops silent clients and sends the world when `send_interval` has passed
+    pub fn update(&mut self, delta: f32) {
+        self.time += delta as f64;
+        for (from, datagram) in receive_all(&self.socket, &mut self.buffer) {
+            self.handle(from, &datagram);
+        }
+
+        let timeout = self.timeout;
+        let mut gone = vec![];
+        for peer in self.peers.values_mut() {
+            peer.silent += delta;
+            if peer.silent > timeout {
+                gone.push(peer.id);
+            }
+        }
+        self.peers.retain(|_, peer| peer.silent <= timeout);
+        for id in gone {
+            log::info!("player {} timed out", id);
+            self.world.remove_prefix(&player_key(id));
+        }
+
+        self.since_send += delta;
+        if self.since_send >= self.send_interval {
+            self.since_send = 0.0;
+            self.send();
+        }
+    }
+
+    fn handle(&mut self, from: SocketAddr, datagram: &[u8]) {
+        let (header, _) = match split_message(datagram) {
+            Some(message) => message,
+            None => return,
+        };
+        match header.first() {
+            Some(&"hello") => {
+                let next_id = &mut self.next_id;
+                let peer = self.peers.entry(from).or_insert_with(|| {
+                    let id = *next_id;
+                    *next_id += 1;
+                    log::info!("player {} joined from {}", id, from);
+                    Peer { id, acked: 0, silent: 0.0 }
+                });
+                peer.silent = 0.0;
+                // repeated until it arrives, the client says hello until welcomed
+                let welcome = format!("welcome {}", peer.id);
+                if let Err(e) = self.socket.send_to(welcome.as_bytes(), from) {
+                    log::warn!("sending to {} failed: {}", from, e);
+                }
+            }
+            Some(&"pose") if header.len() == 9 => {
+                let peer = match self.peers.get_mut(&from) {
+                    Some(peer) => peer,
+                    None => {
+                        // e.g. timed out while its datagrams were lost, it has to say hello again
+                        if let Err(e) = self.socket.send_to(b"unknown", from) {
+                            log::warn!("sending to {} failed: {}", from, e);
+                        }
+                        return;
+                    }
+                };
+                let pose = match PlayerPose::from_words(&header[2..]) {
+                    Some(pose) => pose,
+                    None => return,
+                };
+                peer.silent = 0.0;
+                // acks only go forward, datagrams may arrive out of order
+                peer.acked = peer.acked.max(header[1].parse().unwrap_or(0));
+                let id = peer.id;
+                self.world.capture(&player_key(id), &pose);
+            }
+            Some(&"bye") => {
+                if let Some(peer) = self.peers.remove(&from) {
+                    log::info!("player {} left", peer.id);
+                    self.world.remove_prefix(&player_key(peer.id));
+                }
+            }
+            _ => log::warn!("unknown message from {}", from),
+        }
+    }
+
+    fn send(&mut self) {
+        self.tick += 1;
+        self.history.push_back((self.tick, self.world.clone()));
+        if self.history.len() > HISTORY {
+            self.history.pop_front();
+        }
+        let empty = Snapshot::new();
+        for (address, peer) in self.peers.iter() {
+            // a baseline that fell out of the history gets the whole world
+            let (baseline_tick, baseline) = self
+                .history
+                .iter()
+                .find(|(tick, _)| *tick == peer.acked)
+                .map_or((0, &empty), |(tick, world)| (*tick, world));
+            let (changed, removed) = SnapshotDelta::between(baseline, &self.world);
+            let delta = SnapshotDelta {
+                tick: self.tick,
+                baseline: baseline_tick,
+                server_time: self.time,
+                changed,
+                removed,
+            };
+            for datagram in delta.to_datagrams() {
+                if let Err(e) = self.socket.send_to(datagram.as_bytes(), address) {
+                    log::warn!("sending to {} failed: {}", address, e);
+                    break;
+                }
+            }
+        }
+    }
+}
+
+fn poses(world: &Snapshot) -> Vec<(u32, PlayerPose)> {
+    player_ids(world)
+        .into_iter()
+        .filter_map(|id| {
+            let mut pose = PlayerPose {
+                position: Point3::origin(),
+                orientation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+            };
+            world.restore(&player_key(id), &mut pose).ok()?;
+            Some((id, pose))
+        })
+        .collect()
+}
+
+// sends the pose of the local player and shows the others a little in the past, interpolated
+// between the two states around that time so they move smoothly at the server's send rate
+#[derive(Debug)]
+pub struct Client {
+    socket: UdpSocket,
+    id: Option<u32>,
+    // worlds by tick, oldest first, with the server time they were sent at
+    received: VecDeque<(u64, f64, Snapshot)>,
+    // the parts of a delta split over several datagrams, by tick
+    parts: Option<(u64, Vec<Option<SnapshotDelta>>)>,
+    // the current server time as far as the client can tell
+    server_time: f64,
+    since_send: f32,
+    pub send_interval: f32,
+    // how far in the past the other players are shown, at least two send intervals of the
+    // server so there are two states to interpolate between
+    pub interpolation_delay: f64,
+    buffer: Vec<u8>,
+}
+
+impl Client {
+    pub fn connect<A: ToSocketAddrs>(server: A) -> io::Result<Self> {
+        let server = server.to_socket_addrs()?.next().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no server address"))?;
+        let local: SocketAddr = if server.is_ipv4() { ([0, 0, 0, 0], 0).into() } else { ([0u16; 8], 0).into() };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(server)?;
+        socket.set_nonblocking(true)?;
+        socket.send(b"hello")?;
+        Ok(Self {
+            socket,
+            id: None,
+            received: VecDeque::new(),
+            parts: None,
+            server_time: 0.0,
+            since_send: 0.0,
+            send_interval: 1.0 / 30.0,
+            interpolation_delay: 0.1,
+            buffer: vec![0; MAX_DATAGRAM],
+        })
+    }
+
+    // None until the server welcomed this client
+    pub fn id(&self) -> Option<u32> {
+        self.id
+    }
+
+    // the latest world from the server
+    pub fn world(&self) -> Option<&Snapshot> {
+        self.received.back().map(|(_, _, world)| world)
+    }
+
+    // receives and sends `pose` when `send_interval` has passed
+    pub fn update(&mut self, delta: f32, pose: PlayerPose) {
+        self.server_time += delta as f64;
+        for (_, datagram) in receive_all(&self.socket, &mut self.buffer) {
+            self.handle(&datagram);
+        }
+
+        self.since_send += delta;
+        if self.since_send < self.send_interval {
+            return;
+        }
+        self.since_send = 0.0;
+        let message = match self.id {
+            Some(_) => {
+                let acked = self.received.back().map_or(0, |(tick, _, _)| *tick);
+                format!("pose {} {}", acked, pose.to_words())
+            }
+            None => "hello".to_string(),
+        };
+        if let Err(e) = self.socket.send(message.as_bytes()) {
+            log::warn!("sending to the server failed: {}", e);
+        }
+    }
+
+    fn handle(&mut self, datagram: &[u8]) {
+        let (header, body) = match split_message(datagram) {
+            Some(message) => message,
+            None => return,
+        };
+        match header.first() {
+            Some(&"welcome") => {
+                if self.id.is_none() {
+                    self.id = header.get(1).and_then(|id| id.parse().ok());
+                }
+            }
+            Some(&"unknown") => {
+                if self.id.take().is_some() {
+                    log::info!("the server dropped this client, joining again");
+                }
+            }
+            Some(&"state") => {
+                let (delta, part, parts) = match SnapshotDelta::parse(&header[1..], body) {
+                    Some(part) => part,
+                    None => {
+                        log::warn!("malformed state from the server");
+                        return;
+                    }
+                };
+                if matches!(self.received.back(), Some((tick, _, _)) if *tick >= delta.tick) {
+                    return;
+                }
+                if let Some(delta) = self.assemble(delta, part, parts) {
+                    self.receive(delta);
+                }
+            }
+            _ => log::warn!("unknown message from the server"),
+        }
+    }
+
+    // the whole delta once its last part arrived. a newer tick replaces an incomplete one.
+    fn assemble(&mut self, delta: SnapshotDelta, part: usize, parts: usize) -> Option<SnapshotDelta> {
+        if parts == 1 {
+            return Some(delta);
+        }
+        match &self.parts {
+            Some((tick, _)) if *tick > delta.tick => return None,
+            Some((tick, received)) if *tick == delta.tick && received.len() == parts => {}
+            _ => self.parts = Some((delta.tick, vec![None; parts])),
+        }
+        let (_, received) = self.parts.as_mut().unwrap();
+        received[part] = Some(delta);
+        if received.iter().any(Option::is_none) {
+            return None;
+        }
+        let (_, received) = self.parts.take().unwrap();
+        Some(SnapshotDelta::merge(received.into_iter().flatten().collect()))
+    }
+
+    fn receive(&mut self, delta: SnapshotDelta) {
+        let empty = Snapshot::new();
+        let baseline = if delta.baseline == 0 {
+            &empty
+        } else {
+            match self.received.iter().find(|(tick, _, _)| *tick == delta.baseline) {
+                Some((_, _, world)) => world,
+                // dropped already, the next delta is against what is acked now
+                None => return,
+            }
+        };
+        let world = delta.apply(baseline);
+        self.received.push_back((delta.tick, delta.server_time, world));
+        if self.received.len() > HISTORY {
+            self.received.pop_front();
+        }
+        // the clock follows the server when it ran ahead or fell far behind
+        if delta.server_time > self.server_time || self.server_time - delta.server_time > 1.0 {
+            self.server_time = delta.server_time;
+        }
+    }
+
+    // the other players `interpolation_delay` in the past
+    pub fn remote_players(&self) -> Vec<(u32, PlayerPose)> {
+        if self.received.is_empty() {
+            return vec![];
+        }
+        let render_time = self.server_time - self.interpolation_delay;
+        let newer = self.received.iter().position(|(_, time, _)| *time > render_time);
+        let (from, to, t) = match newer {
+            Some(0) => (0, 0, 0.0),
+            Some(index) => {
+                let (a, b) = (self.received[index - 1].1, self.received[index].1);
+                (index - 1, index, ((render_time - a) / (b - a)) as f32)
+            }
+            // no extrapolation past the latest state
+            None => (self.received.len() - 1, self.received.len() - 1, 0.0),
+        };
+        let before: HashMap<u32, PlayerPose> = poses(&self.received[from].2).into_iter().collect();
+        poses(&self.received[to].2)
+            .into_iter()
+            .filter(|(id, _)| Some(*id) != self.id)
+            .map(|(id, pose)| (id, before.get(&id).map_or(pose, |previous| previous.interpolate(&pose, t))))
+            .collect()
+    }
+
+    // tells the server this player left instead of waiting for the timeout
+    pub fn disconnect(self) {
+        let _ = self.socket.send(b"bye");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{vec3, Rotation3, Rad};
+
+    use super::*;
+
+    fn pose(x: f32, angle: f32) -> PlayerPose {
+        PlayerPose {
+            position: Point3::new(x, 1.0, -2.0),
+            orientation: Quaternion::from_axis_angle(vec3(0.0, 1.0, 0.0), Rad(angle)),
+        }
+    }
+
+    #[test]
+    fn poses_survive_the_wire() {
+        let original = pose(0.1, 0.3);
+        let text = original.to_words();
+        let words: Vec<&str> = text.split_whitespace().collect();
+        assert_eq!(PlayerPose::from_words(&words), Some(original));
+        assert_eq!(PlayerPose::from_words(&words[1..]), None);
+        assert_eq!(PlayerPose::from_words(&["1", "2", "3", "4", "5", "6", "x"]), None);
+    }
+
+    #[test]
+    fn poses_interpolate() {
+        let (a, b) = (pose(0.0, 0.0), pose(4.0, 1.0));
+        assert_eq!(a.interpolate(&b, 0.0).position, a.position);
+        let half = a.interpolate(&b, 0.5);
+        assert_eq!(half.position, Point3::new(2.0, 1.0, -2.0));
+        let expected = pose(0.0, 0.5).orientation;
+        assert!((half.orientation - expected).magnitude() < 1e-5, "{:?}", half.orientation);
+    }
+
+    #[test]
+    fn players_are_found_by_their_position_key() {
+        let mut world = Snapshot::new();
+        world.capture(&player_key(12), &pose(1.0, 0.0));
+        world.capture(&player_key(3), &pose(2.0, 0.0));
+        world.set("score", 5);
+        assert_eq!(player_ids(&world), vec![3, 12]);
+
+        let players = poses(&world);
+        assert_eq!(players.len(), 2);
+        assert_eq!(players[0], (3, pose(2.0, 0.0)));
+    }
+
+    #[test]
+    fn deltas_carry_changes_and_removals() {
+        let mut baseline = Snapshot::new();
+        baseline.set("score", 5);
+        baseline.set("lives", 3);
+        baseline.set("level", 1);
+        let mut world = baseline.clone();
+        world.set("score", 6);
+        world.remove("lives");
+        world.set("boss", true);
+
+        let (changed, removed) = SnapshotDelta::between(&baseline, &world);
+        assert_eq!(changed.keys().collect::<Vec<_>>(), vec!["boss", "score"]);
+        assert_eq!(removed, vec!["lives".to_string()]);
+
+        let delta = SnapshotDelta {
+            tick: 2,
+            baseline: 1,
+            server_time: 0.1,
+            changed,
+            removed,
+        };
+        assert_eq!(delta.apply(&baseline), world);
+        // nothing changed, nothing to send
+        let (changed, removed) = SnapshotDelta::between(&world, &world);
+        assert_eq!((changed.keys().count(), removed.len()), (0, 0));
+    }
+
+    #[test]
+    fn large_deltas_are_split_and_merged() {
+        let mut world = Snapshot::new();
+        for i in 0..4000 {
+            world.capture(&player_key(i), &pose(i as f32, 0.0));
+        }
+        let (changed, removed) = SnapshotDelta::between(&Snapshot::new(), &world);
+        let delta = SnapshotDelta {
+            tick: 7,
+            baseline: 0,
+            server_time: 1.5,
+            changed,
+            removed,
+        };
+
+        let datagrams = delta.to_datagrams();
+        assert!(datagrams.len() > 1);
+        let mut parts = vec![];
+        for (i, datagram) in datagrams.iter().enumerate() {
+            assert!(datagram.len() <= MAX_DATAGRAM);
+            let (header, body) = split_message(datagram.as_bytes()).unwrap();
+            assert_eq!(header[0], "state");
+            let (part, index, count) = SnapshotDelta::parse(&header[1..], body).unwrap();
+            assert_eq!((index, count), (i, datagrams.len()));
+            assert_eq!((part.tick, part.server_time), (7, 1.5));
+            parts.push(part);
+        }
+        assert_eq!(SnapshotDelta::merge(parts), delta);
+    }
+
+    #[test]
+    fn small_deltas_fit_one_datagram() {
+        let mut baseline = Snapshot::new();
+        baseline.set("gone", 1);
+        let delta = SnapshotDelta {
+            tick: 3,
+            baseline: 2,
+            server_time: 0.25,
+            changed: Snapshot::new(),
+            removed: vec!["gone".to_string()],
+        };
+        let datagrams = delta.to_datagrams();
+        assert_eq!(datagrams.len(), 1);
+        let (header, body) = split_message(datagrams[0].as_bytes()).unwrap();
+        let (parsed, part, parts) = SnapshotDelta::parse(&header[1..], body).unwrap();
+        assert_eq!((part, parts), (0, 1));
+        assert_eq!(parsed, delta);
+        assert!(parsed.apply(&baseline).keys().next().is_none());
+
+        // a part past the end is malformed
+        assert!(SnapshotDelta::parse(&["3", "2", "0.25", "1", "1"], "").is_none());
+    }
+}
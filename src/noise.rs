@@ -0,0 +1,348 @@
+use cgmath::{Vector2, Vector3};
+use gl::types::*;
+
+use crate::{AttachmentFormat, GlContext, Texture3D, TextureBuilder};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NoiseKind {
+    // gradient noise on a cubic lattice
+    Perlin,
+    // gradient noise on a simplex lattice, fewer directional artifacts
+    Simplex,
+    // distance to the nearest feature point, 0 at the points, for cells and cloud billows
+    Worley,
+}
+
+// fractal noise generated on the CPU. textures are sampled over [0, 1) on each axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Noise {
+    pub kind: NoiseKind,
+    // lattice cells across the texture in the first octave
+    pub frequency: f32,
+    pub octaves: u32,
+    // frequency multiplier per octave
+    pub lacunarity: f32,
+    // amplitude multiplier per octave
+    pub gain: f32,
+    pub seed: u32,
+    // wraps seamlessly at the texture edges. the frequency and lacunarity are rounded to whole numbers.
+    pub tileable: bool,
+}
+
+impl Noise {
+    pub fn new(kind: NoiseKind) -> Self {
+        Self {
+            kind,
+            frequency: 4.0,
+            octaves: 4,
+            lacunarity: 2.0,
+            gain: 0.5,
+            seed: 0,
+            tileable: false,
+        }
+    }
+
+    pub fn with_frequency(mut self, frequency: f32) -> Self {
+        self.frequency = frequency;
+        self
+    }
+
+    pub fn with_octaves(mut self, octaves: u32, lacunarity: f32, gain: f32) -> Self {
+        self.octaves = octaves;
+        self.lacunarity = lacunarity;
+        self.gain = gain;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.seed = seed;
+        self
+    }
+
+    pub fn tileable(mut self, tileable: bool) -> Self {
+        self.tileable = tileable;
+        self
+    }
+
+    // in [0, 1]
+    pub fn sample_2d(&self, uv: Vector2<f32>) -> f32 {
+        self.fractal([uv.x, uv.y, 0.0], 2)
+    }
+
+    // in [0, 1]
+    pub fn sample_3d(&self, uvw: Vector3<f32>) -> f32 {
+        self.fractal([uvw.x, uvw.y, uvw.z], 3)
+    }
+
+    // row major values in [0, 1], sampled at the texel centers
+    pub fn generate_2d(&self, width: u32, height: u32) -> Vec<f32> {
+        let mut values = Vec::with_capacity(width as usize * height as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let uv = [(x as f32 + 0.5) / width as f32, (y as f32 + 0.5) / height as f32, 0.0];
+                values.push(self.fractal(uv, 2));
+            }
+        }
+        values
+    }
+
+    // slices along z of row major values in [0, 1], as `Texture3D::upload_f32` expects
+    pub fn generate_3d(&self, width: u32, height: u32, depth: u32) -> Vec<f32> {
+        let mut values = Vec::with_capacity(width as usize * height as usize * depth as usize);
+        for z in 0..depth {
+            for y in 0..height {
+                for x in 0..width {
+                    let uvw = [
+                        (x as f32 + 0.5) / width as f32,
+                        (y as f32 + 0.5) / height as f32,
+                        (z as f32 + 0.5) / depth as f32,
+                    ];
+                    values.push(self.fractal(uvw, 3));
+                }
+            }
+        }
+        values
+    }
+
+    // a repeating single channel R16F texture
+    pub fn texture_2d(&self, context: &GlContext, width: u32, height: u32) -> GLuint {
+        let values = self.generate_2d(width, height);
+        TextureBuilder::new()
+            .internal_format(gl::R16F)
+            .wrap(gl::REPEAT)
+            .upload_f32(context, width, height, 1, &values)
+    }
+
+    // a repeating single channel R16F volume
+    pub fn texture_3d(&self, context: &GlContext, width: u32, height: u32, depth: u32) -> Texture3D {
+        let texture = Texture3D::new(context, width, height, depth, AttachmentFormat::new(gl::R16F, gl::RED, gl::FLOAT));
+        texture.upload_f32(context, 1, &self.generate_3d(width, height, depth));
+        texture
+    }
+
+    fn fractal(&self, uvw: [f32; 3], dims: usize) -> f32 {
+        let mut frequency = if self.tileable { self.frequency.round().max(1.0) } else { self.frequency };
+        let lacunarity = if self.tileable { self.lacunarity.round().max(1.0) } else { self.lacunarity };
+        let mut amplitude = 1.0;
+        let mut sum = 0.0;
+        let mut total = 0.0;
+        for octave in 0..self.octaves.max(1) {
+            let seed = self.seed.wrapping_add(octave.wrapping_mul(0x9e37_79b9));
+            let p = [uvw[0] * frequency, uvw[1] * frequency, uvw[2] * frequency];
+            let period = if self.tileable { Some(frequency as i32) } else { None };
+            sum += amplitude * self.octave(p, period, dims, seed);
+            total += amplitude;
+            frequency *= lacunarity;
+            amplitude *= self.gain;
+        }
+        (sum / total * 0.5 + 0.5).clamp(0.0, 1.0)
+    }
+
+    // one octave in [-1, 1]
+    fn octave(&self, p: [f32; 3], period: Option<i32>, dims: usize, seed: u32) -> f32 {
+        match (self.kind, period) {
+            (NoiseKind::Perlin, _) => perlin(p, period, seed),
+            (NoiseKind::Worley, _) => worley(p, period, seed) * 2.0 - 1.0,
+            (NoiseKind::Simplex, None) => simplex(p, seed),
+            // the simplex lattice does not line up with the texture edges, so blend shifted
+            // copies instead. seamless, at the price of some contrast in the middle.
+            (NoiseKind::Simplex, Some(period)) => {
+                let period = period as f32;
+                let mut sum = 0.0;
+                for corner in 0..1 << dims {
+                    let mut q = p;
+                    let mut weight = 1.0;
+                    for (axis, q) in q.iter_mut().enumerate().take(dims) {
+                        let t = p[axis] / period;
+                        if corner & (1 << axis) != 0 {
+                            *q -= period;
+                            weight *= t;
+                        } else {
+                            weight *= 1.0 - t;
+                        }
+                    }
+                    sum += weight * simplex(q, seed);
+                }
+                sum
+            }
+        }
+    }
+}
+
+fn hash(x: i32, y: i32, z: i32, seed: u32) -> u32 {
+    let mut h = seed ^ (x as u32).wrapping_mul(0x8da6_b343) ^ (y as u32).wrapping_mul(0xd816_3841) ^ (z as u32).wrapping_mul(0xcb1a_b31f);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^ (h >> 16)
+}
+
+fn wrap(i: i32, period: Option<i32>) -> i32 {
+    match period {
+        Some(period) => i.rem_euclid(period),
+        None => i,
+    }
+}
+
+// one of the 12 cube edge directions
+fn gradient(hash: u32, x: f32, y: f32, z: f32) -> f32 {
+    match hash % 12 {
+        0 => x + y,
+        1 => -x + y,
+        2 => x - y,
+        3 => -x - y,
+        4 => x + z,
+        5 => -x + z,
+        6 => x - z,
+        7 => -x - z,
+        8 => y + z,
+        9 => -y + z,
+        10 => y - z,
+        _ => -y - z,
+    }
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+fn perlin(p: [f32; 3], period: Option<i32>, seed: u32) -> f32 {
+    let cell = [p[0].floor(), p[1].floor(), p[2].floor()];
+    let f = [p[0] - cell[0], p[1] - cell[1], p[2] - cell[2]];
+    let cell = [cell[0] as i32, cell[1] as i32, cell[2] as i32];
+
+    let corner = |dx: i32, dy: i32, dz: i32| {
+        let h = hash(wrap(cell[0] + dx, period), wrap(cell[1] + dy, period), wrap(cell[2] + dz, period), seed);
+        gradient(h, f[0] - dx as f32, f[1] - dy as f32, f[2] - dz as f32)
+    };
+    let (u, v, w) = (fade(f[0]), fade(f[1]), fade(f[2]));
+    let x00 = lerp(corner(0, 0, 0), corner(1, 0, 0), u);
+    let x10 = lerp(corner(0, 1, 0), corner(1, 1, 0), u);
+    let x01 = lerp(corner(0, 0, 1), corner(1, 0, 1), u);
+    let x11 = lerp(corner(0, 1, 1), corner(1, 1, 1), u);
+    lerp(lerp(x00, x10, v), lerp(x01, x11, v), w).clamp(-1.0, 1.0)
+}
+
+fn simplex(p: [f32; 3], seed: u32) -> f32 {
+    const F3: f32 = 1.0 / 3.0;
+    const G3: f32 = 1.0 / 6.0;
+
+    // skew into the simplex lattice to find the containing cell
+    let s = (p[0] + p[1] + p[2]) * F3;
+    let i = (p[0] + s).floor();
+    let j = (p[1] + s).floor();
+    let k = (p[2] + s).floor();
+    let t = (i + j + k) * G3;
+    let x0 = [p[0] - (i - t), p[1] - (j - t), p[2] - (k - t)];
+
+    // the second and third corners depend on which of the six tetrahedra we are in
+    let (o1, o2) = if x0[0] >= x0[1] {
+        if x0[1] >= x0[2] {
+            ([1, 0, 0], [1, 1, 0])
+        } else if x0[0] >= x0[2] {
+            ([1, 0, 0], [1, 0, 1])
+        } else {
+            ([0, 0, 1], [1, 0, 1])
+        }
+    } else if x0[1] < x0[2] {
+        ([0, 0, 1], [0, 1, 1])
+    } else if x0[0] < x0[2] {
+        ([0, 1, 0], [0, 1, 1])
+    } else {
+        ([0, 1, 0], [1, 1, 0])
+    };
+
+    let (i, j, k) = (i as i32, j as i32, k as i32);
+    let corners = [([0, 0, 0], 0.0), (o1, G3), (o2, 2.0 * G3), ([1, 1, 1], 3.0 * G3)];
+    let mut sum = 0.0;
+    for &(offset, g) in corners.iter() {
+        let x = x0[0] - offset[0] as f32 + g;
+        let y = x0[1] - offset[1] as f32 + g;
+        let z = x0[2] - offset[2] as f32 + g;
+        let t = 0.6 - x * x - y * y - z * z;
+        if t > 0.0 {
+            let h = hash(i + offset[0], j + offset[1], k + offset[2], seed);
+            sum += t * t * t * t * gradient(h, x, y, z);
+        }
+    }
+    (32.0 * sum).clamp(-1.0, 1.0)
+}
+
+// distance to the closest of one random point per cell, in cells and clamped to 1
+fn worley(p: [f32; 3], period: Option<i32>, seed: u32) -> f32 {
+    let cell = [p[0].floor() as i32, p[1].floor() as i32, p[2].floor() as i32];
+    let mut closest = 1.0f32;
+    for dz in -1..=1 {
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let c = [cell[0] + dx, cell[1] + dy, cell[2] + dz];
+                let h = hash(wrap(c[0], period), wrap(c[1], period), wrap(c[2], period), seed);
+                let jitter = |shift: u32| ((h >> shift) & 0x3ff) as f32 / 1023.0;
+                let d = [
+                    c[0] as f32 + jitter(0) - p[0],
+                    c[1] as f32 + jitter(10) - p[1],
+                    c[2] as f32 + jitter(20) - p[2],
+                ];
+                closest = closest.min((d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt());
+            }
+        }
+    }
+    closest
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use cgmath::vec2;
+
+    const KINDS: [NoiseKind; 3] = [NoiseKind::Perlin, NoiseKind::Simplex, NoiseKind::Worley];
+
+    #[test]
+    fn values_are_in_range() {
+        for &kind in KINDS.iter() {
+            let values = Noise::new(kind).generate_2d(32, 32);
+            assert_eq!(values.len(), 32 * 32);
+            assert!(values.iter().all(|v| (0.0..=1.0).contains(v)), "{:?}", kind);
+            // not a constant
+            let (min, max) = values.iter().fold((1.0f32, 0.0f32), |(min, max), &v| (min.min(v), max.max(v)));
+            assert!(max - min > 0.1, "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn same_seed_same_noise() {
+        for &kind in KINDS.iter() {
+            let noise = Noise::new(kind).with_seed(7);
+            assert_eq!(noise.generate_2d(8, 8), noise.generate_2d(8, 8));
+            assert_ne!(noise.generate_2d(8, 8), noise.with_seed(8).generate_2d(8, 8), "{:?}", kind);
+        }
+    }
+
+    #[test]
+    fn tileable_noise_matches_at_the_edges() {
+        for &kind in KINDS.iter() {
+            let noise = Noise::new(kind).with_frequency(3.0).tileable(true);
+            for &t in &[0.1, 0.5, 0.73] {
+                let (left, right) = (noise.sample_2d(vec2(0.0, t)), noise.sample_2d(vec2(1.0, t)));
+                assert!((left - right).abs() < 1e-3, "{:?}", kind);
+                let (bottom, top) = (noise.sample_2d(vec2(t, 0.0)), noise.sample_2d(vec2(t, 1.0)));
+                assert!((bottom - top).abs() < 1e-3, "{:?}", kind);
+            }
+        }
+    }
+
+    #[test]
+    fn generate_3d_is_sliced_along_z() {
+        let noise = Noise::new(NoiseKind::Perlin).with_octaves(1, 2.0, 0.5);
+        let values = noise.generate_3d(4, 4, 2);
+        assert_eq!(values.len(), 32);
+        let texel = noise.sample_3d(Vector3::new(1.5 / 4.0, 2.5 / 4.0, 1.5 / 2.0));
+        assert!((values[16 + 2 * 4 + 1] - texel).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,84 @@
+use cgmath::{Matrix4, Vector3};
+
+use crate::{c_str, Camera, GlContext, Model, Shader};
+
+const NORMAL_VERTEX_SHADER: &str = r#"
+#version 330 core
+
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec3 aNormal;
+
+out VS_OUT {
+    vec3 normal;
+} vs_out;
+
+uniform mat4 model;
+
+void main() {
+    gl_Position = model * vec4(aPos, 1.0);
+    vs_out.normal = normalize(mat3(transpose(inverse(model))) * aNormal);
+}
+"#;
+
+const NORMAL_GEOMETRY_SHADER: &str = r#"
+#version 330 core
+layout (triangles) in;
+layout (line_strip, max_vertices = 6) out;
+
+in VS_OUT {
+    vec3 normal;
+} gs_in[];
+
+uniform mat4 viewProjection;
+uniform float magnitude;
+
+void GenerateLine(int index) {
+    // gl_Position holds the world position here, the line is `magnitude` world units long
+    gl_Position = viewProjection * gl_in[index].gl_Position;
+    EmitVertex();
+    gl_Position = viewProjection * (gl_in[index].gl_Position + vec4(gs_in[index].normal, 0.0) * magnitude);
+    EmitVertex();
+
+    EndPrimitive();
+}
+
+void main() {
+    GenerateLine(0);
+    GenerateLine(1);
+    GenerateLine(2);
+}
+"#;
+
+const NORMAL_FRAGMENT_SHADER: &str = r#"
+#version 330 core
+out vec4 FragColor;
+
+uniform vec3 color;
+
+void main() {
+    FragColor = vec4(color, 1.0);
+}
+"#;
+
+// draws the vertex normals of a model as lines, using a geometry shader
+#[derive(Debug)]
+pub struct NormalVisualizer {
+    shader: Shader,
+}
+
+impl NormalVisualizer {
+    pub fn new(context: &GlContext) -> Self {
+        Self {
+            shader: Shader::with_geometry(context, NORMAL_VERTEX_SHADER, NORMAL_GEOMETRY_SHADER, NORMAL_FRAGMENT_SHADER),
+        }
+    }
+
+    pub fn draw(&self, context: &GlContext, model: &Model, transform: &Matrix4<f32>, camera: &dyn Camera, length: f32, color: Vector3<f32>) {
+        self.shader.use_program();
+        self.shader.set_matrix4(c_str("model\0"), transform);
+        self.shader.set_matrix4(c_str("viewProjection\0"), &(camera.projection() * camera.view()));
+        self.shader.set_float(c_str("magnitude\0"), length);
+        self.shader.set_vec3(c_str("color\0"), color.x, color.y, color.z);
+        model.draw(context, &self.shader);
+    }
+}
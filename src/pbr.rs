@@ -0,0 +1,72 @@
+use crate::{Material, Mesh, Texture, TextureType};
+
+// the texture slots of a metallic-roughness material. `load_obj` tags the
+// maps with their types, this sorts them back into named slots.
+#[derive(Debug, Clone, Default)]
+pub struct PbrMaterial {
+    pub base_color: Option<Texture>,
+    pub normal: Option<Texture>,
+    pub emissive: Option<Texture>,
+    pub occlusion: Option<Texture>,
+    pub roughness: Option<Texture>,
+    pub metallic: Option<Texture>,
+    pub sheen: Option<Texture>,
+    // glTF packing: roughness in green, metallic in blue
+    pub metallic_roughness: Option<Texture>,
+}
+
+impl PbrMaterial {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // the first texture of each type fills its slot, specular maps and lightmaps have no slot
+    pub fn from_textures(textures: &[Texture]) -> Self {
+        let mut material = Self::default();
+        for texture in textures.iter() {
+            let slot = match texture.type_() {
+                TextureType::Diffuse => &mut material.base_color,
+                TextureType::Normal => &mut material.normal,
+                TextureType::Emissive => &mut material.emissive,
+                TextureType::Ambient | TextureType::Occlusion => &mut material.occlusion,
+                TextureType::Roughness => &mut material.roughness,
+                TextureType::Metallic => &mut material.metallic,
+                TextureType::Sheen => &mut material.sheen,
+                TextureType::MetallicRoughness => &mut material.metallic_roughness,
+                TextureType::Specular | TextureType::Lightmap => continue,
+            };
+            if slot.is_none() {
+                *slot = Some(texture.clone());
+            }
+        }
+        material
+    }
+
+    pub fn from_mesh(mesh: &Mesh) -> Self {
+        Self::from_textures(&mesh.textures)
+    }
+
+    // true when roughness and metallic come from some texture
+    pub fn has_metallic_roughness(&self) -> bool {
+        self.metallic_roughness.is_some() || (self.roughness.is_some() && self.metallic.is_some())
+    }
+
+    // the filled slots as a material for `Mesh::draw_with_material`, bound as `material.texture_<type>1`
+    pub fn to_material(&self) -> Material {
+        let slots = [
+            (&self.base_color, TextureType::Diffuse),
+            (&self.normal, TextureType::Normal),
+            (&self.emissive, TextureType::Emissive),
+            // either a map_Ka texture or a baked occlusion map, which the shaders sample differently
+            (&self.occlusion, self.occlusion.as_ref().map_or(TextureType::Occlusion, Texture::type_)),
+            (&self.roughness, TextureType::Roughness),
+            (&self.metallic, TextureType::Metallic),
+            (&self.sheen, TextureType::Sheen),
+            (&self.metallic_roughness, TextureType::MetallicRoughness),
+        ];
+        slots
+            .iter()
+            .filter_map(|(texture, type_)| texture.as_ref().map(|texture| texture.with_type(*type_)))
+            .fold(Material::new(), Material::with_texture)
+    }
+}
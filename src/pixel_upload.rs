@@ -0,0 +1,181 @@
+use std::ptr;
+use std::slice;
+
+use gl::types::*;
+use image::RgbaImage;
+
+use crate::context::check_render_thread;
+use crate::{FrameStats, GlContext};
+
+// RGBA8 pixels mapped from a pixel buffer object.
+// can be sent to a worker to decode or copy into, then handed back to `PixelUploader::upload`
+// or `PixelUploader::release`.
+#[derive(Debug)]
+pub struct StagingBuffer {
+    pbo: GLuint,
+    pixels: *mut u8,
+    width: u32,
+    height: u32,
+}
+
+// the mapping stays valid until the uploader unmaps it on the render thread, see `as_mut_slice`
+unsafe impl Send for StagingBuffer {}
+
+impl StagingBuffer {
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn len(&self) -> usize {
+        self.width as usize * self.height as usize * 4
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Tightly packed rows, the first one ends up at t = 0.
+    ///
+    /// # Safety
+    ///
+    /// The `PixelUploader` that mapped the buffer and its context must still be alive. Dropping
+    /// the uploader deletes the buffer, which frees the mapped memory behind the slice.
+    pub unsafe fn as_mut_slice(&mut self) -> &mut [u8] {
+        slice::from_raw_parts_mut(self.pixels, self.len())
+    }
+
+    /// # Safety
+    ///
+    /// Same as `as_mut_slice`.
+    pub unsafe fn write_image(&mut self, img: &RgbaImage) {
+        assert_eq!((img.width(), img.height()), (self.width, self.height), "image size does not match the staging buffer");
+        self.as_mut_slice().copy_from_slice(img);
+    }
+}
+
+// streams texture data through pixel buffer objects so TexImage returns without waiting
+// for the copy. the buffers are reused once their upload has been issued.
+#[derive(Debug, Default)]
+pub struct PixelUploader {
+    free: Vec<GLuint>,
+    // includes the mapped ones
+    buffers: Vec<GLuint>,
+}
+
+impl PixelUploader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // the buffers are mapped until passed back to `upload`
+    pub fn map(&mut self, _context: &GlContext, width: u32, height: u32) -> StagingBuffer {
+        unsafe { self.map_raw(width, height) }
+    }
+
+    // uploads level 0 of `texture`, reallocating it to the staging size, and rebuilds the mip chain
+    pub fn upload(&mut self, _context: &GlContext, staging: StagingBuffer, texture: GLuint, mipmaps: bool) {
+        unsafe { self.upload_raw(staging, texture, mipmaps) }
+    }
+
+    // updates a region of an existing texture without reallocating it
+    pub fn upload_region(&mut self, _context: &GlContext, staging: StagingBuffer, texture: GLuint, x: u32, y: u32) {
+        check_render_thread("PixelUploader");
+        FrameStats::record_buffer_upload(staging.len());
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+            self.unpack(staging, |width, height| {
+                gl::TexSubImage2D(gl::TEXTURE_2D, 0, conv!(x), conv!(y), width, height, gl::RGBA, gl::UNSIGNED_BYTE, ptr::null());
+            });
+        }
+    }
+
+    // hands a staging buffer back without uploading it, e.g. after a failed decode
+    pub fn release(&mut self, _context: &GlContext, staging: StagingBuffer) {
+        unsafe { self.release_raw(staging) }
+    }
+
+    // copies a decoded image through a staging buffer
+    pub fn upload_image(&mut self, _context: &GlContext, img: &RgbaImage, texture: GLuint, mipmaps: bool) {
+        unsafe { self.upload_image_raw(img, texture, mipmaps) }
+    }
+
+    pub(crate) unsafe fn map_raw(&mut self, width: u32, height: u32) -> StagingBuffer {
+        check_render_thread("PixelUploader");
+        let pbo = match self.free.pop() {
+            Some(pbo) => pbo,
+            None => {
+                let mut pbo = 0;
+                gl::GenBuffers(1, &mut pbo);
+                self.buffers.push(pbo);
+                pbo
+            }
+        };
+        let size = width as usize * height as usize * 4;
+        gl::BindBuffer(gl::PIXEL_UNPACK_BUFFER, pbo);
+        // orphan the previous storage, the driver may still be reading from it
+        gl::BufferData(gl::PIXEL_UNPACK_BUFFER, conv!(size), ptr::null(), gl::STREAM_DRAW);
+        let pixels = if size == 0 {
+            ptr::null_mut()
+        } else {
+            gl::MapBufferRange(gl::PIXEL_UNPACK_BUFFER, 0, conv!(size), gl::MAP_WRITE_BIT | gl::MAP_INVALIDATE_BUFFER_BIT)
+                as *mut u8
+        };
+        gl::BindBuffer(gl::PIXEL_UNPACK_BUFFER, 0);
+        assert!(size == 0 || !pixels.is_null(), "failed to map a pixel buffer");
+
+        StagingBuffer { pbo, pixels, width, height }
+    }
+
+    pub(crate) unsafe fn upload_raw(&mut self, staging: StagingBuffer, texture: GLuint, mipmaps: bool) {
+        check_render_thread("PixelUploader");
+        FrameStats::record_buffer_upload(staging.len());
+        gl::BindTexture(gl::TEXTURE_2D, texture);
+        self.unpack(staging, |width, height| {
+            gl::TexImage2D(gl::TEXTURE_2D, 0, conv!(gl::RGBA), width, height, 0, gl::RGBA, gl::UNSIGNED_BYTE, ptr::null());
+        });
+        if mipmaps {
+            gl::GenerateMipmap(gl::TEXTURE_2D);
+        }
+    }
+
+    pub(crate) unsafe fn upload_image_raw(&mut self, img: &RgbaImage, texture: GLuint, mipmaps: bool) {
+        let mut staging = self.map_raw(img.width(), img.height());
+        staging.write_image(img);
+        self.upload_raw(staging, texture, mipmaps);
+    }
+
+    pub(crate) unsafe fn release_raw(&mut self, staging: StagingBuffer) {
+        check_render_thread("PixelUploader");
+        self.unpack(staging, |_, _| {});
+    }
+
+    // unmaps the staging buffer and sources the pixels of `f` from it
+    unsafe fn unpack<F: FnOnce(GLsizei, GLsizei)>(&mut self, staging: StagingBuffer, f: F) {
+        assert!(self.buffers.contains(&staging.pbo), "staging buffer belongs to another uploader");
+        gl::BindBuffer(gl::PIXEL_UNPACK_BUFFER, staging.pbo);
+        if !staging.pixels.is_null() {
+            gl::UnmapBuffer(gl::PIXEL_UNPACK_BUFFER);
+        }
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+        f(conv!(staging.width), conv!(staging.height));
+        gl::BindBuffer(gl::PIXEL_UNPACK_BUFFER, 0);
+        self.free.push(staging.pbo);
+    }
+}
+
+impl Drop for PixelUploader {
+    fn drop(&mut self) {
+        if self.buffers.is_empty() {
+            return;
+        }
+        check_render_thread("PixelUploader");
+        // deleting also unmaps staging buffers that were never handed back
+        unsafe {
+            gl::DeleteBuffers(conv!(self.buffers.len()), self.buffers.as_ptr());
+        }
+    }
+}
@@ -0,0 +1,743 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::mem;
+use std::path::Path;
+use std::ptr;
+use std::sync::mpsc::{sync_channel, Receiver, TryRecvError};
+use std::thread::{self, JoinHandle};
+
+use cgmath::{Point3, Vector3};
+use gl::types::*;
+
+use crate::context::check_render_thread;
+use crate::{c_str, Camera, CullResult, FrameStats, GlContext, Shader};
+
+// chunks read ahead of their upload, bounding the memory of large files
+const READ_AHEAD: usize = 4;
+
+#[derive(Debug)]
+pub enum PointCloudError {
+    Io(io::Error),
+    Format(String),
+}
+
+impl fmt::Display for PointCloudError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PointCloudError::Io(e) => write!(f, "failed to read point cloud: {}", e),
+            PointCloudError::Format(message) => write!(f, "invalid point cloud: {}", message),
+        }
+    }
+}
+
+impl Error for PointCloudError {}
+
+impl From<io::Error> for PointCloudError {
+    fn from(e: io::Error) -> Self {
+        PointCloudError::Io(e)
+    }
+}
+
+fn format_error<T>(message: String) -> Result<T, PointCloudError> {
+    Err(PointCloudError::Format(message))
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CloudPoint {
+    pub position: Point3<f32>,
+    // linear, from 0 to 1
+    pub color: Vector3<f32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PlyType {
+    I8,
+    U8,
+    I16,
+    U16,
+    I32,
+    U32,
+    F32,
+    F64,
+}
+
+impl PlyType {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "char" | "int8" => PlyType::I8,
+            "uchar" | "uint8" => PlyType::U8,
+            "short" | "int16" => PlyType::I16,
+            "ushort" | "uint16" => PlyType::U16,
+            "int" | "int32" => PlyType::I32,
+            "uint" | "uint32" => PlyType::U32,
+            "float" | "float32" => PlyType::F32,
+            "double" | "float64" => PlyType::F64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            PlyType::I8 | PlyType::U8 => 1,
+            PlyType::I16 | PlyType::U16 => 2,
+            PlyType::I32 | PlyType::U32 | PlyType::F32 => 4,
+            PlyType::F64 => 8,
+        }
+    }
+
+    // integer colors are scaled from their full range to [0, 1]
+    fn color_scale(self) -> f64 {
+        match self {
+            PlyType::U8 => 1.0 / 255.0,
+            PlyType::U16 => 1.0 / 65535.0,
+            _ => 1.0,
+        }
+    }
+
+    fn decode(self, bytes: &[u8], big_endian: bool) -> f64 {
+        macro_rules! read {
+            ($type:ty, $n:expr) => {{
+                let mut raw = [0u8; $n];
+                raw.copy_from_slice(&bytes[..$n]);
+                (if big_endian { <$type>::from_be_bytes(raw) } else { <$type>::from_le_bytes(raw) }) as f64
+            }};
+        }
+        match self {
+            PlyType::I8 => bytes[0] as i8 as f64,
+            PlyType::U8 => bytes[0] as f64,
+            PlyType::I16 => read!(i16, 2),
+            PlyType::U16 => read!(u16, 2),
+            PlyType::I32 => read!(i32, 4),
+            PlyType::U32 => read!(u32, 4),
+            PlyType::F32 => read!(f32, 4),
+            PlyType::F64 => read!(f64, 8),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PlyFormat {
+    Ascii,
+    LittleEndian,
+    BigEndian,
+}
+
+#[derive(Debug)]
+struct PlyLayout {
+    format: PlyFormat,
+    types: Vec<PlyType>,
+    // property indices of x, y, z and red, green, blue
+    position: [usize; 3],
+    color: Option<[usize; 3]>,
+    // bytes of a binary vertex
+    stride: usize,
+}
+
+impl PlyLayout {
+    fn read_header<R: BufRead>(reader: &mut R) -> Result<(Self, u64), PointCloudError> {
+        let mut line = String::new();
+        let mut next_line = |reader: &mut R| -> Result<String, PointCloudError> {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 {
+                return format_error("the PLY header does not end".into());
+            }
+            Ok(line.trim().to_string())
+        };
+        if next_line(reader)? != "ply" {
+            return format_error("not a PLY file".into());
+        }
+
+        let (mut format, mut count, mut names, mut types) = (None, None, vec![], vec![]);
+        // the element the properties belong to
+        let mut in_vertex = false;
+        loop {
+            let line = next_line(reader)?;
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                ["end_header"] => break,
+                ["format", name, _] => {
+                    format = Some(match *name {
+                        "ascii" => PlyFormat::Ascii,
+                        "binary_little_endian" => PlyFormat::LittleEndian,
+                        "binary_big_endian" => PlyFormat::BigEndian,
+                        _ => return format_error(format!("unknown PLY format {}", name)),
+                    })
+                }
+                ["element", "vertex", n] => {
+                    if count.is_some() {
+                        return format_error("more than one vertex element".into());
+                    }
+                    count = Some(n.parse::<u64>().map_err(|_| PointCloudError::Format(format!("bad vertex count {}", n)))?);
+                    in_vertex = true;
+                }
+                ["element", ..] => {
+                    // faces and the like are read past, so they have to follow the points
+                    if count.is_none() {
+                        return format_error("elements before the vertices are not supported".into());
+                    }
+                    in_vertex = false;
+                }
+                ["property", "list", ..] if in_vertex => return format_error("list properties of vertices are not supported".into()),
+                ["property", type_, name] if in_vertex => {
+                    types.push(PlyType::parse(type_).ok_or_else(|| PointCloudError::Format(format!("unknown PLY type {}", type_)))?);
+                    names.push(name.to_string());
+                }
+                _ => {}
+            }
+        }
+
+        let find = |candidates: &[&str]| names.iter().position(|name| candidates.contains(&name.as_str()));
+        let position = match (find(&["x"]), find(&["y"]), find(&["z"])) {
+            (Some(x), Some(y), Some(z)) => [x, y, z],
+            _ => return format_error("the vertices have no x, y and z".into()),
+        };
+        let color = match (find(&["red", "r", "diffuse_red"]), find(&["green", "g", "diffuse_green"]), find(&["blue", "b", "diffuse_blue"])) {
+            (Some(r), Some(g), Some(b)) => Some([r, g, b]),
+            _ => None,
+        };
+        let stride = types.iter().map(|type_| type_.size()).sum();
+        let layout = PlyLayout {
+            format: format.ok_or_else(|| PointCloudError::Format("the PLY header has no format".into()))?,
+            types,
+            position,
+            color,
+            stride,
+        };
+        Ok((layout, count.unwrap_or(0)))
+    }
+
+    fn point(&self, values: &[f64]) -> CloudPoint {
+        let [x, y, z] = self.position;
+        let color = self.color.map_or(Vector3::new(1.0, 1.0, 1.0), |[r, g, b]| {
+            let channel = |index: usize| (values[index] * self.types[index].color_scale()) as f32;
+            Vector3::new(channel(r), channel(g), channel(b))
+        });
+        CloudPoint {
+            position: Point3::new(values[x] as f32, values[y] as f32, values[z] as f32),
+            color,
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Source {
+    // with the color scale, decided by the first line with colors
+    Xyz(Option<f32>),
+    Ply(PlyLayout, u64),
+}
+
+// reads a PLY (ascii or binary, points first) or XYZ (x y z and optionally r g b per line) file
+// a chunk at a time, so files larger than memory can be streamed
+#[derive(Debug)]
+pub struct PointReader {
+    reader: BufReader<File>,
+    source: Source,
+    line: usize,
+}
+
+impl PointReader {
+    // PLY by the extension or the first line, XYZ otherwise
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, PointCloudError> {
+        let mut reader = BufReader::new(File::open(path.as_ref())?);
+        let is_ply = matches!(path.as_ref().extension(), Some(extension) if extension.eq_ignore_ascii_case("ply"))
+            || reader.fill_buf()?.starts_with(b"ply");
+        let source = if is_ply {
+            let (layout, count) = PlyLayout::read_header(&mut reader)?;
+            Source::Ply(layout, count)
+        } else {
+            Source::Xyz(None)
+        };
+        Ok(Self { reader, source, line: 0 })
+    }
+
+    // the points left, when the file says
+    pub fn remaining(&self) -> Option<u64> {
+        match &self.source {
+            Source::Ply(_, remaining) => Some(*remaining),
+            Source::Xyz(_) => None,
+        }
+    }
+
+    // up to `max` points, None at the end of the file
+    pub fn next_chunk(&mut self, max: usize) -> Result<Option<Vec<CloudPoint>>, PointCloudError> {
+        let mut points = Vec::with_capacity(max.min(1 << 20));
+        let mut text = String::new();
+        let mut record = vec![];
+        while points.len() < max {
+            let point = match &mut self.source {
+                Source::Ply(_, 0) => break,
+                Source::Ply(layout, remaining) => {
+                    *remaining -= 1;
+                    let values: Vec<f64> = if layout.format == PlyFormat::Ascii {
+                        text.clear();
+                        self.line += 1;
+                        if self.reader.read_line(&mut text)? == 0 {
+                            return format_error("the file ends before its last point".into());
+                        }
+                        let values: Option<Vec<f64>> = text.split_whitespace().map(|word| word.parse().ok()).collect();
+                        match values {
+                            Some(values) if values.len() >= layout.types.len() => values,
+                            _ => return format_error(format!("bad point on data line {}", self.line)),
+                        }
+                    } else {
+                        record.resize(layout.stride, 0);
+                        self.reader.read_exact(&mut record)?;
+                        let big_endian = layout.format == PlyFormat::BigEndian;
+                        let mut offset = 0;
+                        layout
+                            .types
+                            .iter()
+                            .map(|type_| {
+                                let value = type_.decode(&record[offset..], big_endian);
+                                offset += type_.size();
+                                value
+                            })
+                            .collect()
+                    };
+                    layout.point(&values)
+                }
+                Source::Xyz(scale) => {
+                    text.clear();
+                    self.line += 1;
+                    if self.reader.read_line(&mut text)? == 0 {
+                        break;
+                    }
+                    let line = text.trim();
+                    if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+                        continue;
+                    }
+                    let values: Option<Vec<f32>> = line
+                        .split(|c: char| c.is_whitespace() || c == ',')
+                        .filter(|word| !word.is_empty())
+                        .map(|word| word.parse().ok())
+                        .collect();
+                    let values = match values {
+                        Some(values) if values.len() >= 3 => values,
+                        _ => return format_error(format!("bad point on line {}", self.line)),
+                    };
+                    // x y z r g b, or x y z i r g b as in PTS files. other columns like normals
+                    // are left out, as long as they don't look like colors.
+                    let columns: &[usize] = if values.len() == 7 { &[4, 3] } else { &[3] };
+                    let rgb = columns
+                        .iter()
+                        .filter_map(|&first| values.get(first..first + 3))
+                        .find(|rgb| looks_like_color(rgb));
+                    let color = match rgb {
+                        Some(rgb) => {
+                            let scale = *scale.get_or_insert(if rgb.iter().any(|&c| c > 1.0) { 1.0 / 255.0 } else { 1.0 });
+                            Vector3::new(rgb[0], rgb[1], rgb[2]) * scale
+                        }
+                        None => Vector3::new(1.0, 1.0, 1.0),
+                    };
+                    CloudPoint {
+                        position: Point3::new(values[0], values[1], values[2]),
+                        color,
+                    }
+                }
+            };
+            points.push(point);
+        }
+        Ok(if points.is_empty() { None } else { Some(points) })
+    }
+}
+
+// in [0, 1], or whole numbers up to 255
+fn looks_like_color(rgb: &[f32]) -> bool {
+    rgb.iter().all(|&c| (0.0..=1.0).contains(&c)) || rgb.iter().all(|&c| (0.0..=255.0).contains(&c) && c.fract() == 0.0)
+}
+
+// one buffer of points with its bounds, culled as a whole
+#[derive(Debug)]
+struct CloudChunk {
+    vao: GLuint,
+    vbo: GLuint,
+    count: usize,
+    min: Point3<f32>,
+    max: Point3<f32>,
+}
+
+enum Loaded {
+    Chunk(Vec<CloudPoint>),
+    Failed(String),
+}
+
+// points on the GPU in chunks of their own buffer. `load` reads a file on a background thread,
+// `update` uploads what it read.
+pub struct PointCloud {
+    chunks: Vec<CloudChunk>,
+    loading: Option<Receiver<Loaded>>,
+    loader: Option<JoinHandle<()>>,
+    error: Option<String>,
+}
+
+impl fmt::Debug for PointCloud {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PointCloud")
+            .field("chunks", &self.chunks.len())
+            .field("points", &self.point_count())
+            .field("loading", &self.is_loading())
+            .finish()
+    }
+}
+
+impl PointCloud {
+    pub fn new(_context: &GlContext) -> Self {
+        Self {
+            chunks: vec![],
+            loading: None,
+            loader: None,
+            error: None,
+        }
+    }
+
+    // starts reading `path` in chunks of `chunk_points`, e.g. 65536
+    pub fn load<P: AsRef<Path>>(context: &GlContext, path: P, chunk_points: usize) -> Result<Self, PointCloudError> {
+        let mut reader = PointReader::open(path)?;
+        let (sender, receiver) = sync_channel(READ_AHEAD);
+        let loader = thread::Builder::new()
+            .name("point cloud loading".into())
+            .spawn(move || loop {
+                let message = match reader.next_chunk(chunk_points.max(1)) {
+                    Ok(Some(points)) => Loaded::Chunk(points),
+                    Ok(None) => return,
+                    Err(e) => Loaded::Failed(e.to_string()),
+                };
+                let failed = matches!(message, Loaded::Failed(_));
+                // stops when the cloud is dropped
+                if sender.send(message).is_err() || failed {
+                    return;
+                }
+            })
+            .expect("failed to spawn point cloud loading thread");
+        let mut cloud = Self::new(context);
+        cloud.loading = Some(receiver);
+        cloud.loader = Some(loader);
+        Ok(cloud)
+    }
+
+    pub fn add_points(&mut self, _context: &GlContext, points: &[CloudPoint]) {
+        check_render_thread("PointCloud");
+        if points.is_empty() {
+            return;
+        }
+        let fold = |f: fn(f32, f32) -> f32, start: f32| {
+            points.iter().fold(Point3::new(start, start, start), |acc, point| {
+                Point3::new(f(acc.x, point.position.x), f(acc.y, point.position.y), f(acc.z, point.position.z))
+            })
+        };
+        let (min, max) = (fold(f32::min, f32::INFINITY), fold(f32::max, f32::NEG_INFINITY));
+        let (mut vao, mut vbo) = (0, 0);
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+            gl::GenBuffers(1, &mut vbo);
+            gl::BindVertexArray(vao);
+            gl::BindBuffer(gl::ARRAY_BUFFER, vbo);
+            let size = mem::size_of_val(points);
+            gl::BufferData(gl::ARRAY_BUFFER, conv!(size), points.as_ptr() as *const _, gl::STATIC_DRAW);
+            FrameStats::record_buffer_upload(size);
+            let stride = conv!(mem::size_of::<CloudPoint>());
+            gl::EnableVertexAttribArray(0);
+            gl::VertexAttribPointer(0, 3, gl::FLOAT, gl::FALSE, stride, ptr::null());
+            gl::EnableVertexAttribArray(1);
+            gl::VertexAttribPointer(1, 3, gl::FLOAT, gl::FALSE, stride, mem::size_of::<Point3<f32>>() as *const _);
+            gl::BindVertexArray(0);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+        self.chunks.push(CloudChunk {
+            vao,
+            vbo,
+            count: points.len(),
+            min,
+            max,
+        });
+    }
+
+    // uploads up to `max_chunks` chunks the loader has read, returns how many
+    pub fn update(&mut self, context: &GlContext, max_chunks: usize) -> usize {
+        let mut uploaded = 0;
+        while uploaded < max_chunks {
+            let message = match self.loading.as_ref().map(|loading| loading.try_recv()) {
+                Some(Ok(message)) => message,
+                Some(Err(TryRecvError::Empty)) | None => break,
+                Some(Err(TryRecvError::Disconnected)) => {
+                    self.loading = None;
+                    break;
+                }
+            };
+            match message {
+                Loaded::Chunk(points) => {
+                    self.add_points(context, &points);
+                    uploaded += 1;
+                }
+                Loaded::Failed(message) => {
+                    log::error!("{}", message);
+                    self.error = Some(message);
+                }
+            }
+        }
+        uploaded
+    }
+
+    pub fn is_loading(&self) -> bool {
+        self.loading.is_some()
+    }
+
+    // why loading stopped early
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    pub fn point_count(&self) -> usize {
+        self.chunks.iter().map(|chunk| chunk.count).sum()
+    }
+
+    // of the points uploaded so far
+    pub fn bounds(&self) -> Option<(Point3<f32>, Point3<f32>)> {
+        let mut chunks = self.chunks.iter();
+        let first = chunks.next()?;
+        Some(chunks.fold((first.min, first.max), |(min, max), chunk| {
+            (
+                Point3::new(min.x.min(chunk.min.x), min.y.min(chunk.min.y), min.z.min(chunk.min.z)),
+                Point3::new(max.x.max(chunk.max.x), max.y.max(chunk.max.y), max.z.max(chunk.max.z)),
+            )
+        }))
+    }
+}
+
+impl Drop for PointCloud {
+    fn drop(&mut self) {
+        check_render_thread("PointCloud");
+        // the loader stops at its next chunk
+        self.loading = None;
+        if let Some(loader) = self.loader.take() {
+            let _ = loader.join();
+        }
+        for chunk in self.chunks.iter() {
+            unsafe {
+                gl::DeleteVertexArrays(1, &chunk.vao);
+                gl::DeleteBuffers(1, &chunk.vbo);
+            }
+        }
+    }
+}
+
+const POINT_VERTEX_SHADER: &str = r#"
+#version 330 core
+
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec3 aColor;
+
+out vec3 color;
+
+uniform mat4 view;
+uniform mat4 projection;
+// world units across a point
+uniform float pointSize;
+// pixels per world unit at distance 1
+uniform float pixelScale;
+uniform vec2 sizeRange;
+
+void main() {
+    vec4 viewPos = view * vec4(aPos, 1.0);
+    gl_Position = projection * viewPos;
+    gl_PointSize = clamp(pointSize * pixelScale / max(-viewPos.z, 1e-4), sizeRange.x, sizeRange.y);
+    color = aColor;
+}
+"#;
+
+const POINT_FRAGMENT_SHADER: &str = r#"
+#version 330 core
+out vec4 FragColor;
+
+in vec3 color;
+
+uniform bool round;
+
+void main() {
+    vec2 offset = gl_PointCoord * 2.0 - 1.0;
+    if (round && dot(offset, offset) > 1.0) {
+        discard;
+    }
+    FragColor = vec4(color, 1.0);
+}
+"#;
+
+// draws point clouds as GL_POINTS sized by distance, so a scan looks solid up close
+#[derive(Debug)]
+pub struct PointCloudRenderer {
+    shader: Shader,
+    // world units across a point
+    pub point_size: f32,
+    // in pixels
+    pub min_pixels: f32,
+    pub max_pixels: f32,
+    // discs instead of squares
+    pub round: bool,
+}
+
+impl PointCloudRenderer {
+    pub fn new(context: &GlContext) -> Self {
+        Self {
+            shader: Shader::new(context, POINT_VERTEX_SHADER, POINT_FRAGMENT_SHADER),
+            point_size: 0.02,
+            min_pixels: 1.0,
+            max_pixels: 32.0,
+            round: true,
+        }
+    }
+
+    // `viewport_height` in pixels. chunks outside of the camera's frustum are skipped.
+    pub fn draw(&self, _context: &GlContext, cloud: &PointCloud, camera: &dyn Camera, viewport_height: f32) {
+        check_render_thread("PointCloudRenderer");
+        let projection = camera.projection();
+        let frustum = camera.frustum();
+        self.shader.use_program();
+        self.shader.set_matrix4(c_str("view\0"), &camera.view());
+        self.shader.set_matrix4(c_str("projection\0"), &projection);
+        self.shader.set_float(c_str("pointSize\0"), self.point_size);
+        self.shader.set_float(c_str("pixelScale\0"), viewport_height * 0.5 * projection.y.y);
+        self.shader.set_vec2(c_str("sizeRange\0"), self.min_pixels, self.max_pixels);
+        self.shader.set_integer(c_str("round\0"), self.round as i32);
+        unsafe {
+            let enabled = gl::IsEnabled(gl::PROGRAM_POINT_SIZE) == gl::TRUE;
+            gl::Enable(gl::PROGRAM_POINT_SIZE);
+            for chunk in cloud.chunks.iter() {
+                if !frustum.intersects_aabb(chunk.min, chunk.max) {
+                    FrameStats::record_cull(CullResult::FrustumCulled);
+                    continue;
+                }
+                FrameStats::record_cull(CullResult::Visible);
+                gl::BindVertexArray(chunk.vao);
+                gl::DrawArrays(gl::POINTS, 0, conv!(chunk.count));
+                FrameStats::record_draw(0, 1);
+            }
+            gl::BindVertexArray(0);
+            if !enabled {
+                gl::Disable(gl::PROGRAM_POINT_SIZE);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    // a file in the temp directory, the names differ between tests so they can run at once
+    fn write(name: &str, contents: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("game-engine-{}-{}", std::process::id(), name));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn read_all(name: &str, contents: &[u8]) -> Result<Vec<CloudPoint>, PointCloudError> {
+        let path = write(name, contents);
+        let result = PointReader::open(&path).and_then(|mut reader| {
+            let mut points = vec![];
+            while let Some(chunk) = reader.next_chunk(2)? {
+                assert!(chunk.len() <= 2);
+                points.extend(chunk);
+            }
+            Ok(points)
+        });
+        std::fs::remove_file(&path).unwrap();
+        result
+    }
+
+    fn point(x: f32, y: f32, z: f32, color: Vector3<f32>) -> CloudPoint {
+        CloudPoint {
+            position: Point3::new(x, y, z),
+            color,
+        }
+    }
+
+    #[test]
+    fn ascii_ply() {
+        let file = b"ply\nformat ascii 1.0\ncomment made by hand\nelement vertex 3\nproperty float x\nproperty float y\nproperty float z\nproperty uchar red\nproperty uchar green\nproperty uchar blue\nelement face 0\nproperty list uchar int vertex_indices\nend_header\n0 1 2 255 0 0\n-1.5 0 0 0 255 0\n3 3 3 0 0 0\n";
+        let path = write("ascii.ply", file);
+        let mut reader = PointReader::open(&path).unwrap();
+        assert_eq!(reader.remaining(), Some(3));
+        let first = reader.next_chunk(2).unwrap().unwrap();
+        assert_eq!(first, vec![point(0.0, 1.0, 2.0, Vector3::new(1.0, 0.0, 0.0)), point(-1.5, 0.0, 0.0, Vector3::new(0.0, 1.0, 0.0))]);
+        assert_eq!(reader.remaining(), Some(1));
+        assert_eq!(reader.next_chunk(2).unwrap().unwrap(), vec![point(3.0, 3.0, 3.0, Vector3::new(0.0, 0.0, 0.0))]);
+        assert!(reader.next_chunk(2).unwrap().is_none());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn binary_ply() {
+        for &(format, big_endian) in &[("binary_little_endian", false), ("binary_big_endian", true)] {
+            let mut file = format!(
+                "ply\nformat {} 1.0\nelement vertex 2\nproperty double x\nproperty float y\nproperty short z\nproperty ushort intensity\nend_header\n",
+                format
+            )
+            .into_bytes();
+            for &(x, y, z) in &[(0.5f64, -2.0f32, 7i16), (1.0, 0.25, -3)] {
+                if big_endian {
+                    file.extend_from_slice(&x.to_be_bytes());
+                    file.extend_from_slice(&y.to_be_bytes());
+                    file.extend_from_slice(&z.to_be_bytes());
+                    file.extend_from_slice(&9u16.to_be_bytes());
+                } else {
+                    file.extend_from_slice(&x.to_le_bytes());
+                    file.extend_from_slice(&y.to_le_bytes());
+                    file.extend_from_slice(&z.to_le_bytes());
+                    file.extend_from_slice(&9u16.to_le_bytes());
+                }
+            }
+            let white = Vector3::new(1.0, 1.0, 1.0);
+            let points = read_all(&format!("{}.ply", format), &file).unwrap();
+            assert_eq!(points, vec![point(0.5, -2.0, 7.0, white), point(1.0, 0.25, -3.0, white)], "{}", format);
+        }
+    }
+
+    #[test]
+    fn broken_ply() {
+        let error = |name: &str, file: &[u8]| read_all(name, file).unwrap_err().to_string();
+        assert!(error("header.ply", b"ply\nformat ascii 1.0\nelement vertex 1\n").contains("does not end"));
+        assert!(error("xy.ply", b"ply\nformat ascii 1.0\nelement vertex 1\nproperty float x\nproperty float y\nend_header\n1 2\n")
+            .contains("no x, y and z"));
+        let short = b"ply\nformat ascii 1.0\nelement vertex 2\nproperty float x\nproperty float y\nproperty float z\nend_header\n1 2 3\n";
+        assert!(error("short.ply", short).contains("ends before its last point"));
+        assert!(matches!(PointReader::open(std::env::temp_dir().join("game-engine-missing.xyz")), Err(PointCloudError::Io(_))));
+    }
+
+    #[test]
+    fn xyz_with_and_without_colors() {
+        let file = b"# a comment\n1 2 3\n\n4,5,6, 255,128,0\n// another one\n7 8 9 0 0 255\n";
+        let points = read_all("colors.xyz", file).unwrap();
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[0], point(1.0, 2.0, 3.0, Vector3::new(1.0, 1.0, 1.0)));
+        assert_eq!(points[1], point(4.0, 5.0, 6.0, Vector3::new(255.0, 128.0, 0.0) * (1.0 / 255.0)));
+        assert_eq!(points[2], point(7.0, 8.0, 9.0, Vector3::new(0.0, 0.0, 255.0) * (1.0 / 255.0)));
+
+        // the first colors decide between 0 to 1 and 0 to 255
+        let points = read_all("unit.xyz", b"0 0 0 0.5 1 0\n1 1 1 1 1 1\n").unwrap();
+        assert_eq!(points[0].color, Vector3::new(0.5, 1.0, 0.0));
+        assert_eq!(points[1].color, Vector3::new(1.0, 1.0, 1.0));
+
+        assert!(read_all("bad.xyz", b"1 2\n").unwrap_err().to_string().contains("line 1"));
+    }
+
+    #[test]
+    fn xyz_columns_that_are_not_colors() {
+        // PTS files put the intensity before the color
+        let points = read_all("intensity.pts", b"1 2 3 -1204 10 20 30\n").unwrap();
+        assert_eq!(points[0].color, Vector3::new(10.0, 20.0, 30.0) * (1.0 / 255.0));
+
+        // normals are not colors
+        let points = read_all("normals.xyz", b"1 2 3 0 -1 0\n").unwrap();
+        assert_eq!(points[0].color, Vector3::new(1.0, 1.0, 1.0));
+        assert_eq!(points[0].position, Point3::new(1.0, 2.0, 3.0));
+
+        // nor are values over 255 or fractions over 1
+        let points = read_all("other.xyz", b"1 2 3 300 0 0\n1 2 3 1.5 2 3\n").unwrap();
+        assert!(points.iter().all(|point| point.color == Vector3::new(1.0, 1.0, 1.0)));
+    }
+}
@@ -0,0 +1,739 @@
+use std::any::Any;
+use std::error::Error;
+use std::ffi::CStr;
+use std::path::Path;
+
+use cgmath::{vec3, Matrix4, SquareMatrix, Vector3};
+use gl::types::*;
+
+use crate::context::check_render_thread;
+use crate::{c_str, AttachmentFormat, Framebuffer, FrameStats, OutputEncoding, PixelFormat, Shader, Texture3D};
+
+// draws a single triangle covering the screen; TexCoords spans [0, 1] over the viewport
+pub const FULLSCREEN_VERTEX_SHADER: &str = r#"
+#version 330 core
+
+out vec2 TexCoords;
+
+void main() {
+    vec2 position = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2);
+    TexCoords = position;
+    gl_Position = vec4(position * 2.0 - 1.0, 0.0, 1.0);
+}
+"#;
+
+const MOTION_BLUR_FRAGMENT_SHADER: &str = r#"
+#version 330 core
+
+in vec2 TexCoords;
+out vec4 FragColor;
+
+uniform sampler2D screenColor;
+uniform sampler2D screenDepth;
+uniform mat4 inverseViewProjection;
+uniform mat4 previousViewProjection;
+uniform float strength;
+uniform int samples;
+
+void main() {
+    float depth = texture(screenDepth, TexCoords).r;
+    vec4 current = vec4(TexCoords * 2.0 - 1.0, depth * 2.0 - 1.0, 1.0);
+    // kept homogeneous, with an infinite far plane the sky is at w = 0
+    vec4 world = inverseViewProjection * current;
+
+    vec4 previous = previousViewProjection * world;
+    previous /= previous.w;
+    vec2 velocity = (current.xy - previous.xy) * 0.5 * strength;
+
+    vec3 color = texture(screenColor, TexCoords).rgb;
+    for (int i = 1; i < samples; i++) {
+        vec2 offset = velocity * (float(i) / float(samples - 1) - 0.5);
+        color += texture(screenColor, TexCoords + offset).rgb;
+    }
+    FragColor = vec4(color / float(max(samples, 1)), 1.0);
+}
+"#;
+
+#[derive(Debug)]
+pub struct FullscreenQuad {
+    vao: GLuint,
+}
+
+impl FullscreenQuad {
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn new() -> Self {
+        // the vertices are generated from gl_VertexID, but core profile still needs a VAO bound
+        let mut vao = 0;
+        gl::GenVertexArrays(1, &mut vao);
+        Self { vao }
+    }
+
+    /// draws into the bound framebuffer with depth testing turned off
+    ///
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn draw(&self) {
+        check_render_thread("FullscreenQuad");
+        let depth_test = gl::IsEnabled(gl::DEPTH_TEST) == gl::TRUE;
+        gl::Disable(gl::DEPTH_TEST);
+
+        gl::BindVertexArray(self.vao);
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        gl::BindVertexArray(0);
+        FrameStats::record_draw(1, 1);
+
+        if depth_test {
+            gl::Enable(gl::DEPTH_TEST);
+        }
+    }
+}
+
+impl Drop for FullscreenQuad {
+    fn drop(&mut self) {
+        check_render_thread("FullscreenQuad");
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
+
+pub(crate) unsafe fn bind_texture(shader: &Shader, name: &CStr, unit: GLuint, texture: GLuint) {
+    gl::ActiveTexture(gl::TEXTURE0 + unit);
+    gl::BindTexture(gl::TEXTURE_2D, texture);
+    FrameStats::record_texture_bind();
+    shader.set_integer(name, conv!(unit));
+}
+
+#[derive(Debug)]
+pub struct MotionBlur {
+    shader: Shader,
+    quad: FullscreenQuad,
+    previous_view_projection: Option<Matrix4<f32>>,
+    // 1.0 blurs over the camera movement of one full frame
+    pub strength: f32,
+    pub samples: u32,
+}
+
+impl MotionBlur {
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn new() -> Self {
+        Self {
+            shader: Shader::from_str(FULLSCREEN_VERTEX_SHADER, MOTION_BLUR_FRAGMENT_SHADER),
+            quad: FullscreenQuad::new(),
+            previous_view_projection: None,
+            strength: 1.0,
+            samples: 8,
+        }
+    }
+
+    /// blurs `input` into the bound framebuffer. call once per frame with the matrix the scene was drawn with.
+    ///
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn apply(&mut self, input: &Framebuffer, view_projection: &Matrix4<f32>) {
+        self.draw(input.color_texture(), input.depth_texture(), view_projection);
+    }
+
+    unsafe fn draw(&mut self, color: GLuint, depth: GLuint, view_projection: &Matrix4<f32>) {
+        let previous = self.previous_view_projection.unwrap_or(*view_projection);
+        let inverse = view_projection.invert().unwrap_or_else(Matrix4::identity);
+
+        self.shader.use_program();
+        bind_texture(&self.shader, c_str("screenColor\0"), 0, color);
+        bind_texture(&self.shader, c_str("screenDepth\0"), 1, depth);
+        gl::ActiveTexture(gl::TEXTURE0);
+        self.shader.set_matrix4(c_str("inverseViewProjection\0"), &inverse);
+        self.shader.set_matrix4(c_str("previousViewProjection\0"), &previous);
+        self.shader.set_float(c_str("strength\0"), self.strength);
+        self.shader.set_integer(c_str("samples\0"), conv!(self.samples.max(2)));
+        self.quad.draw();
+
+        self.previous_view_projection = Some(*view_projection);
+    }
+
+    // forget the previous frame, e.g. after a camera cut
+    pub fn reset(&mut self) {
+        self.previous_view_projection = None;
+    }
+}
+
+const DEPTH_OF_FIELD_FRAGMENT_SHADER: &str = r#"
+#version 330 core
+
+in vec2 TexCoords;
+out vec4 FragColor;
+
+uniform sampler2D screenColor;
+uniform sampler2D screenDepth;
+uniform float near;
+// 0 for an infinite far plane
+uniform float inverseFar;
+uniform float focusDistance;
+uniform bool autofocus;
+uniform float aperture;
+uniform float maxBlur;
+uniform int samples;
+
+const float GOLDEN_ANGLE = 2.39996323;
+
+float LinearDepth(vec2 uv) {
+    float ndc = texture(screenDepth, uv).r * 2.0 - 1.0;
+    float n = near * inverseFar;
+    return 2.0 * near / max(1.0 + n - ndc * (1.0 - n), 1e-6);
+}
+
+// circle of confusion radius in pixels
+float CircleOfConfusion(float depth, float focus) {
+    return clamp(aperture * abs(1.0 - focus / depth), 0.0, 1.0) * maxBlur;
+}
+
+void main() {
+    float focus = autofocus ? LinearDepth(vec2(0.5)) : focusDistance;
+    vec2 texelSize = 1.0 / vec2(textureSize(screenColor, 0));
+    float centerDepth = LinearDepth(TexCoords);
+    float centerCoc = CircleOfConfusion(centerDepth, focus);
+
+    vec3 color = texture(screenColor, TexCoords).rgb;
+    float total = 1.0;
+    for (int i = 1; i < samples; i++) {
+        // spiral over the disk of the largest blur
+        float radius = maxBlur * sqrt(float(i) / float(samples));
+        float angle = float(i) * GOLDEN_ANGLE;
+        vec2 uv = TexCoords + vec2(cos(angle), sin(angle)) * radius * texelSize;
+
+        // a sample contributes when its own blur reaches this pixel (scatter as gather).
+        // samples behind this pixel may not blur over it more than it is blurred itself.
+        float sampleDepth = LinearDepth(uv);
+        float sampleCoc = CircleOfConfusion(sampleDepth, focus);
+        if (sampleDepth > centerDepth) {
+            sampleCoc = min(sampleCoc, centerCoc);
+        }
+        float weight = smoothstep(radius - 1.0, radius + 1.0, sampleCoc);
+        color += texture(screenColor, uv).rgb * weight;
+        total += weight;
+    }
+    FragColor = vec4(color / total, 1.0);
+}
+"#;
+
+#[derive(Debug)]
+pub struct DepthOfField {
+    shader: Shader,
+    quad: FullscreenQuad,
+    // distance to the sharp plane in world units, ignored with autofocus
+    pub focus_distance: f32,
+    // focus on whatever is under the screen center
+    pub autofocus: bool,
+    // how quickly objects blur away from the focus plane
+    pub aperture: f32,
+    // largest blur radius in pixels
+    pub max_blur: f32,
+    pub samples: u32,
+}
+
+impl DepthOfField {
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn new() -> Self {
+        Self {
+            shader: Shader::from_str(FULLSCREEN_VERTEX_SHADER, DEPTH_OF_FIELD_FRAGMENT_SHADER),
+            quad: FullscreenQuad::new(),
+            focus_distance: 10.0,
+            autofocus: false,
+            aperture: 1.0,
+            max_blur: 8.0,
+            samples: 32,
+        }
+    }
+
+    /// `near` and `far` must match the projection the scene was drawn with, `far` may be infinite
+    ///
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn apply(&self, input: &Framebuffer, near: f32, far: f32) {
+        self.draw(input.color_texture(), input.depth_texture(), near, far);
+    }
+
+    unsafe fn draw(&self, color: GLuint, depth: GLuint, near: f32, far: f32) {
+        self.shader.use_program();
+        bind_texture(&self.shader, c_str("screenColor\0"), 0, color);
+        bind_texture(&self.shader, c_str("screenDepth\0"), 1, depth);
+        gl::ActiveTexture(gl::TEXTURE0);
+        self.shader.set_float(c_str("near\0"), near);
+        self.shader.set_float(c_str("inverseFar\0"), 1.0 / far);
+        self.shader.set_float(c_str("focusDistance\0"), self.focus_distance);
+        self.shader.set_integer(c_str("autofocus\0"), self.autofocus as i32);
+        self.shader.set_float(c_str("aperture\0"), self.aperture);
+        self.shader.set_float(c_str("maxBlur\0"), self.max_blur);
+        self.shader.set_integer(c_str("samples\0"), conv!(self.samples.max(1)));
+        self.quad.draw();
+    }
+}
+
+const COLOR_GRADING_FRAGMENT_SHADER: &str = r#"
+#version 330 core
+
+in vec2 TexCoords;
+out vec4 FragColor;
+
+uniform sampler2D screenColor;
+uniform sampler3D lut;
+uniform float lutSize;
+uniform float intensity;
+
+void main() {
+    vec3 color = clamp(texture(screenColor, TexCoords).rgb, 0.0, 1.0);
+    // sample at texel centers so 0 and 1 map to the first and last entries
+    vec3 uvw = color * ((lutSize - 1.0) / lutSize) + 0.5 / lutSize;
+    vec3 graded = texture(lut, uvw).rgb;
+    FragColor = vec4(mix(color, graded, intensity), 1.0);
+}
+"#;
+
+// apply after tone mapping: the LUT maps display referred [0, 1] colors
+#[derive(Debug)]
+pub struct ColorGrading {
+    shader: Shader,
+    quad: FullscreenQuad,
+    lut: Texture3D,
+    size: u32,
+    // 0.0 leaves the image untouched, 1.0 applies the LUT fully
+    pub intensity: f32,
+}
+
+impl ColorGrading {
+    /// loads the usual strip layout: `size` slices of `size`x`size` laid out horizontally, blue increasing per slice
+    ///
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn from_strip<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error + 'static>> {
+        let img = image::open(path)?.to_rgb();
+        let size = img.height();
+        if img.width() != size * size {
+            return Err(format!("LUT strip must be {}x{}, found {}x{}", size * size, size, img.width(), size).into());
+        }
+
+        let mut data = Vec::with_capacity((size * size * size * 3) as usize);
+        for b in 0..size {
+            for g in 0..size {
+                for r in 0..size {
+                    data.extend_from_slice(&img.get_pixel(b * size + r, g).0);
+                }
+            }
+        }
+
+        let lut = Texture3D::allocate(size, size, size, AttachmentFormat::new(gl::RGB8, gl::RGB, gl::UNSIGNED_BYTE));
+        lut.upload_raw(PixelFormat::RGB8, &data);
+        lut.set_wrap_raw([gl::CLAMP_TO_EDGE; 3]);
+
+        Ok(Self {
+            shader: Shader::from_str(FULLSCREEN_VERTEX_SHADER, COLOR_GRADING_FRAGMENT_SHADER),
+            quad: FullscreenQuad::new(),
+            lut,
+            size,
+            intensity: 1.0,
+        })
+    }
+
+    pub fn lut_size(&self) -> u32 {
+        self.size
+    }
+
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn apply(&self, input: &Framebuffer) {
+        self.draw(input.color_texture());
+    }
+
+    unsafe fn draw(&self, color: GLuint) {
+        self.shader.use_program();
+        bind_texture(&self.shader, c_str("screenColor\0"), 0, color);
+        self.lut.bind_raw(1);
+        self.shader.set_integer(c_str("lut\0"), 1);
+        gl::ActiveTexture(gl::TEXTURE0);
+        self.shader.set_float(c_str("lutSize\0"), self.size as f32);
+        self.shader.set_float(c_str("intensity\0"), self.intensity);
+        self.quad.draw();
+    }
+}
+
+pub(crate) const COPY_FRAGMENT_SHADER: &str = r#"
+#version 330 core
+
+in vec2 TexCoords;
+out vec4 FragColor;
+
+uniform sampler2D screenColor;
+
+void main() {
+    FragColor = texture(screenColor, TexCoords);
+}
+"#;
+
+const GAMMA_FRAGMENT_SHADER: &str = r#"
+#version 330 core
+
+in vec2 TexCoords;
+out vec4 FragColor;
+
+uniform sampler2D screenColor;
+uniform float gamma;
+
+void main() {
+    vec4 color = texture(screenColor, TexCoords);
+    FragColor = vec4(pow(max(color.rgb, vec3(0.0)), vec3(1.0 / gamma)), color.a);
+}
+"#;
+
+const VIGNETTE_FRAGMENT_SHADER: &str = r#"
+#version 330 core
+
+in vec2 TexCoords;
+out vec4 FragColor;
+
+uniform sampler2D screenColor;
+uniform float intensity;
+uniform float radius;
+uniform float softness;
+
+void main() {
+    vec3 color = texture(screenColor, TexCoords).rgb;
+    float distance = length(TexCoords - 0.5) * 1.41421356;
+    float vignette = 1.0 - smoothstep(radius - softness, radius, distance);
+    FragColor = vec4(color * mix(1.0, vignette, intensity), 1.0);
+}
+"#;
+
+const FADE_FRAGMENT_SHADER: &str = r#"
+#version 330 core
+
+in vec2 TexCoords;
+out vec4 FragColor;
+
+uniform sampler2D screenColor;
+uniform vec3 fadeColor;
+uniform float amount;
+
+void main() {
+    vec3 color = texture(screenColor, TexCoords).rgb;
+    FragColor = vec4(mix(color, fadeColor, amount), 1.0);
+}
+"#;
+
+const FILM_GRAIN_FRAGMENT_SHADER: &str = r#"
+#version 330 core
+
+in vec2 TexCoords;
+out vec4 FragColor;
+
+uniform sampler2D screenColor;
+uniform float intensity;
+uniform float time;
+
+float Hash(vec2 p) {
+    vec3 p3 = fract(vec3(p.xyx) * 0.1031);
+    p3 += dot(p3, p3.yzx + 33.33);
+    return fract((p3.x + p3.y) * p3.z);
+}
+
+void main() {
+    vec3 color = texture(screenColor, TexCoords).rgb;
+    float noise = Hash(gl_FragCoord.xy + fract(time) * 1000.0) - 0.5;
+    // grain is most visible in the midtones
+    float luminance = dot(color, vec3(0.2126, 0.7152, 0.0722));
+    float response = 1.0 - abs(luminance * 2.0 - 1.0);
+    FragColor = vec4(color + noise * intensity * response, 1.0);
+}
+"#;
+
+// per frame information passed to every effect in a PostStack
+#[derive(Debug, Clone, Copy)]
+pub struct PostContext {
+    pub view_projection: Matrix4<f32>,
+    pub near: f32,
+    // may be infinite
+    pub far: f32,
+    // seconds, drives animated effects
+    pub time: f32,
+}
+
+pub trait AsAny {
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Any> AsAny for T {
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+// an effect reads the scene color of the previous stage plus the scene depth and draws into the bound framebuffer
+pub trait PostEffect: AsAny {
+    /// # Safety
+    ///
+    /// called by `PostStack::apply` with the context current. `color` and `depth` are textures of that context.
+    unsafe fn render(&mut self, color: GLuint, depth: GLuint, context: &PostContext);
+}
+
+impl PostEffect for MotionBlur {
+    unsafe fn render(&mut self, color: GLuint, depth: GLuint, context: &PostContext) {
+        self.draw(color, depth, &context.view_projection);
+    }
+}
+
+impl PostEffect for DepthOfField {
+    unsafe fn render(&mut self, color: GLuint, depth: GLuint, context: &PostContext) {
+        self.draw(color, depth, context.near, context.far);
+    }
+}
+
+impl PostEffect for ColorGrading {
+    unsafe fn render(&mut self, color: GLuint, _depth: GLuint, _context: &PostContext) {
+        self.draw(color);
+    }
+}
+
+#[derive(Debug)]
+pub struct Vignette {
+    shader: Shader,
+    quad: FullscreenQuad,
+    pub intensity: f32,
+    // distance from the center where darkening is complete, 1.0 at the corners
+    pub radius: f32,
+    pub softness: f32,
+}
+
+impl Vignette {
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn new() -> Self {
+        Self {
+            shader: Shader::from_str(FULLSCREEN_VERTEX_SHADER, VIGNETTE_FRAGMENT_SHADER),
+            quad: FullscreenQuad::new(),
+            intensity: 0.5,
+            radius: 1.0,
+            softness: 0.6,
+        }
+    }
+}
+
+impl PostEffect for Vignette {
+    unsafe fn render(&mut self, color: GLuint, _depth: GLuint, _context: &PostContext) {
+        self.shader.use_program();
+        bind_texture(&self.shader, c_str("screenColor\0"), 0, color);
+        self.shader.set_float(c_str("intensity\0"), self.intensity);
+        self.shader.set_float(c_str("radius\0"), self.radius);
+        self.shader.set_float(c_str("softness\0"), self.softness);
+        self.quad.draw();
+    }
+}
+
+// blends the screen towards a color, e.g. black while `SceneManager` switches scenes
+#[derive(Debug)]
+pub struct Fade {
+    shader: Shader,
+    quad: FullscreenQuad,
+    pub color: Vector3<f32>,
+    // 0 shows the scene, 1 only the color
+    pub amount: f32,
+}
+
+impl Fade {
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn new() -> Self {
+        Self {
+            shader: Shader::from_str(FULLSCREEN_VERTEX_SHADER, FADE_FRAGMENT_SHADER),
+            quad: FullscreenQuad::new(),
+            color: vec3(0.0, 0.0, 0.0),
+            amount: 0.0,
+        }
+    }
+}
+
+impl PostEffect for Fade {
+    unsafe fn render(&mut self, color: GLuint, _depth: GLuint, _context: &PostContext) {
+        self.shader.use_program();
+        bind_texture(&self.shader, c_str("screenColor\0"), 0, color);
+        self.shader.set_vec3(c_str("fadeColor\0"), self.color.x, self.color.y, self.color.z);
+        self.shader.set_float(c_str("amount\0"), self.amount);
+        self.quad.draw();
+    }
+}
+
+#[derive(Debug)]
+pub struct FilmGrain {
+    shader: Shader,
+    quad: FullscreenQuad,
+    pub intensity: f32,
+}
+
+impl FilmGrain {
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn new() -> Self {
+        Self {
+            shader: Shader::from_str(FULLSCREEN_VERTEX_SHADER, FILM_GRAIN_FRAGMENT_SHADER),
+            quad: FullscreenQuad::new(),
+            intensity: 0.05,
+        }
+    }
+}
+
+impl PostEffect for FilmGrain {
+    unsafe fn render(&mut self, color: GLuint, _depth: GLuint, context: &PostContext) {
+        self.shader.use_program();
+        bind_texture(&self.shader, c_str("screenColor\0"), 0, color);
+        self.shader.set_float(c_str("intensity\0"), self.intensity);
+        self.shader.set_float(c_str("time\0"), context.time);
+        self.quad.draw();
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PostEffectId(usize);
+
+struct Stage {
+    effect: Box<dyn PostEffect>,
+    enabled: bool,
+}
+
+impl std::fmt::Debug for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Stage").field("enabled", &self.enabled).finish()
+    }
+}
+
+// runs effects in insertion order, ping-ponging between two intermediate targets.
+// the targets are floating point and linear, only the write to the output is encoded.
+#[derive(Debug)]
+pub struct PostStack {
+    stages: Vec<Stage>,
+    targets: Vec<Framebuffer>,
+    copy: Shader,
+    gamma: Shader,
+    quad: FullscreenQuad,
+    output_encoding: OutputEncoding,
+}
+
+impl PostStack {
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn new() -> Self {
+        Self {
+            stages: vec![],
+            targets: vec![],
+            copy: Shader::from_str(FULLSCREEN_VERTEX_SHADER, COPY_FRAGMENT_SHADER),
+            gamma: Shader::from_str(FULLSCREEN_VERTEX_SHADER, GAMMA_FRAGMENT_SHADER),
+            quad: FullscreenQuad::new(),
+            output_encoding: OutputEncoding::Linear,
+        }
+    }
+
+    // `OutputEncoding::detect()` when presenting to the window
+    pub fn set_output_encoding(&mut self, encoding: OutputEncoding) {
+        self.output_encoding = encoding;
+    }
+
+    pub fn output_encoding(&self) -> OutputEncoding {
+        self.output_encoding
+    }
+
+    pub fn push<E: PostEffect + 'static>(&mut self, effect: E) -> PostEffectId {
+        self.stages.push(Stage {
+            effect: Box::new(effect),
+            enabled: true,
+        });
+        PostEffectId(self.stages.len() - 1)
+    }
+
+    pub fn set_enabled(&mut self, id: PostEffectId, enabled: bool) {
+        self.stages[id.0].enabled = enabled;
+    }
+
+    pub fn is_enabled(&self, id: PostEffectId) -> bool {
+        self.stages[id.0].enabled
+    }
+
+    pub fn get_mut<E: PostEffect + 'static>(&mut self, id: PostEffectId) -> Option<&mut E> {
+        self.stages[id.0].effect.as_any_mut().downcast_mut()
+    }
+
+    unsafe fn ensure_targets(&mut self, width: i32, height: i32) {
+        if self.targets.first().map(|t| (t.width(), t.height())) != Some((width, height)) {
+            self.targets = vec![Framebuffer::allocate(width, height), Framebuffer::allocate(width, height)];
+        }
+    }
+
+    /// processes `scene` and writes the result into the framebuffer and viewport bound at the time of the call
+    ///
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn apply(&mut self, scene: &Framebuffer, context: &PostContext) {
+        let mut output = 0;
+        gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut output);
+        let mut viewport = [0; 4];
+        gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+        let bind_output = || {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, conv!(output));
+            gl::Viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+        };
+
+        let enabled: Vec<usize> = (0..self.stages.len()).filter(|&i| self.stages[i].enabled).collect();
+        // the number of effects up to the one writing straight to the output, 0 when a final pass does.
+        // a gamma pass needs the last effect to write into a target as well.
+        let gamma_pass = matches!(self.output_encoding, OutputEncoding::Gamma(_));
+        let direct = if gamma_pass { 0 } else { enabled.len() };
+        if !enabled.is_empty() {
+            self.ensure_targets(scene.width(), scene.height());
+        }
+
+        let mut color = scene.color_texture();
+        for (n, &i) in enabled.iter().enumerate() {
+            if n + 1 == direct {
+                bind_output();
+                let effect = &mut self.stages[i].effect;
+                self.output_encoding.write(|| effect.render(color, scene.depth_texture(), context));
+            } else {
+                self.targets[n % 2].bind_raw();
+                self.stages[i].effect.render(color, scene.depth_texture(), context);
+            }
+            gl::ActiveTexture(gl::TEXTURE0);
+            color = self.targets[n % 2].color_texture();
+        }
+        if direct > 0 {
+            return;
+        }
+
+        bind_output();
+        let shader = match self.output_encoding {
+            OutputEncoding::Gamma(gamma) => {
+                self.gamma.use_program();
+                self.gamma.set_float(c_str("gamma\0"), gamma);
+                &self.gamma
+            }
+            _ => {
+                self.copy.use_program();
+                &self.copy
+            }
+        };
+        bind_texture(shader, c_str("screenColor\0"), 0, color);
+        let quad = &self.quad;
+        self.output_encoding.write(|| quad.draw());
+    }
+}
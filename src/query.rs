@@ -0,0 +1,151 @@
+use gl::types::*;
+
+use crate::context::check_render_thread;
+use crate::GlContext;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QueryKind {
+    // number of samples that passed the depth and stencil tests
+    SamplesPassed,
+    // 1 if any sample passed, may be cheaper than counting
+    AnySamplesPassed,
+    // primitives emitted by the vertex or geometry stage, before clipping
+    PrimitivesGenerated,
+    // nanoseconds the GPU spent on the commands in between
+    TimeElapsed,
+}
+
+impl QueryKind {
+    fn target(self) -> GLenum {
+        match self {
+            QueryKind::SamplesPassed => gl::SAMPLES_PASSED,
+            QueryKind::AnySamplesPassed => gl::ANY_SAMPLES_PASSED,
+            QueryKind::PrimitivesGenerated => gl::PRIMITIVES_GENERATED,
+            QueryKind::TimeElapsed => gl::TIME_ELAPSED,
+        }
+    }
+}
+
+// the result of the last `end` becomes available a few frames later, `poll` never stalls
+#[derive(Debug)]
+pub struct Query {
+    id: GLuint,
+    kind: QueryKind,
+    active: bool,
+    // ended but not read back yet
+    pending: bool,
+    result: Option<u64>,
+}
+
+impl Query {
+    pub fn new(_context: &GlContext, kind: QueryKind) -> Self {
+        let mut id = 0;
+        unsafe {
+            gl::GenQueries(1, &mut id);
+        }
+        Self {
+            id,
+            kind,
+            active: false,
+            pending: false,
+            result: None,
+        }
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+
+    pub fn kind(&self) -> QueryKind {
+        self.kind
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.pending
+    }
+
+    // only one query per kind can be active at a time.
+    // a result still in flight is dropped, so call `poll` first to keep it.
+    pub fn begin(&mut self, _context: &GlContext) {
+        check_render_thread("Query");
+        assert!(!self.active, "query is already active");
+        unsafe {
+            gl::BeginQuery(self.kind.target(), self.id);
+        }
+        self.active = true;
+        self.pending = false;
+    }
+
+    pub fn end(&mut self, _context: &GlContext) {
+        check_render_thread("Query");
+        assert!(self.active, "query is not active");
+        unsafe {
+            gl::EndQuery(self.kind.target());
+        }
+        self.active = false;
+        self.pending = true;
+    }
+
+    // begin, run `f`, end
+    pub fn measure<F: FnOnce()>(&mut self, context: &GlContext, f: F) {
+        self.begin(context);
+        f();
+        self.end(context);
+    }
+
+    // the latest available result without waiting for the GPU
+    pub fn poll(&mut self, _context: &GlContext) -> Option<u64> {
+        check_render_thread("Query");
+        if self.pending {
+            let mut available = 0;
+            unsafe {
+                gl::GetQueryObjectuiv(self.id, gl::QUERY_RESULT_AVAILABLE, &mut available);
+            }
+            if available == gl::TRUE.into() {
+                self.read_result();
+            }
+        }
+        self.result
+    }
+
+    // blocks until the pending query finishes
+    pub fn wait(&mut self, _context: &GlContext) -> Option<u64> {
+        check_render_thread("Query");
+        if self.pending {
+            self.read_result();
+        }
+        self.result
+    }
+
+    // the last result read back by `poll` or `wait`
+    pub fn result(&self) -> Option<u64> {
+        self.result
+    }
+
+    // for `AnySamplesPassed`, but works with `SamplesPassed` as well
+    pub fn is_visible(&self) -> Option<bool> {
+        self.result.map(|result| result > 0)
+    }
+
+    fn read_result(&mut self) {
+        let mut result = 0;
+        unsafe {
+            gl::GetQueryObjectui64v(self.id, gl::QUERY_RESULT, &mut result);
+        }
+        self.result = Some(result);
+        self.pending = false;
+    }
+}
+
+impl Drop for Query {
+    fn drop(&mut self) {
+        check_render_thread("Query");
+        unsafe {
+            gl::DeleteQueries(1, &self.id);
+        }
+    }
+}
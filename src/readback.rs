@@ -0,0 +1,128 @@
+use std::ptr;
+use std::slice;
+
+use gl::types::*;
+
+use crate::context::check_render_thread;
+use crate::GlContext;
+
+// RGBA8 pixels copied into a pixel buffer without stalling. poll it once per frame,
+// the data usually arrives one or two frames after the read was issued.
+#[derive(Debug)]
+pub struct Readback {
+    pbo: GLuint,
+    fence: GLsync,
+    width: i32,
+    height: i32,
+    pixels: Option<Vec<u8>>,
+}
+
+impl Readback {
+    // reads a region of color attachment 0 of `framebuffer`, 0 for the default framebuffer
+    pub fn start(_context: &GlContext, framebuffer: GLuint, x: i32, y: i32, width: i32, height: i32) -> Self {
+        unsafe { Self::start_raw(framebuffer, x, y, width, height) }
+    }
+
+    pub(crate) unsafe fn start_raw(framebuffer: GLuint, x: i32, y: i32, width: i32, height: i32) -> Self {
+        check_render_thread("Readback");
+        let size = width.max(0) as usize * height.max(0) as usize * 4;
+
+        let mut pbo = 0;
+        gl::GenBuffers(1, &mut pbo);
+        gl::BindBuffer(gl::PIXEL_PACK_BUFFER, pbo);
+        gl::BufferData(gl::PIXEL_PACK_BUFFER, conv!(size), ptr::null(), gl::STREAM_READ);
+
+        let mut previous = 0;
+        gl::GetIntegerv(gl::READ_FRAMEBUFFER_BINDING, &mut previous);
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, framebuffer);
+        gl::ReadBuffer(if framebuffer == 0 { gl::BACK } else { gl::COLOR_ATTACHMENT0 });
+        gl::PixelStorei(gl::PACK_ALIGNMENT, 4);
+        // returns immediately, the copy goes into the bound pack buffer
+        gl::ReadPixels(x, y, width, height, gl::RGBA, gl::UNSIGNED_BYTE, ptr::null_mut());
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, conv!(previous));
+        gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+
+        let fence = gl::FenceSync(gl::SYNC_GPU_COMMANDS_COMPLETE, 0);
+        // make sure the fence is submitted, otherwise polling without a flush may never see it
+        gl::Flush();
+
+        Self {
+            pbo,
+            fence,
+            width,
+            height,
+            pixels: None,
+        }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.pixels.is_some()
+    }
+
+    // the pixels, bottom row first, once the GPU has finished the copy
+    pub fn poll(&mut self, _context: &GlContext) -> Option<&[u8]> {
+        if self.pixels.is_none() {
+            check_render_thread("Readback");
+            unsafe {
+                match gl::ClientWaitSync(self.fence, 0, 0) {
+                    gl::ALREADY_SIGNALED | gl::CONDITION_SATISFIED => self.fetch(),
+                    _ => return None,
+                }
+            }
+        }
+        self.pixels.as_deref()
+    }
+
+    // blocks until the copy is done
+    pub fn wait(&mut self, _context: &GlContext) -> &[u8] {
+        if self.pixels.is_none() {
+            check_render_thread("Readback");
+            unsafe {
+                while gl::ClientWaitSync(self.fence, gl::SYNC_FLUSH_COMMANDS_BIT, 1_000_000_000) == gl::TIMEOUT_EXPIRED {}
+                self.fetch();
+            }
+        }
+        self.pixels.as_ref().unwrap()
+    }
+
+    // takes the pixels out, None until ready
+    pub fn into_pixels(mut self, context: &GlContext) -> Option<Vec<u8>> {
+        self.poll(context);
+        self.pixels.take()
+    }
+
+    unsafe fn fetch(&mut self) {
+        let size = self.width.max(0) as usize * self.height.max(0) as usize * 4;
+        let mut pixels = vec![0; size];
+        if size > 0 {
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, self.pbo);
+            let mapped = gl::MapBufferRange(gl::PIXEL_PACK_BUFFER, 0, conv!(size), gl::MAP_READ_BIT) as *const u8;
+            if mapped.is_null() {
+                log::error!("failed to map a readback buffer");
+            } else {
+                pixels.copy_from_slice(slice::from_raw_parts(mapped, size));
+                gl::UnmapBuffer(gl::PIXEL_PACK_BUFFER);
+            }
+            gl::BindBuffer(gl::PIXEL_PACK_BUFFER, 0);
+        }
+        self.pixels = Some(pixels);
+    }
+}
+
+impl Drop for Readback {
+    fn drop(&mut self) {
+        check_render_thread("Readback");
+        unsafe {
+            gl::DeleteSync(self.fence);
+            gl::DeleteBuffers(1, &self.pbo);
+        }
+    }
+}
@@ -0,0 +1,316 @@
+use std::ffi::CString;
+
+use gl::types::*;
+
+use crate::context::{check_render_thread, GlContext};
+use crate::{Camera, Framebuffer, GpuInfo, GraphicsSettings, Viewport, ViewUniforms};
+
+// pair with the regular vertex shader for the depth-only pass
+pub const DEPTH_ONLY_FRAGMENT_SHADER: &str = r#"
+#version 330 core
+
+void main() {
+}
+"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PassKind {
+    // color writes are off, only depth is written. bind a cheap shader here.
+    DepthOnly,
+    Shaded,
+}
+
+// one camera of a split-screen frame. the camera's ratio should match `viewport.aspect()`.
+#[derive(Debug, Clone, Copy)]
+pub struct View<'a> {
+    pub viewport: Viewport,
+    pub camera: &'a dyn Camera,
+}
+
+// what `Renderer::pass` sets up before running the pass
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PassDesc<'a> {
+    // None draws into the default framebuffer
+    pub target: Option<&'a Framebuffer>,
+    // defaults to the whole target, or the current viewport for the default framebuffer
+    pub viewport: Option<Viewport>,
+    pub clear_color: Option<[f32; 4]>,
+    pub clear_depth: Option<f32>,
+    pub clear_stencil: Option<i32>,
+}
+
+impl<'a> PassDesc<'a> {
+    pub fn target(target: &'a Framebuffer) -> Self {
+        Self {
+            target: Some(target),
+            ..Self::default()
+        }
+    }
+
+    pub fn default_framebuffer() -> Self {
+        Self::default()
+    }
+
+    pub fn viewport(mut self, viewport: Viewport) -> Self {
+        self.viewport = Some(viewport);
+        self
+    }
+
+    // takes a `Color` or linear [r, g, b, a]
+    pub fn clear_color<C: Into<[f32; 4]>>(mut self, color: C) -> Self {
+        self.clear_color = Some(color.into());
+        self
+    }
+
+    pub fn clear_depth(mut self, depth: f32) -> Self {
+        self.clear_depth = Some(depth);
+        self
+    }
+
+    pub fn clear_stencil(mut self, stencil: i32) -> Self {
+        self.clear_stencil = Some(stencil);
+        self
+    }
+}
+
+// handed to the body of a pass
+#[derive(Debug, Clone, Copy)]
+pub struct PassContext<'a> {
+    pub name: &'a str,
+    pub target: Option<&'a Framebuffer>,
+    pub viewport: Viewport,
+}
+
+// the state a pass changes and puts back afterwards
+struct SavedPassState {
+    framebuffer: GLint,
+    viewport: Viewport,
+    scissor: bool,
+    scissor_box: [GLint; 4],
+    clear_color: [GLfloat; 4],
+    clear_depth: GLfloat,
+    clear_stencil: GLint,
+    color_mask: [GLboolean; 4],
+    depth_mask: GLboolean,
+    stencil_mask: GLint,
+}
+
+impl SavedPassState {
+    unsafe fn save() -> Self {
+        let mut state = Self {
+            framebuffer: 0,
+            viewport: Viewport::current(),
+            scissor: gl::IsEnabled(gl::SCISSOR_TEST) == gl::TRUE,
+            scissor_box: [0; 4],
+            clear_color: [0.0; 4],
+            clear_depth: 1.0,
+            clear_stencil: 0,
+            color_mask: [gl::TRUE; 4],
+            depth_mask: gl::TRUE,
+            stencil_mask: 0,
+        };
+        gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut state.framebuffer);
+        gl::GetIntegerv(gl::SCISSOR_BOX, state.scissor_box.as_mut_ptr());
+        gl::GetFloatv(gl::COLOR_CLEAR_VALUE, state.clear_color.as_mut_ptr());
+        gl::GetFloatv(gl::DEPTH_CLEAR_VALUE, &mut state.clear_depth);
+        gl::GetIntegerv(gl::STENCIL_CLEAR_VALUE, &mut state.clear_stencil);
+        gl::GetBooleanv(gl::COLOR_WRITEMASK, state.color_mask.as_mut_ptr());
+        gl::GetBooleanv(gl::DEPTH_WRITEMASK, &mut state.depth_mask);
+        gl::GetIntegerv(gl::STENCIL_WRITEMASK, &mut state.stencil_mask);
+        state
+    }
+
+    unsafe fn restore(&self) {
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, conv!(self.framebuffer));
+        gl::Viewport(self.viewport.x, self.viewport.y, self.viewport.width, self.viewport.height);
+        if self.scissor {
+            gl::Enable(gl::SCISSOR_TEST);
+        } else {
+            gl::Disable(gl::SCISSOR_TEST);
+        }
+        let [x, y, width, height] = self.scissor_box;
+        gl::Scissor(x, y, width, height);
+        let [r, g, b, a] = self.clear_color;
+        gl::ClearColor(r, g, b, a);
+        gl::ClearDepth(self.clear_depth.into());
+        gl::ClearStencil(self.clear_stencil);
+        let [r, g, b, a] = self.color_mask;
+        gl::ColorMask(r, g, b, a);
+        gl::DepthMask(self.depth_mask);
+        gl::StencilMask(self.stencil_mask as GLuint);
+    }
+}
+
+// closes a pass even when its closure panics, so the debug group stack stays balanced
+struct PassGuard {
+    saved: SavedPassState,
+    debug_group: bool,
+}
+
+impl Drop for PassGuard {
+    fn drop(&mut self) {
+        unsafe {
+            if self.debug_group {
+                gl::PopDebugGroup();
+            }
+            self.saved.restore();
+        }
+    }
+}
+
+unsafe fn debug_groups_supported() -> bool {
+    GpuInfo::current().features.debug_output && gl::PushDebugGroup::is_loaded()
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Renderer {
+    depth_prepass: bool,
+    pub(crate) settings: GraphicsSettings,
+}
+
+impl Renderer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn depth_prepass(&self) -> bool {
+        self.depth_prepass
+    }
+
+    pub fn set_depth_prepass(&mut self, enabled: bool) {
+        self.depth_prepass = enabled;
+    }
+
+    // calls `draw` once per pass. with the prepass enabled the shaded pass only touches visible fragments,
+    // which requires both passes to compute gl_Position identically (declare it `invariant` if in doubt).
+    pub fn render<F: FnMut(PassKind)>(&self, _context: &GlContext, mut draw: F) {
+        check_render_thread("Renderer");
+        if !self.depth_prepass {
+            draw(PassKind::Shaded);
+            return;
+        }
+
+        // the shaded pass draws with the caller's masks and depth function restored afterwards
+        let mut color_mask = [gl::TRUE; 4];
+        let mut depth_mask = gl::TRUE;
+        let mut depth_func = 0;
+        unsafe {
+            gl::GetBooleanv(gl::COLOR_WRITEMASK, color_mask.as_mut_ptr());
+            gl::GetBooleanv(gl::DEPTH_WRITEMASK, &mut depth_mask);
+            gl::GetIntegerv(gl::DEPTH_FUNC, &mut depth_func);
+
+            gl::ColorMask(gl::FALSE, gl::FALSE, gl::FALSE, gl::FALSE);
+            gl::DepthMask(gl::TRUE);
+            gl::DepthFunc(gl::LESS);
+        }
+        draw(PassKind::DepthOnly);
+
+        unsafe {
+            gl::ColorMask(color_mask[0], color_mask[1], color_mask[2], color_mask[3]);
+            gl::DepthMask(gl::FALSE);
+            gl::DepthFunc(gl::EQUAL);
+        }
+        draw(PassKind::Shaded);
+
+        unsafe {
+            gl::DepthMask(depth_mask);
+            gl::DepthFunc(conv!(depth_func));
+        }
+    }
+
+    // renders the scene once per view. each view gets its own cleared rectangle and, if given,
+    // `uniforms` is refilled with the view's camera before `draw(index, pass)` is called.
+    pub fn render_views<F: FnMut(usize, PassKind)>(&self, context: &GlContext, views: &[View], uniforms: Option<&ViewUniforms>, mut draw: F) {
+        check_render_thread("Renderer");
+        let (previous, scissor) = unsafe {
+            let previous = Viewport::current();
+            let scissor = gl::IsEnabled(gl::SCISSOR_TEST) == gl::TRUE;
+            gl::Enable(gl::SCISSOR_TEST);
+            (previous, scissor)
+        };
+
+        for (index, view) in views.iter().enumerate() {
+            unsafe {
+                view.viewport.apply();
+                gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+                if let Some(uniforms) = uniforms {
+                    uniforms.update(&view.camera.view(), &view.camera.projection(), view.camera.position());
+                }
+            }
+            self.render(context, |pass| draw(index, pass));
+        }
+
+        unsafe {
+            if !scissor {
+                gl::Disable(gl::SCISSOR_TEST);
+            }
+            previous.apply();
+        }
+    }
+
+    // runs `f` as a named pass: binds the target, sets the viewport, clears what `desc` asks for and
+    // labels the commands for graphics debuggers. the framebuffer, viewport, scissor, clear values
+    // and write masks are restored afterwards, so passes can be nested and reordered freely.
+    pub fn pass<R, F: FnOnce(&PassContext) -> R>(&self, _context: &GlContext, name: &str, desc: &PassDesc, f: F) -> R {
+        check_render_thread("Renderer");
+        let guard = unsafe {
+            let debug_group = debug_groups_supported();
+            if debug_group {
+                let label = CString::new(name).unwrap_or_default();
+                gl::PushDebugGroup(gl::DEBUG_SOURCE_APPLICATION, 0, -1, label.as_ptr());
+            }
+            PassGuard {
+                saved: SavedPassState::save(),
+                debug_group,
+            }
+        };
+        let saved = &guard.saved;
+
+        let viewport = match (desc.target, desc.viewport) {
+            (_, Some(viewport)) => viewport,
+            (Some(target), None) => Viewport::full(target.width(), target.height()),
+            (None, None) => saved.viewport,
+        };
+        let framebuffer = desc.target.map_or(0, |target| target.id());
+        unsafe {
+            gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, framebuffer);
+            // the scissor limits the clear to the viewport
+            viewport.apply();
+
+            let mut mask = 0;
+            if let Some([r, g, b, a]) = desc.clear_color {
+                gl::ClearColor(r, g, b, a);
+                gl::ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
+                mask |= gl::COLOR_BUFFER_BIT;
+            }
+            if let Some(depth) = desc.clear_depth {
+                gl::ClearDepth(depth.into());
+                gl::DepthMask(gl::TRUE);
+                mask |= gl::DEPTH_BUFFER_BIT;
+            }
+            if let Some(stencil) = desc.clear_stencil {
+                gl::ClearStencil(stencil);
+                gl::StencilMask(!0);
+                mask |= gl::STENCIL_BUFFER_BIT;
+            }
+            if mask != 0 {
+                gl::Enable(gl::SCISSOR_TEST);
+                gl::Clear(mask);
+                // the body draws with the caller's scissor test
+                if !saved.scissor {
+                    gl::Disable(gl::SCISSOR_TEST);
+                }
+                let [r, g, b, a] = saved.color_mask;
+                gl::ColorMask(r, g, b, a);
+                gl::DepthMask(saved.depth_mask);
+                gl::StencilMask(saved.stencil_mask as GLuint);
+            }
+        }
+
+        f(&PassContext {
+            name,
+            target: desc.target,
+            viewport,
+        })
+    }
+}
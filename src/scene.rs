@@ -0,0 +1,358 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use image::DynamicImage;
+
+use crate::context::check_render_thread;
+use crate::{GlContext, Model, ModelData, Texture, TextureBuilder, TextureType};
+
+// the assets a scene is made of, loaded together by `SceneManager::load`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SceneDesc {
+    pub name: String,
+    pub models: Vec<PathBuf>,
+    pub textures: Vec<(PathBuf, TextureType)>,
+}
+
+impl SceneDesc {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            ..Self::default()
+        }
+    }
+
+    pub fn with_model<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.models.push(path.as_ref().to_owned());
+        self
+    }
+
+    pub fn with_texture<P: AsRef<Path>>(mut self, path: P, type_: TextureType) -> Self {
+        self.textures.push((path.as_ref().to_owned(), type_));
+        self
+    }
+}
+
+// a loaded scene, in the order of its `SceneDesc`. its GL objects go when it is dropped.
+#[derive(Debug)]
+pub struct Scene {
+    pub name: String,
+    pub models: Vec<Model>,
+    pub textures: Vec<Texture>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SceneEvent {
+    // the scene is on screen, the previous one is unloaded
+    Switched(String),
+    // loading stopped, the current scene stays
+    Failed { scene: String, path: PathBuf, message: String },
+}
+
+// how far a scene load is, updated by the worker and by `SceneManager::update`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LoadProgress {
+    // uploaded and ready to draw
+    pub items_loaded: usize,
+    pub items_total: usize,
+    // of the files on disk. the textures of a model count once the model is read.
+    pub bytes_loaded: u64,
+    pub bytes_total: u64,
+    // the file being read, None once all are waiting for their upload
+    pub current: Option<PathBuf>,
+}
+
+impl LoadProgress {
+    // from 0 to 1, by bytes when the sizes are known
+    pub fn fraction(&self) -> f32 {
+        if self.bytes_total > 0 {
+            (self.bytes_loaded as f64 / self.bytes_total as f64).min(1.0) as f32
+        } else if self.items_total > 0 {
+            self.items_loaded as f32 / self.items_total as f32
+        } else {
+            1.0
+        }
+    }
+}
+
+// each with the bytes of the files it was read from
+enum Loaded {
+    Model(usize, ModelData, u64),
+    Texture(usize, DynamicImage, u64),
+    Failed(PathBuf, String),
+}
+
+fn file_size(path: &Path) -> u64 {
+    std::fs::metadata(path).map_or(0, |metadata| metadata.len())
+}
+
+fn load_worker(desc: SceneDesc, loaded: Sender<Loaded>, progress: Arc<Mutex<LoadProgress>>) {
+    let set_current = |path: &Path| progress.lock().unwrap().current = Some(path.to_owned());
+    progress.lock().unwrap().bytes_total = desc.models.iter().chain(desc.textures.iter().map(|(path, _)| path)).map(|path| file_size(path)).sum();
+
+    let models = desc.models.iter().enumerate().map(|(index, path)| {
+        set_current(path);
+        ModelData::load_obj(path)
+            .map(|data| {
+                let textures: u64 = data.images.keys().map(|path| file_size(path)).sum();
+                progress.lock().unwrap().bytes_total += textures;
+                Loaded::Model(index, data, file_size(path) + textures)
+            })
+            .map_err(|e| (path, e.to_string()))
+    });
+    let textures = desc.textures.iter().enumerate().map(|(index, (path, _))| {
+        set_current(path);
+        image::open(path).map(|img| Loaded::Texture(index, img, file_size(path))).map_err(|e| (path, e.to_string()))
+    });
+    for result in models.chain(textures) {
+        let message = result.unwrap_or_else(|(path, message)| Loaded::Failed(path.clone(), message));
+        let failed = matches!(message, Loaded::Failed(..));
+        // a newer load replaced this one when the receiver is gone
+        if loaded.send(message).is_err() || failed {
+            break;
+        }
+    }
+    progress.lock().unwrap().current = None;
+}
+
+struct Loading {
+    desc: SceneDesc,
+    loaded: Receiver<Loaded>,
+    // decoded on the worker, waiting for their upload
+    ready: Vec<Loaded>,
+    models: Vec<Option<Model>>,
+    textures: Vec<Option<Texture>>,
+    progress: Arc<Mutex<LoadProgress>>,
+}
+
+impl Loading {
+    fn total(&self) -> usize {
+        self.models.len() + self.textures.len()
+    }
+
+    fn uploaded(&self) -> usize {
+        self.progress.lock().unwrap().items_loaded
+    }
+
+    fn into_scene(self) -> Scene {
+        Scene {
+            name: self.desc.name,
+            models: self.models.into_iter().map(Option::unwrap).collect(),
+            textures: self.textures.into_iter().map(Option::unwrap).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    Idle,
+    // the loaded scene waits for the screen to be covered, `fade_amount` going 0 to 1
+    FadeOut(f32),
+    // the new scene is revealed, `fade_amount` going 1 to 0
+    FadeIn(f32),
+}
+
+// owns the scene on screen and loads the next one in the background. files are read and
+// decoded on a worker thread and uploaded a few at a time in `update`, which swaps the scenes
+// once everything is there, behind a fade when `fade_duration` is set. for the fade, push a
+// `Fade` onto the post stack and copy `fade_amount` into it every frame.
+pub struct SceneManager {
+    current: Option<Scene>,
+    loading: Option<Loading>,
+    // a loaded scene waiting for the fade out
+    next: Option<Scene>,
+    phase: Phase,
+    // seconds for fading out and back in together, 0 swaps instantly
+    pub fade_duration: f32,
+    pub max_uploads_per_update: usize,
+}
+
+impl std::fmt::Debug for SceneManager {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SceneManager")
+            .field("current", &self.current.as_ref().map(|scene| &scene.name))
+            .field("loading", &self.loading.as_ref().map(|loading| &loading.desc.name))
+            .field("phase", &self.phase)
+            .field("fade_duration", &self.fade_duration)
+            .finish()
+    }
+}
+
+impl Default for SceneManager {
+    fn default() -> Self {
+        Self {
+            current: None,
+            loading: None,
+            next: None,
+            phase: Phase::Idle,
+            fade_duration: 0.0,
+            max_uploads_per_update: 4,
+        }
+    }
+}
+
+impl SceneManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn current(&self) -> Option<&Scene> {
+        self.current.as_ref()
+    }
+
+    pub fn current_mut(&mut self) -> Option<&mut Scene> {
+        self.current.as_mut()
+    }
+
+    // starts loading `desc` in the background, abandoning a load still running
+    pub fn load(&mut self, desc: SceneDesc) {
+        let (sender, receiver) = channel();
+        let progress = Arc::new(Mutex::new(LoadProgress {
+            items_total: desc.models.len() + desc.textures.len(),
+            ..LoadProgress::default()
+        }));
+        let (worker_desc, worker_progress) = (desc.clone(), progress.clone());
+        thread::Builder::new()
+            .name("scene loading".into())
+            .spawn(move || load_worker(worker_desc, sender, worker_progress))
+            .expect("failed to spawn scene loading thread");
+
+        // a scene waiting for the fade out is dropped and the screen fades back in
+        self.next = None;
+        if let Phase::FadeOut(t) = self.phase {
+            self.phase = Phase::FadeIn(self.fade_duration * 0.5 - t.min(self.fade_duration * 0.5));
+        }
+
+        self.loading = Some(Loading {
+            models: desc.models.iter().map(|_| None).collect(),
+            textures: desc.textures.iter().map(|_| None).collect(),
+            desc,
+            loaded: receiver,
+            ready: vec![],
+            progress,
+        });
+    }
+
+    // true from `load` until the new scene is on screen
+    pub fn is_loading(&self) -> bool {
+        self.loading.is_some() || self.next.is_some()
+    }
+
+    // the share of the scene uploaded, 1 when nothing is loading
+    pub fn progress(&self) -> f32 {
+        self.load_progress().map_or(1.0, |progress| progress.fraction())
+    }
+
+    // the state of the running load, for a loading screen
+    pub fn load_progress(&self) -> Option<LoadProgress> {
+        self.loading.as_ref().map(|loading| loading.progress.lock().unwrap().clone())
+    }
+
+    // how much a `Fade` should cover the screen
+    pub fn fade_amount(&self) -> f32 {
+        let half = self.fade_duration * 0.5;
+        match self.phase {
+            _ if half <= 0.0 => 0.0,
+            Phase::Idle => 0.0,
+            Phase::FadeOut(t) => (t / half).min(1.0),
+            Phase::FadeIn(t) => 1.0 - (t / half).min(1.0),
+        }
+    }
+
+    // uploads what the worker finished and advances the fade, `delta` in seconds
+    pub fn update(&mut self, context: &GlContext, delta: f32) -> Option<SceneEvent> {
+        check_render_thread("SceneManager");
+        if let Some(event) = self.upload(context) {
+            return Some(event);
+        }
+
+        let half = self.fade_duration * 0.5;
+        match self.phase {
+            Phase::FadeOut(t) => {
+                let t = t + delta;
+                if t >= half {
+                    self.phase = Phase::FadeIn(0.0);
+                    return self.switch();
+                }
+                self.phase = Phase::FadeOut(t);
+            }
+            Phase::FadeIn(t) => {
+                let t = t + delta;
+                self.phase = if t >= half { Phase::Idle } else { Phase::FadeIn(t) };
+            }
+            Phase::Idle => {}
+        }
+        None
+    }
+
+    fn upload(&mut self, context: &GlContext) -> Option<SceneEvent> {
+        let loading = self.loading.as_mut()?;
+        loop {
+            match loading.loaded.try_recv() {
+                Ok(loaded) => loading.ready.push(loaded),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    if loading.ready.is_empty() && loading.uploaded() < loading.total() {
+                        let loading = self.loading.take().unwrap();
+                        return Some(SceneEvent::Failed {
+                            scene: loading.desc.name,
+                            path: PathBuf::new(),
+                            message: "scene loading thread stopped".into(),
+                        });
+                    }
+                    break;
+                }
+            }
+        }
+
+        let count = loading.ready.len().min(self.max_uploads_per_update.max(1));
+        for loaded in loading.ready.drain(..count).collect::<Vec<_>>() {
+            let bytes = match loaded {
+                Loaded::Model(index, data, bytes) => {
+                    loading.models[index] = Some(data.upload(context));
+                    bytes
+                }
+                Loaded::Texture(index, img, bytes) => {
+                    let type_ = loading.desc.textures[index].1;
+                    let id = TextureBuilder::new().srgb(type_.is_color()).upload_image(context, &img);
+                    loading.textures[index] = Some(Texture::adopt(id, type_));
+                    bytes
+                }
+                Loaded::Failed(path, message) => {
+                    let loading = self.loading.take().unwrap();
+                    return Some(SceneEvent::Failed {
+                        scene: loading.desc.name,
+                        path,
+                        message,
+                    });
+                }
+            };
+            let mut progress = loading.progress.lock().unwrap();
+            progress.items_loaded += 1;
+            progress.bytes_loaded += bytes;
+        }
+
+        if loading.uploaded() < loading.total() {
+            return None;
+        }
+        self.next = Some(self.loading.take().unwrap().into_scene());
+        if self.fade_duration > 0.0 && self.current.is_some() {
+            // keep fading out from where an interrupted fade in was
+            let half = self.fade_duration * 0.5;
+            self.phase = Phase::FadeOut(self.fade_amount() * half);
+            None
+        } else {
+            self.switch()
+        }
+    }
+
+    fn switch(&mut self) -> Option<SceneEvent> {
+        let next = self.next.take()?;
+        let name = next.name.clone();
+        // dropping the old scene deletes its meshes and textures
+        drop(self.current.replace(next));
+        Some(SceneEvent::Switched(name))
+    }
+}
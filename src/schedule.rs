@@ -0,0 +1,402 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::Time;
+
+// the parts of a frame, run in this order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Stage {
+    Input,
+    // zero or more times a frame with `Schedule::fixed_step`, for physics and deterministic gameplay
+    FixedUpdate,
+    Update,
+    // after gameplay moved things, e.g. cameras following them
+    LateUpdate,
+    Render,
+    PostRender,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 6] = [
+        Stage::Input,
+        Stage::FixedUpdate,
+        Stage::Update,
+        Stage::LateUpdate,
+        Stage::Render,
+        Stage::PostRender,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Input => "input",
+            Stage::FixedUpdate => "fixed_update",
+            Stage::Update => "update",
+            Stage::LateUpdate => "late_update",
+            Stage::Render => "render",
+            Stage::PostRender => "post_render",
+        }
+    }
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+// what a system is run with besides the state
+#[derive(Debug, Clone, Copy)]
+pub struct StageContext<'a> {
+    pub stage: Stage,
+    // `Schedule::fixed_step` in `FixedUpdate`, `Time::delta` otherwise
+    pub delta: f32,
+    // how far the game is into the next fixed step, in [0, 1), for interpolating when rendering
+    pub alpha: f32,
+    pub time: &'a Time,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScheduleError {
+    // an ordering constraint names a system that was never added
+    UnknownSystem { system: String, dependency: String },
+    // the constraints ask for a system to run before one of an earlier stage
+    WrongStage { system: String, dependency: String },
+    // the constraints within a stage go round in a circle through these systems
+    Cycle { stage: Stage, systems: Vec<String> },
+}
+
+impl fmt::Display for ScheduleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScheduleError::UnknownSystem { system, dependency } => write!(f, "{} is ordered against unknown system {}", system, dependency),
+            ScheduleError::WrongStage { system, dependency } => {
+                write!(f, "{} cannot be ordered against {}, their stages already run the other way round", system, dependency)
+            }
+            ScheduleError::Cycle { stage, systems } => write!(f, "ordering cycle in stage {}: {}", stage.name(), systems.join(", ")),
+        }
+    }
+}
+
+impl Error for ScheduleError {}
+
+type SystemFn<S> = Box<dyn FnMut(&mut S, &StageContext<'_>)>;
+
+struct System<S> {
+    name: String,
+    stage: Stage,
+    run: SystemFn<S>,
+    after: Vec<String>,
+    before: Vec<String>,
+}
+
+// returned by `Schedule::add` to put ordering constraints on the new system
+pub struct SystemConfig<'a, S> {
+    schedule: &'a mut Schedule<S>,
+    index: usize,
+}
+
+impl<'a, S> SystemConfig<'a, S> {
+    // runs after `name` when both are in the same stage
+    pub fn after(self, name: &str) -> Self {
+        self.schedule.systems[self.index].after.push(name.to_string());
+        self
+    }
+
+    pub fn before(self, name: &str) -> Self {
+        self.schedule.systems[self.index].before.push(name.to_string());
+        self
+    }
+}
+
+// the systems of the frame loop by stage. systems are closures over the user's state `S`; within
+// a stage they run in the order they were added unless constrained with `after` and `before`.
+pub struct Schedule<S> {
+    systems: Vec<System<S>>,
+    // indices into `systems` per stage, None when it has to be sorted again
+    order: Option<Vec<Vec<usize>>>,
+    // in seconds
+    pub fixed_step: f32,
+    // fixed steps run at most this often a frame, the rest of the backlog is dropped
+    pub max_fixed_steps: u32,
+    accumulator: f32,
+}
+
+impl<S> fmt::Debug for Schedule<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Schedule")
+            .field("systems", &self.systems.iter().map(|system| (system.stage, &system.name)).collect::<Vec<_>>())
+            .field("fixed_step", &self.fixed_step)
+            .field("max_fixed_steps", &self.max_fixed_steps)
+            .finish()
+    }
+}
+
+impl<S> Default for Schedule<S> {
+    fn default() -> Self {
+        Self {
+            systems: vec![],
+            order: None,
+            fixed_step: 1.0 / 60.0,
+            max_fixed_steps: 8,
+            accumulator: 0.0,
+        }
+    }
+}
+
+impl<S> Schedule<S> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // names are unique across all stages
+    pub fn add<F: FnMut(&mut S, &StageContext<'_>) + 'static>(&mut self, stage: Stage, name: &str, system: F) -> SystemConfig<'_, S> {
+        assert!(!self.systems.iter().any(|system| system.name == name), "system {} is added twice", name);
+        self.systems.push(System {
+            name: name.to_string(),
+            stage,
+            run: Box::new(system),
+            after: vec![],
+            before: vec![],
+        });
+        self.order = None;
+        SystemConfig {
+            index: self.systems.len() - 1,
+            schedule: self,
+        }
+    }
+
+    // true when a system was removed
+    pub fn remove(&mut self, name: &str) -> bool {
+        let len = self.systems.len();
+        self.systems.retain(|system| system.name != name);
+        self.order = None;
+        self.systems.len() != len
+    }
+
+    // the systems of `stage` in the order they run
+    pub fn systems(&mut self, stage: Stage) -> Result<Vec<&str>, ScheduleError> {
+        self.validate()?;
+        let (order, systems) = (self.order.as_ref().unwrap(), &self.systems);
+        Ok(order[stage.index()].iter().map(|&index| systems[index].name.as_str()).collect())
+    }
+
+    // sorts the stages, also done by the first run after a change
+    pub fn validate(&mut self) -> Result<(), ScheduleError> {
+        if self.order.is_none() {
+            self.order = Some(self.sort()?);
+        }
+        Ok(())
+    }
+
+    fn sort(&self) -> Result<Vec<Vec<usize>>, ScheduleError> {
+        let find = |name: &str| self.systems.iter().position(|system| system.name == name);
+        // edges from the system that runs first
+        let mut edges: Vec<(usize, usize)> = vec![];
+        for (index, system) in self.systems.iter().enumerate() {
+            let constraints = system.after.iter().map(|name| (name, true)).chain(system.before.iter().map(|name| (name, false)));
+            for (name, after) in constraints {
+                let other = find(name).ok_or_else(|| ScheduleError::UnknownSystem {
+                    system: system.name.clone(),
+                    dependency: name.clone(),
+                })?;
+                let (first, second) = if after { (other, index) } else { (index, other) };
+                let (first_stage, second_stage) = (self.systems[first].stage, self.systems[second].stage);
+                if first_stage > second_stage {
+                    return Err(ScheduleError::WrongStage {
+                        system: system.name.clone(),
+                        dependency: name.clone(),
+                    });
+                }
+                if first_stage == second_stage {
+                    edges.push((first, second));
+                }
+            }
+        }
+
+        let mut order = vec![vec![]; Stage::ALL.len()];
+        for &stage in Stage::ALL.iter() {
+            let mut pending: Vec<usize> = (0..self.systems.len()).filter(|&index| self.systems[index].stage == stage).collect();
+            // repeatedly takes the earliest added system nothing pending has to run before
+            while !pending.is_empty() {
+                let ready = pending
+                    .iter()
+                    .position(|&candidate| !edges.iter().any(|&(first, second)| second == candidate && pending.contains(&first)));
+                match ready {
+                    Some(position) => order[stage.index()].push(pending.remove(position)),
+                    None => {
+                        return Err(ScheduleError::Cycle {
+                            stage,
+                            systems: pending.iter().map(|&index| self.systems[index].name.clone()).collect(),
+                        })
+                    }
+                }
+            }
+        }
+        Ok(order)
+    }
+
+    // runs one stage. `FixedUpdate` runs as many steps as the time since the last one covers,
+    // or once per advancing tick of `Time::deterministic` with its step.
+    // panics on an invalid schedule, see `validate`.
+    pub fn run_stage(&mut self, stage: Stage, state: &mut S, time: &Time) {
+        if let Err(e) = self.validate() {
+            panic!("{}", e);
+        }
+        let fixed_delta = time.fixed_delta();
+        let steps = if stage == Stage::FixedUpdate && fixed_delta.is_some() {
+            // one step per advancing tick, without the float accumulator
+            (time.delta() > 0.0) as u32
+        } else if stage == Stage::FixedUpdate {
+            self.accumulator += time.delta();
+            let mut steps = 0;
+            while self.fixed_step > 0.0 && self.accumulator >= self.fixed_step && steps < self.max_fixed_steps {
+                self.accumulator -= self.fixed_step;
+                steps += 1;
+            }
+            if steps == self.max_fixed_steps {
+                self.accumulator = self.accumulator.min(self.fixed_step);
+            }
+            steps
+        } else {
+            1
+        };
+
+        let order = self.order.take().unwrap();
+        for _ in 0..steps {
+            let context = StageContext {
+                stage,
+                delta: if stage == Stage::FixedUpdate { fixed_delta.unwrap_or(self.fixed_step) } else { time.delta() },
+                alpha: if fixed_delta.is_some() { 0.0 } else { self.alpha() },
+                time,
+            };
+            for &index in order[stage.index()].iter() {
+                (self.systems[index].run)(state, &context);
+            }
+        }
+        self.order = Some(order);
+    }
+
+    // runs every stage in order, after `Time::tick`
+    pub fn run_frame(&mut self, state: &mut S, time: &Time) {
+        for &stage in Stage::ALL.iter() {
+            self.run_stage(stage, state, time);
+        }
+    }
+
+    fn alpha(&self) -> f32 {
+        if self.fixed_step > 0.0 {
+            (self.accumulator / self.fixed_step).clamp(0.0, 1.0)
+        } else {
+            0.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // records which systems ran, in order
+    type Log = Vec<&'static str>;
+
+    fn logger(name: &'static str) -> impl FnMut(&mut Log, &StageContext<'_>) {
+        move |log: &mut Log, _: &StageContext<'_>| log.push(name)
+    }
+
+    fn ticked(delta: f64) -> Time {
+        let mut time = Time::new();
+        time.tick(0.0);
+        time.tick(delta);
+        time
+    }
+
+    #[test]
+    fn stages_run_in_order_and_systems_as_added() {
+        let mut schedule = Schedule::new();
+        schedule.add(Stage::Render, "draw", logger("draw"));
+        schedule.add(Stage::Update, "move", logger("move"));
+        schedule.add(Stage::Update, "animate", logger("animate"));
+        schedule.add(Stage::Input, "poll", logger("poll"));
+        let mut log = vec![];
+        schedule.run_frame(&mut log, &ticked(0.01));
+        assert_eq!(log, vec!["poll", "move", "animate", "draw"]);
+    }
+
+    #[test]
+    fn constraints_reorder_a_stage() {
+        let mut schedule: Schedule<Log> = Schedule::new();
+        schedule.add(Stage::Update, "a", logger("a")).after("c");
+        schedule.add(Stage::Update, "b", logger("b"));
+        schedule.add(Stage::Update, "c", logger("c")).before("b");
+        // across stages the stage order already holds
+        schedule.add(Stage::LateUpdate, "camera", logger("camera")).after("a");
+        assert_eq!(schedule.systems(Stage::Update).unwrap(), vec!["c", "a", "b"]);
+
+        assert!(schedule.remove("camera"));
+        assert!(!schedule.remove("camera"));
+        assert!(schedule.systems(Stage::LateUpdate).unwrap().is_empty());
+        // constraints naming a removed system are errors, like any other unknown name
+        schedule.remove("b");
+        assert!(matches!(schedule.validate(), Err(ScheduleError::UnknownSystem { .. })));
+    }
+
+    #[test]
+    fn invalid_constraints_are_reported() {
+        let mut schedule: Schedule<Log> = Schedule::new();
+        schedule.add(Stage::Update, "a", logger("a")).after("missing");
+        assert_eq!(
+            schedule.validate(),
+            Err(ScheduleError::UnknownSystem {
+                system: "a".into(),
+                dependency: "missing".into()
+            })
+        );
+
+        let mut schedule: Schedule<Log> = Schedule::new();
+        schedule.add(Stage::Input, "poll", logger("poll"));
+        schedule.add(Stage::Update, "a", logger("a")).before("poll");
+        assert!(matches!(schedule.validate(), Err(ScheduleError::WrongStage { .. })));
+
+        let mut schedule: Schedule<Log> = Schedule::new();
+        schedule.add(Stage::Update, "a", logger("a")).after("b");
+        schedule.add(Stage::Update, "b", logger("b")).after("a");
+        schedule.add(Stage::Update, "c", logger("c"));
+        match schedule.validate() {
+            Err(ScheduleError::Cycle { stage, systems }) => {
+                assert_eq!(stage, Stage::Update);
+                assert_eq!(systems, vec!["a", "b"]);
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+
+    #[test]
+    fn fixed_update_catches_up_with_the_frame() {
+        let mut schedule = Schedule::new();
+        schedule.fixed_step = 0.01;
+        schedule.add(Stage::FixedUpdate, "physics", |steps: &mut Vec<f32>, context: &StageContext<'_>| steps.push(context.delta));
+        let mut steps = vec![];
+        schedule.run_stage(Stage::FixedUpdate, &mut steps, &ticked(0.035));
+        assert_eq!(steps, vec![0.01; 3]);
+        // the rest carries over
+        schedule.run_stage(Stage::FixedUpdate, &mut steps, &ticked(0.006));
+        assert_eq!(steps.len(), 4);
+
+        // a long frame is capped
+        schedule.max_fixed_steps = 2;
+        steps.clear();
+        schedule.run_stage(Stage::FixedUpdate, &mut steps, &ticked(0.2));
+        assert_eq!(steps.len(), 2);
+    }
+
+    #[test]
+    fn deterministic_time_steps_once_per_tick() {
+        let mut schedule = Schedule::new();
+        schedule.add(Stage::FixedUpdate, "physics", |steps: &mut Vec<f32>, context: &StageContext<'_>| steps.push(context.delta));
+        let mut time = Time::deterministic(0.02);
+        let mut steps = vec![];
+        for &now in &[0.0, 0.5, 0.51] {
+            time.tick(now);
+            schedule.run_stage(Stage::FixedUpdate, &mut steps, &time);
+        }
+        assert_eq!(steps, vec![0.02; 3]);
+    }
+}
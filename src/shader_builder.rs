@@ -0,0 +1,122 @@
+use std::ffi::CString;
+
+use gl::types::*;
+
+use crate::{compile_shader, link_program, CreateShaderError, GlContext, GpuInfo, Shader};
+
+// how captured varyings are laid out in the transform feedback buffers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FeedbackBufferMode {
+    // all varyings in one buffer, one vertex after another
+    Interleaved,
+    // one buffer binding per varying
+    Separate,
+}
+
+impl FeedbackBufferMode {
+    fn to_gl(self) -> GLenum {
+        match self {
+            FeedbackBufferMode::Interleaved => gl::INTERLEAVED_ATTRIBS,
+            FeedbackBufferMode::Separate => gl::SEPARATE_ATTRIBS,
+        }
+    }
+}
+
+// collects the stages and the pre-link configuration of a program
+#[derive(Debug, Clone, Default)]
+pub struct ShaderBuilder<'a> {
+    vertex: Option<&'a str>,
+    compute: Option<&'a str>,
+    geometry: Option<&'a str>,
+    fragment: Option<&'a str>,
+    attributes: Vec<(GLuint, &'a str)>,
+    varyings: Vec<&'a str>,
+    feedback_mode: Option<FeedbackBufferMode>,
+}
+
+impl<'a> ShaderBuilder<'a> {
+    pub fn vertex(mut self, source: &'a str) -> Self {
+        self.vertex = Some(source);
+        self
+    }
+
+    // a compute program stands alone, no other stage may be given
+    pub fn compute(mut self, source: &'a str) -> Self {
+        self.compute = Some(source);
+        self
+    }
+
+    pub fn geometry(mut self, source: &'a str) -> Self {
+        self.geometry = Some(source);
+        self
+    }
+
+    // may be left out for programs that only capture transform feedback
+    pub fn fragment(mut self, source: &'a str) -> Self {
+        self.fragment = Some(source);
+        self
+    }
+
+    // overrides the attribute location, for shaders without layout qualifiers
+    pub fn bind_attrib(mut self, location: GLuint, name: &'a str) -> Self {
+        self.attributes.push((location, name));
+        self
+    }
+
+    pub fn transform_feedback_varyings(mut self, varyings: &[&'a str], mode: FeedbackBufferMode) -> Self {
+        self.varyings.extend_from_slice(varyings);
+        self.feedback_mode = Some(mode);
+        self
+    }
+
+    pub fn build(self, _context: &GlContext) -> Result<Shader, CreateShaderError> {
+        unsafe { self.link() }
+    }
+
+    // `build` for callers that already guarantee a current context
+    pub(crate) unsafe fn link(self) -> Result<Shader, CreateShaderError> {
+        let mut stages = vec![];
+        if let Some(compute) = self.compute {
+            if self.vertex.is_some() || self.geometry.is_some() || self.fragment.is_some() {
+                return Err(CreateShaderError {
+                    message: "a compute shader cannot be linked with other stages".to_string(),
+                });
+            }
+            if !GpuInfo::current().features.compute_shader {
+                return Err(CreateShaderError {
+                    message: "compute shaders are not supported by this context".to_string(),
+                });
+            }
+            stages.push(compile_shader(gl::COMPUTE_SHADER, compute)?);
+        } else {
+            let vertex = self.vertex.ok_or_else(|| CreateShaderError {
+                message: "a vertex shader is required".to_string(),
+            })?;
+            stages.push(compile_shader(gl::VERTEX_SHADER, vertex)?);
+            if let Some(geometry) = self.geometry {
+                stages.push(compile_shader(gl::GEOMETRY_SHADER, geometry)?);
+            }
+            if let Some(fragment) = self.fragment {
+                stages.push(compile_shader(gl::FRAGMENT_SHADER, fragment)?);
+            }
+        }
+
+        // owns the program from here on, so it is deleted if linking fails
+        let shader = Shader::from_program(gl::CreateProgram());
+        for stage in stages.iter() {
+            gl::AttachShader(shader.id(), stage.0);
+        }
+        for &(location, name) in self.attributes.iter() {
+            let name = CString::new(name).unwrap();
+            gl::BindAttribLocation(shader.id(), location, name.as_ptr());
+        }
+        if let Some(mode) = self.feedback_mode {
+            let varyings: Vec<CString> = self.varyings.iter().map(|&name| CString::new(name).unwrap()).collect();
+            let pointers: Vec<*const GLchar> = varyings.iter().map(|name| name.as_ptr()).collect();
+            gl::TransformFeedbackVaryings(shader.id(), conv!(pointers.len()), pointers.as_ptr(), mode.to_gl());
+        }
+        link_program(shader.id())?;
+
+        Ok(shader)
+    }
+}
@@ -0,0 +1,227 @@
+use std::ffi::CString;
+use std::ptr;
+
+use cgmath::{ortho, InnerSpace, Matrix4, Point3, Vector3, vec3};
+use gl::types::*;
+
+use crate::context::check_render_thread;
+use crate::Shader;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ShadowSettings {
+    pub resolution: u32,
+    // constant bias applied to the depth comparison
+    pub depth_bias: f32,
+    // bias scaled by how grazing the light hits the surface
+    pub slope_bias: f32,
+    // radius of the PCF kernel in texels, 0 for a single tap
+    pub pcf_kernel_size: u32,
+    // render back faces only in the depth pass to reduce acne on closed meshes
+    pub cull_front_faces: bool,
+}
+
+impl Default for ShadowSettings {
+    fn default() -> Self {
+        Self {
+            resolution: 1024,
+            depth_bias: 0.005,
+            slope_bias: 0.05,
+            pcf_kernel_size: 1,
+            cull_front_faces: true,
+        }
+    }
+}
+
+// include after `#version`. sample with `ShadowFactor(shadow, fragPosLightSpace, normal, lightDir)`.
+pub const SHADOW_GLSL: &str = r#"
+struct Shadow {
+    sampler2D map;
+    float depthBias;
+    float slopeBias;
+    int pcfKernelSize;
+};
+
+float ShadowFactor(Shadow shadow, vec4 fragPosLightSpace, vec3 normal, vec3 lightDir) {
+    vec3 projCoords = fragPosLightSpace.xyz / fragPosLightSpace.w;
+    projCoords = projCoords * 0.5 + 0.5;
+    if (projCoords.z > 1.0) {
+        return 0.0;
+    }
+
+    float bias = max(shadow.slopeBias * (1.0 - dot(normal, lightDir)), shadow.depthBias);
+    vec2 texelSize = 1.0 / vec2(textureSize(shadow.map, 0));
+
+    float result = 0.0;
+    for (int x = -shadow.pcfKernelSize; x <= shadow.pcfKernelSize; x++) {
+        for (int y = -shadow.pcfKernelSize; y <= shadow.pcfKernelSize; y++) {
+            float closest = texture(shadow.map, projCoords.xy + vec2(x, y) * texelSize).r;
+            result += projCoords.z - bias > closest ? 1.0 : 0.0;
+        }
+    }
+    float width = float(2 * shadow.pcfKernelSize + 1);
+    return result / (width * width);
+}
+"#;
+
+#[derive(Debug)]
+pub struct ShadowMap {
+    fbo: GLuint,
+    depth: GLuint,
+    settings: ShadowSettings,
+    // what the depth pass was drawing to before
+    previous_framebuffer: GLint,
+    previous_viewport: [GLint; 4],
+    // whether culling was on and the face culled before the depth pass
+    previous_cull: (bool, GLint),
+}
+
+unsafe fn allocate_depth(texture: GLuint, resolution: u32) {
+    gl::BindTexture(gl::TEXTURE_2D, texture);
+    gl::TexImage2D(
+        gl::TEXTURE_2D,
+        0,
+        conv!(gl::DEPTH_COMPONENT24),
+        conv!(resolution),
+        conv!(resolution),
+        0,
+        gl::DEPTH_COMPONENT,
+        gl::FLOAT,
+        ptr::null(),
+    );
+}
+
+impl ShadowMap {
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn new(settings: ShadowSettings) -> Self {
+        let mut depth = 0;
+        gl::GenTextures(1, &mut depth);
+        allocate_depth(depth, settings.resolution);
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, conv!(gl::NEAREST));
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, conv!(gl::NEAREST));
+        // outside of the light frustum is lit
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, conv!(gl::CLAMP_TO_BORDER));
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, conv!(gl::CLAMP_TO_BORDER));
+        let border = [1.0f32; 4];
+        gl::TexParameterfv(gl::TEXTURE_2D, gl::TEXTURE_BORDER_COLOR, border.as_ptr());
+
+        // the caller's bindings survive the completeness check
+        let (mut draw_framebuffer, mut read_framebuffer) = (0, 0);
+        gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut draw_framebuffer);
+        gl::GetIntegerv(gl::READ_FRAMEBUFFER_BINDING, &mut read_framebuffer);
+        let mut fbo = 0;
+        gl::GenFramebuffers(1, &mut fbo);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+        gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, gl::TEXTURE_2D, depth, 0);
+        gl::DrawBuffer(gl::NONE);
+        gl::ReadBuffer(gl::NONE);
+        if gl::CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+            log::error!("shadow map framebuffer is not complete");
+        }
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, conv!(draw_framebuffer));
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, conv!(read_framebuffer));
+
+        Self {
+            fbo,
+            depth,
+            settings,
+            previous_framebuffer: 0,
+            previous_viewport: [0; 4],
+            previous_cull: (false, conv!(gl::BACK)),
+        }
+    }
+
+    pub fn settings(&self) -> &ShadowSettings {
+        &self.settings
+    }
+
+    /// reallocates the depth texture only when the resolution changes
+    ///
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn set_settings(&mut self, settings: ShadowSettings) {
+        if settings.resolution != self.settings.resolution {
+            allocate_depth(self.depth, settings.resolution);
+        }
+        self.settings = settings;
+    }
+
+    pub fn depth_texture(&self) -> GLuint {
+        self.depth
+    }
+
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn begin_depth_pass(&mut self) {
+        check_render_thread("ShadowMap");
+        gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut self.previous_framebuffer);
+        gl::GetIntegerv(gl::VIEWPORT, self.previous_viewport.as_mut_ptr());
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, self.fbo);
+        gl::Viewport(0, 0, conv!(self.settings.resolution), conv!(self.settings.resolution));
+        gl::Clear(gl::DEPTH_BUFFER_BIT);
+
+        if self.settings.cull_front_faces {
+            self.previous_cull.0 = gl::IsEnabled(gl::CULL_FACE) == gl::TRUE;
+            gl::GetIntegerv(gl::CULL_FACE_MODE, &mut self.previous_cull.1);
+            gl::Enable(gl::CULL_FACE);
+            gl::CullFace(gl::FRONT);
+        }
+    }
+
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn end_depth_pass(&self) {
+        if self.settings.cull_front_faces {
+            let (enabled, mode) = self.previous_cull;
+            gl::CullFace(conv!(mode));
+            if !enabled {
+                gl::Disable(gl::CULL_FACE);
+            }
+        }
+
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, conv!(self.previous_framebuffer));
+        let [x, y, width, height] = self.previous_viewport;
+        gl::Viewport(x, y, width, height);
+    }
+
+    /// binds the depth texture to `unit` and uploads the settings into the `Shadow` struct uniform `name`
+    ///
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread and `shader` must be in use, the values are set with glUniform.
+    pub unsafe fn bind(&self, shader: &Shader, name: &str, unit: GLuint) {
+        gl::ActiveTexture(gl::TEXTURE0 + unit);
+        gl::BindTexture(gl::TEXTURE_2D, self.depth);
+        gl::ActiveTexture(gl::TEXTURE0);
+
+        let uniform = |field: &str| CString::new(format!("{}.{}", name, field)).unwrap();
+        shader.set_integer(&uniform("map"), conv!(unit));
+        shader.set_float(&uniform("depthBias"), self.settings.depth_bias);
+        shader.set_float(&uniform("slopeBias"), self.settings.slope_bias);
+        shader.set_integer(&uniform("pcfKernelSize"), conv!(self.settings.pcf_kernel_size));
+    }
+}
+
+impl Drop for ShadowMap {
+    fn drop(&mut self) {
+        check_render_thread("ShadowMap");
+        unsafe {
+            gl::DeleteFramebuffers(1, &self.fbo);
+            gl::DeleteTextures(1, &self.depth);
+        }
+    }
+}
+
+// orthographic light space matrix covering a sphere of `radius` around `center`
+pub fn directional_light_space(direction: Vector3<f32>, center: Point3<f32>, radius: f32) -> Matrix4<f32> {
+    let direction = direction.normalize();
+    let up = if direction.y.abs() > 0.99 { vec3(0.0, 0.0, 1.0) } else { vec3(0.0, 1.0, 0.0) };
+    let eye = center - direction * radius * 2.0;
+    let view = Matrix4::look_at_dir(eye, direction, up);
+    let projection = ortho(-radius, radius, -radius, radius, 0.0, radius * 4.0);
+    projection * view
+}
@@ -0,0 +1,406 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use cgmath::{InnerSpace, Vector3};
+use gl::types::*;
+
+use crate::{MeshData, Vertex};
+
+// boundary edges weigh this much more than the faces, against eaten away borders
+const BOUNDARY_WEIGHT: f64 = 1000.0;
+
+// the symmetric 4x4 error quadric of Garland and Heckbert, upper triangle by rows
+#[derive(Debug, Clone, Copy, Default)]
+struct Quadric([f64; 10]);
+
+impl Quadric {
+    // the squared distance to the plane n.x + d = 0, times `weight`
+    fn plane(n: Vector3<f64>, d: f64, weight: f64) -> Self {
+        let [a, b, c] = [n.x, n.y, n.z];
+        Quadric([a * a, a * b, a * c, a * d, b * b, b * c, b * d, c * c, c * d, d * d]).scaled(weight)
+    }
+
+    fn scaled(self, weight: f64) -> Self {
+        let mut q = self.0;
+        q.iter_mut().for_each(|x| *x *= weight);
+        Quadric(q)
+    }
+
+    fn add(&mut self, other: &Quadric) {
+        for (x, y) in self.0.iter_mut().zip(other.0.iter()) {
+            *x += y;
+        }
+    }
+
+    fn error(&self, p: Vector3<f64>) -> f64 {
+        let q = &self.0;
+        let (x, y, z) = (p.x, p.y, p.z);
+        q[0] * x * x + 2.0 * q[1] * x * y + 2.0 * q[2] * x * z + 2.0 * q[3] * x
+            + q[4] * y * y + 2.0 * q[5] * y * z + 2.0 * q[6] * y
+            + q[7] * z * z + 2.0 * q[8] * z
+            + q[9]
+    }
+}
+
+// a candidate collapse of position `from` onto `to`, stale once either position changed
+#[derive(Debug, Clone, Copy)]
+struct Collapse {
+    cost: f64,
+    from: usize,
+    to: usize,
+    from_version: u32,
+    to_version: u32,
+}
+
+impl PartialEq for Collapse {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Collapse {}
+
+impl PartialOrd for Collapse {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Collapse {
+    // reversed, the heap pops the cheapest collapse first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.cost.partial_cmp(&self.cost).unwrap_or(Ordering::Equal)
+    }
+}
+
+struct Simplifier {
+    // corners are vertex indices
+    triangles: Vec<[usize; 3]>,
+    alive: Vec<bool>,
+    // vertices at the same position collapse together, so uv and normal seams stay closed
+    vertex_position: Vec<usize>,
+    positions: Vec<Vector3<f64>>,
+    quadrics: Vec<Quadric>,
+    position_vertices: Vec<Vec<usize>>,
+    // may list dead triangles
+    position_triangles: Vec<Vec<usize>>,
+    versions: Vec<u32>,
+    removed: Vec<bool>,
+}
+
+impl Simplifier {
+    fn new(data: &MeshData) -> Self {
+        let mut keys = HashMap::new();
+        let mut positions = vec![];
+        let vertex_position: Vec<usize> = data
+            .verticies
+            .iter()
+            .map(|vertex| {
+                let p = vertex.position;
+                *keys.entry([p.x.to_bits(), p.y.to_bits(), p.z.to_bits()]).or_insert_with(|| {
+                    positions.push(Vector3::new(p.x as f64, p.y as f64, p.z as f64));
+                    positions.len() - 1
+                })
+            })
+            .collect();
+
+        let triangles: Vec<[usize; 3]> = data
+            .indices
+            .chunks_exact(3)
+            .map(|face| [face[0] as usize, face[1] as usize, face[2] as usize])
+            .collect();
+
+        let count = positions.len();
+        let mut simplifier = Self {
+            alive: vec![true; triangles.len()],
+            triangles,
+            vertex_position,
+            positions,
+            quadrics: vec![Quadric::default(); count],
+            position_vertices: vec![vec![]; count],
+            position_triangles: vec![vec![]; count],
+            versions: vec![0; count],
+            removed: vec![false; count],
+        };
+        for (vertex, &position) in simplifier.vertex_position.iter().enumerate() {
+            simplifier.position_vertices[position].push(vertex);
+        }
+        simplifier.init_quadrics();
+        simplifier
+    }
+
+    fn corners(&self, triangle: usize) -> [usize; 3] {
+        let [a, b, c] = self.triangles[triangle];
+        [self.vertex_position[a], self.vertex_position[b], self.vertex_position[c]]
+    }
+
+    fn init_quadrics(&mut self) {
+        let mut edges: HashMap<(usize, usize), (usize, u32)> = HashMap::new();
+        for triangle in 0..self.triangles.len() {
+            let corners = self.corners(triangle);
+            if corners[0] == corners[1] || corners[1] == corners[2] || corners[2] == corners[0] {
+                self.alive[triangle] = false;
+                continue;
+            }
+            let [a, b, c] = [self.positions[corners[0]], self.positions[corners[1]], self.positions[corners[2]]];
+            let cross = (b - a).cross(c - a);
+            let area = cross.magnitude() * 0.5;
+            if area > 0.0 {
+                let n = cross.normalize();
+                let quadric = Quadric::plane(n, -n.dot(a), area);
+                for &corner in corners.iter() {
+                    self.quadrics[corner].add(&quadric);
+                }
+            }
+            for i in 0..3 {
+                let (from, to) = (corners[i], corners[(i + 1) % 3]);
+                self.position_triangles[from].push(triangle);
+                let entry = edges.entry((from.min(to), from.max(to))).or_insert((triangle, 0));
+                entry.1 += 1;
+            }
+        }
+
+        // edges of a single triangle get a plane through them, perpendicular to the face
+        for (&(from, to), &(triangle, count)) in edges.iter() {
+            if count != 1 {
+                continue;
+            }
+            let corners = self.corners(triangle);
+            let [a, b, c] = [self.positions[corners[0]], self.positions[corners[1]], self.positions[corners[2]]];
+            let face = (b - a).cross(c - a);
+            let edge = self.positions[to] - self.positions[from];
+            let n = edge.cross(face);
+            if n.magnitude2() == 0.0 {
+                continue;
+            }
+            let n = n.normalize();
+            let quadric = Quadric::plane(n, -n.dot(self.positions[from]), edge.magnitude2() * BOUNDARY_WEIGHT);
+            self.quadrics[from].add(&quadric);
+            self.quadrics[to].add(&quadric);
+        }
+    }
+
+    fn live_triangle_count(&self) -> usize {
+        self.alive.iter().filter(|&&alive| alive).count()
+    }
+
+    fn neighbours(&self, position: usize) -> Vec<usize> {
+        let mut neighbours = vec![];
+        for &triangle in self.position_triangles[position].iter().filter(|&&t| self.alive[t]) {
+            for corner in self.corners(triangle).iter() {
+                if *corner != position && !neighbours.contains(corner) {
+                    neighbours.push(*corner);
+                }
+            }
+        }
+        neighbours
+    }
+
+    fn cost(&self, from: usize, to: usize) -> f64 {
+        let mut quadric = self.quadrics[from];
+        quadric.add(&self.quadrics[to]);
+        quadric.error(self.positions[to]).max(0.0)
+    }
+
+    // the cheaper direction of collapsing the edge
+    fn candidate(&self, a: usize, b: usize) -> Collapse {
+        let (cost_ab, cost_ba) = (self.cost(a, b), self.cost(b, a));
+        let (from, to, cost) = if cost_ab <= cost_ba { (a, b, cost_ab) } else { (b, a, cost_ba) };
+        Collapse {
+            cost,
+            from,
+            to,
+            from_version: self.versions[from],
+            to_version: self.versions[to],
+        }
+    }
+
+    fn edge_triangles(&self, from: usize, to: usize) -> usize {
+        self.position_triangles[from]
+            .iter()
+            .filter(|&&triangle| self.alive[triangle] && self.corners(triangle).contains(&to))
+            .count()
+    }
+
+    // whether moving `from` onto `to` turns a remaining triangle over
+    fn flips(&self, from: usize, to: usize) -> bool {
+        for &triangle in self.position_triangles[from].iter().filter(|&&t| self.alive[t]) {
+            let corners = self.corners(triangle);
+            if corners.contains(&to) {
+                continue;
+            }
+            let points = |target: Option<usize>| {
+                let p = |c: usize| self.positions[if c == from { target.unwrap_or(from) } else { c }];
+                (p(corners[1]) - p(corners[0])).cross(p(corners[2]) - p(corners[0]))
+            };
+            let (before, after) = (points(None), points(Some(to)));
+            if before.dot(after) <= 0.0 {
+                return true;
+            }
+        }
+        false
+    }
+
+    // returns the number of triangles removed
+    fn collapse(&mut self, from: usize, to: usize) -> usize {
+        // the triangles on the edge go away, their corners tell which vertices meet on either side of a seam
+        let mut remap: HashMap<usize, usize> = HashMap::new();
+        let mut removed = 0;
+        for &triangle in self.position_triangles[from].iter() {
+            if !self.alive[triangle] || !self.corners(triangle).contains(&to) {
+                continue;
+            }
+            self.alive[triangle] = false;
+            removed += 1;
+            let vertices = self.triangles[triangle];
+            let from_vertex = vertices.iter().find(|&&v| self.vertex_position[v] == from);
+            let to_vertex = vertices.iter().find(|&&v| self.vertex_position[v] == to);
+            if let (Some(&from_vertex), Some(&to_vertex)) = (from_vertex, to_vertex) {
+                remap.entry(from_vertex).or_insert(to_vertex);
+            }
+        }
+
+        let triangles = std::mem::take(&mut self.position_triangles[from]);
+        for &triangle in triangles.iter() {
+            if !self.alive[triangle] {
+                continue;
+            }
+            for corner in self.triangles[triangle].iter_mut() {
+                if let Some(&to_vertex) = remap.get(corner) {
+                    *corner = to_vertex;
+                }
+            }
+            self.position_triangles[to].push(triangle);
+        }
+
+        // vertices without a partner move along with the position
+        let vertices = std::mem::take(&mut self.position_vertices[from]);
+        for &vertex in vertices.iter() {
+            self.vertex_position[vertex] = to;
+        }
+        self.position_vertices[to].extend(vertices);
+
+        let quadric = self.quadrics[from];
+        self.quadrics[to].add(&quadric);
+        self.removed[from] = true;
+        self.versions[to] += 1;
+        removed
+    }
+
+    fn run(&mut self, target: usize) {
+        let mut heap = BinaryHeap::new();
+        for position in 0..self.positions.len() {
+            for neighbour in self.neighbours(position) {
+                if position < neighbour {
+                    heap.push(self.candidate(position, neighbour));
+                }
+            }
+        }
+
+        let mut live = self.live_triangle_count();
+        while live > target {
+            let Collapse {
+                from,
+                to,
+                from_version,
+                to_version,
+                ..
+            } = match heap.pop() {
+                Some(collapse) => collapse,
+                None => break,
+            };
+            if self.removed[from] || self.removed[to] || self.versions[from] != from_version || self.versions[to] != to_version {
+                continue;
+            }
+            let (from, to) = if !self.flips(from, to) {
+                (from, to)
+            } else if !self.flips(to, from) {
+                (to, from)
+            } else {
+                continue;
+            };
+            // the last triangles stay, whatever the target
+            if self.edge_triangles(from, to) >= live {
+                continue;
+            }
+
+            live -= self.collapse(from, to);
+            for neighbour in self.neighbours(to) {
+                heap.push(self.candidate(to, neighbour));
+            }
+        }
+    }
+
+    fn finish(&self, data: &MeshData) -> MeshData {
+        let mut renumbered: Vec<Option<GLuint>> = vec![None; data.verticies.len()];
+        let mut verticies: Vec<Vertex> = vec![];
+        let mut indices = vec![];
+        for (triangle, corners) in self.triangles.iter().enumerate() {
+            if !self.alive[triangle] {
+                continue;
+            }
+            for &vertex in corners.iter() {
+                let index = *renumbered[vertex].get_or_insert_with(|| {
+                    let p = self.positions[self.vertex_position[vertex]];
+                    verticies.push(Vertex {
+                        position: Vector3::new(p.x as f32, p.y as f32, p.z as f32),
+                        ..data.verticies[vertex]
+                    });
+                    conv!(verticies.len() - 1)
+                });
+                indices.push(index);
+            }
+        }
+        MeshData::new(verticies, indices)
+    }
+}
+
+impl MeshData {
+    // quadric error edge collapse down to about `target_ratio` of the triangles, e.g. for LODs.
+    // stops early rather than fold triangles over, and keeps the borders of open meshes.
+    pub fn simplify(&self, target_ratio: f32) -> Self {
+        let target = (self.triangle_count() as f64 * target_ratio.clamp(0.0, 1.0) as f64).ceil() as usize;
+        let mut simplifier = Simplifier::new(self);
+        simplifier.run(target);
+        simplifier.finish(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::InnerSpace;
+
+    use crate::mesh_data::tests::grid;
+    use crate::MeshData;
+
+    fn bounds(data: &MeshData) -> (cgmath::Vector3<f32>, cgmath::Vector3<f32>) {
+        data.bounds().unwrap()
+    }
+
+    #[test]
+    fn a_flat_grid_collapses_to_few_triangles() {
+        let grid = grid(8);
+        let simplified = grid.simplify(0.1);
+        assert!(simplified.triangle_count() < grid.triangle_count() / 4, "{}", simplified.triangle_count());
+        assert!(simplified.triangle_count() > 0);
+        // the border is kept, so the outline doesn't shrink
+        let ((min, max), (simplified_min, simplified_max)) = (bounds(&grid), bounds(&simplified));
+        assert!((min - simplified_min).magnitude() < 1e-4 && (max - simplified_max).magnitude() < 1e-4);
+    }
+
+    #[test]
+    fn no_triangle_is_flipped() {
+        let simplified = grid(8).simplify(0.25);
+        for face in simplified.indices.chunks_exact(3) {
+            let p = |i: usize| simplified.verticies[face[i] as usize].position;
+            assert!((p(1) - p(0)).cross(p(2) - p(0)).z > 0.0);
+        }
+        assert!(simplified.indices.iter().all(|&i| (i as usize) < simplified.verticies.len()));
+    }
+
+    #[test]
+    fn ratio_one_keeps_everything() {
+        let grid = grid(4);
+        assert_eq!(grid.simplify(1.0).triangle_count(), grid.triangle_count());
+    }
+}
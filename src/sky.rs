@@ -0,0 +1,262 @@
+use cgmath::{perspective, Deg, InnerSpace, Matrix4, Point3, SquareMatrix, Vector3, vec3, vec4};
+use gl::types::*;
+
+use crate::context::check_render_thread;
+use crate::{c_str, DirectionalLight, FrameStats, GlContext, Shader};
+
+const SKY_VERTEX_SHADER: &str = r#"
+#version 330 core
+
+out vec3 ViewDir;
+
+uniform mat4 inverseViewProjection;
+
+void main() {
+    vec2 position = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2) * 2.0 - 1.0;
+    vec4 world = inverseViewProjection * vec4(position, 1.0, 1.0);
+    ViewDir = world.xyz / world.w;
+    // on the far plane, so it only covers pixels no geometry was drawn to
+    gl_Position = vec4(position, 1.0, 1.0);
+}
+"#;
+
+const SKY_FRAGMENT_SHADER: &str = r#"
+#version 330 core
+
+in vec3 ViewDir;
+out vec4 FragColor;
+
+uniform vec3 sunDirection;
+uniform float turbidity;
+uniform float exposure;
+uniform float sunSize;
+uniform float sunIntensity;
+uniform vec3 nightColor;
+
+const float PI = 3.14159265;
+
+// Preetham et al., "A Practical Analytic Model for Daylight"
+vec3 Perez(float cosTheta, float gamma, float cosGamma, vec3 A, vec3 B, vec3 C, vec3 D, vec3 E) {
+    return (1.0 + A * exp(B / cosTheta)) * (1.0 + C * exp(D * gamma) + E * cosGamma * cosGamma);
+}
+
+void main() {
+    vec3 dir = normalize(ViewDir);
+    vec3 sun = normalize(sunDirection);
+    float T = turbidity;
+
+    // the model only holds with the sun above the horizon, fade to night below it
+    float thetaS = min(acos(clamp(sun.y, -1.0, 1.0)), PI / 2.0 - 0.01);
+    float daylight = smoothstep(-0.1, 0.05, sun.y);
+
+    vec3 A = vec3(0.1787 * T - 1.4630, -0.0193 * T - 0.2592, -0.0167 * T - 0.2608);
+    vec3 B = vec3(-0.3554 * T + 0.4275, -0.0665 * T + 0.0008, -0.0950 * T + 0.0092);
+    vec3 C = vec3(-0.0227 * T + 5.3251, -0.0004 * T + 0.2125, -0.0079 * T + 0.2102);
+    vec3 D = vec3(0.1206 * T - 2.5771, -0.0641 * T - 0.8989, -0.0441 * T - 1.6537);
+    vec3 E = vec3(-0.0670 * T + 0.3703, -0.0033 * T + 0.0452, -0.0109 * T + 0.0529);
+
+    float chi = (4.0 / 9.0 - T / 120.0) * (PI - 2.0 * thetaS);
+    float zenithY = (4.0453 * T - 4.9710) * tan(chi) - 0.2155 * T + 2.4192;
+    vec4 theta = vec4(thetaS * thetaS * thetaS, thetaS * thetaS, thetaS, 1.0);
+    float zenithX = dot(vec3(T * T, T, 1.0), vec3(
+        dot(theta, vec4(0.0017, -0.0037, 0.0021, 0.0)),
+        dot(theta, vec4(-0.0290, 0.0638, -0.0320, 0.0039)),
+        dot(theta, vec4(0.1169, -0.2120, 0.0605, 0.2589))));
+    float zenithYc = dot(vec3(T * T, T, 1.0), vec3(
+        dot(theta, vec4(0.0028, -0.0061, 0.0032, 0.0)),
+        dot(theta, vec4(-0.0421, 0.0897, -0.0415, 0.0052)),
+        dot(theta, vec4(0.1535, -0.2676, 0.0667, 0.2608))));
+    vec3 zenith = vec3(zenithY, zenithX, zenithYc);
+
+    // below the horizon the sky color continues from just above it
+    float cosTheta = max(dir.y, 0.01);
+    float cosGamma = clamp(dot(dir, sun), -1.0, 1.0);
+    float gamma = acos(cosGamma);
+    vec3 Yxy = zenith * Perez(cosTheta, gamma, cosGamma, A, B, C, D, E)
+        / Perez(1.0, thetaS, cos(thetaS), A, B, C, D, E);
+
+    vec3 XYZ = vec3(Yxy.y / Yxy.z * Yxy.x, Yxy.x, (1.0 - Yxy.y - Yxy.z) / Yxy.z * Yxy.x);
+    mat3 XYZToRGB = mat3(3.2406, -0.9689, 0.0557, -1.5372, 1.8758, -0.2040, -0.4986, 0.0415, 1.0570);
+    vec3 color = max(XYZToRGB * XYZ, vec3(0.0)) * exposure;
+
+    if (cosGamma > cos(sunSize)) {
+        color += vec3(1.0, 0.95, 0.85) * sunIntensity;
+    }
+    FragColor = vec4(mix(nightColor, color, daylight), 1.0);
+}
+"#;
+
+// an analytic daylight sky, drawn behind the scene or baked into a cubemap
+#[derive(Debug)]
+pub struct ProceduralSky {
+    shader: Shader,
+    vao: GLuint,
+    // towards the sun
+    pub sun_direction: Vector3<f32>,
+    // haziness of the air, 2 for a clear sky up to about 10 for a hazy one
+    pub turbidity: f32,
+    // scales the luminance of the model, which is in kcd/m^2
+    pub exposure: f32,
+    // angular radius of the sun disc in radians
+    pub sun_size: f32,
+    pub sun_intensity: f32,
+    // the sky once the sun has set
+    pub night_color: Vector3<f32>,
+}
+
+impl ProceduralSky {
+    pub fn new(context: &GlContext) -> Self {
+        // the vertices are generated from gl_VertexID, but core profile still needs a VAO bound
+        let mut vao = 0;
+        unsafe {
+            gl::GenVertexArrays(1, &mut vao);
+        }
+        Self {
+            shader: Shader::new(context, SKY_VERTEX_SHADER, SKY_FRAGMENT_SHADER),
+            vao,
+            sun_direction: vec3(0.3, 0.6, -0.7).normalize(),
+            turbidity: 3.0,
+            exposure: 0.05,
+            sun_size: 0.01,
+            sun_intensity: 20.0,
+            night_color: vec3(0.005, 0.008, 0.02),
+        }
+    }
+
+    // a sun where the light comes from
+    pub fn match_light(&mut self, light: &DirectionalLight) {
+        self.sun_direction = -light.direction.normalize();
+    }
+
+    // draws into the bound framebuffer. only covers pixels at the far plane, so it can go
+    // after the opaque geometry to skip the hidden sky.
+    pub fn render(&self, _context: &GlContext, view: &Matrix4<f32>, projection: &Matrix4<f32>) {
+        unsafe { self.render_raw(view, projection) }
+    }
+
+    unsafe fn render_raw(&self, view: &Matrix4<f32>, projection: &Matrix4<f32>) {
+        let depth_test = gl::IsEnabled(gl::DEPTH_TEST) == gl::TRUE;
+        let mut depth_func = 0;
+        gl::GetIntegerv(gl::DEPTH_FUNC, &mut depth_func);
+        let mut depth_mask = gl::TRUE;
+        gl::GetBooleanv(gl::DEPTH_WRITEMASK, &mut depth_mask);
+        gl::Enable(gl::DEPTH_TEST);
+        gl::DepthFunc(gl::LEQUAL);
+        gl::DepthMask(gl::FALSE);
+
+        self.draw(view, projection);
+
+        gl::DepthMask(depth_mask);
+        gl::DepthFunc(conv!(depth_func));
+        if !depth_test {
+            gl::Disable(gl::DEPTH_TEST);
+        }
+    }
+
+    // a new RGB16F cubemap with `size`x`size` faces, usable in place of `load_cubemap`
+    pub fn render_cubemap(&self, _context: &GlContext, size: u32) -> GLuint {
+        unsafe { self.render_cubemap_raw(size) }
+    }
+
+    unsafe fn render_cubemap_raw(&self, size: u32) -> GLuint {
+        let mut cubemap = 0;
+        gl::GenTextures(1, &mut cubemap);
+        gl::BindTexture(gl::TEXTURE_CUBE_MAP, cubemap);
+        for face in 0..6 {
+            gl::TexImage2D(
+                gl::TEXTURE_CUBE_MAP_POSITIVE_X + face,
+                0,
+                conv!(gl::RGB16F),
+                conv!(size),
+                conv!(size),
+                0,
+                gl::RGB,
+                gl::FLOAT,
+                std::ptr::null(),
+            );
+        }
+        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MIN_FILTER, conv!(gl::LINEAR));
+        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_MAG_FILTER, conv!(gl::LINEAR));
+        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_S, conv!(gl::CLAMP_TO_EDGE));
+        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_T, conv!(gl::CLAMP_TO_EDGE));
+        gl::TexParameteri(gl::TEXTURE_CUBE_MAP, gl::TEXTURE_WRAP_R, conv!(gl::CLAMP_TO_EDGE));
+        self.update_cubemap_raw(cubemap, size);
+        cubemap
+    }
+
+    // redraws the faces of a cubemap from `render_cubemap`, e.g. after the sun moved
+    pub fn update_cubemap(&self, _context: &GlContext, cubemap: GLuint, size: u32) {
+        unsafe { self.update_cubemap_raw(cubemap, size) }
+    }
+
+    unsafe fn update_cubemap_raw(&self, cubemap: GLuint, size: u32) {
+        let mut output = 0;
+        gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut output);
+        let mut viewport = [0; 4];
+        gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+        let depth_test = gl::IsEnabled(gl::DEPTH_TEST) == gl::TRUE;
+        gl::Disable(gl::DEPTH_TEST);
+
+        let mut fbo = 0;
+        gl::GenFramebuffers(1, &mut fbo);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+        gl::Viewport(0, 0, conv!(size), conv!(size));
+
+        let projection = perspective(Deg(90.0), 1.0, 0.1, 10.0);
+        let origin = Point3::new(0.0, 0.0, 0.0);
+        // the usual cubemap face orientations, +x, -x, +y, -y, +z, -z
+        let faces = [
+            (vec3(1.0, 0.0, 0.0), vec3(0.0, -1.0, 0.0)),
+            (vec3(-1.0, 0.0, 0.0), vec3(0.0, -1.0, 0.0)),
+            (vec3(0.0, 1.0, 0.0), vec3(0.0, 0.0, 1.0)),
+            (vec3(0.0, -1.0, 0.0), vec3(0.0, 0.0, -1.0)),
+            (vec3(0.0, 0.0, 1.0), vec3(0.0, -1.0, 0.0)),
+            (vec3(0.0, 0.0, -1.0), vec3(0.0, -1.0, 0.0)),
+        ];
+        for (face, &(forward, up)) in faces.iter().enumerate() {
+            let face: GLuint = conv!(face);
+            gl::FramebufferTexture2D(gl::FRAMEBUFFER, gl::COLOR_ATTACHMENT0, gl::TEXTURE_CUBE_MAP_POSITIVE_X + face, cubemap, 0);
+            let view = Matrix4::look_at(origin, origin + forward, up);
+            self.draw(&view, &projection);
+        }
+
+        gl::BindFramebuffer(gl::FRAMEBUFFER, conv!(output));
+        gl::DeleteFramebuffers(1, &fbo);
+        gl::Viewport(viewport[0], viewport[1], viewport[2], viewport[3]);
+        if depth_test {
+            gl::Enable(gl::DEPTH_TEST);
+        }
+    }
+
+    unsafe fn draw(&self, view: &Matrix4<f32>, projection: &Matrix4<f32>) {
+        check_render_thread("ProceduralSky");
+        // only the rotation matters, the sky is infinitely far away
+        let mut rotation = *view;
+        rotation.w = vec4(0.0, 0.0, 0.0, 1.0);
+        let inverse_view_projection = (projection * rotation).invert().unwrap_or_else(Matrix4::identity);
+
+        let sun = self.sun_direction.normalize();
+        self.shader.use_program();
+        self.shader.set_matrix4(c_str("inverseViewProjection\0"), &inverse_view_projection);
+        self.shader.set_vec3(c_str("sunDirection\0"), sun.x, sun.y, sun.z);
+        self.shader.set_float(c_str("turbidity\0"), self.turbidity);
+        self.shader.set_float(c_str("exposure\0"), self.exposure);
+        self.shader.set_float(c_str("sunSize\0"), self.sun_size);
+        self.shader.set_float(c_str("sunIntensity\0"), self.sun_intensity);
+        self.shader.set_vec3(c_str("nightColor\0"), self.night_color.x, self.night_color.y, self.night_color.z);
+
+        gl::BindVertexArray(self.vao);
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+        gl::BindVertexArray(0);
+        FrameStats::record_draw(1, 1);
+    }
+}
+
+impl Drop for ProceduralSky {
+    fn drop(&mut self) {
+        check_render_thread("ProceduralSky");
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
@@ -0,0 +1,356 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use cgmath::{Matrix4, Point3, Quaternion};
+
+use crate::config::{parse_value, quote};
+use crate::{Camera, CameraPose, FreeCamera, Model, SimRng, SunCycle};
+
+const HEADER: &str = "# game-engine snapshot 1";
+
+#[derive(Debug)]
+pub struct SnapshotError {
+    message: String,
+}
+
+impl SnapshotError {
+    pub(crate) fn new(message: String) -> Self {
+        Self { message }
+    }
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid snapshot: {}", self.message)
+    }
+}
+
+impl Error for SnapshotError {}
+
+// the runtime state of a session at one moment, saved mid-game and restored into the same
+// content. scenes say what to load, a snapshot only what changed since, so it holds values by
+// dotted key and no assets. floats are written so they read back bit for bit, strings quoted
+// and escaped so they stay on their line.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    values: BTreeMap<String, String>,
+}
+
+impl Snapshot {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn parse(source: &str) -> Result<Self, SnapshotError> {
+        let mut values = BTreeMap::new();
+        for (number, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (key, value) = match line.find('=') {
+                Some(index) => (line[..index].trim(), line[index + 1..].trim()),
+                None => return Err(SnapshotError::new(format!("line {}: expected key = value", number + 1))),
+            };
+            values.insert(key.to_string(), value.to_string());
+        }
+        Ok(Self { values })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, Box<dyn Error + 'static>> {
+        Ok(Self::parse(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut text = format!("{}\n", HEADER);
+        for (key, value) in self.values.iter() {
+            text.push_str(&format!("{} = {}\n", key, value));
+        }
+        text
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        fs::write(path, self.to_text())
+    }
+
+    // stores the state of `object` under `key`
+    pub fn capture<T: SaveState + ?Sized>(&mut self, key: &str, object: &T) {
+        object.save_state(self, key);
+    }
+
+    // restores `object` from what `capture` stored under `key`
+    pub fn restore<T: SaveState + ?Sized>(&self, key: &str, object: &mut T) -> Result<(), SnapshotError> {
+        object.load_state(self, key)
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.values.contains_key(key)
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &str> {
+        self.values.keys().map(|key| key.as_str())
+    }
+
+    // key and value pairs in key order, the values as written, e.g. strings still quoted
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.values.iter().map(|(key, value)| (key.as_str(), value.as_str()))
+    }
+
+    pub fn remove(&mut self, key: &str) -> bool {
+        self.values.remove(key).is_some()
+    }
+
+    // removes every key under `key.`
+    pub fn remove_prefix(&mut self, key: &str) {
+        let prefix = format!("{}.", key);
+        self.values.retain(|existing, _| !existing.starts_with(&prefix));
+    }
+
+    // `Debug` rather than `Display` so floats keep every digit
+    pub fn set<T: fmt::Debug>(&mut self, key: &str, value: T) {
+        self.values.insert(key.to_string(), format!("{:?}", value));
+    }
+
+    pub fn set_str(&mut self, key: &str, value: &str) {
+        self.values.insert(key.to_string(), quote(value));
+    }
+
+    // a value as `iter` returns it, e.g. to copy between snapshots
+    pub fn set_raw(&mut self, key: &str, value: &str) {
+        self.values.insert(key.to_string(), value.to_string());
+    }
+
+    pub fn get<T: FromStr>(&self, key: &str) -> Result<T, SnapshotError> {
+        let value = self.raw(key)?;
+        value.parse().map_err(|_| SnapshotError::new(format!("{} has a bad value {:?}", key, value)))
+    }
+
+    pub fn str(&self, key: &str) -> Result<String, SnapshotError> {
+        parse_value(self.raw(key)?).map_err(|message| SnapshotError::new(format!("{}: {}", key, message)))
+    }
+
+    pub fn raw(&self, key: &str) -> Result<&str, SnapshotError> {
+        self.values.get(key).map(|value| value.as_str()).ok_or_else(|| SnapshotError::new(format!("{} is missing", key)))
+    }
+
+    pub fn set_floats(&mut self, key: &str, values: &[f32]) {
+        let words: Vec<String> = values.iter().map(|value| format!("{:?}", value)).collect();
+        self.values.insert(key.to_string(), words.join(" "));
+    }
+
+    pub fn floats(&self, key: &str) -> Result<Vec<f32>, SnapshotError> {
+        self.raw(key)?
+            .split_whitespace()
+            .map(|word| word.parse().map_err(|_| SnapshotError::new(format!("{} has a bad number {:?}", key, word))))
+            .collect()
+    }
+
+    pub fn set_matrix(&mut self, key: &str, matrix: &Matrix4<f32>) {
+        let values: &[f32; 16] = matrix.as_ref();
+        self.set_floats(key, values);
+    }
+
+    pub fn matrix(&self, key: &str) -> Result<Matrix4<f32>, SnapshotError> {
+        let values = self.fixed_floats(key, 16)?;
+        let mut matrix: Matrix4<f32> = Matrix4::from_scale(1.0);
+        let target: &mut [f32; 16] = matrix.as_mut();
+        target.copy_from_slice(&values);
+        Ok(matrix)
+    }
+
+    fn fixed_floats(&self, key: &str, len: usize) -> Result<Vec<f32>, SnapshotError> {
+        let values = self.floats(key)?;
+        if values.len() != len {
+            return Err(SnapshotError::new(format!("{} has {} numbers instead of {}", key, values.len(), len)));
+        }
+        Ok(values)
+    }
+}
+
+// types whose runtime state goes into snapshots. fields are stored under `key.field`. the crate
+// implements it for what it keeps at runtime: the rng, model nodes, cameras and the sun cycle.
+// it has no entities or animation players of its own, games implement it for their components
+// and animation state, e.g. the clip and time of each animated model.
+pub trait SaveState {
+    fn save_state(&self, snapshot: &mut Snapshot, key: &str);
+    fn load_state(&mut self, snapshot: &Snapshot, key: &str) -> Result<(), SnapshotError>;
+}
+
+fn field(key: &str, name: &str) -> String {
+    format!("{}.{}", key, name)
+}
+
+// the rng continues the same sequence after loading
+impl SaveState for SimRng {
+    fn save_state(&self, snapshot: &mut Snapshot, key: &str) {
+        let (state, increment) = self.state();
+        snapshot.set(&field(key, "state"), state);
+        snapshot.set(&field(key, "increment"), increment);
+    }
+
+    fn load_state(&mut self, snapshot: &Snapshot, key: &str) -> Result<(), SnapshotError> {
+        *self = SimRng::from_state(snapshot.get(&field(key, "state"))?, snapshot.get(&field(key, "increment"))?);
+        Ok(())
+    }
+}
+
+// the node transforms and visibility. the model has to be loaded from the same file.
+impl SaveState for Model {
+    fn save_state(&self, snapshot: &mut Snapshot, key: &str) {
+        snapshot.set(&field(key, "nodes"), self.nodes.len());
+        for (index, node) in self.nodes.iter().enumerate() {
+            let node_key = field(key, &index.to_string());
+            snapshot.set_str(&field(&node_key, "name"), &node.name);
+            snapshot.set_matrix(&field(&node_key, "transform"), &node.transform);
+            snapshot.set(&field(&node_key, "visible"), node.visible);
+        }
+    }
+
+    fn load_state(&mut self, snapshot: &Snapshot, key: &str) -> Result<(), SnapshotError> {
+        let count: usize = snapshot.get(&field(key, "nodes"))?;
+        if count != self.nodes.len() {
+            return Err(SnapshotError::new(format!("{} has {} nodes, the model {}", key, count, self.nodes.len())));
+        }
+        // read in full first so a bad value leaves the model untouched
+        let mut states = Vec::with_capacity(count);
+        for (index, node) in self.nodes.iter().enumerate() {
+            let node_key = field(key, &index.to_string());
+            let name = snapshot.str(&field(&node_key, "name"))?;
+            if name != node.name {
+                return Err(SnapshotError::new(format!("{} node {} is {:?}, the model's {:?}", key, index, name, node.name)));
+            }
+            let transform = snapshot.matrix(&field(&node_key, "transform"))?;
+            let visible: bool = snapshot.get(&field(&node_key, "visible"))?;
+            states.push((transform, visible));
+        }
+        for (node, (transform, visible)) in self.nodes.iter_mut().zip(states) {
+            node.transform = transform;
+            node.visible = visible;
+        }
+        Ok(())
+    }
+}
+
+impl SaveState for FreeCamera {
+    fn save_state(&self, snapshot: &mut Snapshot, key: &str) {
+        let (position, orientation) = (self.position(), self.orientation());
+        snapshot.set_floats(&field(key, "position"), &[position.x, position.y, position.z]);
+        snapshot.set_floats(&field(key, "orientation"), &[orientation.s, orientation.v.x, orientation.v.y, orientation.v.z]);
+    }
+
+    fn load_state(&mut self, snapshot: &Snapshot, key: &str) -> Result<(), SnapshotError> {
+        let position = snapshot.fixed_floats(&field(key, "position"), 3)?;
+        let orientation = snapshot.fixed_floats(&field(key, "orientation"), 4)?;
+        self.set_pose(
+            Point3::new(position[0], position[1], position[2]),
+            Quaternion::new(orientation[0], orientation[1], orientation[2], orientation[3]),
+        );
+        Ok(())
+    }
+}
+
+impl SaveState for SunCycle {
+    fn save_state(&self, snapshot: &mut Snapshot, key: &str) {
+        snapshot.set(&field(key, "time_of_day"), self.time_of_day);
+    }
+
+    // the probes are refreshed by the next update
+    fn load_state(&mut self, snapshot: &Snapshot, key: &str) -> Result<(), SnapshotError> {
+        self.set_time_of_day(snapshot.get(&field(key, "time_of_day"))?);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Rad;
+    use rand::RngCore;
+
+    use super::*;
+
+    #[test]
+    fn values_read_back_exactly() {
+        let mut snapshot = Snapshot::new();
+        snapshot.set("player.health", 0.1f32 + 0.2);
+        snapshot.set("player.level", 12u32);
+        snapshot.set_floats("player.position", &[1.0 / 3.0, -0.0, 1e-30]);
+        let matrix = Matrix4::from_angle_y(Rad(0.7)) * Matrix4::from_scale(1.3);
+        snapshot.set_matrix("player.transform", &matrix);
+
+        let parsed = Snapshot::parse(&snapshot.to_text()).unwrap();
+        assert_eq!(parsed, snapshot);
+        assert_eq!(parsed.get::<f32>("player.health").unwrap(), 0.1f32 + 0.2);
+        assert_eq!(parsed.get::<u32>("player.level").unwrap(), 12);
+        assert_eq!(parsed.floats("player.position").unwrap(), vec![1.0 / 3.0, -0.0, 1e-30]);
+        assert_eq!(parsed.matrix("player.transform").unwrap(), matrix);
+    }
+
+    #[test]
+    fn missing_and_bad_values_are_errors() {
+        let snapshot = Snapshot::parse("a = 1\nb = one\nc = 1 2 x\n").unwrap();
+        assert_eq!(snapshot.get::<u32>("b").unwrap_err().to_string(), "invalid snapshot: b has a bad value \"one\"");
+        assert!(snapshot.get::<u32>("d").unwrap_err().to_string().contains("d is missing"));
+        assert!(snapshot.floats("c").is_err());
+        assert!(snapshot.matrix("a").unwrap_err().to_string().contains("1 numbers instead of 16"));
+        assert!(Snapshot::parse("# comment\nno value\n").unwrap_err().to_string().contains("line 2"));
+    }
+
+    #[test]
+    fn the_rng_continues_its_sequence() {
+        let mut rng = SimRng::new(11);
+        rng.next_u32();
+        let mut snapshot = Snapshot::new();
+        snapshot.capture("rng", &rng);
+        let mut restored = SimRng::new(0);
+        Snapshot::parse(&snapshot.to_text()).unwrap().restore("rng", &mut restored).unwrap();
+        assert_eq!(restored.next_u64(), rng.next_u64());
+    }
+
+    #[test]
+    fn cameras_keep_their_pose() {
+        let camera = FreeCamera::new(Point3::new(1.0, 2.0, 3.0), Quaternion::new(0.6, 0.0, 0.8, 0.0), 1.0, 1.5);
+        let mut snapshot = Snapshot::new();
+        snapshot.capture("camera", &camera);
+        let mut restored = FreeCamera::new(Point3::new(0.0, 0.0, 0.0), Quaternion::new(1.0, 0.0, 0.0, 0.0), 1.0, 1.5);
+        snapshot.restore("camera", &mut restored).unwrap();
+        assert_eq!(restored.position(), camera.position());
+        assert_eq!(restored.orientation(), camera.orientation());
+
+        snapshot.set_floats("camera.position", &[1.0, 2.0]);
+        assert!(snapshot.restore("camera", &mut restored).is_err());
+    }
+
+    #[test]
+    fn strings_are_escaped() {
+        let mut snapshot = Snapshot::new();
+        snapshot.set_str("player.name", "line\nbreak \"quoted\" = sign\\");
+        let parsed = Snapshot::parse(&snapshot.to_text()).unwrap();
+        assert_eq!(parsed.str("player.name").unwrap(), "line\nbreak \"quoted\" = sign\\");
+        assert_eq!(parsed.raw("player.name").unwrap().lines().count(), 1);
+    }
+
+    #[test]
+    fn keys_are_sorted_and_removable() {
+        let mut snapshot = Snapshot::new();
+        for key in ["enemy.1.hp", "enemy.0.hp", "enemy_count", "camera.fov"].iter() {
+            snapshot.set(key, 1);
+        }
+        assert_eq!(snapshot.keys().collect::<Vec<_>>(), vec!["camera.fov", "enemy.0.hp", "enemy.1.hp", "enemy_count"]);
+        snapshot.remove_prefix("enemy");
+        assert_eq!(snapshot.keys().collect::<Vec<_>>(), vec!["camera.fov", "enemy_count"]);
+        assert!(snapshot.remove("camera.fov"));
+        assert!(!snapshot.remove("camera.fov"));
+        assert!(!snapshot.contains("camera.fov"));
+
+        let mut copy = Snapshot::new();
+        snapshot.set_str("name", "x");
+        for (key, value) in snapshot.iter() {
+            copy.set_raw(key, value);
+        }
+        assert_eq!(copy, snapshot);
+    }
+}
@@ -0,0 +1,483 @@
+use cgmath::{EuclideanSpace, InnerSpace, MetricSpace, Point3, Vector3};
+
+// one cubic Bézier segment, passes through the first and the last point
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CubicBezier {
+    pub points: [Point3<f32>; 4],
+}
+
+impl CubicBezier {
+    pub fn new(p0: Point3<f32>, p1: Point3<f32>, p2: Point3<f32>, p3: Point3<f32>) -> Self {
+        Self { points: [p0, p1, p2, p3] }
+    }
+
+    // the Catmull-Rom segment from `from` to `to`, with tangents from the neighbours
+    pub fn catmull_rom(previous: Point3<f32>, from: Point3<f32>, to: Point3<f32>, next: Point3<f32>) -> Self {
+        Self::new(from, from + (to - previous) / 6.0, to - (next - from) / 6.0, to)
+    }
+
+    // the uniform cubic B-spline segment between the middle two control points
+    pub fn b_spline(p0: Point3<f32>, p1: Point3<f32>, p2: Point3<f32>, p3: Point3<f32>) -> Self {
+        let (p0, p1, p2, p3) = (p0.to_vec(), p1.to_vec(), p2.to_vec(), p3.to_vec());
+        Self::new(
+            Point3::from_vec((p0 + p1 * 4.0 + p2) / 6.0),
+            Point3::from_vec((p1 * 2.0 + p2) / 3.0),
+            Point3::from_vec((p1 + p2 * 2.0) / 3.0),
+            Point3::from_vec((p1 + p2 * 4.0 + p3) / 6.0),
+        )
+    }
+
+    pub fn evaluate(&self, t: f32) -> Point3<f32> {
+        let [p0, p1, p2, p3] = self.points;
+        let u = 1.0 - t;
+        Point3::from_vec(
+            p0.to_vec() * (u * u * u) + p1.to_vec() * (3.0 * u * u * t) + p2.to_vec() * (3.0 * u * t * t) + p3.to_vec() * (t * t * t),
+        )
+    }
+
+    // first derivative by the segment parameter
+    pub fn velocity(&self, t: f32) -> Vector3<f32> {
+        let [p0, p1, p2, p3] = self.points;
+        let u = 1.0 - t;
+        (p1 - p0) * (3.0 * u * u) + (p2 - p1) * (6.0 * u * t) + (p3 - p2) * (3.0 * t * t)
+    }
+
+    pub fn acceleration(&self, t: f32) -> Vector3<f32> {
+        let [p0, p1, p2, p3] = self.points;
+        ((p2 - p1) - (p1 - p0)) * (6.0 * (1.0 - t)) + ((p3 - p2) - (p2 - p1)) * (6.0 * t)
+    }
+}
+
+// a piecewise cubic curve. `t` runs from 0 to 1 over the whole curve, every segment gets the
+// same share of it, so speed along the curve is not constant; see `ArcLength` for that.
+pub trait Curve {
+    fn segment_count(&self) -> usize;
+
+    fn segment(&self, index: usize) -> CubicBezier;
+
+    // the segment containing `t` and the parameter inside it
+    fn locate(&self, t: f32) -> (usize, f32) {
+        let count = self.segment_count().max(1);
+        let scaled = t.clamp(0.0, 1.0) * count as f32;
+        let index = (scaled as usize).min(count - 1);
+        (index, scaled - index as f32)
+    }
+
+    fn point(&self, t: f32) -> Point3<f32> {
+        let (index, local) = self.locate(t);
+        self.segment(index).evaluate(local)
+    }
+
+    // derivative by `t`
+    fn velocity(&self, t: f32) -> Vector3<f32> {
+        let (index, local) = self.locate(t);
+        self.segment(index).velocity(local) * self.segment_count().max(1) as f32
+    }
+
+    // unit direction of travel, zero where the curve stops
+    fn tangent(&self, t: f32) -> Vector3<f32> {
+        let velocity = self.velocity(t);
+        if velocity.magnitude2() > 1e-12 {
+            velocity.normalize()
+        } else {
+            // a handle on the end point, look a little further along
+            let (index, local) = self.locate(t);
+            let segment = self.segment(index);
+            let d = segment.evaluate((local + 1e-3).min(1.0)) - segment.evaluate((local - 1e-3).max(0.0));
+            if d.magnitude2() > 1e-12 { d.normalize() } else { Vector3::new(0.0, 0.0, 0.0) }
+        }
+    }
+}
+
+impl Curve for CubicBezier {
+    fn segment_count(&self) -> usize {
+        1
+    }
+
+    fn segment(&self, _index: usize) -> CubicBezier {
+        *self
+    }
+}
+
+// passes through every point, the open ends repeat the first and last point
+#[derive(Debug, Clone, PartialEq)]
+pub struct CatmullRom {
+    pub points: Vec<Point3<f32>>,
+    pub closed: bool,
+}
+
+impl CatmullRom {
+    pub fn new(points: Vec<Point3<f32>>, closed: bool) -> Self {
+        Self { points, closed }
+    }
+}
+
+// neighbours of segment `index` of `n` points, wrapping when closed and clamped to the ends otherwise
+fn segment_points(points: &[Point3<f32>], index: usize, closed: bool) -> [Point3<f32>; 4] {
+    let n = points.len();
+    let at = |i: isize| {
+        let i = if closed { i.rem_euclid(n as isize) } else { i.clamp(0, n as isize - 1) };
+        points[i as usize]
+    };
+    let i = index as isize;
+    [at(i - 1), at(i), at(i + 1), at(i + 2)]
+}
+
+fn piecewise_segment_count(points: usize, closed: bool) -> usize {
+    match points {
+        0 | 1 => 0,
+        n if closed => n,
+        n => n - 1,
+    }
+}
+
+impl Curve for CatmullRom {
+    fn segment_count(&self) -> usize {
+        piecewise_segment_count(self.points.len(), self.closed)
+    }
+
+    fn segment(&self, index: usize) -> CubicBezier {
+        match self.points.len() {
+            0 => CubicBezier::new(Point3::origin(), Point3::origin(), Point3::origin(), Point3::origin()),
+            1 => CubicBezier::new(self.points[0], self.points[0], self.points[0], self.points[0]),
+            _ => {
+                let [previous, from, to, next] = segment_points(&self.points, index, self.closed);
+                CubicBezier::catmull_rom(previous, from, to, next)
+            }
+        }
+    }
+}
+
+// uniform cubic B-spline. smoother than Catmull-Rom but only passes near the control points,
+// except the ends of an open spline which are held in place.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BSpline {
+    pub points: Vec<Point3<f32>>,
+    pub closed: bool,
+}
+
+impl BSpline {
+    pub fn new(points: Vec<Point3<f32>>, closed: bool) -> Self {
+        Self { points, closed }
+    }
+}
+
+impl Curve for BSpline {
+    fn segment_count(&self) -> usize {
+        piecewise_segment_count(self.points.len(), self.closed)
+    }
+
+    fn segment(&self, index: usize) -> CubicBezier {
+        match self.points.len() {
+            0 => CubicBezier::new(Point3::origin(), Point3::origin(), Point3::origin(), Point3::origin()),
+            1 => CubicBezier::new(self.points[0], self.points[0], self.points[0], self.points[0]),
+            n => {
+                let [mut p0, p1, p2, mut p3] = segment_points(&self.points, index, self.closed);
+                if !self.closed {
+                    // mirror the missing neighbours so the curve starts and ends on the end points
+                    if index == 0 {
+                        p0 = p1 + (p1 - p2);
+                    }
+                    if index + 2 >= n {
+                        p3 = p2 + (p2 - p1);
+                    }
+                }
+                CubicBezier::b_spline(p0, p1, p2, p3)
+            }
+        }
+    }
+}
+
+// a table from distance along a curve to its parameter, for constant speed motion and evenly
+// spaced samples. the accuracy depends on `samples`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ArcLength {
+    // (t, distance from the start), increasing in both
+    table: Vec<(f32, f32)>,
+}
+
+impl ArcLength {
+    pub fn new(curve: &dyn Curve, samples: usize) -> Self {
+        let samples = samples.max(1);
+        let mut table = Vec::with_capacity(samples + 1);
+        let mut previous = curve.point(0.0);
+        let mut length = 0.0;
+        table.push((0.0, 0.0));
+        for i in 1..=samples {
+            let t = i as f32 / samples as f32;
+            let point = curve.point(t);
+            length += point.distance(previous);
+            table.push((t, length));
+            previous = point;
+        }
+        Self { table }
+    }
+
+    pub fn length(&self) -> f32 {
+        self.table.last().map_or(0.0, |&(_, length)| length)
+    }
+
+    // the parameter `distance` along the curve, clamped to the ends
+    pub fn parameter(&self, distance: f32) -> f32 {
+        let distance = distance.clamp(0.0, self.length());
+        let i = self.table.partition_point(|&(_, length)| length < distance);
+        if i == 0 {
+            return 0.0;
+        }
+        let ((t0, d0), (t1, d1)) = (self.table[i - 1], self.table[i.min(self.table.len() - 1)]);
+        if d1 > d0 { t0 + (t1 - t0) * (distance - d0) / (d1 - d0) } else { t1 }
+    }
+
+    // parameters spaced `spacing` apart along the curve, including both ends
+    pub fn even_parameters(&self, spacing: f32) -> Vec<f32> {
+        let length = self.length();
+        if spacing <= 0.0 || length <= 0.0 {
+            return vec![0.0, 1.0];
+        }
+        let count = (length / spacing).ceil().max(1.0) as usize;
+        (0..=count).map(|i| self.parameter(length * i as f32 / count as f32)).collect()
+    }
+}
+
+// an orientation along a curve, for extruding road and river cross sections or aiming cameras
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CurveFrame {
+    pub position: Point3<f32>,
+    pub tangent: Vector3<f32>,
+    pub normal: Vector3<f32>,
+    pub binormal: Vector3<f32>,
+}
+
+impl CurveFrame {
+    // keeps `normal` as close to `up` as the tangent allows, so cross sections stay level.
+    // falls back to another axis when the curve runs along `up`.
+    pub fn with_up(position: Point3<f32>, tangent: Vector3<f32>, up: Vector3<f32>) -> Self {
+        let mut binormal = tangent.cross(up);
+        if binormal.magnitude2() < 1e-8 {
+            let other = if tangent.x.abs() < 0.9 { Vector3::unit_x() } else { Vector3::unit_z() };
+            binormal = tangent.cross(other);
+        }
+        let binormal = binormal.normalize();
+        Self {
+            position,
+            tangent,
+            normal: binormal.cross(tangent),
+            binormal,
+        }
+    }
+}
+
+// frames at the parameters `ts` that twist as little as possible between them (the double
+// reflection method), for tubes and curves without a natural up direction
+pub fn rotation_minimizing_frames(curve: &dyn Curve, ts: &[f32], up: Vector3<f32>) -> Vec<CurveFrame> {
+    let mut frames: Vec<CurveFrame> = Vec::with_capacity(ts.len());
+    for &t in ts {
+        let position = curve.point(t);
+        let tangent = curve.tangent(t);
+        let frame = match frames.last() {
+            None => CurveFrame::with_up(position, tangent, up),
+            Some(previous) => {
+                let v1 = position - previous.position;
+                let c1 = v1.magnitude2();
+                if c1 < 1e-12 {
+                    CurveFrame { position, ..*previous }
+                } else {
+                    let normal = previous.normal - v1 * (2.0 / c1 * v1.dot(previous.normal));
+                    let reflected_tangent = previous.tangent - v1 * (2.0 / c1 * v1.dot(previous.tangent));
+                    let v2 = tangent - reflected_tangent;
+                    let c2 = v2.magnitude2();
+                    let normal = if c2 > 1e-12 { normal - v2 * (2.0 / c2 * v2.dot(normal)) } else { normal };
+                    let normal = normal.normalize();
+                    CurveFrame {
+                        position,
+                        tangent,
+                        normal,
+                        binormal: tangent.cross(normal),
+                    }
+                }
+            }
+        };
+        frames.push(frame);
+    }
+    frames
+}
+
+// timing curve through (0, 0) and (1, 1) with two handles, like CSS `cubic-bezier`.
+// `apply` maps time to progress, for timings the `Easing` presets don't cover.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BezierEasing {
+    pub x1: f32,
+    pub y1: f32,
+    pub x2: f32,
+    pub y2: f32,
+}
+
+impl BezierEasing {
+    pub fn new(x1: f32, y1: f32, x2: f32, y2: f32) -> Self {
+        // x has to increase for the curve to be a function of time
+        Self {
+            x1: x1.clamp(0.0, 1.0),
+            y1,
+            x2: x2.clamp(0.0, 1.0),
+            y2,
+        }
+    }
+
+    fn bezier(a: f32, b: f32, s: f32) -> f32 {
+        let u = 1.0 - s;
+        3.0 * u * u * s * a + 3.0 * u * s * s * b + s * s * s
+    }
+
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        // find s with x(s) = t by bisection, x is monotonic
+        let (mut low, mut high) = (0.0, 1.0);
+        let mut s = t;
+        for _ in 0..24 {
+            let x = Self::bezier(self.x1, self.x2, s);
+            if (x - t).abs() < 1e-6 {
+                break;
+            }
+            if x < t {
+                low = s;
+            } else {
+                high = s;
+            }
+            s = (low + high) * 0.5;
+        }
+        Self::bezier(self.y1, self.y2, s)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: Point3<f32>, b: Point3<f32>) -> bool {
+        a.distance(b) < 1e-4
+    }
+
+    fn points() -> Vec<Point3<f32>> {
+        vec![
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 2.0, 0.0),
+            Point3::new(3.0, 2.0, 1.0),
+            Point3::new(4.0, 0.0, 1.0),
+        ]
+    }
+
+    #[test]
+    fn bezier_passes_through_its_ends() {
+        let p = points();
+        let bezier = CubicBezier::new(p[0], p[1], p[2], p[3]);
+        assert!(close(bezier.evaluate(0.0), p[0]));
+        assert!(close(bezier.evaluate(1.0), p[3]));
+        // the handles set the end tangents
+        assert!((bezier.velocity(0.0) - (p[1] - p[0]) * 3.0).magnitude() < 1e-4);
+        assert!((bezier.velocity(1.0) - (p[3] - p[2]) * 3.0).magnitude() < 1e-4);
+    }
+
+    #[test]
+    fn velocity_matches_finite_differences() {
+        let p = points();
+        let bezier = CubicBezier::new(p[0], p[1], p[2], p[3]);
+        let h = 1e-3;
+        for &t in &[0.2, 0.5, 0.8] {
+            let numeric = (bezier.evaluate(t + h) - bezier.evaluate(t - h)) / (2.0 * h);
+            assert!((numeric - bezier.velocity(t)).magnitude() < 1e-2);
+            let numeric = (bezier.velocity(t + h) - bezier.velocity(t - h)) / (2.0 * h);
+            assert!((numeric - bezier.acceleration(t)).magnitude() < 1e-2);
+        }
+    }
+
+    #[test]
+    fn catmull_rom_passes_through_every_point() {
+        let p = points();
+        let curve = CatmullRom::new(p.clone(), false);
+        assert_eq!(curve.segment_count(), 3);
+        for (i, &point) in p.iter().enumerate() {
+            assert!(close(curve.point(i as f32 / 3.0), point));
+        }
+
+        let closed = CatmullRom::new(p.clone(), true);
+        assert_eq!(closed.segment_count(), 4);
+        assert!(close(closed.point(1.0), p[0]));
+    }
+
+    #[test]
+    fn open_b_spline_is_held_at_the_ends() {
+        let p = points();
+        let curve = BSpline::new(p.clone(), false);
+        assert!(close(curve.point(0.0), p[0]));
+        assert!(close(curve.point(1.0), p[3]));
+        // but only passes near the inner points
+        assert!(!close(curve.point(1.0 / 3.0), p[1]));
+    }
+
+    #[test]
+    fn degenerate_curves_stay_in_place() {
+        assert_eq!(CatmullRom::new(vec![], false).segment_count(), 0);
+        let single = BSpline::new(vec![Point3::new(1.0, 2.0, 3.0)], false);
+        assert!(close(single.point(0.5), Point3::new(1.0, 2.0, 3.0)));
+    }
+
+    #[test]
+    fn curves_that_do_not_move_have_no_tangent() {
+        let single = BSpline::new(vec![Point3::new(1.0, 2.0, 3.0)], false);
+        assert_eq!(single.tangent(0.5), Vector3::new(0.0, 0.0, 0.0));
+        let still = CatmullRom::new(vec![Point3::new(1.0, 0.0, 0.0); 3], false);
+        assert_eq!(still.tangent(0.25), Vector3::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn arc_length_of_a_straight_line() {
+        let line = CubicBezier::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Point3::new(1.0, 0.0, 0.0),
+            Point3::new(2.0, 0.0, 0.0),
+            Point3::new(3.0, 0.0, 0.0),
+        );
+        let arc = ArcLength::new(&line, 64);
+        assert!((arc.length() - 3.0).abs() < 1e-4);
+        assert!((line.point(arc.parameter(1.5)).x - 1.5).abs() < 1e-3);
+        assert_eq!(arc.parameter(-1.0), 0.0);
+        assert!((arc.parameter(10.0) - 1.0).abs() < 1e-6);
+
+        let ts = arc.even_parameters(1.0);
+        assert_eq!(ts.len(), 4);
+        for (i, &t) in ts.iter().enumerate() {
+            assert!((line.point(t).x - i as f32).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn frames_are_orthonormal() {
+        let curve = CatmullRom::new(points(), false);
+        let ts: Vec<f32> = (0..=16).map(|i| i as f32 / 16.0).collect();
+        let frames = rotation_minimizing_frames(&curve, &ts, Vector3::unit_y());
+        assert_eq!(frames.len(), ts.len());
+        for frame in frames {
+            assert!((frame.tangent.magnitude() - 1.0).abs() < 1e-3);
+            assert!((frame.normal.magnitude() - 1.0).abs() < 1e-3);
+            assert!(frame.tangent.dot(frame.normal).abs() < 1e-3);
+            assert!(frame.binormal.dot(frame.normal).abs() < 1e-3);
+        }
+
+        // straight up still gets a frame
+        let frame = CurveFrame::with_up(Point3::origin(), Vector3::unit_y(), Vector3::unit_y());
+        assert!((frame.normal.magnitude() - 1.0).abs() < 1e-4);
+    }
+
+    #[test]
+    fn easing_hits_its_ends() {
+        let ease = BezierEasing::new(0.42, 0.0, 0.58, 1.0);
+        assert!(ease.apply(0.0).abs() < 1e-4);
+        assert!((ease.apply(1.0) - 1.0).abs() < 1e-4);
+        // symmetric curve
+        assert!((ease.apply(0.5) - 0.5).abs() < 1e-3);
+        assert!(ease.apply(0.25) < 0.25);
+
+        let linear = BezierEasing::new(0.0, 0.0, 1.0, 1.0);
+        assert!((linear.apply(0.3) - 0.3).abs() < 0.05);
+    }
+}
@@ -0,0 +1,74 @@
+use gl::types::*;
+use glfw::{Glfw, WindowHint};
+
+use crate::context::check_render_thread;
+use crate::GlContext;
+
+// how linear scene colors are encoded when written to the final target
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum OutputEncoding {
+    // written as is, for targets that store linear values
+    #[default]
+    Linear,
+    // converted by the hardware with GL_FRAMEBUFFER_SRGB, the target must be sRGB-capable
+    Srgb,
+    // converted in a final shader pass, for default framebuffers without sRGB support
+    Gamma(f32),
+}
+
+impl OutputEncoding {
+    // the best encoding for the default framebuffer of the current context
+    pub fn detect(context: &GlContext) -> Self {
+        if default_framebuffer_is_srgb(context) {
+            OutputEncoding::Srgb
+        } else {
+            OutputEncoding::Gamma(2.2)
+        }
+    }
+
+    pub(crate) unsafe fn write<F: FnOnce()>(self, f: F) {
+        match self {
+            OutputEncoding::Srgb => srgb_writes(f),
+            _ => f(),
+        }
+    }
+}
+
+// call before creating the window. not every platform honors the hint, check with `default_framebuffer_is_srgb`.
+pub fn request_srgb_framebuffer(glfw: &mut Glfw) {
+    glfw.window_hint(WindowHint::SRgbCapable(true));
+}
+
+pub fn default_framebuffer_is_srgb(_context: &GlContext) -> bool {
+    check_render_thread("default_framebuffer_is_srgb");
+    let mut encoding = 0;
+    unsafe {
+        let mut previous = 0;
+        gl::GetIntegerv(gl::DRAW_FRAMEBUFFER_BINDING, &mut previous);
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, 0);
+        gl::GetFramebufferAttachmentParameteriv(
+            gl::DRAW_FRAMEBUFFER,
+            gl::BACK_LEFT,
+            gl::FRAMEBUFFER_ATTACHMENT_COLOR_ENCODING,
+            &mut encoding,
+        );
+        gl::BindFramebuffer(gl::DRAW_FRAMEBUFFER, conv!(previous));
+    }
+    encoding as GLenum == gl::SRGB
+}
+
+// enables the hardware conversion only while `f` draws, so intermediate targets stay linear
+pub fn with_srgb_writes<F: FnOnce()>(_context: &GlContext, f: F) {
+    check_render_thread("with_srgb_writes");
+    unsafe { srgb_writes(f) }
+}
+
+// leaves GL_FRAMEBUFFER_SRGB as it was, a caller may have it enabled already
+unsafe fn srgb_writes<F: FnOnce()>(f: F) {
+    let enabled = gl::IsEnabled(gl::FRAMEBUFFER_SRGB) == gl::TRUE;
+    gl::Enable(gl::FRAMEBUFFER_SRGB);
+    f();
+    if !enabled {
+        gl::Disable(gl::FRAMEBUFFER_SRGB);
+    }
+}
@@ -0,0 +1,300 @@
+use cgmath::{Matrix4, Point3};
+use gl::types::*;
+
+use crate::fog::fog_glsl;
+use crate::light::cookie_glsl;
+use crate::{c_str, DirectionalLight, FogSettings, PointLight, Shader, SpotLight};
+
+// a literal so the fragment shader's `#define` can be spliced in with `concat!`
+macro_rules! max_point_lights {
+    () => {
+        16
+    };
+}
+
+pub const MAX_POINT_LIGHTS: usize = max_point_lights!();
+
+// out of the way of the mesh textures, which start from unit 0
+const SPOT_COOKIE_UNIT: GLuint = 15;
+
+pub const STANDARD_VERTEX_SHADER: &str = r#"
+#version 330 core
+
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec3 aNormal;
+layout (location = 2) in vec2 aTexCoord;
+layout (location = 7) in vec4 aColor;
+layout (location = 8) in vec2 aLightmapCoord;
+layout (location = 9) in vec4 aTangent;
+
+out vec3 FragPos;
+out vec3 Normal;
+out vec2 TexCoords;
+out vec4 VertexColor;
+out vec2 LightmapCoords;
+out vec4 Tangent;
+
+uniform mat4 model;
+uniform mat4 view;
+uniform mat4 projection;
+
+void main() {
+    gl_Position = projection * view * model * vec4(aPos, 1.0);
+    FragPos = vec3(model * vec4(aPos, 1.0));
+    Normal = mat3(transpose(inverse(model))) * aNormal;
+    TexCoords = aTexCoord;
+    VertexColor = aColor;
+    LightmapCoords = aLightmapCoord;
+    Tangent = vec4(mat3(model) * aTangent.xyz, aTangent.w);
+}
+"#;
+
+pub const STANDARD_FRAGMENT_SHADER: &str = concat!(
+    r#"
+#version 330 core
+
+struct Material {
+    sampler2D texture_diffuse1;
+    sampler2D texture_specular1;
+    sampler2D texture_normal1;
+    sampler2D texture_emissive1;
+    sampler2D texture_ambient1;
+    sampler2D texture_lightmap1;
+    sampler2D texture_occlusion1;
+    bool hasNormalMap;
+    bool hasEmissiveMap;
+    bool hasAmbientMap;
+    bool hasLightmap;
+    bool hasOcclusionMap;
+    float shininess;
+};
+
+struct DirectionalLight {
+    vec3 direction;
+
+    vec3 ambient;
+    vec3 diffuse;
+    vec3 specular;
+};
+
+struct PointLight {
+    vec3 position;
+
+    vec3 ambient;
+    vec3 diffuse;
+    vec3 specular;
+
+    float constant;
+    float linear;
+    float quadratic;
+};
+
+struct SpotLight {
+    vec3 position;
+    vec3 direction;
+
+    // in cosine
+    float cutOff;
+    float outerCutOff;
+
+    vec3 ambient;
+    vec3 diffuse;
+    vec3 specular;
+
+    bool hasCookie;
+    mat4 projector;
+};
+"#,
+    fog_glsl!(),
+    cookie_glsl!(),
+    "\n#define MAX_POINT_LIGHTS ",
+    max_point_lights!(),
+    r#"
+
+in vec2 TexCoords;
+in vec3 Normal;
+in vec3 FragPos;
+in vec4 VertexColor;
+in vec2 LightmapCoords;
+in vec4 Tangent;
+layout (location = 0) out vec4 FragColor;
+// the light the surface emits itself, for a bloom bright pass. dropped without a second color attachment.
+layout (location = 1) out vec4 EmissiveColor;
+
+uniform vec3 cameraPos;
+
+uniform Material material;
+
+uniform bool hasDirLight;
+uniform DirectionalLight dirLight;
+uniform int pointLightCount;
+uniform PointLight pointLights[MAX_POINT_LIGHTS];
+uniform bool hasSpotLight;
+uniform SpotLight spotLight;
+uniform sampler2D spotLightCookie;
+
+// tangent space normal mapping, with the vertex tangents when the mesh has them
+vec3 PerturbNormal(vec3 normal, vec3 position, vec2 uv) {
+    vec3 mapped = texture(material.texture_normal1, uv).xyz * 2.0 - 1.0;
+    if (dot(Tangent.xyz, Tangent.xyz) > 0.0) {
+        // mikktspace: the interpolated frame is used as is, without normalizing
+        vec3 bitangent = Tangent.w * cross(normal, Tangent.xyz);
+        return normalize(mapped.x * Tangent.xyz + mapped.y * bitangent + mapped.z * normal);
+    }
+
+    // otherwise the frame comes from the screen space derivatives
+    vec3 dp1 = dFdx(position);
+    vec3 dp2 = dFdy(position);
+    vec2 duv1 = dFdx(uv);
+    vec2 duv2 = dFdy(uv);
+
+    vec3 dp2perp = cross(dp2, normal);
+    vec3 dp1perp = cross(normal, dp1);
+    vec3 tangent = dp2perp * duv1.x + dp1perp * duv2.x;
+    vec3 bitangent = dp2perp * duv1.y + dp1perp * duv2.y;
+    float invmax = inversesqrt(max(dot(tangent, tangent), dot(bitangent, bitangent)));
+    mat3 tbn = mat3(tangent * invmax, bitangent * invmax, normal);
+    return normalize(tbn * mapped);
+}
+
+// map_Ka when present, otherwise the diffuse color like Ka = Kd. darkened by baked occlusion.
+vec3 AmbientColor() {
+    float occlusion = material.hasOcclusionMap ? texture(material.texture_occlusion1, LightmapCoords).r : 1.0;
+    if (material.hasAmbientMap) {
+        return texture(material.texture_ambient1, TexCoords).rgb * VertexColor.rgb * occlusion;
+    }
+    return texture(material.texture_diffuse1, TexCoords).rgb * VertexColor.rgb * occlusion;
+}
+
+vec3 Shade(vec3 ambient, vec3 diffuse, vec3 specular, vec3 lightDir, vec3 normal, vec3 viewDir) {
+    vec3 diffuseColor = texture(material.texture_diffuse1, TexCoords).rgb * VertexColor.rgb;
+    vec3 specularColor = texture(material.texture_specular1, TexCoords).rgb;
+
+    float diff = max(dot(normal, lightDir), 0.0);
+    vec3 reflectDir = reflect(-lightDir, normal);
+    float spec = pow(max(dot(viewDir, reflectDir), 0.0), material.shininess);
+
+    return ambient * AmbientColor() + diffuse * diff * diffuseColor + specular * spec * specularColor;
+}
+
+vec3 CalcDirectionalLight(DirectionalLight light, vec3 normal, vec3 viewDir) {
+    return Shade(light.ambient, light.diffuse, light.specular, normalize(-light.direction), normal, viewDir);
+}
+
+vec3 CalcPointLight(PointLight light, vec3 normal, vec3 viewDir) {
+    float distance = length(light.position - FragPos);
+    float attenuation = 1.0 / (light.constant + light.linear * distance + light.quadratic * distance * distance);
+
+    vec3 lightDir = normalize(light.position - FragPos);
+    return attenuation * Shade(light.ambient, light.diffuse, light.specular, lightDir, normal, viewDir);
+}
+
+vec3 CalcSpotLight(SpotLight light, vec3 normal, vec3 viewDir) {
+    vec3 lightDir = normalize(light.position - FragPos);
+    float theta = dot(-lightDir, normalize(light.direction));
+    float epsilon = light.cutOff - light.outerCutOff;
+    float intensity = clamp((theta - light.outerCutOff) / epsilon, 0.0, 1.0);
+
+    vec3 cookie = light.hasCookie ? SampleCookie(spotLightCookie, light.projector, FragPos) : vec3(1.0);
+
+    vec3 ambient = light.ambient * AmbientColor();
+    return ambient + intensity * cookie * Shade(vec3(0.0), light.diffuse, light.specular, lightDir, normal, viewDir);
+}
+
+void main() {
+    vec3 norm = normalize(Normal);
+    if (material.hasNormalMap) {
+        norm = PerturbNormal(norm, FragPos, TexCoords);
+    }
+    vec3 viewDir = normalize(cameraPos - FragPos);
+
+    vec3 result = vec3(0.0);
+    if (hasDirLight) {
+        result += CalcDirectionalLight(dirLight, norm, viewDir);
+    }
+    for (int i = 0; i < min(pointLightCount, MAX_POINT_LIGHTS); i++) {
+        result += CalcPointLight(pointLights[i], norm, viewDir);
+    }
+    if (hasSpotLight) {
+        result += CalcSpotLight(spotLight, norm, viewDir);
+    }
+
+    // baked static lighting, on top of the lights that were not baked
+    if (material.hasLightmap) {
+        vec3 albedo = texture(material.texture_diffuse1, TexCoords).rgb * VertexColor.rgb;
+        result += texture(material.texture_lightmap1, LightmapCoords).rgb * albedo;
+    }
+
+    vec3 emissive = material.hasEmissiveMap ? texture(material.texture_emissive1, TexCoords).rgb : vec3(0.0);
+    result += emissive;
+
+    result = ApplyFog(result, length(cameraPos - FragPos));
+    FragColor = vec4(result, 1.0);
+    EmissiveColor = vec4(emissive, 1.0);
+}
+"#
+);
+
+/// the forward material shader used with `Model::load_obj` output
+///
+/// # Safety
+///
+/// a GL context must be current on the calling thread.
+pub unsafe fn standard_shader() -> Shader {
+    Shader::from_str(STANDARD_VERTEX_SHADER, STANDARD_FRAGMENT_SHADER)
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct StandardLights {
+    pub directional: Option<DirectionalLight>,
+    // only the first MAX_POINT_LIGHTS are used
+    pub points: Vec<PointLight>,
+    pub spot: Option<SpotLight>,
+}
+
+impl StandardLights {
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread and `shader` must be in use, the values are set with glUniform.
+    pub unsafe fn set_uniforms(&self, shader: &Shader) {
+        shader.set_integer(c_str("hasDirLight\0"), self.directional.is_some() as i32);
+        if let Some(light) = &self.directional {
+            light.set_uniforms(shader, "dirLight");
+        }
+
+        let count = self.points.len().min(MAX_POINT_LIGHTS);
+        shader.set_integer(c_str("pointLightCount\0"), conv!(count));
+        for (i, light) in self.points.iter().take(count).enumerate() {
+            light.set_uniforms(shader, &format!("pointLights[{}]", i));
+        }
+
+        shader.set_integer(c_str("hasSpotLight\0"), self.spot.is_some() as i32);
+        if let Some(light) = &self.spot {
+            light.set_uniforms(shader, "spotLight");
+            light.bind_cookie(shader, c_str("spotLightCookie\0"), SPOT_COOKIE_UNIT);
+            gl::ActiveTexture(gl::TEXTURE0);
+        }
+    }
+}
+
+/// uploads everything the standard shader needs apart from `model` and the material
+///
+/// # Safety
+///
+/// a GL context must be current on the calling thread and `shader` must be in use, the values are set with glUniform.
+pub unsafe fn set_standard_uniforms(
+    shader: &Shader,
+    view: &Matrix4<f32>,
+    projection: &Matrix4<f32>,
+    camera_position: Point3<f32>,
+    lights: &StandardLights,
+    fog: &FogSettings,
+) {
+    shader.set_matrix4(c_str("view\0"), view);
+    shader.set_matrix4(c_str("projection\0"), projection);
+    shader.set_vec3(c_str("cameraPos\0"), camera_position.x, camera_position.y, camera_position.z);
+    lights.set_uniforms(shader);
+    fog.set_uniforms(shader);
+    // the mesh binds the textures, the shininess is not part of the OBJ import yet
+    shader.set_float(c_str("material.shininess\0"), 32.0);
+}
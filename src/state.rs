@@ -0,0 +1,345 @@
+use glfw::WindowEvent;
+
+use crate::Time;
+
+// what the stack does after a state's update
+pub enum Transition<C> {
+    None,
+    // covers the state with a new one, e.g. a pause menu over gameplay
+    Push(Box<dyn GameState<C>>),
+    Pop,
+    // swaps the top state, e.g. the title screen for gameplay
+    Replace(Box<dyn GameState<C>>),
+    // pops every state
+    Quit,
+}
+
+impl<C> std::fmt::Debug for Transition<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Transition::None => "None",
+            Transition::Push(_) => "Push",
+            Transition::Pop => "Pop",
+            Transition::Replace(_) => "Replace",
+            Transition::Quit => "Quit",
+        })
+    }
+}
+
+// a screen of the game. `C` is whatever the game hands every state, e.g. its assets and renderer.
+pub trait GameState<C> {
+    fn enter(&mut self, _context: &mut C) {}
+
+    fn exit(&mut self, _context: &mut C) {}
+
+    // another state was pushed on top
+    fn cover(&mut self, _context: &mut C) {}
+
+    // the state on top was popped
+    fn uncover(&mut self, _context: &mut C) {}
+
+    fn update(&mut self, context: &mut C, time: &Time) -> Transition<C>;
+
+    fn render(&mut self, _context: &mut C) {}
+
+    // true consumes the event, false passes it to the state below
+    fn process_event(&mut self, _context: &mut C, _event: &WindowEvent) -> bool {
+        false
+    }
+
+    // whether the states below keep updating while this one is on top. false pauses them.
+    fn update_below(&self) -> bool {
+        false
+    }
+
+    // whether the states below are drawn first, e.g. behind a translucent menu
+    fn render_below(&self) -> bool {
+        false
+    }
+}
+
+// the active game states, the last one on top
+pub struct StateStack<C> {
+    states: Vec<Box<dyn GameState<C>>>,
+}
+
+impl<C> std::fmt::Debug for StateStack<C> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StateStack").field("len", &self.states.len()).finish()
+    }
+}
+
+impl<C> Default for StateStack<C> {
+    fn default() -> Self {
+        Self { states: vec![] }
+    }
+}
+
+impl<C> StateStack<C> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+
+    // the game quits when this turns true
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+
+    pub fn push(&mut self, context: &mut C, mut state: Box<dyn GameState<C>>) {
+        if let Some(top) = self.states.last_mut() {
+            top.cover(context);
+        }
+        state.enter(context);
+        self.states.push(state);
+    }
+
+    pub fn pop(&mut self, context: &mut C) -> Option<Box<dyn GameState<C>>> {
+        let mut state = self.states.pop()?;
+        state.exit(context);
+        if let Some(top) = self.states.last_mut() {
+            top.uncover(context);
+        }
+        Some(state)
+    }
+
+    // swaps the top state without uncovering the one below
+    pub fn replace(&mut self, context: &mut C, mut state: Box<dyn GameState<C>>) -> Option<Box<dyn GameState<C>>> {
+        let old = self.states.pop().map(|mut old| {
+            old.exit(context);
+            old
+        });
+        state.enter(context);
+        self.states.push(state);
+        old
+    }
+
+    pub fn clear(&mut self, context: &mut C) {
+        while let Some(mut state) = self.states.pop() {
+            state.exit(context);
+        }
+    }
+
+    pub fn apply(&mut self, context: &mut C, transition: Transition<C>) {
+        match transition {
+            Transition::None => {}
+            Transition::Push(state) => self.push(context, state),
+            Transition::Pop => {
+                self.pop(context);
+            }
+            Transition::Replace(state) => {
+                self.replace(context, state);
+            }
+            Transition::Quit => self.clear(context),
+        }
+    }
+
+    // applies a transition returned by the state at `index` to that state instead of the top.
+    // states above it stay where they are and are not covered or uncovered.
+    fn apply_at(&mut self, context: &mut C, index: usize, transition: Transition<C>) {
+        if index + 1 >= self.states.len() {
+            return self.apply(context, transition);
+        }
+        match transition {
+            Transition::None => {}
+            Transition::Push(mut state) => {
+                state.enter(context);
+                self.states.insert(index + 1, state);
+            }
+            Transition::Pop => self.states.remove(index).exit(context),
+            Transition::Replace(mut state) => {
+                self.states[index].exit(context);
+                state.enter(context);
+                self.states[index] = state;
+            }
+            Transition::Quit => self.clear(context),
+        }
+    }
+
+    // the lowest state for which `keep` holds of every state above it
+    fn lowest(&self, keep: impl Fn(&dyn GameState<C>) -> bool) -> usize {
+        let mut lowest = self.states.len().saturating_sub(1);
+        while lowest > 0 && keep(self.states[lowest].as_ref()) {
+            lowest -= 1;
+        }
+        lowest
+    }
+
+    // updates the top state and the ones below it lets run, from the bottom up. each transition
+    // is applied afterwards to the state that returned it, from the top down so the indices of
+    // the states below are not moved by the ones above.
+    pub fn update(&mut self, context: &mut C, time: &Time) {
+        let lowest = self.lowest(|state| state.update_below());
+        let mut transitions = vec![];
+        for (index, state) in self.states.iter_mut().enumerate().skip(lowest) {
+            transitions.push((index, state.update(context, time)));
+        }
+        for (index, transition) in transitions.into_iter().rev() {
+            if index < self.states.len() {
+                self.apply_at(context, index, transition);
+            }
+        }
+    }
+
+    // renders the top state over the ones it shows, from the bottom up
+    pub fn render(&mut self, context: &mut C) {
+        let lowest = self.lowest(|state| state.render_below());
+        for state in self.states.iter_mut().skip(lowest) {
+            state.render(context);
+        }
+    }
+
+    // offers the event to the states from the top down until one consumes it. returns whether one did.
+    pub fn process_event(&mut self, context: &mut C, event: &WindowEvent) -> bool {
+        self.states.iter_mut().rev().any(|state| state.process_event(context, event))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+
+    use super::*;
+
+    // logs its callbacks and returns the scripted transitions, then None
+    struct Scripted {
+        name: &'static str,
+        transitions: VecDeque<Transition<Vec<String>>>,
+        update_below: bool,
+        render_below: bool,
+    }
+
+    fn state(name: &'static str) -> Scripted {
+        Scripted {
+            name,
+            transitions: VecDeque::new(),
+            update_below: false,
+            render_below: false,
+        }
+    }
+
+    impl Scripted {
+        fn then(mut self, transition: Transition<Vec<String>>) -> Self {
+            self.transitions.push_back(transition);
+            self
+        }
+
+        fn boxed(self) -> Box<dyn GameState<Vec<String>>> {
+            Box::new(self)
+        }
+    }
+
+    impl GameState<Vec<String>> for Scripted {
+        fn enter(&mut self, log: &mut Vec<String>) {
+            log.push(format!("{} enter", self.name));
+        }
+
+        fn exit(&mut self, log: &mut Vec<String>) {
+            log.push(format!("{} exit", self.name));
+        }
+
+        fn cover(&mut self, log: &mut Vec<String>) {
+            log.push(format!("{} cover", self.name));
+        }
+
+        fn uncover(&mut self, log: &mut Vec<String>) {
+            log.push(format!("{} uncover", self.name));
+        }
+
+        fn update(&mut self, log: &mut Vec<String>, _time: &Time) -> Transition<Vec<String>> {
+            log.push(format!("{} update", self.name));
+            self.transitions.pop_front().unwrap_or(Transition::None)
+        }
+
+        fn render(&mut self, log: &mut Vec<String>) {
+            log.push(format!("{} render", self.name));
+        }
+
+        fn process_event(&mut self, log: &mut Vec<String>, _event: &WindowEvent) -> bool {
+            log.push(format!("{} event", self.name));
+            self.name == "menu"
+        }
+
+        fn update_below(&self) -> bool {
+            self.update_below
+        }
+
+        fn render_below(&self) -> bool {
+            self.render_below
+        }
+    }
+
+    #[test]
+    fn push_and_pop_cover_and_uncover() {
+        let (mut stack, mut log) = (StateStack::new(), vec![]);
+        stack.push(&mut log, state("game").boxed());
+        stack.push(&mut log, state("pause").boxed());
+        assert_eq!(stack.len(), 2);
+        stack.pop(&mut log);
+        stack.replace(&mut log, state("title").boxed());
+        assert_eq!(log, vec!["game enter", "game cover", "pause enter", "pause exit", "game uncover", "game exit", "title enter"]);
+
+        log.clear();
+        stack.push(&mut log, state("menu").boxed());
+        stack.clear(&mut log);
+        assert!(stack.is_empty());
+        assert_eq!(log, vec!["title cover", "menu enter", "menu exit", "title exit"]);
+    }
+
+    #[test]
+    fn only_the_top_and_what_it_lets_through_updates() {
+        let (mut stack, mut log) = (StateStack::new(), vec![]);
+        stack.push(&mut log, state("game").boxed());
+        stack.push(&mut log, state("hud").boxed());
+        stack.push(&mut log, Scripted { update_below: true, render_below: true, ..state("menu") }.boxed());
+        log.clear();
+
+        stack.update(&mut log, &Time::new());
+        stack.render(&mut log);
+        assert_eq!(log, vec!["hud update", "menu update", "hud render", "menu render"]);
+
+        log.clear();
+        stack.process_event(&mut log, &WindowEvent::Focus(true));
+        assert_eq!(log, vec!["menu event"]);
+        stack.pop(&mut log);
+        log.clear();
+        assert!(!stack.process_event(&mut log, &WindowEvent::Focus(true)));
+        assert_eq!(log, vec!["hud event", "game event"]);
+    }
+
+    #[test]
+    fn transitions_apply_to_the_state_that_returned_them() {
+        let (mut stack, mut log) = (StateStack::new(), vec![]);
+        stack.push(&mut log, state("game").then(Transition::Replace(state("level2").boxed())).boxed());
+        stack.push(&mut log, Scripted { update_below: true, ..state("hud") }.boxed());
+        log.clear();
+
+        stack.update(&mut log, &Time::new());
+        assert_eq!(log, vec!["game update", "hud update", "game exit", "level2 enter"]);
+        // the hud is still on top
+        log.clear();
+        stack.update(&mut log, &Time::new());
+        assert_eq!(log, vec!["level2 update", "hud update"]);
+
+        // a state below popping itself leaves the top alone
+        let (mut stack, mut log) = (StateStack::new(), vec![]);
+        stack.push(&mut log, state("game").then(Transition::Pop).boxed());
+        stack.push(&mut log, Scripted { update_below: true, ..state("hud") }.then(Transition::Push(state("menu").boxed())).boxed());
+        log.clear();
+        stack.update(&mut log, &Time::new());
+        assert_eq!(log, vec!["game update", "hud update", "hud cover", "menu enter", "game exit"]);
+        assert_eq!(stack.len(), 2);
+    }
+
+    #[test]
+    fn quit_empties_the_stack() {
+        let (mut stack, mut log) = (StateStack::new(), vec![]);
+        stack.push(&mut log, state("game").then(Transition::Quit).boxed());
+        stack.push(&mut log, Scripted { update_below: true, ..state("hud") }.then(Transition::Pop).boxed());
+        stack.update(&mut log, &Time::new());
+        assert!(stack.is_empty());
+    }
+}
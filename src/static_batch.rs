@@ -0,0 +1,77 @@
+use cgmath::Matrix4;
+use gl::types::*;
+
+use crate::{GlContext, Mesh, MeshData, Model, Shader, Texture, TextureType};
+
+// level geometry that never moves, pre-transformed and merged into one mesh per
+// set of textures so it draws in a handful of calls
+#[derive(Debug)]
+pub struct StaticBatch {
+    batches: Vec<Mesh>,
+}
+
+// the meshes gathered for one set of textures
+struct Group {
+    key: Vec<(GLuint, TextureType)>,
+    textures: Vec<Texture>,
+    meshes: Vec<MeshData>,
+}
+
+impl StaticBatch {
+    // merges the visible meshes of every model placed by its matrix. meshes with the
+    // same textures in the same roles end up in the same batch.
+    pub fn build(context: &GlContext, models: &[(Model, Matrix4<f32>)]) -> Self {
+        let mut groups: Vec<Group> = vec![];
+
+        for (model, model_matrix) in models.iter() {
+            for (index, node) in model.nodes.iter().enumerate() {
+                if !model.is_visible(index) {
+                    continue;
+                }
+                let transform = model_matrix * model.node_transform(index);
+
+                for &mesh in node.meshes.iter() {
+                    let mesh = &model.meshes[mesh];
+                    let key: Vec<_> = mesh.textures.iter().map(|texture| (texture.id(), texture.type_())).collect();
+                    let group = match groups.iter().position(|group| group.key == key) {
+                        Some(group) => group,
+                        None => {
+                            groups.push(Group {
+                                key,
+                                textures: mesh.textures.clone(),
+                                meshes: vec![],
+                            });
+                            groups.len() - 1
+                        }
+                    };
+                    groups[group].meshes.push(MeshData::from_mesh(mesh).transform(&transform));
+                }
+            }
+        }
+
+        let batches = groups
+            .into_iter()
+            .map(|group| MeshData::merge(&group.meshes).upload(context, group.textures))
+            .collect();
+        Self { batches }
+    }
+
+    // the `model` uniform is left to the caller, the geometry is already in world space
+    pub fn draw(&self, context: &GlContext, shader: &Shader) {
+        for batch in self.batches.iter() {
+            batch.draw(context, shader);
+        }
+    }
+
+    pub fn meshes(&self) -> &[Mesh] {
+        &self.batches
+    }
+
+    pub fn draw_calls(&self) -> usize {
+        self.batches.len()
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.batches.iter().map(Mesh::triangle_count).sum()
+    }
+}
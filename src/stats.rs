@@ -0,0 +1,134 @@
+use std::cell::Cell;
+use std::fmt;
+
+// what a culling test decided for one object
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CullResult {
+    Visible,
+    FrustumCulled,
+    OcclusionCulled,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CullingStats {
+    pub tested: u32,
+    pub frustum_culled: u32,
+    pub occlusion_culled: u32,
+    // passed every test. may be less than the draw calls when objects are not culled at all.
+    pub visible: u32,
+}
+
+const EMPTY_CULLING: CullingStats = CullingStats {
+    tested: 0,
+    frustum_culled: 0,
+    occlusion_culled: 0,
+    visible: 0,
+};
+
+// counters of the work submitted through the crate since the last `end_frame`.
+// raw GL calls made outside the crate are not seen unless reported with the `record_*` functions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FrameStats {
+    pub draw_calls: u32,
+    pub instances: u64,
+    pub triangles: u64,
+    pub texture_binds: u32,
+    pub buffer_uploads: u32,
+    pub uploaded_bytes: u64,
+    pub culling: CullingStats,
+}
+
+thread_local! {
+    static STATS: Cell<FrameStats> = const { Cell::new(FrameStats {
+        draw_calls: 0,
+        instances: 0,
+        triangles: 0,
+        texture_binds: 0,
+        buffer_uploads: 0,
+        uploaded_bytes: 0,
+        culling: EMPTY_CULLING,
+    }) };
+}
+
+fn update<F: FnOnce(&mut FrameStats)>(f: F) {
+    STATS.with(|stats| {
+        let mut current = stats.get();
+        f(&mut current);
+        stats.set(current);
+    });
+}
+
+impl FrameStats {
+    // the counts of the frame in progress
+    pub fn current() -> Self {
+        STATS.with(|stats| stats.get())
+    }
+
+    // returns the counts of the finished frame and starts counting the next one
+    pub fn end_frame() -> Self {
+        STATS.with(|stats| stats.replace(Self::default()))
+    }
+
+    pub fn record_draw(triangles: usize, instances: usize) {
+        update(|stats| {
+            stats.draw_calls += 1;
+            stats.instances += instances as u64;
+            stats.triangles += (triangles * instances) as u64;
+        });
+    }
+
+    pub fn record_texture_bind() {
+        update(|stats| stats.texture_binds += 1);
+    }
+
+    pub fn record_buffer_upload(bytes: usize) {
+        update(|stats| {
+            stats.buffer_uploads += 1;
+            stats.uploaded_bytes += bytes as u64;
+        });
+    }
+
+    // called by the culling code once per tested object
+    pub fn record_cull(result: CullResult) {
+        match result {
+            CullResult::Visible => Self::record_culling(1, 0, 0),
+            CullResult::FrustumCulled => Self::record_culling(1, 1, 0),
+            CullResult::OcclusionCulled => Self::record_culling(1, 0, 1),
+        }
+    }
+
+    // for culling done in bulk, e.g. counts read back from the GPU
+    pub fn record_culling(tested: u32, frustum_culled: u32, occlusion_culled: u32) {
+        update(|stats| {
+            let culling = &mut stats.culling;
+            culling.tested += tested;
+            culling.frustum_culled += frustum_culled;
+            culling.occlusion_culled += occlusion_culled;
+            culling.visible += tested.saturating_sub(frustum_culled + occlusion_culled);
+        });
+    }
+}
+
+impl fmt::Display for FrameStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "draw calls: {}, instances: {}, triangles: {}, texture binds: {}, buffer uploads: {} ({} bytes)",
+            self.draw_calls, self.instances, self.triangles, self.texture_binds, self.buffer_uploads, self.uploaded_bytes,
+        )?;
+        if self.culling.tested > 0 {
+            write!(f, ", {}", self.culling)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for CullingStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "culling: {} tested, {} frustum culled, {} occlusion culled, {} visible",
+            self.tested, self.frustum_culled, self.occlusion_culled, self.visible,
+        )
+    }
+}
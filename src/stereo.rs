@@ -0,0 +1,181 @@
+use cgmath::{frustum, Matrix4, vec3};
+
+use crate::post::bind_texture;
+use crate::{c_str, Camera, Framebuffer, FullscreenQuad, Shader, Viewport, FULLSCREEN_VERTEX_SHADER};
+
+const ANAGLYPH_FRAGMENT_SHADER: &str = r#"
+#version 330 core
+
+in vec2 TexCoords;
+out vec4 FragColor;
+
+// both eyes side by side
+uniform sampler2D stereo;
+
+void main() {
+    vec3 left = texture(stereo, vec2(TexCoords.x * 0.5, TexCoords.y)).rgb;
+    vec3 right = texture(stereo, vec2(TexCoords.x * 0.5 + 0.5, TexCoords.y)).rgb;
+    // red-cyan glasses see the luminance of each eye through one filter
+    float leftLuma = dot(left, vec3(0.299, 0.587, 0.114));
+    FragColor = vec4(leftLuma, right.g, right.b, 1.0);
+}
+"#;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Eye {
+    Left,
+    Right,
+}
+
+impl Eye {
+    pub const BOTH: [Eye; 2] = [Eye::Left, Eye::Right];
+
+    // -1.0 for the left eye, 1.0 for the right eye
+    fn sign(self) -> f32 {
+        match self {
+            Eye::Left => -1.0,
+            Eye::Right => 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StereoSettings {
+    // interpupillary distance in world units
+    pub ipd: f32,
+    // distance at which both eyes' frusta converge, objects there appear at screen depth
+    pub convergence: f32,
+}
+
+impl Default for StereoSettings {
+    fn default() -> Self {
+        Self {
+            ipd: 0.064,
+            convergence: 10.0,
+        }
+    }
+}
+
+impl StereoSettings {
+    // the camera's view shifted half the IPD sideways
+    pub fn eye_view(&self, camera: &dyn Camera, eye: Eye) -> Matrix4<f32> {
+        // moving the eye right moves the world left in view space
+        Matrix4::from_translation(vec3(-eye.sign() * self.ipd * 0.5, 0.0, 0.0)) * camera.view()
+    }
+
+    // asymmetric frustum so both eyes share the image plane at the convergence distance.
+    // the camera's ratio is that of one eye.
+    pub fn eye_projection(&self, camera: &dyn Camera, eye: Eye) -> Matrix4<f32> {
+        let near = camera.near();
+        // the off axis frustum needs a finite far plane, this one is far enough to not matter
+        let far = if camera.far().is_infinite() { 1.0e7 } else { camera.far() };
+        let top = near * (camera.fov() / 2.0).to_radians().tan();
+        let right = top * camera.ratio();
+        let shift = -eye.sign() * self.ipd * 0.5 * near / self.convergence;
+        frustum(-right + shift, right + shift, -top, top, near, far)
+    }
+}
+
+// renders both eyes side by side into one target, as an HMD compositor expects
+#[derive(Debug)]
+pub struct StereoRenderer {
+    pub settings: StereoSettings,
+    target: Framebuffer,
+    eye_width: i32,
+    eye_height: i32,
+    anaglyph_shader: Shader,
+    quad: FullscreenQuad,
+}
+
+impl StereoRenderer {
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn new(eye_width: i32, eye_height: i32) -> Self {
+        Self {
+            settings: StereoSettings::default(),
+            target: Framebuffer::allocate(2 * eye_width, eye_height),
+            eye_width,
+            eye_height,
+            anaglyph_shader: Shader::from_str(FULLSCREEN_VERTEX_SHADER, ANAGLYPH_FRAGMENT_SHADER),
+            quad: FullscreenQuad::new(),
+        }
+    }
+
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn resize(&mut self, eye_width: i32, eye_height: i32) {
+        if (eye_width, eye_height) != (self.eye_width, self.eye_height) {
+            self.target = Framebuffer::allocate(2 * eye_width, eye_height);
+            self.eye_width = eye_width;
+            self.eye_height = eye_height;
+        }
+    }
+
+    // the side by side target, left eye on the left half
+    pub fn target(&self) -> &Framebuffer {
+        &self.target
+    }
+
+    pub fn eye_viewport(&self, eye: Eye) -> Viewport {
+        match eye {
+            Eye::Left => Viewport::new(0, 0, self.eye_width, self.eye_height),
+            Eye::Right => Viewport::new(self.eye_width, 0, self.eye_width, self.eye_height),
+        }
+    }
+
+    /// calls `draw(eye, view, projection)` for each eye with the target bound and the eye's half cleared.
+    /// the default framebuffer is bound afterwards; restore the viewport before drawing to it.
+    ///
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn render<F: FnMut(Eye, &Matrix4<f32>, &Matrix4<f32>)>(&self, camera: &dyn Camera, mut draw: F) {
+        self.target.bind_raw();
+        let scissor = gl::IsEnabled(gl::SCISSOR_TEST) == gl::TRUE;
+        gl::Enable(gl::SCISSOR_TEST);
+        for &eye in Eye::BOTH.iter() {
+            self.eye_viewport(eye).apply();
+            gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+            draw(eye, &self.settings.eye_view(camera, eye), &self.settings.eye_projection(camera, eye));
+        }
+        if !scissor {
+            gl::Disable(gl::SCISSOR_TEST);
+        }
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+    }
+
+    /// copies both eyes side by side into `destination` of the bound draw framebuffer
+    ///
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn present_side_by_side(&self, destination: Viewport) {
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, self.target.id());
+        gl::BlitFramebuffer(
+            0,
+            0,
+            2 * self.eye_width,
+            self.eye_height,
+            destination.x,
+            destination.y,
+            destination.x + destination.width,
+            destination.y + destination.height,
+            gl::COLOR_BUFFER_BIT,
+            gl::LINEAR,
+        );
+        gl::BindFramebuffer(gl::READ_FRAMEBUFFER, 0);
+    }
+
+    /// red-cyan composite of both eyes into the bound framebuffer, for testing without a headset
+    ///
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn present_anaglyph(&self) {
+        self.anaglyph_shader.use_program();
+        bind_texture(&self.anaglyph_shader, c_str("stereo\0"), 0, self.target.color_texture());
+        self.quad.draw();
+    }
+}
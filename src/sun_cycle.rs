@@ -0,0 +1,142 @@
+use std::f32::consts::PI;
+
+use cgmath::{InnerSpace, Rad, Vector3, vec3};
+
+use crate::{srgb_to_linear, Color, DirectionalLight, ProceduralSky};
+
+// linear RGB of a black body at `kelvin`, from Tanner Helland's fit. 6500K is about white.
+pub fn color_temperature(kelvin: f32) -> Vector3<f32> {
+    let t = kelvin.clamp(1000.0, 40000.0) / 100.0;
+    let r = if t <= 66.0 { 255.0 } else { 329.698_73 * (t - 60.0).powf(-0.133_204_76) };
+    let g = if t <= 66.0 {
+        99.470_8 * t.ln() - 161.119_57
+    } else {
+        288.122_17 * (t - 60.0).powf(-0.075_514_85)
+    };
+    let b = if t >= 66.0 {
+        255.0
+    } else if t <= 19.0 {
+        0.0
+    } else {
+        138.517_73 * (t - 10.0).ln() - 305.044_8
+    };
+    // the fit is in display sRGB
+    let linear = |c: f32| srgb_to_linear((c / 255.0).clamp(0.0, 1.0));
+    vec3(linear(r), linear(g), linear(b))
+}
+
+// moves the sun over the day. the light warms up towards the horizon and fades out at night.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SunCycle {
+    // hours in [0, 24), noon at 12
+    pub time_of_day: f32,
+    // real seconds per game day, 0 stops the clock
+    pub day_length: f32,
+    // tilt of the sun path away from the zenith, 0 passes straight overhead at noon
+    pub latitude: Rad<f32>,
+    // turns the sun path around y, at 0 the sun rises in +x
+    pub azimuth: Rad<f32>,
+    pub noon_intensity: f32,
+    // in kelvin
+    pub horizon_temperature: f32,
+    pub noon_temperature: f32,
+    // share of the light used as the ambient term
+    pub ambient: f32,
+    // game hours between `update` asking for the light probes to be refreshed
+    pub probe_interval: f32,
+    since_probe: f32,
+}
+
+impl Default for SunCycle {
+    fn default() -> Self {
+        Self {
+            time_of_day: 12.0,
+            day_length: 600.0,
+            latitude: Rad(0.6),
+            azimuth: Rad(0.0),
+            noon_intensity: 1.0,
+            horizon_temperature: 2000.0,
+            noon_temperature: 6500.0,
+            ambient: 0.1,
+            probe_interval: 0.5,
+            since_probe: 0.0,
+        }
+    }
+}
+
+impl SunCycle {
+    pub fn new(time_of_day: f32) -> Self {
+        Self {
+            time_of_day: time_of_day.rem_euclid(24.0),
+            ..Self::default()
+        }
+    }
+
+    // advances the clock. true when `probe_interval` game hours have passed since the last
+    // time it returned true, the time to re-render reflection probes or `ProceduralSky::update_cubemap`.
+    pub fn update(&mut self, delta_time: f32) -> bool {
+        if self.day_length > 0.0 {
+            let hours = delta_time / self.day_length * 24.0;
+            self.time_of_day = (self.time_of_day + hours).rem_euclid(24.0);
+            self.since_probe += hours.abs();
+        }
+        if self.since_probe >= self.probe_interval {
+            self.since_probe = 0.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    // jumps to a time, the next `update` refreshes the probes
+    pub fn set_time_of_day(&mut self, time_of_day: f32) {
+        self.time_of_day = time_of_day.rem_euclid(24.0);
+        self.since_probe = self.probe_interval;
+    }
+
+    // towards the sun
+    pub fn sun_direction(&self) -> Vector3<f32> {
+        let hour_angle = (self.time_of_day - 12.0) / 24.0 * 2.0 * PI;
+        let (x, y) = (-hour_angle.sin(), hour_angle.cos());
+        let (sin_lat, cos_lat) = self.latitude.0.sin_cos();
+        let (sin_az, cos_az) = self.azimuth.0.sin_cos();
+        let (y, z) = (y * cos_lat, -y * sin_lat);
+        vec3(x * cos_az + z * sin_az, y, -x * sin_az + z * cos_az).normalize()
+    }
+
+    // sine of the sun's altitude, negative at night
+    pub fn elevation(&self) -> f32 {
+        self.sun_direction().y
+    }
+
+    pub fn is_day(&self) -> bool {
+        self.elevation() > 0.0
+    }
+
+    pub fn temperature(&self) -> f32 {
+        let t = self.elevation().max(0.0).sqrt();
+        self.horizon_temperature + (self.noon_temperature - self.horizon_temperature) * t
+    }
+
+    pub fn intensity(&self) -> f32 {
+        let t = ((self.elevation() + 0.05) / 0.15).clamp(0.0, 1.0);
+        self.noon_intensity * t * t * (3.0 - 2.0 * t)
+    }
+
+    // the light color scaled by the intensity
+    pub fn radiance(&self) -> Vector3<f32> {
+        color_temperature(self.temperature()) * self.intensity()
+    }
+
+    pub fn apply(&self, light: &mut DirectionalLight) {
+        let radiance = Color::from(self.radiance());
+        light.direction = -self.sun_direction();
+        light.diffuse = radiance;
+        light.specular = radiance;
+        light.ambient = radiance * self.ambient;
+    }
+
+    pub fn apply_sky(&self, sky: &mut ProceduralSky) {
+        sky.sun_direction = self.sun_direction();
+    }
+}
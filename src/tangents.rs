@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+
+use cgmath::{InnerSpace, Vector3, vec3};
+use gl::types::*;
+
+use crate::{MeshData, Vertex};
+
+// below this the uv mapping of a triangle is taken as degenerate
+const UV_EPSILON: f32 = 1e-12;
+
+// verticies are welded by everything mikktspace looks at, so split copies of a vertex agree
+fn weld_key(vertex: &Vertex, positive: bool) -> ([u32; 8], bool) {
+    let (p, n, uv) = (vertex.position, vertex.normal, vertex.tex_coords);
+    (
+        [p.x.to_bits(), p.y.to_bits(), p.z.to_bits(), n.x.to_bits(), n.y.to_bits(), n.z.to_bits(), uv.x.to_bits(), uv.y.to_bits()],
+        positive,
+    )
+}
+
+// projected onto the plane of `normal`, zero when nothing is left
+fn project(v: Vector3<f32>, normal: Vector3<f32>) -> Vector3<f32> {
+    let v = v - normal * normal.dot(v);
+    if v.magnitude2() > 0.0 {
+        v.normalize()
+    } else {
+        v
+    }
+}
+
+// fills `Vertex::tangent` the way mikktspace does, which is what normal maps from the usual
+// baking tools expect: per face uv derivatives weighted by the corner angles, orthogonalized
+// against the vertex normal, with the bitangent sign in w, -1 on mirrored faces. verticies
+// shared by mirrored and unmirrored faces are split in two, so the vertex count may grow.
+pub fn compute_tangents(data: &mut MeshData) {
+    struct Face {
+        tangent: Vector3<f32>,
+        // the uv mapping is not mirrored
+        positive: bool,
+    }
+
+    let faces: Vec<Face> = data
+        .indices
+        .chunks_exact(3)
+        .map(|face| {
+            let v = [&data.verticies[face[0] as usize], &data.verticies[face[1] as usize], &data.verticies[face[2] as usize]];
+            let (e1, e2) = (v[1].position - v[0].position, v[2].position - v[0].position);
+            let (d1, d2) = (v[1].tex_coords - v[0].tex_coords, v[2].tex_coords - v[0].tex_coords);
+            let area = d1.x * d2.y - d2.x * d1.y;
+            if area.abs() < UV_EPSILON {
+                return Face {
+                    tangent: vec3(0.0, 0.0, 0.0),
+                    positive: true,
+                };
+            }
+            // only the direction matters, the magnitude is normalized away per corner
+            Face {
+                tangent: (e1 * d2.y - e2 * d1.y) * area.signum(),
+                positive: area > 0.0,
+            }
+        })
+        .collect();
+
+    let mut sums: HashMap<([u32; 8], bool), Vector3<f32>> = HashMap::new();
+    for (face, indices) in faces.iter().zip(data.indices.chunks_exact(3)) {
+        for corner in 0..3 {
+            let vertex = &data.verticies[indices[corner] as usize];
+            let next = data.verticies[indices[(corner + 1) % 3] as usize].position - vertex.position;
+            let previous = data.verticies[indices[(corner + 2) % 3] as usize].position - vertex.position;
+            if next.magnitude2() == 0.0 || previous.magnitude2() == 0.0 {
+                continue;
+            }
+            let angle = next.normalize().dot(previous.normalize()).clamp(-1.0, 1.0).acos();
+            let normal = vertex.normal.normalize();
+            *sums.entry(weld_key(vertex, face.positive)).or_insert_with(|| vec3(0.0, 0.0, 0.0)) +=
+                project(face.tangent, normal) * angle;
+        }
+    }
+
+    // the first orientation a vertex is used with keeps it, the other gets a copy
+    let mut split: HashMap<(GLuint, bool), GLuint> = HashMap::new();
+    let mut orientation: Vec<Option<bool>> = vec![None; data.verticies.len()];
+    let verticies = &mut data.verticies;
+    for (face, indices) in faces.iter().zip(data.indices.chunks_exact_mut(3)) {
+        for index in indices.iter_mut() {
+            let first = *orientation[*index as usize].get_or_insert(face.positive);
+            if first != face.positive {
+                let copy = *split.entry((*index, face.positive)).or_insert_with(|| {
+                    verticies.push(verticies[*index as usize]);
+                    conv!(verticies.len() - 1)
+                });
+                *index = copy;
+            }
+        }
+    }
+    orientation.resize(data.verticies.len(), None);
+    for (&(_, positive), &copy) in split.iter() {
+        orientation[copy as usize] = Some(positive);
+    }
+
+    for (vertex, positive) in data.verticies.iter_mut().zip(orientation) {
+        let normal = vertex.normal.normalize();
+        let positive = positive.unwrap_or(true);
+        let sum = sums.get(&weld_key(vertex, positive)).cloned().unwrap_or_else(|| vec3(0.0, 0.0, 0.0));
+        let mut tangent = project(sum, normal);
+        if tangent.magnitude2() == 0.0 {
+            // anything in the plane will do for faces without a usable mapping
+            let helper = if normal.x.abs() > 0.9 { vec3(0.0, 1.0, 0.0) } else { vec3(1.0, 0.0, 0.0) };
+            tangent = project(helper, normal);
+        }
+        // the bitangent is w * cross(normal, tangent)
+        vertex.tangent = tangent.extend(if positive { 1.0 } else { -1.0 });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{vec2, vec3, InnerSpace};
+
+    use super::compute_tangents;
+    use crate::mesh_data::tests::grid;
+
+    #[test]
+    fn tangents_follow_u() {
+        let mut grid = grid(2);
+        compute_tangents(&mut grid);
+        for vertex in grid.verticies.iter() {
+            assert!((vertex.tangent.truncate() - vec3(1.0, 0.0, 0.0)).magnitude() < 1e-4);
+            assert_eq!(vertex.tangent.w, 1.0);
+        }
+    }
+
+    #[test]
+    fn mirrored_uvs_flip_the_sign() {
+        let mut grid = grid(2);
+        for vertex in grid.verticies.iter_mut() {
+            vertex.tex_coords = vec2(1.0 - vertex.tex_coords.x, vertex.tex_coords.y);
+        }
+        compute_tangents(&mut grid);
+        for vertex in grid.verticies.iter() {
+            assert!((vertex.tangent.truncate() - vec3(-1.0, 0.0, 0.0)).magnitude() < 1e-4);
+            assert_eq!(vertex.tangent.w, -1.0);
+        }
+    }
+
+    #[test]
+    fn a_vertex_on_a_mirror_seam_is_split() {
+        // the right half of the grid maps u backwards
+        let mut grid = grid(2);
+        for vertex in grid.verticies.iter_mut() {
+            let x = vertex.position.x;
+            vertex.tex_coords.x = if x <= 0.5 { x } else { 1.0 - x };
+        }
+        let before = grid.verticies.len();
+        compute_tangents(&mut grid);
+        // the three verticies along x = 0.5
+        assert_eq!(grid.verticies.len(), before + 3);
+        for face in grid.indices.chunks_exact(3) {
+            let right = face.iter().any(|&i| grid.verticies[i as usize].position.x > 0.75);
+            for &i in face.iter() {
+                let sign = grid.verticies[i as usize].tangent.w;
+                assert_eq!(sign, if right { -1.0 } else { 1.0 });
+            }
+        }
+    }
+
+    #[test]
+    fn tangents_are_orthogonal_to_the_normal() {
+        let mut grid = grid(2);
+        for vertex in grid.verticies.iter_mut() {
+            vertex.normal = vec3(0.3, 0.0, 1.0).normalize();
+        }
+        compute_tangents(&mut grid);
+        for vertex in grid.verticies.iter() {
+            assert!(vertex.tangent.truncate().dot(vertex.normal).abs() < 1e-4);
+            assert!((vertex.tangent.truncate().magnitude() - 1.0).abs() < 1e-4);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use std::error::Error;
+use std::fmt;
+use std::sync::mpsc::Receiver;
+
+use gl::types::*;
+use glfw::{Context, ContextCreationApi, Glfw, Window, WindowEvent, WindowHint};
+
+use crate::GlContext;
+
+#[derive(Debug)]
+pub struct TestContextError {
+    message: String,
+}
+
+impl fmt::Display for TestContextError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "failed to create a test context: {}", self.message)
+    }
+}
+
+impl Error for TestContextError {}
+
+// a GL 3.3 core context behind a hidden window, for tests and tools that render offscreen.
+// the native context API is tried first, then EGL and OSMesa where glfw was built with them,
+// which also works without a display on some drivers. glfw wants to be initialized from one
+// thread at a time, so run such tests with `--test-threads=1`.
+pub struct TestContext {
+    // dropped before `glfw`
+    context: GlContext,
+    window: Window,
+    _events: Receiver<(f64, WindowEvent)>,
+    glfw: Glfw,
+}
+
+impl fmt::Debug for TestContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TestContext").field("size", &self.window.get_framebuffer_size()).finish()
+    }
+}
+
+impl TestContext {
+    pub fn new() -> Result<Self, TestContextError> {
+        Self::with_size(64, 64)
+    }
+
+    pub fn with_size(width: u32, height: u32) -> Result<Self, TestContextError> {
+        let error = |message: String| TestContextError { message };
+        let mut glfw = glfw::init(glfw::LOG_ERRORS).map_err(|e| error(e.to_string()))?;
+
+        let apis = [ContextCreationApi::Native, ContextCreationApi::Egl, ContextCreationApi::OsMesa];
+        let created = apis.iter().find_map(|&api| {
+            glfw.default_window_hints();
+            glfw.window_hint(WindowHint::ContextVersion(3, 3));
+            glfw.window_hint(WindowHint::OpenGlProfile(glfw::OpenGlProfileHint::Core));
+            glfw.window_hint(WindowHint::Visible(false));
+            glfw.window_hint(WindowHint::ContextCreationApi(api));
+            glfw.create_window(width, height, "test", glfw::WindowMode::Windowed)
+        });
+        let (mut window, events) = created.ok_or_else(|| error("no context API could create a hidden window".into()))?;
+        window.make_current();
+        glfw.set_swap_interval(glfw::SwapInterval::None);
+
+        let context = GlContext::load(&mut window);
+        Ok(Self {
+            context,
+            window,
+            _events: events,
+            glfw,
+        })
+    }
+
+    pub fn context(&self) -> &GlContext {
+        &self.context
+    }
+
+    pub fn window(&mut self) -> &mut Window {
+        &mut self.window
+    }
+
+    pub fn glfw(&mut self) -> &mut Glfw {
+        &mut self.glfw
+    }
+
+    // drains the GL error queue, empty when every call so far succeeded
+    pub fn errors(&self) -> Vec<GLenum> {
+        let mut errors = vec![];
+        loop {
+            let error = unsafe { gl::GetError() };
+            if error == gl::NO_ERROR {
+                return errors;
+            }
+            errors.push(error);
+        }
+    }
+
+    // the status of the bound draw framebuffer, gl::FRAMEBUFFER_COMPLETE when it can be rendered to
+    pub fn framebuffer_status(&self) -> GLenum {
+        unsafe { gl::CheckFramebufferStatus(gl::DRAW_FRAMEBUFFER) }
+    }
+}
@@ -0,0 +1,177 @@
+use gl::types::*;
+
+use crate::context::check_render_thread;
+use crate::texture_builder::float_bytes;
+use crate::{AttachmentFormat, GlContext, PixelFormat};
+
+// a volume sampled as `sampler3D`, e.g. color grading LUTs, tiling noise or froxel data
+#[derive(Debug)]
+pub struct Texture3D {
+    id: GLuint,
+    width: u32,
+    height: u32,
+    depth: u32,
+    format: AttachmentFormat,
+}
+
+impl Texture3D {
+    // allocates the volume without contents, filtered trilinearly and repeating
+    pub fn new(_context: &GlContext, width: u32, height: u32, depth: u32, format: AttachmentFormat) -> Self {
+        unsafe { Self::allocate(width, height, depth, format) }
+    }
+
+    pub(crate) unsafe fn allocate(width: u32, height: u32, depth: u32, format: AttachmentFormat) -> Self {
+        let mut id = 0;
+        gl::GenTextures(1, &mut id);
+        gl::BindTexture(gl::TEXTURE_3D, id);
+        gl::TexImage3D(
+            gl::TEXTURE_3D,
+            0,
+            conv!(format.internal_format),
+            conv!(width),
+            conv!(height),
+            conv!(depth),
+            0,
+            format.format,
+            format.type_,
+            std::ptr::null(),
+        );
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_MIN_FILTER, conv!(gl::LINEAR));
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_MAG_FILTER, conv!(gl::LINEAR));
+        for &axis in [gl::TEXTURE_WRAP_S, gl::TEXTURE_WRAP_T, gl::TEXTURE_WRAP_R].iter() {
+            gl::TexParameteri(gl::TEXTURE_3D, axis, conv!(gl::REPEAT));
+        }
+        gl::BindTexture(gl::TEXTURE_3D, 0);
+
+        Self {
+            id,
+            width,
+            height,
+            depth,
+            format,
+        }
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn depth(&self) -> u32 {
+        self.depth
+    }
+
+    pub fn format(&self) -> AttachmentFormat {
+        self.format
+    }
+
+    // replaces the whole volume: tightly packed rows, then slices along r
+    pub fn upload(&self, _context: &GlContext, format: PixelFormat, pixels: &[u8]) {
+        check_render_thread("Texture3D");
+        unsafe {
+            self.upload_raw(format, pixels);
+        }
+    }
+
+    pub fn upload_f32(&self, context: &GlContext, channels: usize, voxels: &[f32]) {
+        self.upload(context, PixelFormat::float(channels), float_bytes(voxels));
+    }
+
+    pub(crate) unsafe fn upload_raw(&self, format: PixelFormat, pixels: &[u8]) {
+        let expected = self.width as usize * self.height as usize * self.depth as usize * format.channels() * format.bytes_per_channel();
+        assert!(
+            pixels.len() >= expected,
+            "{} bytes of voxels for a {}x{}x{} texture, expected {}",
+            pixels.len(),
+            self.width,
+            self.height,
+            self.depth,
+            expected
+        );
+        gl::BindTexture(gl::TEXTURE_3D, self.id);
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+        gl::TexSubImage3D(
+            gl::TEXTURE_3D,
+            0,
+            0,
+            0,
+            0,
+            conv!(self.width),
+            conv!(self.height),
+            conv!(self.depth),
+            format.format,
+            format.type_,
+            pixels.as_ptr() as *const _,
+        );
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+        gl::BindTexture(gl::TEXTURE_3D, 0);
+    }
+
+    // call after uploading when sampling with a mipmapped min filter
+    pub fn generate_mipmaps(&self, _context: &GlContext) {
+        check_render_thread("Texture3D");
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_3D, self.id);
+            gl::GenerateMipmap(gl::TEXTURE_3D);
+            gl::BindTexture(gl::TEXTURE_3D, 0);
+        }
+    }
+
+    pub fn set_filter(&self, _context: &GlContext, min_filter: GLenum, mag_filter: GLenum) {
+        check_render_thread("Texture3D");
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_3D, self.id);
+            gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_MIN_FILTER, conv!(min_filter));
+            gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_MAG_FILTER, conv!(mag_filter));
+            gl::BindTexture(gl::TEXTURE_3D, 0);
+        }
+    }
+
+    // the same wrap mode on all three axes
+    pub fn set_wrap(&self, context: &GlContext, wrap: GLenum) {
+        self.set_wrap_axes(context, [wrap; 3]);
+    }
+
+    pub fn set_wrap_axes(&self, _context: &GlContext, wrap: [GLenum; 3]) {
+        check_render_thread("Texture3D");
+        unsafe {
+            self.set_wrap_raw(wrap);
+        }
+    }
+
+    pub(crate) unsafe fn set_wrap_raw(&self, wrap: [GLenum; 3]) {
+        gl::BindTexture(gl::TEXTURE_3D, self.id);
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_WRAP_S, conv!(wrap[0]));
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_WRAP_T, conv!(wrap[1]));
+        gl::TexParameteri(gl::TEXTURE_3D, gl::TEXTURE_WRAP_R, conv!(wrap[2]));
+        gl::BindTexture(gl::TEXTURE_3D, 0);
+    }
+
+    pub fn bind(&self, _context: &GlContext, unit: GLuint) {
+        check_render_thread("Texture3D");
+        unsafe {
+            self.bind_raw(unit);
+        }
+    }
+
+    pub(crate) unsafe fn bind_raw(&self, unit: GLuint) {
+        gl::ActiveTexture(gl::TEXTURE0 + unit);
+        gl::BindTexture(gl::TEXTURE_3D, self.id);
+    }
+}
+
+impl Drop for Texture3D {
+    fn drop(&mut self) {
+        check_render_thread("Texture3D");
+        unsafe {
+            gl::DeleteTextures(1, &self.id);
+        }
+    }
+}
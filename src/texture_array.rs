@@ -0,0 +1,174 @@
+use gl::types::*;
+use image::{DynamicImage, GenericImageView};
+
+use crate::context::check_render_thread;
+use crate::{AttachmentFormat, DepthSampler, GlContext, PixelFormat};
+
+// layers of equally sized 2d images sampled as `sampler2DArray` with vec3(uv, layer),
+// e.g. shadow cascades, terrain splat layers or the frames of a flipbook
+#[derive(Debug)]
+pub struct TextureArray {
+    id: GLuint,
+    width: u32,
+    height: u32,
+    layers: u32,
+    format: AttachmentFormat,
+}
+
+impl TextureArray {
+    // allocates the layers without contents, sampled linearly and clamped to the edge
+    pub fn new(_context: &GlContext, width: u32, height: u32, layers: u32, format: AttachmentFormat) -> Self {
+        let mut id = 0;
+        unsafe {
+            gl::GenTextures(1, &mut id);
+            gl::BindTexture(gl::TEXTURE_2D_ARRAY, id);
+            gl::TexImage3D(
+                gl::TEXTURE_2D_ARRAY,
+                0,
+                conv!(format.internal_format),
+                conv!(width),
+                conv!(height),
+                conv!(layers),
+                0,
+                format.format,
+                format.type_,
+                std::ptr::null(),
+            );
+            gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_MIN_FILTER, conv!(gl::LINEAR));
+            gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_MAG_FILTER, conv!(gl::LINEAR));
+            gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_WRAP_S, conv!(gl::CLAMP_TO_EDGE));
+            gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_WRAP_T, conv!(gl::CLAMP_TO_EDGE));
+            gl::BindTexture(gl::TEXTURE_2D_ARRAY, 0);
+        }
+        Self {
+            id,
+            width,
+            height,
+            layers,
+            format,
+        }
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn layers(&self) -> u32 {
+        self.layers
+    }
+
+    pub fn format(&self) -> AttachmentFormat {
+        self.format
+    }
+
+    // replaces a whole layer, `pixels` holds tightly packed rows like `TextureBuilder::upload`
+    pub fn upload_layer(&self, _context: &GlContext, layer: u32, format: PixelFormat, pixels: &[u8]) {
+        check_render_thread("TextureArray");
+        assert!(layer < self.layers, "layer {} out of {}", layer, self.layers);
+        let expected = self.width as usize * self.height as usize * format.channels() * format.bytes_per_channel();
+        assert!(pixels.len() >= expected, "{} bytes of pixels for a {}x{} layer, expected {}", pixels.len(), self.width, self.height, expected);
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D_ARRAY, self.id);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+            gl::TexSubImage3D(
+                gl::TEXTURE_2D_ARRAY,
+                0,
+                0,
+                0,
+                conv!(layer),
+                conv!(self.width),
+                conv!(self.height),
+                1,
+                format.format,
+                format.type_,
+                pixels.as_ptr() as *const _,
+            );
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+            gl::BindTexture(gl::TEXTURE_2D_ARRAY, 0);
+        }
+    }
+
+    // the image must have the size of the layers
+    pub fn upload_image(&self, context: &GlContext, layer: u32, img: &DynamicImage) {
+        assert!(
+            img.dimensions() == (self.width, self.height),
+            "image is {:?}, layers are {}x{}",
+            img.dimensions(),
+            self.width,
+            self.height
+        );
+        self.upload_layer(context, layer, PixelFormat::of_image(img), &img.raw_pixels());
+    }
+
+    // call after uploading all layers when sampling with a mipmapped min filter
+    pub fn generate_mipmaps(&self, _context: &GlContext) {
+        check_render_thread("TextureArray");
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D_ARRAY, self.id);
+            gl::GenerateMipmap(gl::TEXTURE_2D_ARRAY);
+            gl::BindTexture(gl::TEXTURE_2D_ARRAY, 0);
+        }
+    }
+
+    pub fn set_filter(&self, _context: &GlContext, min_filter: GLenum, mag_filter: GLenum) {
+        check_render_thread("TextureArray");
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D_ARRAY, self.id);
+            gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_MIN_FILTER, conv!(min_filter));
+            gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_MAG_FILTER, conv!(mag_filter));
+            gl::BindTexture(gl::TEXTURE_2D_ARRAY, 0);
+        }
+    }
+
+    pub fn set_wrap(&self, _context: &GlContext, wrap: GLenum) {
+        check_render_thread("TextureArray");
+        unsafe {
+            gl::BindTexture(gl::TEXTURE_2D_ARRAY, self.id);
+            gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_WRAP_S, conv!(wrap));
+            gl::TexParameteri(gl::TEXTURE_2D_ARRAY, gl::TEXTURE_WRAP_T, conv!(wrap));
+            gl::BindTexture(gl::TEXTURE_2D_ARRAY, 0);
+        }
+    }
+
+    // for depth arrays, e.g. `DepthSampler::SHADOW` to sample cascades with `sampler2DArrayShadow`
+    pub fn set_depth_sampler(&self, _context: &GlContext, sampler: DepthSampler) {
+        check_render_thread("TextureArray");
+        unsafe {
+            sampler.apply(gl::TEXTURE_2D_ARRAY, self.id);
+        }
+    }
+
+    // attaches one layer to the bound draw framebuffer, e.g. to render a shadow cascade
+    pub fn attach_layer(&self, _context: &GlContext, attachment: GLenum, layer: u32) {
+        check_render_thread("TextureArray");
+        assert!(layer < self.layers, "layer {} out of {}", layer, self.layers);
+        unsafe {
+            gl::FramebufferTextureLayer(gl::DRAW_FRAMEBUFFER, attachment, self.id, 0, conv!(layer));
+        }
+    }
+
+    pub fn bind(&self, _context: &GlContext, unit: GLuint) {
+        check_render_thread("TextureArray");
+        unsafe {
+            gl::ActiveTexture(gl::TEXTURE0 + unit);
+            gl::BindTexture(gl::TEXTURE_2D_ARRAY, self.id);
+        }
+    }
+}
+
+impl Drop for TextureArray {
+    fn drop(&mut self) {
+        check_render_thread("TextureArray");
+        unsafe {
+            gl::DeleteTextures(1, &self.id);
+        }
+    }
+}
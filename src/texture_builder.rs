@@ -0,0 +1,308 @@
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use gl::types::*;
+use image::{open, DynamicImage, DynamicImage::*, GenericImageView, ImageError};
+
+use crate::context::check_render_thread;
+use crate::features::decompress_s3tc;
+use crate::{Features, GlContext, GpuInfo};
+
+// not part of the 4.5 core bindings
+const TEXTURE_MAX_ANISOTROPY: GLenum = 0x84FE;
+
+// the anisotropy of new textures as f32 bits, infinity for the most the GPU supports
+static DEFAULT_ANISOTROPY: AtomicU32 = AtomicU32::new(0x7f80_0000);
+
+// the anisotropic filtering of textures built from now on, 1 turns it off.
+// `GraphicsSettings` sets this together with the existing textures.
+pub fn set_default_anisotropy(anisotropy: f32) {
+    DEFAULT_ANISOTROPY.store(anisotropy.max(1.0).to_bits(), Ordering::Relaxed);
+}
+
+pub fn default_anisotropy() -> f32 {
+    f32::from_bits(DEFAULT_ANISOTROPY.load(Ordering::Relaxed))
+}
+
+// sets the anisotropic filtering of a bound 2d texture, clamped to what the GPU supports.
+// returns the level set, None without the extension.
+pub(crate) unsafe fn apply_anisotropy(anisotropy: f32) -> Option<f32> {
+    let anisotropy = anisotropy.max(1.0).min(GpuInfo::current().max_anisotropy?);
+    gl::TexParameterf(gl::TEXTURE_2D, TEXTURE_MAX_ANISOTROPY, anisotropy);
+    Some(anisotropy)
+}
+
+// how the pixels handed to the builder are laid out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct PixelFormat {
+    pub format: GLenum,
+    pub type_: GLenum,
+}
+
+impl PixelFormat {
+    pub const R8: Self = Self::new(gl::RED, gl::UNSIGNED_BYTE);
+    pub const RG8: Self = Self::new(gl::RG, gl::UNSIGNED_BYTE);
+    pub const RGB8: Self = Self::new(gl::RGB, gl::UNSIGNED_BYTE);
+    pub const RGBA8: Self = Self::new(gl::RGBA, gl::UNSIGNED_BYTE);
+    pub const BGR8: Self = Self::new(gl::BGR, gl::UNSIGNED_BYTE);
+    pub const BGRA8: Self = Self::new(gl::BGRA, gl::UNSIGNED_BYTE);
+    pub const R32F: Self = Self::new(gl::RED, gl::FLOAT);
+    pub const RG32F: Self = Self::new(gl::RG, gl::FLOAT);
+    pub const RGB32F: Self = Self::new(gl::RGB, gl::FLOAT);
+    pub const RGBA32F: Self = Self::new(gl::RGBA, gl::FLOAT);
+
+    pub const fn new(format: GLenum, type_: GLenum) -> Self {
+        Self { format, type_ }
+    }
+
+    // 32 bit floats with 1 to 4 channels
+    pub fn float(channels: usize) -> Self {
+        match channels {
+            1 => Self::R32F,
+            2 => Self::RG32F,
+            3 => Self::RGB32F,
+            4 => Self::RGBA32F,
+            _ => panic!("textures have 1 to 4 channels, got {}", channels),
+        }
+    }
+
+    pub fn channels(self) -> usize {
+        match self.format {
+            gl::RED => 1,
+            gl::RG => 2,
+            gl::RGB | gl::BGR => 3,
+            _ => 4,
+        }
+    }
+
+    pub fn bytes_per_channel(self) -> usize {
+        match self.type_ {
+            gl::UNSIGNED_BYTE => 1,
+            gl::UNSIGNED_SHORT | gl::HALF_FLOAT => 2,
+            _ => 4,
+        }
+    }
+
+    // the internal format used unless one is chosen explicitly
+    pub fn default_internal_format(self) -> GLenum {
+        let float = self.type_ == gl::FLOAT || self.type_ == gl::HALF_FLOAT;
+        match (self.channels(), float) {
+            (1, false) => gl::R8,
+            (2, false) => gl::RG8,
+            (3, false) => gl::RGB8,
+            (_, false) => gl::RGBA8,
+            (1, true) => gl::R32F,
+            (2, true) => gl::RG32F,
+            (3, true) => gl::RGB32F,
+            (_, true) => gl::RGBA32F,
+        }
+    }
+
+    pub(crate) fn of_image(img: &DynamicImage) -> Self {
+        match img {
+            ImageLuma8(_) => Self::R8,
+            ImageLumaA8(_) => Self::RG8,
+            ImageRgb8(_) => Self::RGB8,
+            ImageRgba8(_) => Self::RGBA8,
+            ImageBgr8(_) => Self::BGR8,
+            ImageBgra8(_) => Self::BGRA8,
+        }
+    }
+}
+
+// creates 2d textures from files or raw pixels. `load_texture` uses the defaults.
+#[derive(Debug, Clone, Copy)]
+pub struct TextureBuilder {
+    internal_format: Option<GLenum>,
+    wrap: Option<GLenum>,
+    min_filter: GLenum,
+    mag_filter: GLenum,
+    mipmaps: bool,
+    // 8 bit color data is sRGB encoded and gets decoded when sampled
+    srgb: bool,
+    // show gray and gray-alpha images as gray instead of red and red-green
+    swizzle_gray: bool,
+}
+
+impl Default for TextureBuilder {
+    fn default() -> Self {
+        Self {
+            internal_format: None,
+            wrap: None,
+            min_filter: gl::LINEAR_MIPMAP_LINEAR,
+            mag_filter: gl::LINEAR,
+            mipmaps: true,
+            srgb: false,
+            swizzle_gray: true,
+        }
+    }
+}
+
+impl TextureBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // e.g. gl::RGBA16F to keep float data at half precision, or gl::SRGB8_ALPHA8 for albedo
+    pub fn internal_format(mut self, internal_format: GLenum) -> Self {
+        self.internal_format = Some(internal_format);
+        self
+    }
+
+    // by default textures with alpha clamp to the edge and the others repeat
+    pub fn wrap(mut self, wrap: GLenum) -> Self {
+        self.wrap = Some(wrap);
+        self
+    }
+
+    pub fn filter(mut self, min_filter: GLenum, mag_filter: GLenum) -> Self {
+        self.min_filter = min_filter;
+        self.mag_filter = mag_filter;
+        self
+    }
+
+    // without mipmaps a mipmapped min filter falls back to its base level filter
+    pub fn mipmaps(mut self, mipmaps: bool) -> Self {
+        self.mipmaps = mipmaps;
+        self
+    }
+
+    // for textures holding colors, see `TextureType::is_color`. gray images stay linear, and an
+    // explicit `internal_format` wins.
+    pub fn srgb(mut self, srgb: bool) -> Self {
+        self.srgb = srgb;
+        self
+    }
+
+    pub fn swizzle_gray(mut self, swizzle_gray: bool) -> Self {
+        self.swizzle_gray = swizzle_gray;
+        self
+    }
+
+    pub fn load<P: AsRef<Path>>(&self, _context: &GlContext, path: P) -> Result<GLuint, ImageError> {
+        unsafe { self.load_raw(path) }
+    }
+
+    pub fn upload_image(&self, _context: &GlContext, img: &DynamicImage) -> GLuint {
+        unsafe { self.upload_image_raw(img) }
+    }
+
+    // `pixels` holds tightly packed rows of `width` pixels
+    pub fn upload(&self, _context: &GlContext, width: u32, height: u32, format: PixelFormat, pixels: &[u8]) -> GLuint {
+        unsafe { self.upload_raw(width, height, format, pixels) }
+    }
+
+    // float data with 1 to 4 channels, stored as 32 bit floats unless another internal format is chosen
+    pub fn upload_f32(&self, _context: &GlContext, width: u32, height: u32, channels: usize, pixels: &[f32]) -> GLuint {
+        unsafe { self.upload_raw(width, height, PixelFormat::float(channels), float_bytes(pixels)) }
+    }
+
+    pub(crate) unsafe fn load_raw<P: AsRef<Path>>(&self, path: P) -> Result<GLuint, ImageError> {
+        Ok(self.upload_image_raw(&open(path)?))
+    }
+
+    pub(crate) unsafe fn upload_image_raw(&self, img: &DynamicImage) -> GLuint {
+        let (width, height) = img.dimensions();
+        self.upload_raw(width, height, PixelFormat::of_image(img), &img.raw_pixels())
+    }
+
+    pub(crate) unsafe fn upload_raw(&self, width: u32, height: u32, format: PixelFormat, pixels: &[u8]) -> GLuint {
+        check_render_thread("TextureBuilder");
+        let expected = width as usize * height as usize * format.channels() * format.bytes_per_channel();
+        assert!(pixels.len() >= expected, "{} bytes of pixels for a {}x{} texture, expected {}", pixels.len(), width, height, expected);
+
+        let mut texture = 0;
+        gl::GenTextures(1, &mut texture);
+        gl::BindTexture(gl::TEXTURE_2D, texture);
+
+        // rows of single channel or rgb images are not 4 byte aligned in general
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+        gl::TexImage2D(
+            gl::TEXTURE_2D,
+            0,
+            conv!(self.internal_format.unwrap_or_else(|| self.default_internal_format(format))),
+            conv!(width),
+            conv!(height),
+            0,
+            format.format,
+            format.type_,
+            pixels.as_ptr() as *const _,
+        );
+        gl::PixelStorei(gl::UNPACK_ALIGNMENT, 4);
+
+        if self.mipmaps {
+            gl::GenerateMipmap(gl::TEXTURE_2D);
+        }
+        self.set_parameters(format.channels(), self.mipmaps);
+
+        texture
+    }
+
+    // the level 0 image of a compressed texture, decoded on the CPU when the driver lacks the
+    // format. None when it can't be decoded either, the caller should load an uncompressed copy.
+    pub fn upload_compressed(&self, _context: &GlContext, width: u32, height: u32, internal_format: GLenum, data: &[u8]) -> Option<GLuint> {
+        check_render_thread("TextureBuilder");
+        let family = Features::compressed_format_of(internal_format)?;
+        if !unsafe { GpuInfo::current() }.features.supports_compressed_format(family) {
+            log::warn!("{:?} textures are not supported by this context, decoding on the CPU", family);
+            let pixels = decompress_s3tc(internal_format, width, height, data)?;
+            let builder = Self { internal_format: None, ..*self };
+            return Some(unsafe { builder.upload_raw(width, height, PixelFormat::RGBA8, &pixels) });
+        }
+
+        unsafe {
+            let mut texture = 0;
+            gl::GenTextures(1, &mut texture);
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+            gl::CompressedTexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                internal_format,
+                conv!(width),
+                conv!(height),
+                0,
+                conv!(data.len()),
+                data.as_ptr() as *const _,
+            );
+            // compressed formats can't be rendered to, so GenerateMipmap is not available
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAX_LEVEL, 0);
+            self.set_parameters(4, false);
+            Some(texture)
+        }
+    }
+
+    unsafe fn set_parameters(&self, channels: usize, mipmaps: bool) {
+        let has_alpha = channels == 4 || (channels == 2 && self.swizzle_gray);
+        let wrap = self.wrap.unwrap_or(if has_alpha { gl::CLAMP_TO_EDGE } else { gl::REPEAT });
+        let min_filter = match self.min_filter {
+            gl::NEAREST_MIPMAP_NEAREST | gl::NEAREST_MIPMAP_LINEAR if !mipmaps => gl::NEAREST,
+            gl::LINEAR_MIPMAP_NEAREST | gl::LINEAR_MIPMAP_LINEAR if !mipmaps => gl::LINEAR,
+            filter => filter,
+        };
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, conv!(wrap));
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, conv!(wrap));
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, conv!(min_filter));
+        gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, conv!(self.mag_filter));
+
+        if self.swizzle_gray && channels <= 2 {
+            let alpha = if channels == 2 { gl::GREEN } else { gl::ONE };
+            let swizzle: [GLint; 4] = [conv!(gl::RED), conv!(gl::RED), conv!(gl::RED), conv!(alpha)];
+            gl::TexParameteriv(gl::TEXTURE_2D, gl::TEXTURE_SWIZZLE_RGBA, swizzle.as_ptr());
+        }
+
+        apply_anisotropy(default_anisotropy());
+    }
+
+    fn default_internal_format(&self, format: PixelFormat) -> GLenum {
+        match format.default_internal_format() {
+            gl::RGB8 if self.srgb => gl::SRGB8,
+            gl::RGBA8 if self.srgb => gl::SRGB8_ALPHA8,
+            internal_format => internal_format,
+        }
+    }
+}
+
+// the bytes of float pixels, as `PixelFormat::float` describes them
+pub(crate) fn float_bytes(pixels: &[f32]) -> &[u8] {
+    unsafe { std::slice::from_raw_parts(pixels.as_ptr() as *const u8, std::mem::size_of_val(pixels)) }
+}
@@ -0,0 +1,320 @@
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+use cgmath::{MetricSpace, Point3};
+use gl::types::*;
+use image::{GenericImageView, RgbaImage};
+
+use crate::context::{check_render_thread, GlContext};
+use crate::{PixelUploader, StagingBuffer};
+
+#[derive(Debug, Clone, Copy)]
+pub struct StreamingConfig {
+    // upper bound of the full resolution textures kept on the GPU
+    pub budget_bytes: usize,
+    // longest edge of the resident low resolution version
+    pub low_res_size: u32,
+    pub upgrade_distance: f32,
+    pub evict_distance: f32,
+    pub max_uploads_per_update: usize,
+}
+
+impl Default for StreamingConfig {
+    fn default() -> Self {
+        Self {
+            budget_bytes: 256 * 1024 * 1024,
+            low_res_size: 64,
+            upgrade_distance: 20.0,
+            evict_distance: 40.0,
+            max_uploads_per_update: 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StreamedTextureId(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Residency {
+    // nothing decoded yet, a placeholder is bound
+    Placeholder,
+    Low,
+    // the full resolution image is being decoded
+    Pending,
+    Full,
+}
+
+#[derive(Debug)]
+struct Entry {
+    path: PathBuf,
+    position: Point3<f32>,
+    texture: GLuint,
+    residency: Residency,
+    low: Option<RgbaImage>,
+    // of the source image, known once the low resolution version is decoded
+    full_size: (u32, u32),
+    full_bytes: usize,
+    // the full resolution failed to decode, it is not requested again
+    failed: bool,
+}
+
+enum Job {
+    Low(usize, PathBuf, u32),
+    // decoded straight into the mapped pixel buffer
+    Full(usize, PathBuf, StagingBuffer),
+}
+
+enum Decoded {
+    Low(usize, RgbaImage, (u32, u32)),
+    Full(usize, StagingBuffer),
+    Failed(usize, PathBuf, String, Option<StagingBuffer>),
+}
+
+#[derive(Debug)]
+pub struct TextureStreamer {
+    config: StreamingConfig,
+    entries: Vec<Entry>,
+    jobs: Option<Sender<Job>>,
+    decoded: Receiver<Decoded>,
+    worker: Option<JoinHandle<()>>,
+    resident_bytes: usize,
+    // full resolution images go through pixel buffers
+    uploader: PixelUploader,
+}
+
+// `TextureStreamer` joins this thread before its uploader is dropped, which keeps the staging buffers mapped
+fn decode_worker(jobs: Receiver<Job>, decoded: Sender<Decoded>) {
+    for job in jobs {
+        let result = match job {
+            Job::Low(id, path, size) => match image::open(&path) {
+                Ok(img) => Decoded::Low(id, img.thumbnail(size, size).to_rgba(), img.dimensions()),
+                Err(e) => Decoded::Failed(id, path, e.to_string(), None),
+            },
+            Job::Full(id, path, mut staging) => match image::open(&path) {
+                Ok(img) if img.dimensions() == (staging.width(), staging.height()) => {
+                    unsafe { staging.write_image(&img.to_rgba()) };
+                    Decoded::Full(id, staging)
+                }
+                Ok(_) => Decoded::Failed(id, path, "the image changed size".into(), Some(staging)),
+                Err(e) => Decoded::Failed(id, path, e.to_string(), Some(staging)),
+            },
+        };
+        if decoded.send(result).is_err() {
+            break;
+        }
+    }
+}
+
+// size of an RGBA8 image including its mip chain
+fn texture_bytes(width: u32, height: u32) -> usize {
+    (width as usize * height as usize * 4) * 4 / 3
+}
+
+impl TextureStreamer {
+    pub fn new(config: StreamingConfig) -> Self {
+        let (job_sender, job_receiver) = channel();
+        let (decoded_sender, decoded_receiver) = channel();
+        let worker = thread::Builder::new()
+            .name("texture streaming".into())
+            .spawn(move || decode_worker(job_receiver, decoded_sender))
+            .expect("failed to spawn texture streaming thread");
+
+        Self {
+            config,
+            entries: vec![],
+            jobs: Some(job_sender),
+            decoded: decoded_receiver,
+            worker: Some(worker),
+            resident_bytes: 0,
+            uploader: PixelUploader::new(),
+        }
+    }
+
+    fn send(&self, job: Job) {
+        if let Some(jobs) = &self.jobs {
+            jobs.send(job).expect("texture streaming thread has stopped");
+        }
+    }
+
+    // registers a texture used around `position`. a grey placeholder is bound until the low resolution version arrives.
+    pub fn add<P: AsRef<Path>>(&mut self, _context: &GlContext, path: P, position: Point3<f32>) -> StreamedTextureId {
+        check_render_thread("TextureStreamer");
+        let id = self.entries.len();
+        let path = path.as_ref().to_owned();
+
+        let mut texture = 0;
+        unsafe {
+            gl::GenTextures(1, &mut texture);
+            gl::BindTexture(gl::TEXTURE_2D, texture);
+            let placeholder: [u8; 4] = [128, 128, 128, 255];
+            gl::TexImage2D(gl::TEXTURE_2D, 0, conv!(gl::RGBA), 1, 1, 0, gl::RGBA, gl::UNSIGNED_BYTE, placeholder.as_ptr() as *const _);
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, conv!(gl::REPEAT));
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, conv!(gl::REPEAT));
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, conv!(gl::LINEAR_MIPMAP_LINEAR));
+            gl::TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, conv!(gl::LINEAR));
+        }
+
+        self.send(Job::Low(id, path.clone(), self.config.low_res_size));
+        self.entries.push(Entry {
+            path,
+            position,
+            texture,
+            residency: Residency::Placeholder,
+            low: None,
+            full_size: (0, 0),
+            full_bytes: 0,
+            failed: false,
+        });
+
+        StreamedTextureId(id)
+    }
+
+    pub fn set_position(&mut self, id: StreamedTextureId, position: Point3<f32>) {
+        self.entries[id.0].position = position;
+    }
+
+    pub fn texture(&self, id: StreamedTextureId) -> GLuint {
+        self.entries[id.0].texture
+    }
+
+    pub fn residency(&self, id: StreamedTextureId) -> Residency {
+        self.entries[id.0].residency
+    }
+
+    pub fn resident_bytes(&self) -> usize {
+        self.resident_bytes
+    }
+
+    pub fn config(&self) -> &StreamingConfig {
+        &self.config
+    }
+
+    pub fn set_budget(&mut self, budget_bytes: usize) {
+        self.config.budget_bytes = budget_bytes;
+    }
+
+    fn pending_bytes(&self) -> usize {
+        self.entries
+            .iter()
+            .filter(|entry| entry.residency == Residency::Pending)
+            .map(|entry| texture_bytes(entry.full_size.0, entry.full_size.1))
+            .sum()
+    }
+
+    fn downgrade(&mut self, context: &GlContext, index: usize) {
+        let entry = &mut self.entries[index];
+        if let Some(low) = &entry.low {
+            self.uploader.upload_image(context, low, entry.texture, true);
+        }
+        self.resident_bytes -= entry.full_bytes;
+        entry.full_bytes = 0;
+        entry.residency = Residency::Low;
+    }
+
+    // call once per frame: uploads finished decodes, requests upgrades near the camera and evicts distant textures
+    pub fn update(&mut self, context: &GlContext, camera: Point3<f32>) {
+        check_render_thread("TextureStreamer");
+        let mut uploads = 0;
+        while uploads < self.config.max_uploads_per_update {
+            let decoded = match self.decoded.try_recv() {
+                Ok(decoded) => decoded,
+                Err(_) => break,
+            };
+
+            match decoded {
+                Decoded::Low(id, img, full_size) => {
+                    let entry = &mut self.entries[id];
+                    self.uploader.upload_image(context, &img, entry.texture, true);
+                    entry.low = Some(img);
+                    entry.full_size = full_size;
+                    entry.residency = Residency::Low;
+                }
+                Decoded::Full(id, staging) => {
+                    let entry = &mut self.entries[id];
+                    // the entry may have been evicted while decoding
+                    if entry.residency != Residency::Pending {
+                        self.uploader.release(context, staging);
+                        continue;
+                    }
+                    entry.full_bytes = texture_bytes(staging.width(), staging.height());
+                    self.uploader.upload(context, staging, entry.texture, true);
+                    entry.residency = Residency::Full;
+                    self.resident_bytes += entry.full_bytes;
+                }
+                Decoded::Failed(id, path, message, staging) => {
+                    if let Some(staging) = staging {
+                        self.uploader.release(context, staging);
+                    }
+                    log::error!("failed to stream texture {}: {}", path.display(), message);
+                    let entry = &mut self.entries[id];
+                    if entry.residency == Residency::Pending {
+                        entry.residency = Residency::Low;
+                        entry.failed = true;
+                    }
+                }
+            }
+            uploads += 1;
+        }
+
+        let mut order: Vec<(usize, f32)> = self
+            .entries
+            .iter()
+            .enumerate()
+            .map(|(i, entry)| (i, entry.position.distance(camera)))
+            .collect();
+        order.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal));
+
+        // evict from the farthest until both the distance and the budget constraints hold
+        for &(i, distance) in order.iter().rev() {
+            if self.entries[i].residency != Residency::Full {
+                continue;
+            }
+            if distance > self.config.evict_distance || self.resident_bytes > self.config.budget_bytes {
+                self.downgrade(context, i);
+            }
+        }
+        for &(i, distance) in order.iter().rev() {
+            if self.entries[i].residency == Residency::Pending && distance > self.config.evict_distance {
+                self.entries[i].residency = Residency::Low;
+            }
+        }
+
+        // decodes in flight will land on the GPU, so they count against the budget already
+        let mut committed = self.resident_bytes + self.pending_bytes();
+        for &(i, distance) in order.iter() {
+            if distance > self.config.upgrade_distance {
+                break;
+            }
+            let entry = &self.entries[i];
+            if entry.residency == Residency::Low && !entry.failed {
+                let (width, height) = entry.full_size;
+                let bytes = texture_bytes(width, height);
+                if committed + bytes > self.config.budget_bytes {
+                    break;
+                }
+                committed += bytes;
+                let staging = self.uploader.map(context, width, height);
+                self.entries[i].residency = Residency::Pending;
+                self.send(Job::Full(i, self.entries[i].path.clone(), staging));
+            }
+        }
+    }
+}
+
+impl Drop for TextureStreamer {
+    fn drop(&mut self) {
+        check_render_thread("TextureStreamer");
+        // closing the channel stops the worker
+        self.jobs = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+
+        for entry in self.entries.iter() {
+            unsafe {
+                gl::DeleteTextures(1, &entry.texture);
+            }
+        }
+    }
+}
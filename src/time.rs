@@ -0,0 +1,274 @@
+use crate::{SaveState, Snapshot, SnapshotError};
+
+// frame timing for the update loop. `tick` is called once per frame with the clock, e.g.
+// `glfw.get_time()`, and systems read `delta`, which follows the scale and pausing.
+// input, cameras and UI that should keep responding use `real_delta` instead.
+// `Time::deterministic` ignores the clock for the game time, see `fixed_delta`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Time {
+    scale: f32,
+    paused: bool,
+    step_requested: bool,
+    // the game time a `step_frame` advances, in seconds
+    pub step_length: f32,
+    // longer frames (breakpoints, window drags) are cut to this before scaling
+    pub max_delta: f32,
+    last: Option<f64>,
+    real_delta: f32,
+    delta: f32,
+    real_elapsed: f64,
+    elapsed: f64,
+    frame: u64,
+    fixed_delta: Option<f32>,
+    simulation_frame: u64,
+}
+
+impl Default for Time {
+    fn default() -> Self {
+        Self {
+            scale: 1.0,
+            paused: false,
+            step_requested: false,
+            step_length: 1.0 / 60.0,
+            max_delta: 0.25,
+            last: None,
+            real_delta: 0.0,
+            delta: 0.0,
+            real_elapsed: 0.0,
+            elapsed: 0.0,
+            frame: 0,
+            fixed_delta: None,
+            simulation_frame: 0,
+        }
+    }
+}
+
+impl Time {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // every tick that is not paused advances the game by exactly `step`, however long the frame
+    // took, so replays and lockstep peers see the same deltas
+    pub fn deterministic(step: f32) -> Self {
+        Self {
+            fixed_delta: Some(step),
+            ..Self::default()
+        }
+    }
+
+    // starts the next frame at `now` seconds and returns its `delta`. the first tick only sets the start.
+    pub fn tick(&mut self, now: f64) -> f32 {
+        let real = self.last.map_or(0.0, |last| (now - last).max(0.0) as f32);
+        self.last = Some(now);
+        self.frame += 1;
+        self.real_delta = real;
+        self.real_elapsed += real as f64;
+
+        if let Some(step) = self.fixed_delta {
+            let advance = !self.paused || std::mem::replace(&mut self.step_requested, false);
+            self.delta = if advance {
+                self.simulation_frame += 1;
+                step
+            } else {
+                0.0
+            };
+            // counted in frames rather than summed, so it does not drift from the frame index
+            self.elapsed = self.simulation_frame as f64 * step as f64;
+            return self.delta;
+        }
+
+        self.delta = if !self.paused {
+            real.min(self.max_delta) * self.scale
+        } else if self.step_requested {
+            self.step_requested = false;
+            self.step_length
+        } else {
+            0.0
+        };
+        self.elapsed += self.delta as f64;
+        if self.delta > 0.0 {
+            self.simulation_frame += 1;
+        }
+        self.delta
+    }
+
+    // game seconds of this frame, 0 while paused
+    pub fn delta(&self) -> f32 {
+        self.delta
+    }
+
+    // wall clock seconds of this frame, unclamped and unscaled
+    pub fn real_delta(&self) -> f32 {
+        self.real_delta
+    }
+
+    pub fn elapsed(&self) -> f64 {
+        self.elapsed
+    }
+
+    pub fn real_elapsed(&self) -> f64 {
+        self.real_elapsed
+    }
+
+    // ticks so far, paused ones included
+    pub fn frame_count(&self) -> u64 {
+        self.frame
+    }
+
+    // ticks that advanced the game time. index replays and network inputs with this.
+    pub fn simulation_frame(&self) -> u64 {
+        self.simulation_frame
+    }
+
+    // the step of a deterministic time, whose scale is ignored
+    pub fn fixed_delta(&self) -> Option<f32> {
+        self.fixed_delta
+    }
+
+    // 0.5 for half speed slow motion. negative values are taken as 0.
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = scale.max(0.0);
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.step_requested = false;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        if self.paused {
+            self.resume();
+        } else {
+            self.pause();
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    // while paused, the next tick advances the game by `step_length`, for frame by frame debugging
+    pub fn step_frame(&mut self) {
+        if self.paused {
+            self.step_requested = true;
+        }
+    }
+}
+
+// the clock is not saved: the first tick after loading starts timing again
+impl SaveState for Time {
+    fn save_state(&self, snapshot: &mut Snapshot, key: &str) {
+        let field = |name: &str| format!("{}.{}", key, name);
+        snapshot.set(&field("scale"), self.scale);
+        snapshot.set(&field("paused"), self.paused);
+        snapshot.set(&field("elapsed"), self.elapsed);
+        snapshot.set(&field("real_elapsed"), self.real_elapsed);
+        snapshot.set(&field("frame"), self.frame);
+        snapshot.set(&field("simulation_frame"), self.simulation_frame);
+    }
+
+    fn load_state(&mut self, snapshot: &Snapshot, key: &str) -> Result<(), SnapshotError> {
+        let field = |name: &str| format!("{}.{}", key, name);
+        self.scale = snapshot.get(&field("scale"))?;
+        self.paused = snapshot.get(&field("paused"))?;
+        self.elapsed = snapshot.get(&field("elapsed"))?;
+        self.real_elapsed = snapshot.get(&field("real_elapsed"))?;
+        self.frame = snapshot.get(&field("frame"))?;
+        self.simulation_frame = snapshot.get(&field("simulation_frame"))?;
+        self.step_requested = false;
+        self.last = None;
+        self.delta = 0.0;
+        self.real_delta = 0.0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_tick_only_starts_the_clock() {
+        let mut time = Time::new();
+        assert_eq!(time.tick(10.0), 0.0);
+        assert!((time.tick(10.5) - 0.25).abs() < 1e-6, "clamped to max_delta");
+        assert!((time.tick(10.6) - 0.1).abs() < 1e-5);
+        assert_eq!(time.frame_count(), 3);
+        assert!((time.real_elapsed() - 0.6).abs() < 1e-5);
+        assert!((time.elapsed() - 0.35).abs() < 1e-5);
+    }
+
+    #[test]
+    fn scale_and_pause() {
+        let mut time = Time::new();
+        time.tick(0.0);
+        time.set_scale(0.5);
+        assert!((time.tick(0.1) - 0.05).abs() < 1e-6);
+        assert!((time.real_delta() - 0.1).abs() < 1e-6);
+        time.set_scale(-1.0);
+        assert_eq!(time.scale(), 0.0);
+        time.set_scale(1.0);
+
+        time.pause();
+        assert_eq!(time.tick(0.2), 0.0);
+        time.step_frame();
+        assert_eq!(time.tick(0.3), time.step_length);
+        assert_eq!(time.tick(0.4), 0.0);
+        time.toggle_pause();
+        assert!(!time.is_paused());
+        assert!(time.tick(0.5) > 0.0);
+    }
+
+    #[test]
+    fn the_first_tick_is_no_simulation_frame() {
+        let mut time = Time::new();
+        time.tick(10.0);
+        assert_eq!(time.simulation_frame(), 0);
+        time.tick(10.1);
+        time.tick(10.2);
+        assert_eq!((time.frame_count(), time.simulation_frame()), (3, 2));
+    }
+
+    #[test]
+    fn deterministic_time_ignores_the_clock() {
+        let mut time = Time::deterministic(0.02);
+        time.set_scale(3.0);
+        for &now in &[0.0, 5.0, 5.001] {
+            assert_eq!(time.tick(now), 0.02);
+        }
+        time.pause();
+        assert_eq!(time.tick(6.0), 0.0);
+        time.step_frame();
+        assert_eq!(time.tick(7.0), 0.02);
+        assert_eq!(time.simulation_frame(), 4);
+        assert_eq!(time.elapsed(), 4.0 * 0.02f32 as f64);
+    }
+
+    #[test]
+    fn snapshots_restart_the_clock() {
+        let mut time = Time::new();
+        time.tick(0.0);
+        time.tick(0.1);
+        time.pause();
+        let mut snapshot = Snapshot::new();
+        snapshot.capture("time", &time);
+
+        let mut loaded = Time::new();
+        snapshot.restore("time", &mut loaded).unwrap();
+        assert!(loaded.is_paused());
+        assert_eq!(loaded.elapsed(), time.elapsed());
+        assert_eq!(loaded.frame_count(), 2);
+        // the first tick after loading does not count the time in between
+        loaded.resume();
+        assert_eq!(loaded.tick(100.0), 0.0);
+    }
+}
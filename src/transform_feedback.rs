@@ -0,0 +1,153 @@
+use gl::types::*;
+
+use crate::context::check_render_thread;
+use crate::{GlContext, GpuInfo};
+
+// the primitive mode captured between `begin` and `end`. draw calls must use a matching mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FeedbackPrimitive {
+    Points,
+    Lines,
+    Triangles,
+}
+
+impl FeedbackPrimitive {
+    fn to_gl(self) -> GLenum {
+        match self {
+            FeedbackPrimitive::Points => gl::POINTS,
+            FeedbackPrimitive::Lines => gl::LINES,
+            FeedbackPrimitive::Triangles => gl::TRIANGLES,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Binding {
+    buffer: GLuint,
+    offset: usize,
+    // None binds the whole buffer
+    size: Option<usize>,
+}
+
+// captures the varyings declared with `ShaderBuilder::transform_feedback_varyings` into buffers.
+// uses a transform feedback object where available, on plain GL 3.3 the bindings are
+// re-applied to the default object at every `begin`.
+#[derive(Debug)]
+pub struct TransformFeedback {
+    // 0 when transform feedback objects are not supported
+    id: GLuint,
+    bindings: Vec<Option<Binding>>,
+    active: Option<FeedbackPrimitive>,
+    discard: bool,
+}
+
+impl TransformFeedback {
+    pub fn new(_context: &GlContext) -> Self {
+        let mut id = 0;
+        unsafe {
+            if GpuInfo::current().features.transform_feedback_objects {
+                gl::GenTransformFeedbacks(1, &mut id);
+            }
+        }
+        Self {
+            id,
+            bindings: vec![],
+            active: None,
+            discard: false,
+        }
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+
+    // captures into the whole of `buffer` at binding `index`.
+    // the index is the position of the varying when the mode is `Separate`, 0 for `Interleaved`.
+    pub fn bind_buffer(&mut self, index: GLuint, buffer: GLuint) {
+        self.set_binding(index, Binding { buffer, offset: 0, size: None });
+    }
+
+    // `offset` must be a multiple of 4
+    pub fn bind_buffer_range(&mut self, index: GLuint, buffer: GLuint, offset: usize, size: usize) {
+        self.set_binding(index, Binding { buffer, offset, size: Some(size) });
+    }
+
+    fn set_binding(&mut self, index: GLuint, binding: Binding) {
+        assert!(self.active.is_none(), "cannot rebind transform feedback buffers while capturing");
+        let index = index as usize;
+        if self.bindings.len() <= index {
+            self.bindings.resize(index + 1, None);
+        }
+        self.bindings[index] = Some(binding);
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active.is_some()
+    }
+
+    // starts capturing. the capturing program must be in use before this.
+    // with `discard` set nothing is rasterized, for passes that only process vertices.
+    pub fn begin(&mut self, _context: &GlContext, primitive: FeedbackPrimitive, discard: bool) {
+        check_render_thread("TransformFeedback");
+        assert!(self.active.is_none(), "transform feedback is already active");
+        unsafe {
+            if self.id != 0 {
+                gl::BindTransformFeedback(gl::TRANSFORM_FEEDBACK, self.id);
+            }
+            for (index, binding) in self.bindings.iter().enumerate() {
+                if let Some(binding) = binding {
+                    match binding.size {
+                        Some(size) => gl::BindBufferRange(
+                            gl::TRANSFORM_FEEDBACK_BUFFER,
+                            conv!(index),
+                            binding.buffer,
+                            conv!(binding.offset),
+                            conv!(size),
+                        ),
+                        None => gl::BindBufferBase(gl::TRANSFORM_FEEDBACK_BUFFER, conv!(index), binding.buffer),
+                    }
+                }
+            }
+            if discard {
+                gl::Enable(gl::RASTERIZER_DISCARD);
+            }
+            gl::BeginTransformFeedback(primitive.to_gl());
+        }
+        self.active = Some(primitive);
+        self.discard = discard;
+    }
+
+    pub fn end(&mut self, _context: &GlContext) {
+        check_render_thread("TransformFeedback");
+        assert!(self.active.is_some(), "transform feedback is not active");
+        unsafe {
+            gl::EndTransformFeedback();
+            if self.discard {
+                gl::Disable(gl::RASTERIZER_DISCARD);
+            }
+            if self.id != 0 {
+                gl::BindTransformFeedback(gl::TRANSFORM_FEEDBACK, 0);
+            }
+        }
+        self.active = None;
+        self.discard = false;
+    }
+
+    // begin, run `f`, end
+    pub fn capture<F: FnOnce()>(&mut self, context: &GlContext, primitive: FeedbackPrimitive, discard: bool, f: F) {
+        self.begin(context, primitive, discard);
+        f();
+        self.end(context);
+    }
+}
+
+impl Drop for TransformFeedback {
+    fn drop(&mut self) {
+        if self.id != 0 {
+            check_render_thread("TransformFeedback");
+            unsafe {
+                gl::DeleteTransformFeedbacks(1, &self.id);
+            }
+        }
+    }
+}
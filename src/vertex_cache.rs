@@ -0,0 +1,252 @@
+use std::collections::{HashMap, VecDeque};
+
+use gl::types::*;
+
+use crate::{MeshData, Vertex};
+
+// the post transform cache modelled by the optimizer, bigger than most hardware has
+const CACHE_SIZE: usize = 32;
+
+// Tom Forsyth's "Linear-Speed Vertex Cache Optimisation" scoring
+const CACHE_DECAY_POWER: f32 = 1.5;
+const LAST_TRIANGLE_SCORE: f32 = 0.75;
+const VALENCE_BOOST_SCALE: f32 = 2.0;
+const VALENCE_BOOST_POWER: f32 = 0.5;
+
+fn vertex_score(cache_position: Option<usize>, remaining: usize) -> f32 {
+    if remaining == 0 {
+        return -1.0;
+    }
+    let cache = match cache_position {
+        // the triangle just drawn, using its verticies again is no better than anywhere else in the cache
+        Some(position) if position < 3 => LAST_TRIANGLE_SCORE,
+        Some(position) => (1.0 - (position - 3) as f32 / (CACHE_SIZE - 3) as f32).powf(CACHE_DECAY_POWER),
+        None => 0.0,
+    };
+    // verticies with few triangles left are finished off so they can leave the cache
+    cache + VALENCE_BOOST_SCALE * (remaining as f32).powf(-VALENCE_BOOST_POWER)
+}
+
+impl MeshData {
+    // a copy where verticies that are identical in every attribute are stored once
+    pub fn deduplicate(&self) -> Self {
+        let mut unique: HashMap<[u32; 18], GLuint> = HashMap::new();
+        let mut verticies: Vec<Vertex> = vec![];
+        let indices = self
+            .indices
+            .iter()
+            .map(|&index| {
+                let vertex = &self.verticies[index as usize];
+                *unique.entry(vertex_key(vertex)).or_insert_with(|| {
+                    verticies.push(*vertex);
+                    conv!(verticies.len() - 1)
+                })
+            })
+            .collect();
+        Self::new(verticies, indices)
+    }
+
+    // the same triangles reordered so verticies are reused while still in the post transform
+    // cache, then the verticies reordered by first use for fetching. renders the same.
+    pub fn optimize_vertex_cache(&self) -> Self {
+        let triangle_count = self.triangle_count();
+        let vertex_count = self.verticies.len();
+
+        let mut vertex_triangles: Vec<Vec<usize>> = vec![vec![]; vertex_count];
+        for (triangle, face) in self.indices.chunks_exact(3).enumerate() {
+            for &index in face.iter() {
+                vertex_triangles[index as usize].push(triangle);
+            }
+        }
+        let mut remaining: Vec<usize> = vertex_triangles.iter().map(Vec::len).collect();
+        let mut scores: Vec<f32> = remaining.iter().map(|&remaining| vertex_score(None, remaining)).collect();
+        let triangle_score = |face: &[GLuint], scores: &[f32]| face.iter().map(|&index| scores[index as usize]).sum::<f32>();
+        let mut emitted = vec![false; triangle_count];
+
+        let mut cache: Vec<GLuint> = Vec::with_capacity(CACHE_SIZE + 3);
+        let mut indices: Vec<GLuint> = Vec::with_capacity(self.indices.len());
+        let mut next_unemitted = 0;
+        let mut best: Option<usize> = None;
+
+        for _ in 0..triangle_count {
+            let triangle = match best {
+                Some(triangle) => triangle,
+                // nothing around the cache is left, e.g. at the end of a disconnected piece
+                None => {
+                    while emitted[next_unemitted] {
+                        next_unemitted += 1;
+                    }
+                    next_unemitted
+                }
+            };
+            emitted[triangle] = true;
+            let face = &self.indices[3 * triangle..3 * triangle + 3];
+            indices.extend_from_slice(face);
+
+            // the triangle's verticies move to the front of the cache
+            for &index in face.iter() {
+                remaining[index as usize] -= 1;
+                cache.retain(|&cached| cached != index);
+            }
+            let previous = std::mem::take(&mut cache);
+            cache.extend_from_slice(face);
+            cache.extend(previous);
+
+            for (position, &index) in cache.iter().enumerate() {
+                let position = if position < CACHE_SIZE { Some(position) } else { None };
+                scores[index as usize] = vertex_score(position, remaining[index as usize]);
+            }
+
+            best = None;
+            let mut best_score = f32::NEG_INFINITY;
+            for &index in cache.iter() {
+                for &other in vertex_triangles[index as usize].iter() {
+                    if emitted[other] {
+                        continue;
+                    }
+                    let score = triangle_score(&self.indices[3 * other..3 * other + 3], &scores);
+                    if score > best_score {
+                        best_score = score;
+                        best = Some(other);
+                    }
+                }
+            }
+            cache.truncate(CACHE_SIZE);
+        }
+
+        // verticies in the order the triangles first use them, unused ones dropped
+        let mut renumbered: Vec<Option<GLuint>> = vec![None; vertex_count];
+        let mut verticies: Vec<Vertex> = Vec::with_capacity(vertex_count);
+        for index in indices.iter_mut() {
+            let vertex = &self.verticies[*index as usize];
+            *index = *renumbered[*index as usize].get_or_insert_with(|| {
+                verticies.push(*vertex);
+                conv!(verticies.len() - 1)
+            });
+        }
+        Self::new(verticies, indices)
+    }
+
+    // the average number of vertex shader runs per triangle with a FIFO cache of `cache_size`,
+    // between 0.5 for an ideal grid and 3
+    pub fn average_cache_miss_ratio(&self, cache_size: usize) -> f32 {
+        if self.indices.is_empty() {
+            return 0.0;
+        }
+        let mut cache: VecDeque<GLuint> = VecDeque::with_capacity(cache_size);
+        let mut misses = 0;
+        for &index in self.indices.iter() {
+            if !cache.contains(&index) {
+                misses += 1;
+                if cache.len() == cache_size {
+                    cache.pop_front();
+                }
+                cache.push_back(index);
+            }
+        }
+        misses as f32 / self.triangle_count() as f32
+    }
+}
+
+fn vertex_key(vertex: &Vertex) -> [u32; 18] {
+    let mut key = [0; 18];
+    let floats = [
+        vertex.position.x,
+        vertex.position.y,
+        vertex.position.z,
+        vertex.normal.x,
+        vertex.normal.y,
+        vertex.normal.z,
+        vertex.tex_coords.x,
+        vertex.tex_coords.y,
+        vertex.color.x,
+        vertex.color.y,
+        vertex.color.z,
+        vertex.color.w,
+        vertex.lightmap_coords.x,
+        vertex.lightmap_coords.y,
+        vertex.tangent.x,
+        vertex.tangent.y,
+        vertex.tangent.z,
+        vertex.tangent.w,
+    ];
+    for (key, float) in key.iter_mut().zip(floats.iter()) {
+        *key = float.to_bits();
+    }
+    key
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::vec3;
+
+    use crate::mesh_data::tests::grid;
+    use crate::MeshData;
+
+    // the triangles as sets of positions, in any order
+    fn triangles(data: &MeshData) -> Vec<[[u32; 3]; 3]> {
+        let mut triangles: Vec<_> = data
+            .indices
+            .chunks_exact(3)
+            .map(|face| {
+                let key = |i: usize| {
+                    let p = data.verticies[face[i] as usize].position;
+                    [p.x.to_bits(), p.y.to_bits(), p.z.to_bits()]
+                };
+                // rotated so the smallest corner comes first, which keeps the winding
+                let corners = [key(0), key(1), key(2)];
+                let first = (0..3).min_by_key(|&i| corners[i]).unwrap();
+                [corners[first], corners[(first + 1) % 3], corners[(first + 2) % 3]]
+            })
+            .collect();
+        triangles.sort();
+        triangles
+    }
+
+    #[test]
+    fn deduplicate_merges_identical_verticies() {
+        let grid = grid(3);
+        // every corner its own vertex
+        let split = MeshData::new(grid.indices.iter().map(|&i| grid.verticies[i as usize]).collect(), (0..grid.indices.len() as u32).collect());
+        let merged = split.deduplicate();
+        assert_eq!(merged.verticies.len(), grid.verticies.len());
+        assert_eq!(triangles(&merged), triangles(&grid));
+    }
+
+    #[test]
+    fn deduplicate_keeps_verticies_that_differ() {
+        let mut grid = grid(1);
+        let copy = grid.verticies[0];
+        grid.verticies.push(crate::Vertex { normal: vec3(1.0, 0.0, 0.0), ..copy });
+        grid.indices[3] = 4;
+        assert_eq!(grid.deduplicate().verticies.len(), 5);
+    }
+
+    #[test]
+    fn optimizing_keeps_the_triangles_and_lowers_misses() {
+        // the faces shuffled into a bad order, 37 is coprime to the 512 triangles
+        let mut grid = grid(16);
+        let faces: Vec<[u32; 3]> = grid.indices.chunks_exact(3).map(|f| [f[0], f[1], f[2]]).collect();
+        grid.indices = (0..faces.len()).flat_map(|i| faces[i * 37 % faces.len()].to_vec()).collect();
+
+        let optimized = grid.optimize_vertex_cache();
+        assert_eq!(triangles(&optimized), triangles(&grid));
+        assert!(optimized.average_cache_miss_ratio(16) < grid.average_cache_miss_ratio(16));
+        assert!(optimized.average_cache_miss_ratio(16) < 1.0);
+        // verticies come in the order they are first used
+        let mut next = 0;
+        for &index in optimized.indices.iter() {
+            assert!(index <= next);
+            if index == next {
+                next += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn miss_ratio_of_a_single_triangle() {
+        let triangle = MeshData::new(grid(1).verticies, vec![0, 1, 2]);
+        assert_eq!(triangle.average_cache_miss_ratio(16), 3.0);
+        assert_eq!(MeshData::default().average_cache_miss_ratio(16), 0.0);
+    }
+}
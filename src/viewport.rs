@@ -0,0 +1,338 @@
+use std::mem;
+use std::ptr;
+
+use cgmath::{Matrix, Matrix4, Point2, Point3};
+use gl::types::*;
+
+use crate::context::check_render_thread;
+use crate::FrameStats;
+
+// rectangle with fractional coordinates, origin at the bottom left. either in pixels or as
+// fractions of a framebuffer, see `Viewport::from_fraction`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Rect {
+    pub fn new(x: f32, y: f32, width: f32, height: f32) -> Self {
+        Self { x, y, width, height }
+    }
+
+    // the whole of a framebuffer, as a fraction
+    pub fn unit() -> Self {
+        Self::new(0.0, 0.0, 1.0, 1.0)
+    }
+
+    pub fn center(&self) -> Point2<f32> {
+        Point2::new(self.x + self.width * 0.5, self.y + self.height * 0.5)
+    }
+
+    pub fn contains(&self, point: Point2<f32>) -> bool {
+        point.x >= self.x && point.y >= self.y && point.x < self.x + self.width && point.y < self.y + self.height
+    }
+
+    pub fn intersection(&self, other: &Rect) -> Option<Rect> {
+        let (x0, y0) = (self.x.max(other.x), self.y.max(other.y));
+        let (x1, y1) = ((self.x + self.width).min(other.x + other.width), (self.y + self.height).min(other.y + other.height));
+        if x1 > x0 && y1 > y0 {
+            Some(Rect::new(x0, y0, x1 - x0, y1 - y0))
+        } else {
+            None
+        }
+    }
+}
+
+// rectangle of the framebuffer in pixels, origin at the bottom left like glViewport
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Viewport {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Viewport {
+    pub fn new(x: i32, y: i32, width: i32, height: i32) -> Self {
+        Self { x, y, width, height }
+    }
+
+    pub fn full(width: i32, height: i32) -> Self {
+        Self::new(0, 0, width, height)
+    }
+
+    pub fn aspect(&self) -> f32 {
+        self.width as f32 / self.height.max(1) as f32
+    }
+
+    // `fraction` of a framebuffer, rounded so neighbouring fractions share their edges
+    pub fn from_fraction(fraction: &Rect, framebuffer_width: i32, framebuffer_height: i32) -> Self {
+        let (width, height) = (framebuffer_width as f32, framebuffer_height as f32);
+        let x0 = (fraction.x * width).round() as i32;
+        let y0 = (fraction.y * height).round() as i32;
+        let x1 = ((fraction.x + fraction.width) * width).round() as i32;
+        let y1 = ((fraction.y + fraction.height) * height).round() as i32;
+        Self::new(x0, y0, x1 - x0, y1 - y0)
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.x as f32, self.y as f32, self.width as f32, self.height as f32)
+    }
+
+    pub fn contains(&self, pixel: Point2<f32>) -> bool {
+        self.rect().contains(pixel)
+    }
+
+    // the largest rectangle of `aspect` centered in the framebuffer, with bars on the top and
+    // bottom (letterbox) or the sides (pillarbox) where the shapes differ
+    pub fn fit(aspect: f32, framebuffer_width: i32, framebuffer_height: i32) -> Self {
+        let full = framebuffer_width as f32 / framebuffer_height.max(1) as f32;
+        if aspect <= 0.0 || (full - aspect).abs() < 1e-6 {
+            return Self::full(framebuffer_width, framebuffer_height);
+        }
+        let (width, height) = if aspect < full {
+            (((framebuffer_height as f32) * aspect).round() as i32, framebuffer_height)
+        } else {
+            (framebuffer_width, ((framebuffer_width as f32) / aspect).round() as i32)
+        };
+        Self::new((framebuffer_width - width) / 2, (framebuffer_height - height) / 2, width, height)
+    }
+
+    // like `fit` for content of `width` by `height` pixels, but only scaled by whole numbers so
+    // pixel art stays sharp. content larger than the framebuffer keeps a scale of one.
+    pub fn fit_pixel_perfect(width: i32, height: i32, framebuffer_width: i32, framebuffer_height: i32) -> Self {
+        let scale = (framebuffer_width / width.max(1)).min(framebuffer_height / height.max(1)).max(1);
+        let (width, height) = (width * scale, height * scale);
+        Self::new((framebuffer_width - width) / 2, (framebuffer_height - height) / 2, width, height)
+    }
+
+    // the parts of the framebuffer outside the viewport, to clear them after a `fit`
+    pub fn bars(&self, framebuffer_width: i32, framebuffer_height: i32) -> Vec<Self> {
+        let (right, top) = (self.x + self.width, self.y + self.height);
+        let bars = [
+            Self::new(0, 0, framebuffer_width, self.y),
+            Self::new(0, top, framebuffer_width, framebuffer_height - top),
+            Self::new(0, self.y, self.x, self.height),
+            Self::new(right, self.y, framebuffer_width - right, self.height),
+        ];
+        bars.iter().copied().filter(|bar| bar.width > 0 && bar.height > 0).collect()
+    }
+
+    // framebuffer pixels to normalized device coordinates of this viewport, -1 to 1 inside it
+    pub fn to_ndc(&self, pixel: Point2<f32>) -> Point2<f32> {
+        Point2::new(
+            (pixel.x - self.x as f32) / self.width.max(1) as f32 * 2.0 - 1.0,
+            (pixel.y - self.y as f32) / self.height.max(1) as f32 * 2.0 - 1.0,
+        )
+    }
+
+    pub fn from_ndc(&self, ndc: Point2<f32>) -> Point2<f32> {
+        Point2::new(
+            self.x as f32 + (ndc.x + 1.0) * 0.5 * self.width as f32,
+            self.y as f32 + (ndc.y + 1.0) * 0.5 * self.height as f32,
+        )
+    }
+
+    // a cursor position from glfw, in screen coordinates with the origin at the top left, to
+    // framebuffer pixels. the sizes differ on high dpi displays.
+    pub fn cursor_to_pixel(cursor: (f64, f64), window_size: (i32, i32), framebuffer_size: (i32, i32)) -> Point2<f32> {
+        let scale_x = framebuffer_size.0 as f64 / window_size.0.max(1) as f64;
+        let scale_y = framebuffer_size.1 as f64 / window_size.1.max(1) as f64;
+        Point2::new((cursor.0 * scale_x) as f32, (framebuffer_size.1 as f64 - cursor.1 * scale_y) as f32)
+    }
+
+    // the layout for local multiplayer: one view fills the screen, two are stacked top to bottom,
+    // three and four share the quadrants (the fourth stays empty with three players)
+    pub fn split_screen(width: i32, height: i32, players: usize) -> Vec<Self> {
+        let (half_width, half_height) = (width / 2, height / 2);
+        match players {
+            0 => vec![],
+            1 => vec![Self::full(width, height)],
+            2 => vec![
+                Self::new(0, half_height, width, height - half_height),
+                Self::new(0, 0, width, half_height),
+            ],
+            _ => {
+                let quadrants = [
+                    Self::new(0, half_height, half_width, height - half_height),
+                    Self::new(half_width, half_height, width - half_width, height - half_height),
+                    Self::new(0, 0, half_width, half_height),
+                    Self::new(half_width, 0, width - half_width, half_height),
+                ];
+                quadrants.iter().copied().take(players.min(4)).collect()
+            }
+        }
+    }
+
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn current() -> Self {
+        let mut viewport = [0; 4];
+        gl::GetIntegerv(gl::VIEWPORT, viewport.as_mut_ptr());
+        Self::new(viewport[0], viewport[1], viewport[2], viewport[3])
+    }
+
+    /// sets the viewport and the scissor rectangle, so clears stay inside
+    ///
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn apply(&self) {
+        gl::Viewport(self.x, self.y, self.width, self.height);
+        gl::Scissor(self.x, self.y, self.width, self.height);
+    }
+
+    /// clears only this rectangle to `color`, the scissor test and rectangle are restored afterwards
+    ///
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn clear<C: Into<[f32; 4]>>(&self, color: C) {
+        let color = color.into();
+        let scissor = gl::IsEnabled(gl::SCISSOR_TEST) == gl::TRUE;
+        let mut previous = [0; 4];
+        gl::GetIntegerv(gl::SCISSOR_BOX, previous.as_mut_ptr());
+        gl::Enable(gl::SCISSOR_TEST);
+        gl::Scissor(self.x, self.y, self.width, self.height);
+        gl::ClearColor(color[0], color[1], color[2], color[3]);
+        gl::Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        gl::Scissor(previous[0], previous[1], previous[2], previous[3]);
+        if !scissor {
+            gl::Disable(gl::SCISSOR_TEST);
+        }
+    }
+}
+
+// GLSL for the block `ViewUniforms` writes. bind it with `Shader::bind_uniform_block(c_str!("View"), binding)`.
+pub const VIEW_UNIFORMS_GLSL: &str = r#"
+layout (std140) uniform View {
+    mat4 view;
+    mat4 projection;
+    vec4 cameraPosition;
+};
+"#;
+
+// uniform buffer holding the camera of the view being rendered
+#[derive(Debug)]
+pub struct ViewUniforms {
+    ubo: GLuint,
+    binding: GLuint,
+}
+
+const VIEW_UNIFORMS_SIZE: usize = 2 * mem::size_of::<Matrix4<f32>>() + 4 * mem::size_of::<f32>();
+
+impl ViewUniforms {
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn new(binding: GLuint) -> Self {
+        let mut ubo = 0;
+        gl::GenBuffers(1, &mut ubo);
+        gl::BindBuffer(gl::UNIFORM_BUFFER, ubo);
+        gl::BufferData(gl::UNIFORM_BUFFER, conv!(VIEW_UNIFORMS_SIZE), ptr::null(), gl::DYNAMIC_DRAW);
+        gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+        gl::BindBufferBase(gl::UNIFORM_BUFFER, binding, ubo);
+        Self { ubo, binding }
+    }
+
+    pub fn binding(&self) -> GLuint {
+        self.binding
+    }
+
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn update(&self, view: &Matrix4<f32>, projection: &Matrix4<f32>, camera_position: Point3<f32>) {
+        check_render_thread("ViewUniforms");
+        let matrix_size = mem::size_of::<Matrix4<f32>>();
+        let position = [camera_position.x, camera_position.y, camera_position.z, 1.0];
+        gl::BindBuffer(gl::UNIFORM_BUFFER, self.ubo);
+        gl::BufferSubData(gl::UNIFORM_BUFFER, 0, conv!(matrix_size), view.as_ptr() as *const _);
+        gl::BufferSubData(gl::UNIFORM_BUFFER, conv!(matrix_size), conv!(matrix_size), projection.as_ptr() as *const _);
+        gl::BufferSubData(gl::UNIFORM_BUFFER, conv!(2 * matrix_size), conv!(mem::size_of_val(&position)), position.as_ptr() as *const _);
+        gl::BindBuffer(gl::UNIFORM_BUFFER, 0);
+        FrameStats::record_buffer_upload(VIEW_UNIFORMS_SIZE);
+    }
+}
+
+impl Drop for ViewUniforms {
+    fn drop(&mut self) {
+        check_render_thread("ViewUniforms");
+        unsafe {
+            gl::DeleteBuffers(1, &self.ubo);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_screen_covers_the_screen() {
+        assert!(Viewport::split_screen(801, 601, 0).is_empty());
+        for players in 1..=4 {
+            let views = Viewport::split_screen(801, 601, players);
+            assert_eq!(views.len(), players);
+            if players != 3 {
+                let area: i32 = views.iter().map(|view| view.width * view.height).sum();
+                assert_eq!(area, 801 * 601);
+            }
+        }
+        // the first player is on top
+        assert_eq!(Viewport::split_screen(800, 600, 2)[0], Viewport::new(0, 300, 800, 300));
+        assert_eq!(Viewport::split_screen(800, 600, 9).len(), 4);
+    }
+
+    #[test]
+    fn fit_letterboxes_and_pillarboxes() {
+        assert_eq!(Viewport::fit(16.0 / 9.0, 1920, 1080), Viewport::full(1920, 1080));
+        let letterbox = Viewport::fit(2.0, 1600, 1000);
+        assert_eq!(letterbox, Viewport::new(0, 100, 1600, 800));
+        assert_eq!(letterbox.bars(1600, 1000), vec![Viewport::new(0, 0, 1600, 100), Viewport::new(0, 900, 1600, 100)]);
+        let pillarbox = Viewport::fit(1.0, 1600, 1000);
+        assert_eq!(pillarbox, Viewport::new(300, 0, 1000, 1000));
+        assert_eq!(pillarbox.bars(1600, 1000), vec![Viewport::new(0, 0, 300, 1000), Viewport::new(1300, 0, 300, 1000)]);
+        assert!(Viewport::full(10, 10).bars(10, 10).is_empty());
+    }
+
+    #[test]
+    fn pixel_perfect_scales_by_whole_numbers() {
+        assert_eq!(Viewport::fit_pixel_perfect(320, 180, 1920, 1080), Viewport::full(1920, 1080));
+        assert_eq!(Viewport::fit_pixel_perfect(320, 180, 1000, 1000), Viewport::new(20, 230, 960, 540));
+        assert_eq!(Viewport::fit_pixel_perfect(320, 180, 200, 100), Viewport::new(-60, -40, 320, 180));
+    }
+
+    #[test]
+    fn fractions_share_their_edges() {
+        let (width, height) = (1001, 777);
+        let left = Viewport::from_fraction(&Rect::new(0.0, 0.0, 1.0 / 3.0, 1.0), width, height);
+        let right = Viewport::from_fraction(&Rect::new(1.0 / 3.0, 0.0, 2.0 / 3.0, 1.0), width, height);
+        assert_eq!(left.x + left.width, right.x);
+        assert_eq!(right.x + right.width, width);
+        assert_eq!(Viewport::from_fraction(&Rect::unit(), width, height), Viewport::full(width, height));
+    }
+
+    #[test]
+    fn pixels_and_ndc() {
+        let viewport = Viewport::new(100, 50, 200, 100);
+        assert_eq!(viewport.to_ndc(Point2::new(200.0, 100.0)), Point2::new(0.0, 0.0));
+        assert_eq!(viewport.to_ndc(Point2::new(100.0, 150.0)), Point2::new(-1.0, 1.0));
+        assert_eq!(viewport.from_ndc(Point2::new(1.0, -1.0)), Point2::new(300.0, 50.0));
+        assert!(viewport.contains(Point2::new(100.0, 50.0)));
+        assert!(!viewport.contains(Point2::new(300.0, 50.0)));
+        assert_eq!(viewport.aspect(), 2.0);
+
+        // a high dpi window, the cursor's origin at the top left
+        assert_eq!(Viewport::cursor_to_pixel((100.0, 50.0), (800, 600), (1600, 1200)), Point2::new(200.0, 1100.0));
+        assert_eq!(
+            Rect::new(0.0, 0.0, 2.0, 2.0).intersection(&Rect::new(1.0, 1.0, 2.0, 2.0)),
+            Some(Rect::new(1.0, 1.0, 1.0, 1.0))
+        );
+        assert_eq!(Rect::unit().intersection(&Rect::new(1.0, 0.0, 1.0, 1.0)), None);
+    }
+}
@@ -0,0 +1,167 @@
+use cgmath::{InnerSpace, Matrix4, SquareMatrix, Vector3, vec3};
+use gl::types::*;
+
+use crate::post::bind_texture;
+use crate::{c_str, FullscreenQuad, PostContext, PostEffect, Shader, ShadowMap, FULLSCREEN_VERTEX_SHADER};
+
+const VOLUMETRIC_FOG_FRAGMENT_SHADER: &str = r#"
+#version 330 core
+
+in vec2 TexCoords;
+out vec4 FragColor;
+
+uniform sampler2D screenColor;
+uniform sampler2D screenDepth;
+uniform sampler2D shadowMap;
+uniform bool hasShadow;
+uniform mat4 lightSpace;
+uniform mat4 inverseViewProjection;
+
+uniform vec3 lightDirection;
+uniform vec3 lightColor;
+uniform vec3 ambientColor;
+uniform float density;
+uniform float heightFalloff;
+uniform float baseHeight;
+uniform float anisotropy;
+uniform float maxDistance;
+uniform int steps;
+
+const float PI = 3.14159265;
+
+vec3 WorldPosition(float depth) {
+    vec4 world = inverseViewProjection * vec4(TexCoords * 2.0 - 1.0, depth * 2.0 - 1.0, 1.0);
+    return world.xyz / world.w;
+}
+
+float Visibility(vec3 position) {
+    if (!hasShadow) {
+        return 1.0;
+    }
+    vec4 projected = lightSpace * vec4(position, 1.0);
+    vec3 coords = projected.xyz / projected.w * 0.5 + 0.5;
+    if (coords.z > 1.0) {
+        return 1.0;
+    }
+    return coords.z - 0.002 > texture(shadowMap, coords.xy).r ? 0.0 : 1.0;
+}
+
+// Henyey-Greenstein
+float Phase(float cosTheta) {
+    float g2 = anisotropy * anisotropy;
+    return (1.0 - g2) / (4.0 * PI * pow(1.0 + g2 - 2.0 * anisotropy * cosTheta, 1.5));
+}
+
+void main() {
+    vec3 color = texture(screenColor, TexCoords).rgb;
+    vec3 start = WorldPosition(0.0);
+    vec3 end = WorldPosition(texture(screenDepth, TexCoords).r);
+
+    vec3 ray = end - start;
+    float rayLength = min(length(ray), maxDistance);
+    vec3 rayDir = normalize(ray);
+    float stepLength = rayLength / float(steps);
+    float phase = Phase(dot(rayDir, -lightDirection));
+
+    // dither the start to trade banding for noise
+    float jitter = fract(sin(dot(gl_FragCoord.xy, vec2(12.9898, 78.233))) * 43758.5453);
+
+    float transmittance = 1.0;
+    vec3 scattered = vec3(0.0);
+    for (int i = 0; i < steps; i++) {
+        vec3 position = start + rayDir * stepLength * (float(i) + jitter);
+        float localDensity = density * exp(-heightFalloff * (position.y - baseHeight));
+        float extinction = exp(-localDensity * stepLength);
+
+        vec3 inScatter = (lightColor * phase * Visibility(position) + ambientColor) * localDensity;
+        // integrate the scattering over the step analytically
+        scattered += transmittance * inScatter * (1.0 - extinction) / max(localDensity, 0.00001);
+        transmittance *= extinction;
+    }
+
+    FragColor = vec4(color * transmittance + scattered, 1.0);
+}
+"#;
+
+// raymarched height fog lit by a directional light. push it first so it composites before the other effects.
+#[derive(Debug)]
+pub struct VolumetricFog {
+    shader: Shader,
+    quad: FullscreenQuad,
+    shadow: Option<(GLuint, Matrix4<f32>)>,
+    // direction the light travels in
+    pub light_direction: Vector3<f32>,
+    pub light_color: Vector3<f32>,
+    pub ambient_color: Vector3<f32>,
+    // extinction per world unit at `base_height`
+    pub density: f32,
+    pub height_falloff: f32,
+    pub base_height: f32,
+    // -1.0 back scattering, 0.0 isotropic, towards 1.0 forward scattering (stronger shafts)
+    pub anisotropy: f32,
+    pub max_distance: f32,
+    pub steps: u32,
+}
+
+impl VolumetricFog {
+    /// # Safety
+    ///
+    /// a GL context must be current on the calling thread.
+    pub unsafe fn new() -> Self {
+        Self {
+            shader: Shader::from_str(FULLSCREEN_VERTEX_SHADER, VOLUMETRIC_FOG_FRAGMENT_SHADER),
+            quad: FullscreenQuad::new(),
+            shadow: None,
+            light_direction: vec3(-0.2, -1.0, -0.3).normalize(),
+            light_color: vec3(1.0, 1.0, 1.0),
+            ambient_color: vec3(0.05, 0.05, 0.05),
+            density: 0.02,
+            height_falloff: 0.1,
+            base_height: 0.0,
+            anisotropy: 0.6,
+            max_distance: 100.0,
+            steps: 32,
+        }
+    }
+
+    // light shafts need the directional light's shadow map and the matrix it was rendered with
+    pub fn set_shadow(&mut self, shadow: &ShadowMap, light_space: Matrix4<f32>) {
+        self.shadow = Some((shadow.depth_texture(), light_space));
+    }
+
+    pub fn clear_shadow(&mut self) {
+        self.shadow = None;
+    }
+}
+
+impl PostEffect for VolumetricFog {
+    unsafe fn render(&mut self, color: GLuint, depth: GLuint, context: &PostContext) {
+        let inverse = context.view_projection.invert().unwrap_or_else(Matrix4::identity);
+        let direction = self.light_direction.normalize();
+
+        self.shader.use_program();
+        bind_texture(&self.shader, c_str("screenColor\0"), 0, color);
+        bind_texture(&self.shader, c_str("screenDepth\0"), 1, depth);
+        match self.shadow {
+            Some((texture, light_space)) => {
+                bind_texture(&self.shader, c_str("shadowMap\0"), 2, texture);
+                self.shader.set_integer(c_str("hasShadow\0"), 1);
+                self.shader.set_matrix4(c_str("lightSpace\0"), &light_space);
+            }
+            None => self.shader.set_integer(c_str("hasShadow\0"), 0),
+        }
+        gl::ActiveTexture(gl::TEXTURE0);
+
+        self.shader.set_matrix4(c_str("inverseViewProjection\0"), &inverse);
+        self.shader.set_vec3(c_str("lightDirection\0"), direction.x, direction.y, direction.z);
+        self.shader.set_vec3(c_str("lightColor\0"), self.light_color.x, self.light_color.y, self.light_color.z);
+        self.shader.set_vec3(c_str("ambientColor\0"), self.ambient_color.x, self.ambient_color.y, self.ambient_color.z);
+        self.shader.set_float(c_str("density\0"), self.density);
+        self.shader.set_float(c_str("heightFalloff\0"), self.height_falloff);
+        self.shader.set_float(c_str("baseHeight\0"), self.base_height);
+        self.shader.set_float(c_str("anisotropy\0"), self.anisotropy);
+        self.shader.set_float(c_str("maxDistance\0"), self.max_distance);
+        self.shader.set_integer(c_str("steps\0"), conv!(self.steps.max(1)));
+        self.quad.draw();
+    }
+}
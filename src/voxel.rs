@@ -0,0 +1,589 @@
+use std::collections::{HashMap, HashSet};
+
+use cgmath::{InnerSpace, Point3, Vector2, Vector3, Vector4};
+use gl::types::*;
+use image::{DynamicImage, GenericImageView};
+
+use crate::context::check_render_thread;
+use crate::{c_str, AttachmentFormat, Camera, CullResult, FrameStats, GlContext, Mesh, MeshData, Shader, TextureArray, Vertex};
+
+// blocks per chunk side
+pub const VOXEL_CHUNK_SIZE: usize = 16;
+const N: usize = VOXEL_CHUNK_SIZE;
+
+// an index into a `BlockRegistry`, 0 is air
+pub type BlockId = u16;
+pub const AIR: BlockId = 0;
+
+// the texture array layers of a block's faces
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockMaterial {
+    pub top: u32,
+    pub bottom: u32,
+    pub side: u32,
+}
+
+impl BlockMaterial {
+    // the same layer on every face
+    pub fn all(layer: u32) -> Self {
+        Self {
+            top: layer,
+            bottom: layer,
+            side: layer,
+        }
+    }
+}
+
+// the kinds of blocks, every one of them opaque
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BlockRegistry {
+    materials: Vec<(String, BlockMaterial)>,
+}
+
+impl BlockRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, name: &str, material: BlockMaterial) -> BlockId {
+        self.materials.push((name.to_string(), material));
+        conv!(self.materials.len())
+    }
+
+    pub fn find(&self, name: &str) -> Option<BlockId> {
+        self.materials.iter().position(|(existing, _)| existing == name).map(|index| conv!(index + 1))
+    }
+
+    pub fn material(&self, block: BlockId) -> Option<&BlockMaterial> {
+        (block as usize).checked_sub(1).and_then(|index| self.materials.get(index)).map(|(_, material)| material)
+    }
+
+    pub fn name(&self, block: BlockId) -> Option<&str> {
+        (block as usize).checked_sub(1).and_then(|index| self.materials.get(index)).map(|(name, _)| name.as_str())
+    }
+}
+
+// block textures as the layers of one array, in the order given. pixel art stays sharp with
+// nearest filtering, mipmaps keep distant blocks from shimmering.
+pub fn block_textures(context: &GlContext, images: &[DynamicImage]) -> TextureArray {
+    assert!(!images.is_empty(), "no block textures");
+    let (width, height) = images[0].dimensions();
+    let array = TextureArray::new(context, width, height, conv!(images.len()), AttachmentFormat::RGBA8);
+    for (layer, img) in images.iter().enumerate() {
+        array.upload_image(context, conv!(layer), img);
+    }
+    array.generate_mipmaps(context);
+    array.set_filter(context, gl::NEAREST_MIPMAP_LINEAR, gl::NEAREST);
+    array.set_wrap(context, gl::REPEAT);
+    array
+}
+
+// 16^3 blocks, indexed x fastest then z then y
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VoxelChunk {
+    blocks: Vec<BlockId>,
+    solid: usize,
+}
+
+impl Default for VoxelChunk {
+    fn default() -> Self {
+        Self {
+            blocks: vec![AIR; N * N * N],
+            solid: 0,
+        }
+    }
+}
+
+impl VoxelChunk {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn index(x: usize, y: usize, z: usize) -> usize {
+        assert!(x < N && y < N && z < N, "block ({}, {}, {}) outside of the chunk", x, y, z);
+        x + z * N + y * N * N
+    }
+
+    pub fn get(&self, x: usize, y: usize, z: usize) -> BlockId {
+        self.blocks[Self::index(x, y, z)]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, z: usize, block: BlockId) {
+        let slot = &mut self.blocks[Self::index(x, y, z)];
+        self.solid = self.solid + (block != AIR) as usize - (*slot != AIR) as usize;
+        *slot = block;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.solid == 0
+    }
+}
+
+// which side of a block, for the material layer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Face {
+    Top,
+    Bottom,
+    Side,
+}
+
+// the visible faces of `chunk` merged into as few quads as possible: faces of the same layer
+// that face the same way in the same plane become one rectangle, textured per block through
+// repeating coordinates. `solid` answers for blocks in chunk coordinates one outside of it as
+// well, so faces against neighbor chunks are left out. the layer goes in `lightmap_coords.x`.
+pub fn greedy_mesh<F: Fn(i32, i32, i32) -> bool>(chunk: &VoxelChunk, registry: &BlockRegistry, origin: Vector3<f32>, solid: F) -> MeshData {
+    let (mut verticies, mut indices): (Vec<Vertex>, Vec<GLuint>) = (vec![], vec![]);
+    let mut mask = vec![0u32; N * N];
+    for d in 0..3 {
+        let (u, v) = ((d + 1) % 3, (d + 2) % 3);
+        for &dir in [1i32, -1].iter() {
+            let face = match (d, dir) {
+                (1, 1) => Face::Top,
+                (1, _) => Face::Bottom,
+                _ => Face::Side,
+            };
+            for slice in 0..N {
+                // the layer + 1 of every visible face in the slice, 0 for none
+                for j in 0..N {
+                    for i in 0..N {
+                        let mut p = [0usize; 3];
+                        p[d] = slice;
+                        p[u] = i;
+                        p[v] = j;
+                        let block = chunk.get(p[0], p[1], p[2]);
+                        let mut neighbor = [p[0] as i32, p[1] as i32, p[2] as i32];
+                        neighbor[d] += dir;
+                        mask[i + j * N] = match registry.material(block) {
+                            Some(material) if !solid(neighbor[0], neighbor[1], neighbor[2]) => {
+                                1 + match face {
+                                    Face::Top => material.top,
+                                    Face::Bottom => material.bottom,
+                                    Face::Side => material.side,
+                                }
+                            }
+                            _ => 0,
+                        };
+                    }
+                }
+
+                for j in 0..N {
+                    let mut i = 0;
+                    while i < N {
+                        let layer = mask[i + j * N];
+                        if layer == 0 {
+                            i += 1;
+                            continue;
+                        }
+                        let mut width = 1;
+                        while i + width < N && mask[i + width + j * N] == layer {
+                            width += 1;
+                        }
+                        let mut height = 1;
+                        while j + height < N && (i..i + width).all(|k| mask[k + (j + height) * N] == layer) {
+                            height += 1;
+                        }
+                        for row in j..j + height {
+                            for k in i..i + width {
+                                mask[k + row * N] = 0;
+                            }
+                        }
+
+                        let mut base = [0.0f32; 3];
+                        base[d] = (slice as i32 + (dir > 0) as i32) as f32;
+                        base[u] = i as f32;
+                        base[v] = j as f32;
+                        let (mut du, mut dv) = ([0.0f32; 3], [0.0f32; 3]);
+                        du[u] = width as f32;
+                        dv[v] = height as f32;
+                        let mut normal = Vector3::new(0.0, 0.0, 0.0);
+                        normal[d] = dir as f32;
+                        let corners = [
+                            base,
+                            [base[0] + du[0], base[1] + du[1], base[2] + du[2]],
+                            [base[0] + du[0] + dv[0], base[1] + du[1] + dv[1], base[2] + du[2] + dv[2]],
+                            [base[0] + dv[0], base[1] + dv[1], base[2] + dv[2]],
+                        ];
+                        let first: GLuint = conv!(verticies.len());
+                        for corner in corners.iter() {
+                            let position = origin + Vector3::new(corner[0], corner[1], corner[2]);
+                            // texture up is world up on the sides
+                            let tex_coords = match d {
+                                0 => Vector2::new(position.z, -position.y),
+                                1 => Vector2::new(position.x, position.z),
+                                _ => Vector2::new(position.x, -position.y),
+                            };
+                            verticies.push(Vertex {
+                                position,
+                                normal,
+                                tex_coords,
+                                color: Vector4::new(1.0, 1.0, 1.0, 1.0),
+                                lightmap_coords: Vector2::new((layer - 1) as f32, 0.0),
+                                tangent: Vector4::new(0.0, 0.0, 0.0, 0.0),
+                            });
+                        }
+                        // u cross v points along +d, so the corners go counter clockwise seen from the front of + faces
+                        if dir > 0 {
+                            indices.extend_from_slice(&[first, first + 1, first + 2, first, first + 2, first + 3]);
+                        } else {
+                            indices.extend_from_slice(&[first, first + 2, first + 1, first, first + 3, first + 2]);
+                        }
+                        i += width;
+                    }
+                }
+            }
+        }
+    }
+    MeshData::new(verticies, indices)
+}
+
+// chunk coordinates of a block and its coordinates inside the chunk
+fn split(x: i32, y: i32, z: i32) -> ((i32, i32, i32), (usize, usize, usize)) {
+    let n = N as i32;
+    (
+        (x.div_euclid(n), y.div_euclid(n), z.div_euclid(n)),
+        (x.rem_euclid(n) as usize, y.rem_euclid(n) as usize, z.rem_euclid(n) as usize),
+    )
+}
+
+// an unbounded grid of chunks with one mesh each. editing blocks marks their chunk dirty, plus
+// the neighbors sharing the changed face, and `update` remeshes a few dirty chunks per call.
+#[derive(Debug, Default)]
+pub struct VoxelWorld {
+    chunks: HashMap<(i32, i32, i32), VoxelChunk>,
+    meshes: HashMap<(i32, i32, i32), Mesh>,
+    dirty: HashSet<(i32, i32, i32)>,
+}
+
+impl VoxelWorld {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn block(&self, x: i32, y: i32, z: i32) -> BlockId {
+        let (chunk, (lx, ly, lz)) = split(x, y, z);
+        self.chunks.get(&chunk).map_or(AIR, |chunk| chunk.get(lx, ly, lz))
+    }
+
+    pub fn set_block(&mut self, x: i32, y: i32, z: i32, block: BlockId) {
+        let (coord, (lx, ly, lz)) = split(x, y, z);
+        let chunk = self.chunks.entry(coord).or_default();
+        if chunk.get(lx, ly, lz) == block {
+            return;
+        }
+        chunk.set(lx, ly, lz, block);
+        self.dirty.insert(coord);
+        let local = [lx, ly, lz];
+        for axis in 0..3 {
+            let mut neighbor = [coord.0, coord.1, coord.2];
+            if local[axis] == 0 {
+                neighbor[axis] -= 1;
+            } else if local[axis] == N - 1 {
+                neighbor[axis] += 1;
+            } else {
+                continue;
+            }
+            let neighbor = (neighbor[0], neighbor[1], neighbor[2]);
+            if self.chunks.contains_key(&neighbor) {
+                self.dirty.insert(neighbor);
+            }
+        }
+    }
+
+    // fills the blocks from `min` to `max`, both included
+    pub fn fill(&mut self, min: (i32, i32, i32), max: (i32, i32, i32), block: BlockId) {
+        for y in min.1..=max.1 {
+            for z in min.2..=max.2 {
+                for x in min.0..=max.0 {
+                    self.set_block(x, y, z, block);
+                }
+            }
+        }
+    }
+
+    pub fn chunk(&self, coord: (i32, i32, i32)) -> Option<&VoxelChunk> {
+        self.chunks.get(&coord)
+    }
+
+    pub fn dirty_chunks(&self) -> usize {
+        self.dirty.len()
+    }
+
+    // the mesh of a chunk on the CPU, positions in world space
+    pub fn mesh_chunk(&self, coord: (i32, i32, i32), registry: &BlockRegistry) -> Option<MeshData> {
+        let chunk = self.chunks.get(&coord)?;
+        let n = N as i32;
+        let (ox, oy, oz) = (coord.0 * n, coord.1 * n, coord.2 * n);
+        let solid = |x: i32, y: i32, z: i32| {
+            if (0..n).contains(&x) && (0..n).contains(&y) && (0..n).contains(&z) {
+                chunk.get(x as usize, y as usize, z as usize) != AIR
+            } else {
+                self.block(ox + x, oy + y, oz + z) != AIR
+            }
+        };
+        let origin = Vector3::new(ox as f32, oy as f32, oz as f32);
+        Some(greedy_mesh(chunk, registry, origin, solid))
+    }
+
+    // remeshes up to `max_chunks` dirty chunks, the nearest to `camera` first, and returns how many
+    pub fn update(&mut self, context: &GlContext, registry: &BlockRegistry, camera: Point3<f32>, max_chunks: usize) -> usize {
+        check_render_thread("VoxelWorld");
+        let size = N as f32;
+        let mut dirty: Vec<(i32, i32, i32)> = self.dirty.iter().cloned().collect();
+        let distance = |coord: &(i32, i32, i32)| {
+            let center = Vector3::new(coord.0 as f32 + 0.5, coord.1 as f32 + 0.5, coord.2 as f32 + 0.5) * size;
+            let offset = center - Vector3::new(camera.x, camera.y, camera.z);
+            offset.x * offset.x + offset.y * offset.y + offset.z * offset.z
+        };
+        dirty.sort_by(|a, b| distance(a).partial_cmp(&distance(b)).unwrap_or(std::cmp::Ordering::Equal).then(a.cmp(b)));
+        dirty.truncate(max_chunks);
+
+        for coord in dirty.iter() {
+            self.dirty.remove(coord);
+            let data = match self.mesh_chunk(*coord, registry) {
+                Some(data) if !data.indices.is_empty() => data,
+                // empty chunks keep no mesh and no blocks
+                _ => {
+                    self.meshes.remove(coord);
+                    if matches!(self.chunks.get(coord), Some(chunk) if chunk.is_empty()) {
+                        self.chunks.remove(coord);
+                    }
+                    continue;
+                }
+            };
+            self.meshes.insert(*coord, data.upload(context, vec![]));
+        }
+        dirty.len()
+    }
+
+    pub fn meshes(&self) -> impl Iterator<Item = (&(i32, i32, i32), &Mesh)> {
+        self.meshes.iter()
+    }
+}
+
+const VOXEL_VERTEX_SHADER: &str = r#"
+#version 330 core
+
+layout (location = 0) in vec3 aPos;
+layout (location = 1) in vec3 aNormal;
+layout (location = 2) in vec2 aTexCoords;
+layout (location = 8) in vec2 aLayer;
+
+out vec3 normal;
+out vec3 texCoords;
+
+uniform mat4 viewProjection;
+
+void main() {
+    gl_Position = viewProjection * vec4(aPos, 1.0);
+    normal = aNormal;
+    texCoords = vec3(aTexCoords, aLayer.x);
+}
+"#;
+
+const VOXEL_FRAGMENT_SHADER: &str = r#"
+#version 330 core
+out vec4 FragColor;
+
+in vec3 normal;
+in vec3 texCoords;
+
+uniform sampler2DArray blocks;
+// towards the light
+uniform vec3 lightDirection;
+uniform vec3 lightColor;
+uniform vec3 ambient;
+
+void main() {
+    vec4 albedo = texture(blocks, texCoords);
+    if (albedo.a < 0.5) {
+        discard;
+    }
+    float diffuse = max(dot(normal, lightDirection), 0.0);
+    FragColor = vec4(albedo.rgb * (ambient + lightColor * diffuse), 1.0);
+}
+"#;
+
+// draws the chunk meshes of a `VoxelWorld` with one directional light
+#[derive(Debug)]
+pub struct VoxelRenderer {
+    shader: Shader,
+    // towards the light
+    pub light_direction: Vector3<f32>,
+    pub light_color: Vector3<f32>,
+    pub ambient: Vector3<f32>,
+}
+
+impl VoxelRenderer {
+    pub fn new(context: &GlContext) -> Self {
+        Self {
+            shader: Shader::new(context, VOXEL_VERTEX_SHADER, VOXEL_FRAGMENT_SHADER),
+            light_direction: Vector3::new(0.3, 1.0, 0.5),
+            light_color: Vector3::new(0.8, 0.8, 0.75),
+            ambient: Vector3::new(0.3, 0.32, 0.35),
+        }
+    }
+
+    // chunks outside of the camera's frustum are skipped
+    pub fn draw(&self, context: &GlContext, world: &VoxelWorld, blocks: &TextureArray, camera: &dyn Camera) {
+        let view_projection = camera.projection() * camera.view();
+        let frustum = camera.frustum();
+        let light = self.light_direction.normalize();
+        self.shader.use_program();
+        self.shader.set_matrix4(c_str("viewProjection\0"), &view_projection);
+        self.shader.set_vec3(c_str("lightDirection\0"), light.x, light.y, light.z);
+        self.shader.set_vec3(c_str("lightColor\0"), self.light_color.x, self.light_color.y, self.light_color.z);
+        self.shader.set_vec3(c_str("ambient\0"), self.ambient.x, self.ambient.y, self.ambient.z);
+        self.shader.set_integer(c_str("blocks\0"), 0);
+        blocks.bind(context, 0);
+
+        let size = N as f32;
+        for (coord, mesh) in world.meshes() {
+            let min = Point3::new(coord.0 as f32, coord.1 as f32, coord.2 as f32) * size;
+            if !frustum.intersects_aabb(min, min + Vector3::new(size, size, size)) {
+                FrameStats::record_cull(CullResult::FrustumCulled);
+                continue;
+            }
+            FrameStats::record_cull(CullResult::Visible);
+            mesh.draw(context, &self.shader);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> (BlockRegistry, BlockId, BlockId) {
+        let mut registry = BlockRegistry::new();
+        let grass = registry.add("grass", BlockMaterial { top: 0, bottom: 2, side: 1 });
+        let stone = registry.add("stone", BlockMaterial::all(3));
+        (registry, grass, stone)
+    }
+
+    // blocks of `chunk` in chunk coordinates, air outside of it
+    fn inside(chunk: &VoxelChunk, x: i32, y: i32, z: i32) -> bool {
+        let n = N as i32;
+        (0..n).contains(&x) && (0..n).contains(&y) && (0..n).contains(&z) && chunk.get(x as usize, y as usize, z as usize) != AIR
+    }
+
+    fn quads(mesh: &MeshData) -> usize {
+        assert_eq!(mesh.verticies.len() % 4, 0);
+        assert_eq!(mesh.indices.len(), mesh.verticies.len() / 4 * 6);
+        mesh.verticies.len() / 4
+    }
+
+    // every triangle is wound counter clockwise seen from the side its normal points to
+    fn assert_facing_out(mesh: &MeshData) {
+        for triangle in mesh.indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|k| mesh.verticies[triangle[k] as usize]);
+            let face = (b.position - a.position).cross(c.position - a.position);
+            assert!(face.dot(a.normal) > 0.0, "{:?} against {:?}", face, a.normal);
+        }
+    }
+
+    #[test]
+    fn registry_ids_start_after_air() {
+        let (registry, grass, stone) = registry();
+        assert_eq!((grass, stone), (1, 2));
+        assert_eq!(registry.find("stone"), Some(stone));
+        assert_eq!(registry.find("dirt"), None);
+        assert_eq!(registry.name(grass), Some("grass"));
+        assert_eq!(registry.material(stone), Some(&BlockMaterial::all(3)));
+        assert_eq!(registry.material(AIR), None);
+        assert_eq!(registry.material(3), None);
+    }
+
+    #[test]
+    fn chunks_count_their_solid_blocks() {
+        let mut chunk = VoxelChunk::new();
+        assert!(chunk.is_empty());
+        chunk.set(1, 2, 3, 1);
+        chunk.set(1, 2, 3, 2);
+        assert_eq!(chunk.get(1, 2, 3), 2);
+        assert_eq!(chunk.get(3, 2, 1), AIR);
+        assert!(!chunk.is_empty());
+        chunk.set(1, 2, 3, AIR);
+        assert!(chunk.is_empty());
+    }
+
+    #[test]
+    fn a_single_block_has_six_faces() {
+        let (registry, grass, _) = registry();
+        let mut chunk = VoxelChunk::new();
+        chunk.set(2, 3, 4, grass);
+        let origin = Vector3::new(16.0, 0.0, -16.0);
+        let mesh = greedy_mesh(&chunk, &registry, origin, |x, y, z| inside(&chunk, x, y, z));
+        assert_eq!(quads(&mesh), 6);
+        assert_facing_out(&mesh);
+
+        for vertex in mesh.verticies.iter() {
+            let local = vertex.position - origin;
+            assert!(local.x >= 2.0 && local.x <= 3.0 && local.y >= 3.0 && local.y <= 4.0 && local.z >= 4.0 && local.z <= 5.0);
+            let expected = match vertex.normal.y as i32 {
+                1 => 0.0,
+                -1 => 2.0,
+                _ => 1.0,
+            };
+            assert_eq!(vertex.lightmap_coords.x, expected, "{:?}", vertex.normal);
+        }
+    }
+
+    #[test]
+    fn faces_of_the_same_layer_merge() {
+        let (registry, grass, stone) = registry();
+        let mut chunk = VoxelChunk::new();
+        for x in 0..4 {
+            for z in 0..3 {
+                chunk.set(x, 0, z, stone);
+            }
+        }
+        let mesh = greedy_mesh(&chunk, &registry, Vector3::new(0.0, 0.0, 0.0), |x, y, z| inside(&chunk, x, y, z));
+        assert_eq!(quads(&mesh), 6);
+        assert_facing_out(&mesh);
+        // the texture repeats once per block
+        let top: Vec<_> = mesh.verticies.iter().filter(|vertex| vertex.normal.y > 0.0).collect();
+        assert!(top.iter().any(|vertex| vertex.tex_coords == Vector2::new(4.0, 3.0)));
+
+        // a block with other layers splits the faces it is part of
+        chunk.set(0, 0, 0, grass);
+        let mesh = greedy_mesh(&chunk, &registry, Vector3::new(0.0, 0.0, 0.0), |x, y, z| inside(&chunk, x, y, z));
+        assert!(quads(&mesh) > 6);
+    }
+
+    #[test]
+    fn faces_against_solid_neighbors_are_hidden() {
+        let (registry, _, stone) = registry();
+        let mut chunk = VoxelChunk::new();
+        chunk.set(0, 0, 0, stone);
+        chunk.set(1, 0, 0, stone);
+        // the two blocks share one face, and the ground below is outside of the chunk
+        let mesh = greedy_mesh(&chunk, &registry, Vector3::new(0.0, 0.0, 0.0), |x, y, z| y < 0 || inside(&chunk, x, y, z));
+        assert_eq!(quads(&mesh), 5);
+        assert!(mesh.verticies.iter().all(|vertex| vertex.normal.y >= 0.0));
+    }
+
+    #[test]
+    fn worlds_span_chunks() {
+        let (registry, _, stone) = registry();
+        let mut world = VoxelWorld::new();
+        world.set_block(-1, 0, 0, stone);
+        assert_eq!(world.block(-1, 0, 0), stone);
+        assert_eq!(world.chunk((-1, 0, 0)).unwrap().get(15, 0, 0), stone);
+        assert_eq!(world.dirty_chunks(), 1);
+
+        // the neighbor chunk exists now, so its shared face gets remeshed too
+        world.set_block(0, 5, 5, stone);
+        assert_eq!(world.dirty_chunks(), 2);
+        world.set_block(0, 5, 5, stone);
+        assert_eq!(world.dirty_chunks(), 2);
+
+        world.fill((-1, 0, 0), (0, 0, 0), stone);
+        let left = world.mesh_chunk((-1, 0, 0), &registry).unwrap();
+        let right = world.mesh_chunk((0, 0, 0), &registry).unwrap();
+        // the face between the chunks is in neither mesh
+        assert!(left.verticies.iter().all(|vertex| vertex.normal.x <= 0.0));
+        assert!(right.verticies.iter().filter(|vertex| vertex.position.y < 1.0).all(|vertex| vertex.normal.x >= 0.0));
+        assert_eq!(quads(&left), 5);
+        assert!(world.mesh_chunk((5, 0, 0), &registry).is_none());
+    }
+}
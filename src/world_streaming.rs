@@ -0,0 +1,577 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+use cgmath::{InnerSpace, Matrix4, Point3, Vector2, Vector3, Vector4};
+use gl::types::*;
+
+use crate::context::check_render_thread;
+use crate::{compute_tangents, GlContext, Mesh, MeshData, Model, ModelData, Texture, Vertex};
+
+// a square column of the world on the xz plane, covering [x, x + 1) * chunk size and the same on z
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ChunkCoord {
+    pub x: i32,
+    pub z: i32,
+}
+
+impl ChunkCoord {
+    pub fn new(x: i32, z: i32) -> Self {
+        Self { x, z }
+    }
+
+    pub fn containing(position: Point3<f32>, chunk_size: f32) -> Self {
+        Self::new((position.x / chunk_size).floor() as i32, (position.z / chunk_size).floor() as i32)
+    }
+
+    // the corner with the smallest x and z, at height 0
+    pub fn origin(self, chunk_size: f32) -> Point3<f32> {
+        Point3::new(self.x as f32 * chunk_size, 0.0, self.z as f32 * chunk_size)
+    }
+
+    // horizontal distance from `position` to the nearest point of the chunk
+    pub fn distance(self, position: Point3<f32>, chunk_size: f32) -> f32 {
+        let origin = self.origin(chunk_size);
+        let dx = (origin.x - position.x).max(position.x - origin.x - chunk_size).max(0.0);
+        let dz = (origin.z - position.z).max(position.z - origin.z - chunk_size).max(0.0);
+        Vector2::new(dx, dz).magnitude()
+    }
+}
+
+// what a chunk holds once read, built on a worker thread
+#[derive(Debug, Default)]
+pub struct ChunkData {
+    // in world space
+    pub terrain: Option<MeshData>,
+    // with their model matrices
+    pub models: Vec<(ModelData, Matrix4<f32>)>,
+}
+
+impl ChunkData {
+    pub fn size_bytes(&self) -> usize {
+        let terrain = self
+            .terrain
+            .as_ref()
+            .map_or(0, |mesh| mesh.verticies.len() * std::mem::size_of::<Vertex>() + mesh.indices.len() * 4);
+        terrain + self.models.iter().map(|(model, _)| model.size_bytes()).sum::<usize>()
+    }
+}
+
+// where chunks come from, e.g. files per chunk or generated terrain. called from several worker
+// threads at once. Ok(None) for chunks with nothing in them.
+pub trait ChunkSource: Send + Sync + 'static {
+    fn load(&self, coord: ChunkCoord, chunk_size: f32) -> Result<Option<ChunkData>, Box<dyn Error + Send + Sync>>;
+}
+
+// terrain from a height function, sampled on a grid of `resolution` cells per chunk side, and
+// static OBJ models placed in the chunk their translation falls into
+pub struct HeightfieldSource<F> {
+    height: F,
+    pub resolution: u32,
+    // world units per repeat of the texture coordinates
+    pub texture_scale: f32,
+    models: Vec<(PathBuf, Matrix4<f32>)>,
+}
+
+impl<F> std::fmt::Debug for HeightfieldSource<F> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("HeightfieldSource")
+            .field("resolution", &self.resolution)
+            .field("texture_scale", &self.texture_scale)
+            .field("models", &self.models)
+            .finish()
+    }
+}
+
+impl<F: Fn(f32, f32) -> f32 + Send + Sync + 'static> HeightfieldSource<F> {
+    pub fn new(height: F, resolution: u32) -> Self {
+        Self {
+            height,
+            resolution: resolution.max(1),
+            texture_scale: 8.0,
+            models: vec![],
+        }
+    }
+
+    pub fn with_model<P: AsRef<Path>>(mut self, path: P, transform: Matrix4<f32>) -> Self {
+        self.models.push((path.as_ref().to_owned(), transform));
+        self
+    }
+
+    fn terrain(&self, coord: ChunkCoord, chunk_size: f32) -> MeshData {
+        let (n, origin) = (self.resolution, coord.origin(chunk_size));
+        let cell = chunk_size / n as f32;
+        // central differences over one cell, continuous across chunk borders
+        let normal = |x: f32, z: f32| {
+            let dx = (self.height)(x + cell, z) - (self.height)(x - cell, z);
+            let dz = (self.height)(x, z + cell) - (self.height)(x, z - cell);
+            Vector3::new(-dx, 2.0 * cell, -dz).normalize()
+        };
+        let mut verticies = Vec::with_capacity(((n + 1) * (n + 1)) as usize);
+        for j in 0..=n {
+            for i in 0..=n {
+                let (x, z) = (origin.x + i as f32 * cell, origin.z + j as f32 * cell);
+                let tex_coords = Vector2::new(x, z) / self.texture_scale;
+                verticies.push(Vertex {
+                    position: Vector3::new(x, (self.height)(x, z), z),
+                    normal: normal(x, z),
+                    tex_coords,
+                    color: Vector4::new(1.0, 1.0, 1.0, 1.0),
+                    lightmap_coords: Vector2::new(i as f32, j as f32) / n as f32,
+                    tangent: Vector4::new(0.0, 0.0, 0.0, 0.0),
+                });
+            }
+        }
+        let mut indices: Vec<GLuint> = Vec::with_capacity((n * n * 6) as usize);
+        for j in 0..n {
+            for i in 0..n {
+                let a = i + j * (n + 1);
+                let (b, c) = (a + 1, a + n + 1);
+                // counter clockwise seen from above
+                indices.extend_from_slice(&[a, c, b, b, c, c + 1]);
+            }
+        }
+        let mut mesh = MeshData::new(verticies, indices);
+        compute_tangents(&mut mesh);
+        mesh
+    }
+}
+
+impl<F: Fn(f32, f32) -> f32 + Send + Sync + 'static> ChunkSource for HeightfieldSource<F> {
+    fn load(&self, coord: ChunkCoord, chunk_size: f32) -> Result<Option<ChunkData>, Box<dyn Error + Send + Sync>> {
+        let mut models = vec![];
+        for (path, transform) in self.models.iter() {
+            let position = Point3::new(transform.w.x, transform.w.y, transform.w.z);
+            if ChunkCoord::containing(position, chunk_size) == coord {
+                let model = ModelData::load_obj(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+                models.push((model, *transform));
+            }
+        }
+        Ok(Some(ChunkData {
+            terrain: Some(self.terrain(coord, chunk_size)),
+            models,
+        }))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct WorldStreamingConfig {
+    // world units per chunk side
+    pub chunk_size: f32,
+    // chunks closer to the camera than this are loaded
+    pub load_radius: f32,
+    // and unloaded once further than this, larger so chunks on the edge do not flicker
+    pub unload_radius: f32,
+    // upper bound of the chunk data kept on the GPU, the farthest chunks go first
+    pub budget_bytes: usize,
+    pub max_uploads_per_update: usize,
+    // bytes uploaded per update before the rest waits, at least one chunk goes each update
+    pub upload_bytes_per_update: usize,
+    pub workers: usize,
+}
+
+impl Default for WorldStreamingConfig {
+    fn default() -> Self {
+        Self {
+            chunk_size: 64.0,
+            load_radius: 192.0,
+            unload_radius: 256.0,
+            budget_bytes: 512 * 1024 * 1024,
+            max_uploads_per_update: 2,
+            upload_bytes_per_update: 8 * 1024 * 1024,
+            workers: 2,
+        }
+    }
+}
+
+// a chunk on the GPU
+#[derive(Debug)]
+pub struct Chunk {
+    pub coord: ChunkCoord,
+    pub terrain: Option<Mesh>,
+    pub models: Vec<(Model, Matrix4<f32>)>,
+    bytes: usize,
+}
+
+impl Chunk {
+    pub fn size_bytes(&self) -> usize {
+        self.bytes
+    }
+}
+
+#[derive(Debug)]
+enum ChunkState {
+    // sent to a worker
+    Loading,
+    // read, waiting for its upload
+    Ready(ChunkData),
+    // nothing in it or failed, not asked for again while in range
+    Empty,
+    Loaded(Chunk),
+    // dropped over budget, asked for again once its bytes fit
+    Evicted(usize),
+}
+
+type Loaded = (ChunkCoord, Result<Option<ChunkData>, String>);
+
+fn worker(source: Arc<dyn ChunkSource>, chunk_size: f32, jobs: Arc<Mutex<Receiver<ChunkCoord>>>, loaded: Sender<Loaded>) {
+    loop {
+        // the lock is released before loading so the other workers can take jobs
+        let job = jobs.lock().unwrap().recv();
+        let coord = match job {
+            Ok(coord) => coord,
+            Err(_) => return,
+        };
+        let result = source.load(coord, chunk_size).map_err(|e| e.to_string());
+        if loaded.send((coord, result)).is_err() {
+            return;
+        }
+    }
+}
+
+// what `StreamingWorld::update` did
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WorldStreamingStats {
+    pub requested: usize,
+    pub uploaded: usize,
+    pub uploaded_bytes: usize,
+    pub unloaded: usize,
+}
+
+// keeps the chunks around the camera loaded: workers read them from the source nearest first,
+// `update` uploads a budgeted few per frame and drops the ones that fell out of range
+pub struct StreamingWorld {
+    config: WorldStreamingConfig,
+    chunks: HashMap<ChunkCoord, ChunkState>,
+    jobs: Option<Sender<ChunkCoord>>,
+    loaded: Receiver<Loaded>,
+    workers: Vec<JoinHandle<()>>,
+    // bound to every terrain mesh
+    terrain_textures: Vec<Texture>,
+    resident_bytes: usize,
+}
+
+impl std::fmt::Debug for StreamingWorld {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StreamingWorld")
+            .field("config", &self.config)
+            .field("chunks", &self.chunks.len())
+            .field("resident_bytes", &self.resident_bytes)
+            .finish()
+    }
+}
+
+impl StreamingWorld {
+    pub fn new<S: ChunkSource>(config: WorldStreamingConfig, source: S) -> Self {
+        let source: Arc<dyn ChunkSource> = Arc::new(source);
+        let (job_sender, job_receiver) = channel();
+        let (loaded_sender, loaded_receiver) = channel();
+        let job_receiver = Arc::new(Mutex::new(job_receiver));
+        let workers = (0..config.workers.max(1))
+            .map(|index| {
+                let (source, jobs, loaded) = (source.clone(), job_receiver.clone(), loaded_sender.clone());
+                thread::Builder::new()
+                    .name(format!("world streaming {}", index))
+                    .spawn(move || worker(source, config.chunk_size, jobs, loaded))
+                    .expect("failed to spawn world streaming thread")
+            })
+            .collect();
+        Self {
+            config,
+            chunks: HashMap::new(),
+            jobs: Some(job_sender),
+            loaded: loaded_receiver,
+            workers,
+            terrain_textures: vec![],
+            resident_bytes: 0,
+        }
+    }
+
+    pub fn config(&self) -> &WorldStreamingConfig {
+        &self.config
+    }
+
+    // used by terrain uploaded from now on
+    pub fn set_terrain_textures(&mut self, textures: Vec<Texture>) {
+        self.terrain_textures = textures;
+    }
+
+    pub fn resident_bytes(&self) -> usize {
+        self.resident_bytes
+    }
+
+    // the chunks ready to draw, in no particular order
+    pub fn chunks(&self) -> impl Iterator<Item = &Chunk> {
+        self.chunks.values().filter_map(|state| match state {
+            ChunkState::Loaded(chunk) => Some(chunk),
+            _ => None,
+        })
+    }
+
+    pub fn chunk(&self, coord: ChunkCoord) -> Option<&Chunk> {
+        match self.chunks.get(&coord) {
+            Some(ChunkState::Loaded(chunk)) => Some(chunk),
+            _ => None,
+        }
+    }
+
+    // chunks requested or waiting for their upload
+    pub fn pending(&self) -> usize {
+        self.chunks
+            .values()
+            .filter(|state| matches!(state, ChunkState::Loading | ChunkState::Ready(_)))
+            .count()
+    }
+
+    // call once per frame with the camera position
+    pub fn update(&mut self, context: &GlContext, camera: Point3<f32>) -> WorldStreamingStats {
+        check_render_thread("StreamingWorld");
+        let mut stats = WorldStreamingStats::default();
+        let size = self.config.chunk_size;
+
+        while let Ok((coord, result)) = self.loaded.try_recv() {
+            // dropped while it was loading
+            if !matches!(self.chunks.get(&coord), Some(ChunkState::Loading)) {
+                continue;
+            }
+            let state = match result {
+                Ok(Some(data)) => ChunkState::Ready(data),
+                Ok(None) => ChunkState::Empty,
+                Err(message) => {
+                    log::error!("failed to load chunk ({}, {}): {}", coord.x, coord.z, message);
+                    ChunkState::Empty
+                }
+            };
+            self.chunks.insert(coord, state);
+        }
+
+        // out of range chunks go, the ones still loading are dropped when they arrive
+        let unload_radius = self.config.unload_radius;
+        let before = self.chunks.len();
+        let mut freed = 0;
+        self.chunks.retain(|coord, state| {
+            let keep = coord.distance(camera, size) <= unload_radius;
+            if let (false, ChunkState::Loaded(chunk)) = (keep, &*state) {
+                freed += chunk.bytes;
+            }
+            keep
+        });
+        self.resident_bytes -= freed;
+        stats.unloaded = before - self.chunks.len();
+
+        // the ready ones nearest first, within the per frame budget
+        let mut ready: Vec<(ChunkCoord, f32)> = self
+            .chunks
+            .iter()
+            .filter(|(_, state)| matches!(state, ChunkState::Ready(_)))
+            .map(|(&coord, _)| (coord, coord.distance(camera, size)))
+            .collect();
+        ready.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal).then(a.0.cmp(&b.0)));
+        for (coord, _) in ready {
+            if stats.uploaded >= self.config.max_uploads_per_update
+                || (stats.uploaded > 0 && stats.uploaded_bytes >= self.config.upload_bytes_per_update)
+            {
+                break;
+            }
+            let data = match self.chunks.remove(&coord) {
+                Some(ChunkState::Ready(data)) => data,
+                _ => unreachable!(),
+            };
+            let bytes = data.size_bytes();
+            let chunk = Chunk {
+                coord,
+                terrain: data.terrain.map(|mesh| mesh.upload(context, self.terrain_textures.clone())),
+                models: data.models.into_iter().map(|(model, transform)| (model.upload(context), transform)).collect(),
+                bytes,
+            };
+            self.chunks.insert(coord, ChunkState::Loaded(chunk));
+            self.resident_bytes += bytes;
+            stats.uploaded += 1;
+            stats.uploaded_bytes += bytes;
+        }
+
+        self.evict(camera);
+
+        // asks for the missing chunks in range, nearest first, a few at a time so the order
+        // follows the camera
+        let in_flight = self.chunks.values().filter(|state| matches!(state, ChunkState::Loading)).count();
+        let mut wanted = self.missing(camera);
+        wanted.truncate((self.workers.len() * 2).saturating_sub(in_flight));
+        if self.resident_bytes < self.config.budget_bytes {
+            for coord in wanted {
+                if let Some(jobs) = &self.jobs {
+                    if jobs.send(coord).is_ok() {
+                        self.chunks.insert(coord, ChunkState::Loading);
+                        stats.requested += 1;
+                    }
+                }
+            }
+        }
+        stats
+    }
+
+    // drops the farthest loaded chunks while over budget, keeping the one under the camera
+    fn evict(&mut self, camera: Point3<f32>) {
+        if self.resident_bytes <= self.config.budget_bytes {
+            return;
+        }
+        let size = self.config.chunk_size;
+        let mut loaded: Vec<(ChunkCoord, f32)> = self
+            .chunks
+            .iter()
+            .filter(|(_, state)| matches!(state, ChunkState::Loaded(_)))
+            .map(|(&coord, _)| (coord, coord.distance(camera, size)))
+            .collect();
+        loaded.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        for (coord, distance) in loaded {
+            if self.resident_bytes <= self.config.budget_bytes || distance == 0.0 {
+                break;
+            }
+            if let Some(ChunkState::Loaded(chunk)) = self.chunks.remove(&coord) {
+                self.resident_bytes -= chunk.bytes;
+                self.chunks.insert(coord, ChunkState::Evicted(chunk.bytes));
+            }
+        }
+    }
+
+    // chunks in the load radius that are not known yet or were evicted and fit again, nearest first
+    fn missing(&self, camera: Point3<f32>) -> Vec<ChunkCoord> {
+        let size = self.config.chunk_size;
+        let center = ChunkCoord::containing(camera, size);
+        let reach = (self.config.load_radius / size).ceil() as i32;
+        let mut missing: Vec<(ChunkCoord, f32)> = vec![];
+        for z in center.z - reach..=center.z + reach {
+            for x in center.x - reach..=center.x + reach {
+                let coord = ChunkCoord::new(x, z);
+                let distance = coord.distance(camera, size);
+                let wanted = match self.chunks.get(&coord) {
+                    None => true,
+                    Some(ChunkState::Evicted(bytes)) => self.resident_bytes + bytes <= self.config.budget_bytes,
+                    Some(_) => false,
+                };
+                if distance <= self.config.load_radius && wanted {
+                    missing.push((coord, distance));
+                }
+            }
+        }
+        missing.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal).then(a.0.cmp(&b.0)));
+        missing.into_iter().map(|(coord, _)| coord).collect()
+    }
+}
+
+impl Drop for StreamingWorld {
+    fn drop(&mut self) {
+        check_render_thread("StreamingWorld");
+        // closing the channel stops the workers once they finish the chunk at hand
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Nothing;
+
+    impl ChunkSource for Nothing {
+        fn load(&self, _coord: ChunkCoord, _chunk_size: f32) -> Result<Option<ChunkData>, Box<dyn Error + Send + Sync>> {
+            Ok(None)
+        }
+    }
+
+    fn world(load_radius: f32) -> StreamingWorld {
+        let config = WorldStreamingConfig {
+            chunk_size: 10.0,
+            load_radius,
+            unload_radius: load_radius * 2.0,
+            budget_bytes: 1000,
+            workers: 1,
+            ..WorldStreamingConfig::default()
+        };
+        StreamingWorld::new(config, Nothing)
+    }
+
+    #[test]
+    fn chunk_coordinates() {
+        assert_eq!(ChunkCoord::containing(Point3::new(5.0, 100.0, 15.0), 10.0), ChunkCoord::new(0, 1));
+        assert_eq!(ChunkCoord::containing(Point3::new(-0.5, 0.0, -10.0), 10.0), ChunkCoord::new(-1, -1));
+        assert_eq!(ChunkCoord::new(-2, 3).origin(10.0), Point3::new(-20.0, 0.0, 30.0));
+
+        let chunk = ChunkCoord::new(1, 0);
+        // inside and on the border
+        assert_eq!(chunk.distance(Point3::new(15.0, 50.0, 5.0), 10.0), 0.0);
+        assert_eq!(chunk.distance(Point3::new(10.0, 0.0, 0.0), 10.0), 0.0);
+        assert_eq!(chunk.distance(Point3::new(5.0, 0.0, 5.0), 10.0), 5.0);
+        assert_eq!(chunk.distance(Point3::new(23.0, 0.0, 14.0), 10.0), 5.0);
+    }
+
+    #[test]
+    fn heightfields_sample_the_height_function() {
+        let source = HeightfieldSource::new(|x: f32, z: f32| x * 0.5 + z, 4);
+        let data = source.load(ChunkCoord::new(1, -1), 8.0).unwrap().unwrap();
+        assert!(data.models.is_empty());
+        assert_eq!(data.size_bytes(), 25 * std::mem::size_of::<Vertex>() + 96 * 4);
+        let terrain = data.terrain.unwrap();
+        assert_eq!(terrain.verticies.len(), 25);
+        assert_eq!(terrain.indices.len(), 4 * 4 * 6);
+
+        let expected = Vector3::new(-0.5, 1.0, -1.0).normalize();
+        for vertex in terrain.verticies.iter() {
+            let p = vertex.position;
+            assert!(p.x >= 8.0 && p.x <= 16.0 && p.z >= -8.0 && p.z <= 0.0, "{:?}", p);
+            assert_eq!(p.y, p.x * 0.5 + p.z);
+            assert!((vertex.normal - expected).magnitude() < 1e-5, "{:?}", vertex.normal);
+        }
+        // every triangle faces up
+        for triangle in terrain.indices.chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|k| terrain.verticies[triangle[k] as usize].position);
+            assert!((b - a).cross(c - a).y > 0.0);
+        }
+    }
+
+    #[test]
+    fn missing_chunks_are_nearest_first() {
+        let mut world = world(10.0);
+        let camera = Point3::new(5.0, 0.0, 5.0);
+        let missing = world.missing(camera);
+        assert_eq!(missing[0], ChunkCoord::new(0, 0));
+        assert_eq!(missing.len(), 9);
+        assert!(missing.iter().all(|coord| coord.distance(camera, 10.0) <= 10.0));
+
+        world.chunks.insert(ChunkCoord::new(0, 0), ChunkState::Loading);
+        world.chunks.insert(ChunkCoord::new(1, 0), ChunkState::Empty);
+        let missing = world.missing(camera);
+        assert_eq!(missing.len(), 7);
+        assert!(!missing.contains(&ChunkCoord::new(0, 0)) && !missing.contains(&ChunkCoord::new(1, 0)));
+    }
+
+    #[test]
+    fn terrain_has_tangents() {
+        let source = HeightfieldSource::new(|x: f32, _z: f32| x.sin(), 8);
+        let terrain = source.load(ChunkCoord::new(0, 0), 4.0).unwrap().unwrap().terrain.unwrap();
+        for vertex in terrain.verticies.iter() {
+            let tangent = vertex.tangent.truncate();
+            assert!((tangent.magnitude() - 1.0).abs() < 1e-4, "{:?}", vertex.tangent);
+            assert!(tangent.dot(vertex.normal).abs() < 1e-4);
+            assert_eq!(vertex.tangent.w.abs(), 1.0);
+        }
+    }
+
+    #[test]
+    fn evicted_chunks_come_back_once_they_fit() {
+        let mut world = world(4.0);
+        let camera = Point3::new(5.0, 0.0, 5.0);
+        world.chunks.insert(ChunkCoord::new(0, 0), ChunkState::Evicted(600));
+        world.resident_bytes = 600;
+        assert!(world.missing(camera).is_empty());
+
+        world.resident_bytes = 400;
+        assert_eq!(world.missing(camera), vec![ChunkCoord::new(0, 0)]);
+    }
+}
@@ -0,0 +1,93 @@
+// tests that need a GL context. they are ignored by default since CI machines often have no
+// display, run them with `cargo test --test gl -- --ignored --test-threads=1`.
+
+use cgmath::{vec2, vec3, vec4};
+use game_engine::*;
+
+const VERTEX_SHADER: &str = r#"
+#version 330 core
+
+layout (location = 0) in vec3 aPos;
+
+void main() {
+    gl_Position = vec4(aPos, 1.0);
+}
+"#;
+
+const FRAGMENT_SHADER: &str = r#"
+#version 330 core
+
+out vec4 FragColor;
+
+void main() {
+    FragColor = vec4(1.0);
+}
+"#;
+
+fn vertex(x: f32, y: f32) -> Vertex {
+    Vertex {
+        position: vec3(x, y, 0.0),
+        normal: vec3(0.0, 0.0, 1.0),
+        tex_coords: vec2(x, y),
+        color: vec4(1.0, 1.0, 1.0, 1.0),
+        lightmap_coords: vec2(x, y),
+        tangent: vec4(1.0, 0.0, 0.0, 1.0),
+    }
+}
+
+#[test]
+#[ignore = "needs a GL context"]
+fn shaders_compile_and_report_errors() {
+    let test = TestContext::new().unwrap();
+    let shader = Shader::builder().vertex(VERTEX_SHADER).fragment(FRAGMENT_SHADER).build(test.context()).unwrap();
+    assert!(shader.is_valid());
+    assert!(test.errors().is_empty());
+
+    let broken = FRAGMENT_SHADER.replace("vec4(1.0)", "vec4(undefined)");
+    let error = Shader::builder().vertex(VERTEX_SHADER).fragment(&broken).build(test.context()).unwrap_err();
+    assert!(error.to_string().contains("failed to compile fragment shader"), "{}", error);
+}
+
+#[test]
+#[ignore = "needs a GL context"]
+fn meshes_upload_their_buffers() {
+    let test = TestContext::new().unwrap();
+    let verticies = vec![vertex(0.0, 0.0), vertex(1.0, 0.0), vertex(0.0, 1.0), vertex(1.0, 1.0)];
+    let mesh = Mesh::new(test.context(), verticies.clone(), vec![0, 1, 2, 2, 1, 3], vec![]);
+    assert_ne!(mesh.vao(), 0);
+    assert_eq!(mesh.verticies, verticies);
+
+    unsafe {
+        gl::BindVertexArray(mesh.vao());
+        let (mut vertex_bytes, mut index_bytes, mut buffer) = (0, 0, 0);
+        gl::GetVertexAttribiv(0, gl::VERTEX_ATTRIB_ARRAY_BUFFER_BINDING, &mut buffer);
+        gl::BindBuffer(gl::ARRAY_BUFFER, buffer as u32);
+        gl::GetBufferParameteriv(gl::ARRAY_BUFFER, gl::BUFFER_SIZE, &mut vertex_bytes);
+        gl::GetBufferParameteriv(gl::ELEMENT_ARRAY_BUFFER, gl::BUFFER_SIZE, &mut index_bytes);
+        gl::BindVertexArray(0);
+        assert_eq!(vertex_bytes as usize, 4 * std::mem::size_of::<Vertex>());
+        assert_eq!(index_bytes as usize, 6 * std::mem::size_of::<u32>());
+    }
+
+    let shader = Shader::new(test.context(), VERTEX_SHADER, FRAGMENT_SHADER);
+    mesh.draw(test.context(), &shader);
+    assert!(test.errors().is_empty());
+}
+
+#[test]
+#[ignore = "needs a GL context"]
+fn framebuffers_are_complete() {
+    let test = TestContext::new().unwrap();
+    let context = test.context();
+    let targets = [
+        Framebuffer::new(context, 64, 32),
+        Framebuffer::builder(16, 16).color(AttachmentFormat::RGBA8).color(AttachmentFormat::RGBA16F).build(context),
+        Framebuffer::builder(16, 16).depth_with(AttachmentFormat::DEPTH24_STENCIL8, AttachmentStorage::Renderbuffer).build(context),
+        Framebuffer::builder(16, 16).color(AttachmentFormat::RGBA8).depth(AttachmentFormat::DEPTH24_STENCIL8).samples(4).build(context),
+    ];
+    for target in targets.iter() {
+        target.bind(context);
+        assert_eq!(test.framebuffer_status(), gl::FRAMEBUFFER_COMPLETE, "{}x{}", target.width(), target.height());
+    }
+    assert!(test.errors().is_empty());
+}